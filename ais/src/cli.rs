@@ -2,6 +2,7 @@ use crate::oppai::{Config, Solver};
 use clap::{Arg, ArgAction, ArgGroup, ArgMatches, value_parser};
 use oppai_minimax::minimax::{MinimaxConfig, MinimaxType};
 use oppai_uct::uct::{UcbType, UctConfig, UctKomiType};
+use oppai_zero::{symmetric_model::Symmetries, zero::ZeroConfig};
 
 pub fn groups() -> [ArgGroup; 2] {
   [
@@ -26,7 +27,7 @@ pub fn groups() -> [ArgGroup; 2] {
   ]
 }
 
-pub fn args() -> [Arg; 20] {
+pub fn args() -> [Arg; 24] {
   [
     Arg::new("solver")
       .short('s')
@@ -41,8 +42,8 @@ pub fn args() -> [Arg; 20] {
       .long("threads-count")
       .help(
         "Number of threads to use. Will be determined automatically if not specified: \
-         for Minimax number of physical cores will be chosen, for UCT - number of \
-         logical cores",
+         for Minimax and Zero number of physical cores will be chosen, for UCT - \
+         number of logical cores",
       )
       .num_args(1)
       .value_parser(value_parser!(usize)),
@@ -52,6 +53,25 @@ pub fn args() -> [Arg; 20] {
       .num_args(1)
       .value_parser(value_parser!(usize))
       .default_value("10000"),
+    Arg::new("cache-size")
+      .long("cache-size")
+      .help("Count of neural network evaluations that cache for Zero can contain, 0 disables it")
+      .num_args(1)
+      .value_parser(value_parser!(usize))
+      .default_value("0"),
+    Arg::new("symmetries")
+      .long("symmetries")
+      .help(
+        "Symmetries of the board Zero evaluates every position under: identity, random for a single random one, \
+         or a number of random ones to average, 8 for all of them",
+      )
+      .num_args(1)
+      .value_parser(value_parser!(Symmetries))
+      .default_value("identity"),
+    Arg::new("gumbel")
+      .long("gumbel")
+      .help("Select Zero's root moves by Gumbel sequential halving instead of PUCT, stronger at small playout budgets")
+      .action(ArgAction::SetTrue),
     Arg::new("minimax-type")
       .long("minimax-type")
       .help("Minimax type")
@@ -174,6 +194,16 @@ pub fn args() -> [Arg; 20] {
       .num_args(1)
       .value_parser(value_parser!(humantime::Duration))
       .default_value("1s"),
+    Arg::new("komi")
+      .long("komi")
+      .help(
+        "Doubled komi added to Red's score, so that half-point komi is possible. \
+         A game initialized through the protocol brings its own komi instead",
+      )
+      .num_args(1)
+      .value_parser(value_parser!(i32))
+      .allow_negative_numbers(true)
+      .default_value("0"),
   ]
 }
 
@@ -199,9 +229,16 @@ pub fn parse_config(matches: &ArgMatches) -> Config {
     hash_table_size: matches.get_one("hash-table-size").copied().unwrap(),
     rebuild_trajectories: matches.get_flag("rebuild-trajectories"),
   };
+  let zero_config = ZeroConfig {
+    threads_count: threads_count.unwrap_or_else(num_cpus::get_physical),
+    cache_size: matches.get_one("cache-size").copied().unwrap(),
+    symmetries: matches.get_one("symmetries").copied().unwrap(),
+    gumbel: matches.get_flag("gumbel"),
+  };
   Config {
     uct: uct_config,
     minimax: minimax_config,
+    zero: zero_config,
     solver: matches.get_one("solver").copied().unwrap(),
     ladders: matches.get_flag("no-ladders-solver"),
     ladders_score_limit: matches.get_one("ladders-score-limit").copied().unwrap(),
@@ -211,5 +248,6 @@ pub fn parse_config(matches: &ArgMatches) -> Config {
       .copied()
       .unwrap()
       .into(),
+    komi_x_2: matches.get_one("komi").copied().unwrap(),
   }
 }
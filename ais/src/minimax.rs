@@ -6,6 +6,7 @@ use std::any::TypeId;
 pub struct Minimax(pub InnerMinimax);
 
 impl AI for Minimax {
+  /// The estimation is in half points, so that a fractional komi is exact.
   type Analysis = SingleAnalysis<i32, u32>;
   type Confidence = u32;
 
@@ -15,7 +15,11 @@ use oppai_field::{
 use oppai_minimax::minimax::{Minimax as InnerMinimax, MinimaxConfig};
 use oppai_patterns::patterns::Patterns as InnerPatterns;
 use oppai_uct::uct::{UctConfig, UctRoot};
-use oppai_zero::{mcgs::PlaySelectionWeight, model::Model, zero::Zero as InnerZero};
+use oppai_zero::{
+  mcgs::PlaySelectionWeight,
+  model::Model,
+  zero::{Zero as InnerZero, ZeroConfig},
+};
 use rand::{Rng, SeedableRng, distr::StandardUniform, prelude::Distribution};
 use std::{
   convert::identity,
@@ -39,11 +43,14 @@ pub enum Solver {
 pub struct Config {
   pub uct: UctConfig,
   pub minimax: MinimaxConfig,
+  pub zero: ZeroConfig,
   pub solver: Solver,
   pub ladders: bool,
   pub ladders_score_limit: u32,
   pub ladders_depth_limit: u32,
   pub ladders_time_limit: Duration,
+  /// Komi for Red, doubled so that half points are representable.
+  pub komi_x_2: i32,
 }
 
 impl Default for Config {
@@ -51,11 +58,13 @@ impl Default for Config {
     Self {
       uct: Default::default(),
       minimax: Default::default(),
+      zero: Default::default(),
       solver: Solver::Uct,
       ladders: true,
       ladders_score_limit: 0,
       ladders_depth_limit: 0,
       ladders_time_limit: Duration::from_secs(1),
+      komi_x_2: 0,
     }
   }
 }
@@ -140,19 +149,36 @@ impl<N: Float + Sum + Display + Debug + 'static> OppaiWeight<N> {
 pub struct OppaiEstimation<N: Float + Sum + Display + Debug + 'static>(<InnerAnalysis<N> as Analysis>::Estimation);
 
 impl<N: Float + Sum + Display + Debug + 'static> OppaiEstimation<N> {
+  /// The estimation as a number. Score based AIs estimate in points, Minimax's
+  /// half points being converted.
   pub fn to_f64(&self) -> Option<f64> {
     match self.0 {
       Either::Left(()) => None,
       Either::Right(Either::Left(())) => None,
       Either::Right(Either::Right(Either::Left(e))) => Some(e as f64),
       Either::Right(Either::Right(Either::Right(Either::Left(Either::Left(()))))) => None,
-      Either::Right(Either::Right(Either::Right(Either::Left(Either::Right(Either::Left(e)))))) => Some(e as f64),
+      Either::Right(Either::Right(Either::Right(Either::Left(Either::Right(Either::Left(e)))))) => Some(e as f64 / 2.0),
       Either::Right(Either::Right(Either::Right(Either::Left(Either::Right(Either::Right(())))))) => None,
       Either::Right(Either::Right(Either::Right(Either::Right(Either::Left(e))))) => Some(e),
       Either::Right(Either::Right(Either::Right(Either::Right(Either::Right(Either::Left(e)))))) => e.to_f64(),
       Either::Right(Either::Right(Either::Right(Either::Right(Either::Right(Either::Right(e)))))) => e.to_f64(),
     }
   }
+
+  /// Probability of winning for the analyzed player. Only search based AIs
+  /// estimate it: UCT directly and Zero as a win-loss value in `[-1, 1]`.
+  pub fn to_winrate(&self) -> Option<f64> {
+    match self.0 {
+      Either::Right(Either::Right(Either::Right(Either::Right(Either::Left(e))))) => Some(e),
+      Either::Right(Either::Right(Either::Right(Either::Right(Either::Right(Either::Left(e)))))) => {
+        e.to_f64().map(|e| (e + 1.0) / 2.0)
+      }
+      Either::Right(Either::Right(Either::Right(Either::Right(Either::Right(Either::Right(e)))))) => {
+        e.to_f64().map(|e| (e + 1.0) / 2.0)
+      }
+      _ => None,
+    }
+  }
 }
 
 #[derive(Clone, PartialEq, PartialOrd)]
@@ -198,7 +224,7 @@ impl<N: Float + Sum + Display + Debug + 'static> Analysis for OppaiAnalysis<N> {
   }
 }
 
-impl<N: Float + Sum + Display + Debug + 'static, M: Model<N> + 'static> AI for Oppai<N, M> {
+impl<N: Float + Sum + Display + Debug + Send + 'static, M: Model<N> + 'static> AI for Oppai<N, M> {
   type Analysis = OppaiAnalysis<N>;
   type Confidence = InConfidence;
 
@@ -247,15 +273,20 @@ impl<N: Float + Sum + Display + Debug + 'static, M: Model<N> + 'static> Oppai<N,
     let ai = match config.solver {
       Solver::Heuristic => Either::Left(Either::Left(Heuristic)),
       Solver::Minimax => Either::Left(Either::Right((
-        Minimax(InnerMinimax::new(config.minimax.clone())),
+        Minimax(InnerMinimax::new(config.minimax.clone(), config.komi_x_2)),
         Heuristic,
       ))),
       Solver::Uct => Either::Right(Either::Left(Uct(UctRoot::new(
         config.uct.clone(),
         length(width, height),
+        config.komi_x_2,
       )))),
-      Solver::Zero => Either::Right(Either::Right(Either::Left(Zero(InnerZero::new(model))))),
-      Solver::ZeroPolicy => Either::Right(Either::Right(Either::Right(ZeroPolicy::new(model)))),
+      Solver::Zero => Either::Right(Either::Right(Either::Left(Zero(InnerZero::new(
+        config.zero.clone(),
+        model,
+        config.komi_x_2,
+      ))))),
+      Solver::ZeroPolicy => Either::Right(Either::Right(Either::Right(ZeroPolicy::new(model, config.komi_x_2)))),
     };
     Oppai {
       config,
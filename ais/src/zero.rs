@@ -16,7 +16,7 @@ use std::{
 
 pub struct Zero<N: Float + Sum + Display + Debug, M: Model<N>>(pub InnerZero<N, M>);
 
-impl<N: Float + Sum + Display + Debug + PartialOrd + 'static, M: Model<N> + 'static> AI for Zero<N, M> {
+impl<N: Float + Sum + Display + Debug + PartialOrd + Send + 'static, M: Model<N> + 'static> AI for Zero<N, M> {
   type Analysis = SimpleAnalysis<PlaySelectionWeight<N>, N, u32>;
   type Confidence = u32;
 
@@ -29,14 +29,14 @@ impl<N: Float + Sum + Display + Debug + PartialOrd + 'static, M: Model<N> + 'sta
     should_stop: &SS,
   ) -> Self::Analysis
   where
-    R: Rng + SeedableRng<Seed = S>,
+    R: Rng + SeedableRng<Seed = S> + Send,
     StandardUniform: Distribution<S>,
     SS: Fn() -> bool + Sync,
   {
-    if let Ok((moves, confidence, estimation)) = self
-      .0
-      .best_moves(field, player, rng, should_stop, confidence.unwrap_or(u32::MAX))
-      .await
+    if let Ok((moves, confidence, estimation)) =
+      self
+        .0
+        .best_moves(field, player, rng, should_stop, confidence.unwrap_or(u32::MAX))
     {
       SimpleAnalysis {
         moves,
@@ -59,13 +59,16 @@ impl<N: Float + Sum + Display + Debug + PartialOrd + 'static, M: Model<N> + 'sta
 /// Monte Carlo search.
 pub struct ZeroPolicy<N: Float + Sum + Display + Debug, M: Model<N>> {
   pub model: M,
+  /// Komi for Red, doubled.
+  pub komi_x_2: i32,
   phantom: PhantomData<N>,
 }
 
 impl<N: Float + Sum + Display + Debug, M: Model<N>> ZeroPolicy<N, M> {
-  pub fn new(model: M) -> Self {
+  pub fn new(model: M, komi_x_2: i32) -> Self {
     ZeroPolicy {
       model,
+      komi_x_2,
       phantom: PhantomData,
     }
   }
@@ -88,7 +91,7 @@ impl<N: Float + Sum + Display + Debug + PartialOrd + 'static, M: Model<N> + 'sta
     StandardUniform: Distribution<S>,
     SS: Fn() -> bool + Sync,
   {
-    if let Ok((moves, estimation)) = policy_moves(&self.model, field, player).await {
+    if let Ok((moves, estimation)) = policy_moves(&self.model, field, player, self.komi_x_2).await {
       SimpleAnalysis {
         moves,
         estimation,
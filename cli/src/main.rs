@@ -28,7 +28,7 @@ use either::Either;
 use num_traits::Float;
 use oppai_ai::{ai::AI, analysis::Analysis};
 use oppai_ais::{
-  oppai::{InConfidence, Oppai},
+  oppai::{Config as AIConfig, InConfidence, Oppai},
   time_limited_ai::TimeLimitedAI,
 };
 use oppai_field::field::Field;
@@ -83,7 +83,11 @@ where
     let request = serde_json::from_str(&s)?;
 
     let response = match request {
-      Request::Init { width, height } => {
+      Request::Init {
+        width,
+        height,
+        komi_x_2,
+      } => {
         let mut rng = make_rng::<SmallRng>();
         let model: CliModel<B> = match &model {
           Some(model) => Either::Right(Predictor {
@@ -95,7 +99,16 @@ where
         state_option = Some(State::<B> {
           field: Field::new_from_rng(width, height, &mut rng),
           rng,
-          oppai: Oppai::new(width, height, config.ai.clone(), patterns.clone(), model),
+          oppai: Oppai::new(
+            width,
+            height,
+            AIConfig {
+              komi_x_2,
+              ..config.ai.clone()
+            },
+            patterns.clone(),
+            model,
+          ),
         });
         Response::Init
       }
@@ -132,7 +145,10 @@ where
           })
           .collect();
         moves.sort_unstable_by(|a, b| b.weight.partial_cmp(&a.weight).unwrap_or(std::cmp::Ordering::Equal));
-        Response::Analyze { moves }
+        Response::Analyze {
+          moves,
+          winrate: analysis.estimation().to_winrate(),
+        }
       }
       Request::Analyze {
         player,
@@ -162,7 +178,10 @@ where
           })
           .collect();
         moves.sort_unstable_by(|a, b| b.weight.partial_cmp(&a.weight).unwrap_or(std::cmp::Ordering::Equal));
-        Response::Analyze { moves }
+        Response::Analyze {
+          moves,
+          winrate: analysis.estimation().to_winrate(),
+        }
       }
     };
 
@@ -49,8 +49,14 @@ impl Client {
     Ok(response)
   }
 
-  pub async fn init(&mut self, width: u32, height: u32) -> Result<()> {
-    self.request(Request::Init { width, height }).await?;
+  pub async fn init(&mut self, width: u32, height: u32, komi_x_2: i32) -> Result<()> {
+    self
+      .request(Request::Init {
+        width,
+        height,
+        komi_x_2,
+      })
+      .await?;
 
     let response = self.response().await?;
     if let Response::Init = response {
@@ -90,12 +96,25 @@ impl Client {
   }
 
   pub async fn analyze(&mut self, player: Player, constraint: Constraint) -> Result<Vec<Move>> {
+    self
+      .analyze_with_winrate(player, constraint)
+      .await
+      .map(|(moves, _)| moves)
+  }
+
+  /// Same as `analyze` but also returns the probability of winning for the
+  /// player if the engine estimates it.
+  pub async fn analyze_with_winrate(
+    &mut self,
+    player: Player,
+    constraint: Constraint,
+  ) -> Result<(Vec<Move>, Option<f64>)> {
     self.request(Request::Analyze { player, constraint }).await?;
 
     let response = self.response().await?;
 
-    if let Response::Analyze { moves } = response {
-      Ok(moves)
+    if let Response::Analyze { moves, winrate } = response {
+      Ok((moves, winrate))
     } else {
       Err(Error::other(format!("Wrong response type: {:?}", response)))
     }
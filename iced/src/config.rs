@@ -1,7 +1,10 @@
 use clap::{Arg, ArgAction, Command, crate_authors, crate_description, crate_name, crate_version, value_parser};
 use oppai_ais::cli::*;
 use oppai_ais::oppai::Config as AIConfig;
-use oppai_initial::initial::InitialPosition;
+use oppai_initial::{
+  initial::InitialPosition,
+  opening::{Opening, Placement},
+};
 #[cfg(not(target_arch = "wasm32"))]
 use oppai_zero_burn::model::ModelConfig;
 use std::time::Duration;
@@ -39,7 +42,8 @@ pub struct Config {
   pub width: u32,
   pub height: u32,
   pub canvas_config: CanvasConfig,
-  pub initial_position: InitialPosition,
+  pub opening: Opening,
+  pub opening_placement: Placement,
   pub patterns: Vec<String>,
   pub patterns_cache: Option<String>,
   pub ai_config: AIConfig,
@@ -62,7 +66,8 @@ impl Default for Config {
       width: 39,
       height: 32,
       canvas_config: CanvasConfig::default(),
-      initial_position: InitialPosition::Cross,
+      opening: InitialPosition::Cross.into(),
+      opening_placement: Placement::Centred,
       patterns: Vec::new(),
       patterns_cache: None,
       ai_config: AIConfig::default(),
@@ -180,14 +185,22 @@ pub fn cli_parse() -> Config {
         .action(ArgAction::SetFalse),
     )
     .arg(
-      Arg::new("initial-position")
-        .long("initial-position")
-        .help("Initial position on the field")
+      Arg::new("opening")
+        .long("opening")
+        .alias("initial-position")
+        .help("Opening to start the games with: a standard opening or an opening file")
         .num_args(1)
-        .value_parser(value_parser!(InitialPosition))
-        .ignore_case(true)
+        .value_parser(value_parser!(Opening))
         .default_value("Cross"),
     )
+    .arg(
+      Arg::new("opening-placement")
+        .long("opening-placement")
+        .help("Where to place the opening on the field")
+        .num_args(1)
+        .value_parser(value_parser!(Placement))
+        .default_value("Centred"),
+    )
     .arg(
       Arg::new("patterns-file")
         .short('p')
@@ -287,7 +300,8 @@ pub fn cli_parse() -> Config {
   let extended_filling = matches.get_flag("no-extended-filling");
   let maximum_area_filling = matches.get_flag("no-maximum-area-filling");
   let last_point_mark = matches.get_flag("no-last-point-mark");
-  let initial_position = matches.get_one("initial-position").copied().unwrap();
+  let opening = matches.get_one::<Opening>("opening").cloned().unwrap();
+  let opening_placement = matches.get_one("opening-placement").copied().unwrap();
   let patterns = matches
     .get_many("patterns-file")
     .map_or_else(Vec::new, |patterns| patterns.cloned().collect());
@@ -324,7 +338,8 @@ pub fn cli_parse() -> Config {
       maximum_area_filling,
       last_point_mark,
     },
-    initial_position,
+    opening,
+    opening_placement,
     patterns,
     patterns_cache,
     ai_config,
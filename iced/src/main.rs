@@ -176,17 +176,22 @@ fn run_app<M: Model<f32> + Clone + Send + 'static>(config: Config, model: M) ->
         Arc::new(patterns),
         model.clone(),
       );
-      let moves = config.initial_position.points(
+      let moves = config.opening.points(
         extended_field.field.width(),
         extended_field.field.height(),
         extended_field.player,
+        config.opening_placement,
+        &mut rng,
       );
-      put_points_grounded(&mut extended_field, moves.clone());
+      put_points_grounded(&mut extended_field, moves.iter().copied());
       let game = Game {
         config: config.clone(),
         rng,
         model: model.clone(),
-        moves: moves.map(|(pos, player)| (pos, player, Default::default())).collect(),
+        moves: moves
+          .into_iter()
+          .map(|(pos, player)| (pos, player, Default::default()))
+          .collect(),
         canvas_field: CanvasField {
           extended_field,
           field_cache: Default::default(),
@@ -485,10 +490,12 @@ impl<M: Model<f32> + Clone + Send + 'static> Game<M> {
           self.canvas_field.extended_field.field.width(),
           self.canvas_field.extended_field.field.height(),
         ));
-        let points = self.config.initial_position.points(
+        let points = self.config.opening.points(
           self.canvas_field.extended_field.field.width(),
           self.canvas_field.extended_field.field.height(),
           self.canvas_field.extended_field.player,
+          self.config.opening_placement,
+          &mut self.rng,
         );
         put_points_grounded(&mut self.canvas_field.extended_field, points);
         self.put_all_bot_points();
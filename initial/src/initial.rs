@@ -1,77 +1,58 @@
-use oppai_field::field::{Pos, to_pos};
-use oppai_field::player::Player;
-use strum::{EnumString, VariantNames};
+use crate::opening::Pattern;
+use strum::{EnumString, IntoStaticStr, VariantNames};
 
-fn cross(width: u32, height: u32, player: Player) -> [(Pos, Player); 4] {
-  let w2 = width / 2;
-  let h2 = height / 2;
-  [
-    (to_pos(width + 1, w2 - 1, h2 - 1), player),
-    (to_pos(width + 1, w2 - 1, h2), player.next()),
-    (to_pos(width + 1, w2, h2), player),
-    (to_pos(width + 1, w2, h2 - 1), player.next()),
-  ]
-}
-
-fn two_crosses(width: u32, height: u32, player: Player) -> [(Pos, Player); 8] {
-  let w2 = width / 2;
-  let h2 = height / 2;
-  [
-    (to_pos(width + 1, w2 - 2, h2 - 1), player),
-    (to_pos(width + 1, w2 - 2, h2), player.next()),
-    (to_pos(width + 1, w2 - 1, h2), player),
-    (to_pos(width + 1, w2 - 1, h2 - 1), player.next()),
-    (to_pos(width + 1, w2, h2), player),
-    (to_pos(width + 1, w2, h2 - 1), player.next()),
-    (to_pos(width + 1, w2 + 1, h2 - 1), player),
-    (to_pos(width + 1, w2 + 1, h2), player.next()),
-  ]
-}
-
-fn triple_cross(width: u32, height: u32, player: Player) -> [(Pos, Player); 8] {
-  let w2 = width / 2;
-  let h2 = height / 2;
-  [
-    (to_pos(width + 1, w2 - 1, h2 - 1), player),
-    (to_pos(width + 1, w2 - 1, h2), player.next()),
-    (to_pos(width + 1, w2, h2), player),
-    (to_pos(width + 1, w2, h2 - 1), player.next()),
-    (to_pos(width + 1, w2 + 1, h2 - 1), player),
-    (to_pos(width + 1, w2, h2 - 2), player.next()),
-    (to_pos(width + 1, w2, h2 + 1), player),
-    (to_pos(width + 1, w2 + 1, h2), player.next()),
-  ]
-}
-
-#[derive(Clone, Copy, PartialEq, Eq, Debug, EnumString, VariantNames)]
+/// The standard openings, offered by every tool.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, EnumString, IntoStaticStr, VariantNames)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[strum(ascii_case_insensitive)]
 pub enum InitialPosition {
   Empty,
   Cross,
   TwoCrosses,
   TripleCross,
+  FourCrosses,
 }
 
 impl InitialPosition {
-  pub fn points(self, width: u32, height: u32, player: Player) -> impl Iterator<Item = (Pos, Player)> + Clone {
-    let cross = if self == InitialPosition::Cross {
-      Some(cross(width, height, player))
-    } else {
-      None
-    };
-    let two_crosses = if self == InitialPosition::TwoCrosses {
-      Some(two_crosses(width, height, player))
-    } else {
-      None
-    };
-    let triple_cross = if self == InitialPosition::TripleCross {
-      Some(triple_cross(width, height, player))
-    } else {
-      None
-    };
-    cross
-      .into_iter()
-      .flatten()
-      .chain(two_crosses.into_iter().flatten())
-      .chain(triple_cross.into_iter().flatten())
+  /// The opening in the `construct_field` image format: lowercase letters are
+  /// the moves of the player who starts the opening, uppercase ones the moves
+  /// of the other player, in the order of the letters.
+  pub fn image(self) -> &'static str {
+    match self {
+      InitialPosition::Empty => "",
+      InitialPosition::Cross => {
+        "
+        aD
+        Bc
+        "
+      }
+      InitialPosition::TwoCrosses => {
+        "
+        aDFg
+        BceH
+        "
+      }
+      InitialPosition::TripleCross => {
+        "
+        .F.
+        aDe
+        BcH
+        .g.
+        "
+      }
+      InitialPosition::FourCrosses => {
+        "
+        aD.He
+        Bc.gF
+        .....
+        Jk.oN
+        iL.Pm
+        "
+      }
+    }
+  }
+
+  pub fn pattern(self) -> Pattern {
+    Pattern::parse(self.image()).expect("standard openings are valid")
   }
 }
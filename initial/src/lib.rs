@@ -1 +1,4 @@
 pub mod initial;
+pub mod opening;
+#[cfg(test)]
+pub mod opening_test;
@@ -0,0 +1,238 @@
+//! Openings: the moves placed on the field before a game starts.
+//!
+//! An opening is a [`Pattern`] - either one of the standard
+//! [`InitialPosition`]s or a user-defined one read from a file - placed on the
+//! field according to a [`Placement`]: in the centre, the way games between
+//! people start, or somewhere around it with a random symmetry, to vary the
+//! games an AI plays against itself.
+
+use crate::initial::InitialPosition;
+use oppai_field::construct_field::construct_moves;
+use oppai_field::field::{Pos, to_pos, to_xy};
+use oppai_field::player::Player;
+use rand::{Rng, RngExt};
+use std::{fmt, fs, io, str::FromStr, sync::Arc};
+use strum::{EnumString, VariantNames};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ParseOpeningError {
+  #[error("the lines of the opening have different lengths")]
+  Ragged,
+  #[error("not a standard opening, nor a readable opening file: {0}")]
+  Io(#[from] io::Error),
+}
+
+/// Moves of an opening relative to its bounding box, `false` marking the moves
+/// of the player who starts the opening and `true` the moves of the other one.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Pattern {
+  pub width: u32,
+  pub height: u32,
+  pub moves: Vec<(u32, u32, bool)>,
+}
+
+impl Pattern {
+  /// Parses an opening in the `construct_field` image format, e.g.
+  ///
+  /// ```text
+  /// aD
+  /// Bc
+  /// ```
+  ///
+  /// Letters are moves, played in alphabetical order, the uppercase one first
+  /// when the same letter is used in both cases. Lowercase letters belong to
+  /// the player who starts the opening. Any other character is an empty cell.
+  pub fn parse(image: &str) -> Result<Pattern, ParseOpeningError> {
+    let image = image.replace('\r', "");
+    let mut lines = image
+      .lines()
+      .map(|line| line.trim_matches(' '))
+      .filter(|line| !line.is_empty());
+    let Some(width) = lines.next().map(str::len) else {
+      return Ok(Pattern::default());
+    };
+    if lines.any(|line| line.len() != width) {
+      return Err(ParseOpeningError::Ragged);
+    }
+
+    let (width, height, moves) = construct_moves(&image);
+    Ok(Pattern {
+      width,
+      height,
+      moves: moves
+        .into_iter()
+        .map(|(player, pos)| {
+          let (x, y) = to_xy(width + 1, pos);
+          (x, y, player.to_bool())
+        })
+        .collect(),
+    })
+  }
+
+  /// Applies one of the 8 symmetries of a rectangle: bit 0 mirrors the pattern
+  /// horizontally, bit 1 vertically and bit 2 transposes it.
+  pub fn transform(&self, symmetry: u8) -> Pattern {
+    let transpose = symmetry & 4 != 0;
+    let (width, height) = if transpose {
+      (self.height, self.width)
+    } else {
+      (self.width, self.height)
+    };
+    let moves = self
+      .moves
+      .iter()
+      .map(|&(x, y, second)| {
+        let x = if symmetry & 1 != 0 { self.width - 1 - x } else { x };
+        let y = if symmetry & 2 != 0 { self.height - 1 - y } else { y };
+        if transpose { (y, x, second) } else { (x, y, second) }
+      })
+      .collect();
+    Pattern { width, height, moves }
+  }
+
+  fn fits(&self, width: u32, height: u32) -> bool {
+    self.width <= width && self.height <= height
+  }
+
+  /// The moves of the pattern placed with its top left corner at the given
+  /// offset on a field of the given width, `player` starting the opening.
+  fn points(&self, width: u32, x_offset: u32, y_offset: u32, player: Player) -> Vec<(Pos, Player)> {
+    self
+      .moves
+      .iter()
+      .map(|&(x, y, second)| {
+        (
+          to_pos(width + 1, x_offset + x, y_offset + y),
+          if second { player.next() } else { player },
+        )
+      })
+      .collect()
+  }
+}
+
+/// Where an opening is placed on the field.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, EnumString, VariantNames)]
+#[strum(ascii_case_insensitive)]
+pub enum Placement {
+  /// In the centre, as it's given.
+  Centred,
+  /// Around the centre, under a random symmetry.
+  Random,
+}
+
+/// Picks a random offset for a pattern of the given size placed on a field of
+/// the given size.
+///
+/// The pattern is kept around the center of the field: the offset deviates from
+/// it by at most a sixth of the field size. The deviation is symmetrical, so
+/// there is no skew towards either side regardless of the parity of the sizes -
+/// when the free space can't be split evenly the two offsets closest to the
+/// center are both allowed instead of preferring one of them.
+fn offset<R: Rng>(field_size: u32, size: u32, rng: &mut R) -> u32 {
+  let free = field_size.saturating_sub(size);
+  let deviation = field_size / 6;
+  // Both bounds are clamped by the same condition (`deviation > free / 2`),
+  // hence clamping can't make the range asymmetrical.
+  let min = (free / 2).saturating_sub(deviation);
+  let max = (free / 2 + free % 2 + deviation).min(free);
+
+  rng.random_range(min..=max)
+}
+
+/// A standard or a user-defined opening.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Opening {
+  Standard(InitialPosition),
+  Custom {
+    /// Where the opening was read from, to tell it apart from the others.
+    name: String,
+    pattern: Arc<Pattern>,
+  },
+}
+
+impl Opening {
+  /// Reads a user-defined opening from a file in the `construct_field` image
+  /// format.
+  fn from_file(path: &str) -> Result<Opening, ParseOpeningError> {
+    let pattern = Pattern::parse(&fs::read_to_string(path)?)?;
+    Ok(Opening::Custom {
+      name: path.to_owned(),
+      pattern: Arc::new(pattern),
+    })
+  }
+
+  pub fn pattern(&self) -> Pattern {
+    match self {
+      Opening::Standard(initial_position) => initial_position.pattern(),
+      Opening::Custom { pattern, .. } => pattern.as_ref().clone(),
+    }
+  }
+
+  /// The moves of the opening on a field of the given size, `player` starting
+  /// it, in the order they are played. There are none when the opening doesn't
+  /// fit the field.
+  pub fn points<R: Rng>(
+    &self,
+    width: u32,
+    height: u32,
+    player: Player,
+    placement: Placement,
+    rng: &mut R,
+  ) -> Vec<(Pos, Player)> {
+    let pattern = self.pattern();
+    match placement {
+      Placement::Centred => {
+        if !pattern.fits(width, height) {
+          return Vec::new();
+        }
+        pattern.points(
+          width,
+          width / 2 - pattern.width / 2,
+          height / 2 - pattern.height / 2,
+          player,
+        )
+      }
+      Placement::Random => {
+        let symmetries = (0..8)
+          .map(|symmetry| pattern.transform(symmetry))
+          .filter(|pattern| pattern.fits(width, height))
+          .collect::<Vec<_>>();
+        if symmetries.is_empty() {
+          return Vec::new();
+        }
+        let pattern = &symmetries[rng.random_range(0..symmetries.len())];
+        let x_offset = offset(width, pattern.width, rng);
+        let y_offset = offset(height, pattern.height, rng);
+        pattern.points(width, x_offset, y_offset, player)
+      }
+    }
+  }
+}
+
+impl From<InitialPosition> for Opening {
+  fn from(initial_position: InitialPosition) -> Self {
+    Opening::Standard(initial_position)
+  }
+}
+
+/// A standard opening by its name, or else a user-defined one by the path of
+/// its file.
+impl FromStr for Opening {
+  type Err = ParseOpeningError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    s.parse::<InitialPosition>()
+      .map(Opening::Standard)
+      .or_else(|_| Opening::from_file(s))
+  }
+}
+
+impl fmt::Display for Opening {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Opening::Standard(initial_position) => write!(f, "{}", <&str>::from(initial_position)),
+      Opening::Custom { name, .. } => write!(f, "{name}"),
+    }
+  }
+}
@@ -0,0 +1,230 @@
+use crate::initial::InitialPosition;
+use crate::opening::{Opening, ParseOpeningError, Pattern, Placement};
+use oppai_field::field::to_xy;
+use oppai_field::player::Player;
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+use std::collections::HashSet;
+use strum::VariantNames;
+
+fn coordinates(
+  opening: InitialPosition,
+  width: u32,
+  height: u32,
+  player: Player,
+  placement: Placement,
+  rng: &mut Xoshiro256PlusPlus,
+) -> Vec<(u32, u32, Player)> {
+  Opening::from(opening)
+    .points(width, height, player, placement, rng)
+    .into_iter()
+    .map(|(pos, player)| {
+      let (x, y) = to_xy(width + 1, pos);
+      (x, y, player)
+    })
+    .collect()
+}
+
+#[test]
+fn centred_openings() {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+  let (r, b) = (Player::Red, Player::Black);
+  assert_eq!(
+    coordinates(InitialPosition::Cross, 10, 9, r, Placement::Centred, &mut rng),
+    [(4, 3, r), (4, 4, b), (5, 4, r), (5, 3, b)]
+  );
+  assert_eq!(
+    coordinates(InitialPosition::TwoCrosses, 11, 10, b, Placement::Centred, &mut rng),
+    [
+      (3, 4, b),
+      (3, 5, r),
+      (4, 5, b),
+      (4, 4, r),
+      (5, 5, b),
+      (5, 4, r),
+      (6, 4, b),
+      (6, 5, r)
+    ]
+  );
+  assert_eq!(
+    coordinates(InitialPosition::TripleCross, 10, 10, r, Placement::Centred, &mut rng),
+    [
+      (4, 4, r),
+      (4, 5, b),
+      (5, 5, r),
+      (5, 4, b),
+      (6, 4, r),
+      (5, 3, b),
+      (5, 6, r),
+      (6, 5, b)
+    ]
+  );
+  assert!(coordinates(InitialPosition::Empty, 10, 10, r, Placement::Centred, &mut rng).is_empty());
+}
+
+// Every standard opening alternates the players, starting with the given one.
+#[test]
+fn openings_alternate_players() {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+  for name in InitialPosition::VARIANTS {
+    let opening = name.parse::<InitialPosition>().unwrap();
+    for placement in [Placement::Centred, Placement::Random] {
+      let points = coordinates(opening, 20, 20, Player::Black, placement, &mut rng);
+      assert_eq!(points.len(), opening.pattern().moves.len());
+      for (i, &(.., player)) in points.iter().enumerate() {
+        let expected = if i % 2 == 0 { Player::Black } else { Player::Red };
+        assert_eq!(player, expected, "{name}: move {i}");
+      }
+    }
+  }
+}
+
+/// All the cells the openings can occupy on a field of the given size.
+fn occupied(width: u32, height: u32) -> HashSet<(u32, u32)> {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+  let mut result = HashSet::new();
+
+  for i in 0..100_000 {
+    let opening = if i % 9 == 0 {
+      InitialPosition::TripleCross
+    } else {
+      InitialPosition::Cross
+    };
+    let moves = coordinates(opening, width, height, Player::Red, Placement::Random, &mut rng);
+    assert!(!moves.is_empty());
+    for (x, y, _) in moves {
+      assert!(x < width && y < height);
+      result.insert((x, y));
+    }
+  }
+
+  result
+}
+
+#[test]
+fn symmetrical() {
+  for (width, height) in [(10, 10), (11, 11), (10, 11), (39, 32), (30, 40)] {
+    let cells = occupied(width, height);
+    for &(x, y) in &cells {
+      assert!(
+        cells.contains(&(width - 1 - x, y)),
+        "{width}x{height}: ({x}, {y}) has no horizontal mirror"
+      );
+      assert!(
+        cells.contains(&(x, height - 1 - y)),
+        "{width}x{height}: ({x}, {y}) has no vertical mirror"
+      );
+    }
+  }
+}
+
+#[test]
+fn deviation() {
+  for (width, height) in [(10, 10), (11, 11), (10, 11), (39, 32), (30, 40)] {
+    let cells = occupied(width, height);
+    // A sixth of the field size in either direction from the center, the size
+    // of the biggest pattern drawn by `occupied` (the triple cross, 4) and one
+    // more cell for the odd free space.
+    let x_spread = 2 * (width / 6) + 4 + 1;
+    let y_spread = 2 * (height / 6) + 4 + 1;
+    let min_x = cells.iter().map(|&(x, _)| x).min().unwrap();
+    let max_x = cells.iter().map(|&(x, _)| x).max().unwrap();
+    let min_y = cells.iter().map(|&(_, y)| y).min().unwrap();
+    let max_y = cells.iter().map(|&(_, y)| y).max().unwrap();
+    assert!(max_x - min_x < x_spread, "{width}x{height}: {min_x}..={max_x}");
+    assert!(max_y - min_y < y_spread, "{width}x{height}: {min_y}..={max_y}");
+  }
+}
+
+// A random placement only uses the symmetries that fit the field.
+#[test]
+fn random_placement_fits() {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+  for _ in 0..1000 {
+    for (x, y, _) in coordinates(
+      InitialPosition::TwoCrosses,
+      4,
+      2,
+      Player::Red,
+      Placement::Random,
+      &mut rng,
+    ) {
+      assert!(x < 4 && y < 2);
+    }
+  }
+  assert!(
+    coordinates(
+      InitialPosition::FourCrosses,
+      4,
+      4,
+      Player::Red,
+      Placement::Random,
+      &mut rng
+    )
+    .is_empty()
+  );
+  assert!(
+    coordinates(
+      InitialPosition::FourCrosses,
+      4,
+      4,
+      Player::Red,
+      Placement::Centred,
+      &mut rng
+    )
+    .is_empty()
+  );
+}
+
+#[test]
+fn transforms() {
+  let pattern = InitialPosition::TripleCross.pattern();
+  for symmetry in 0..8 {
+    let transformed = pattern.transform(symmetry);
+    // Mirrors and the transposition undo themselves.
+    if symmetry <= 4 {
+      assert_eq!(transformed.transform(symmetry), pattern);
+    }
+    for &(x, y, _) in &transformed.moves {
+      assert!(x < transformed.width && y < transformed.height);
+    }
+  }
+  assert_eq!(pattern.transform(4).width, pattern.height);
+  assert_eq!(pattern.transform(0), pattern);
+}
+
+#[test]
+fn parse_pattern() {
+  let pattern = Pattern::parse(
+    "
+    .A.
+    bc.
+    ",
+  )
+  .unwrap();
+  assert_eq!(pattern.width, 3);
+  assert_eq!(pattern.height, 2);
+  assert_eq!(pattern.moves, [(1, 0, true), (0, 1, false), (1, 1, false)]);
+
+  assert!(matches!(Pattern::parse("ab\nc"), Err(ParseOpeningError::Ragged)));
+  assert_eq!(Pattern::parse("\n  \n").unwrap(), Pattern::default());
+}
+
+#[test]
+fn parse_opening() {
+  assert_eq!(
+    "triplecross".parse::<Opening>().unwrap(),
+    Opening::Standard(InitialPosition::TripleCross)
+  );
+  assert!(matches!(
+    "no such opening".parse::<Opening>(),
+    Err(ParseOpeningError::Io(_))
+  ));
+
+  let path = std::env::temp_dir().join(format!("oppai-opening-{}.txt", std::process::id()));
+  std::fs::write(&path, "aD\r\nBc\r\n").unwrap();
+  let opening = path.to_str().unwrap().parse::<Opening>().unwrap();
+  std::fs::remove_file(&path).unwrap();
+  assert_eq!(opening.pattern(), InitialPosition::Cross.pattern());
+  assert_eq!(opening.to_string(), path.to_str().unwrap());
+}
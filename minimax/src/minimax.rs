@@ -44,12 +44,25 @@ impl Default for MinimaxConfig {
 pub struct Minimax {
   config: MinimaxConfig,
   hash_table: HashTable,
+  /// Komi for Red, doubled.
+  komi_x_2: i32,
+}
+
+/// Converts a score of `player` to an estimation. Estimations are measured in
+/// half points so that a fractional komi is compared exactly.
+#[inline]
+fn estimation(score: i32, player: Player, komi_x_2: i32) -> i32 {
+  score * 2 + if player == Player::Red { komi_x_2 } else { -komi_x_2 }
 }
 
 impl Minimax {
-  pub fn new(config: MinimaxConfig) -> Minimax {
+  pub fn new(config: MinimaxConfig, komi_x_2: i32) -> Minimax {
     let hash_table = HashTable::new(config.hash_table_size);
-    Minimax { config, hash_table }
+    Minimax {
+      config,
+      hash_table,
+      komi_x_2,
+    }
   }
 
   #[inline]
@@ -73,6 +86,7 @@ impl Minimax {
     beta: i32,
     empty_board: &mut Vec<u32>,
     hash_table: &HashTable,
+    komi_x_2: i32,
     should_stop: &SS,
   ) -> i32 {
     if should_stop() {
@@ -85,10 +99,10 @@ impl Minimax {
       return i32::MAX;
     }
     if depth == 0 {
-      return field.score(player);
+      return estimation(field.score(player), player, komi_x_2);
     }
     if trajectories_pruning.moves.is_empty() {
-      return field.score(player);
+      return estimation(field.score(player), player, komi_x_2);
     }
     let mut cur_alpha = alpha;
     let hash_value = hash_table.get(field.colored_hash(player));
@@ -126,6 +140,7 @@ impl Minimax {
         -beta + 1,
         empty_board,
         hash_table,
+        komi_x_2,
         should_stop,
       );
       if cur_estimation >= beta {
@@ -151,6 +166,7 @@ impl Minimax {
         -cur_alpha,
         empty_board,
         hash_table,
+        komi_x_2,
         should_stop,
       );
       field.undo();
@@ -197,6 +213,7 @@ impl Minimax {
         -cur_alpha,
         empty_board,
         hash_table,
+        komi_x_2,
         should_stop,
       );
       if cur_estimation > cur_alpha && cur_estimation < beta {
@@ -210,6 +227,7 @@ impl Minimax {
           -cur_estimation,
           empty_board,
           hash_table,
+          komi_x_2,
           should_stop,
         );
       }
@@ -251,7 +269,7 @@ impl Minimax {
       depth, player, beta
     );
     if depth == 0 || should_stop() {
-      return field.score(player);
+      return estimation(field.score(player), player, self.komi_x_2);
     }
     debug!(
       "Moves in consideration: {:?}.",
@@ -262,7 +280,7 @@ impl Minimax {
         .collect::<Vec<(u32, u32)>>()
     );
     if trajectories_pruning.moves.is_empty() || should_stop() {
-      return field.score(player);
+      return estimation(field.score(player), player, self.komi_x_2);
     }
     #[cfg(not(target_arch = "wasm32"))]
     {
@@ -320,6 +338,7 @@ impl Minimax {
                 -cur_alpha,
                 &mut local_empty_board,
                 &self.hash_table,
+                self.komi_x_2,
                 should_stop,
               );
               if should_stop() {
@@ -336,6 +355,7 @@ impl Minimax {
                   -cur_estimation,
                   &mut local_empty_board,
                   &self.hash_table,
+                  self.komi_x_2,
                   should_stop,
                 );
               }
@@ -441,6 +461,7 @@ impl Minimax {
           -best_alpha,
           &mut empty_board,
           &self.hash_table,
+          self.komi_x_2,
           should_stop,
         );
         if should_stop() {
@@ -457,6 +478,7 @@ impl Minimax {
             -cur_estimation,
             &mut empty_board,
             &self.hash_table,
+            self.komi_x_2,
             should_stop,
           );
         }
@@ -487,6 +509,12 @@ impl Minimax {
     }
   }
 
+  /// Converts a score bound given by trajectories to an estimation, falling back
+  /// to the current score when there are no trajectories.
+  fn bound(&self, score: Option<i32>, field: &Field, player: Player) -> i32 {
+    estimation(score.unwrap_or_else(|| field.score(player)), player, self.komi_x_2)
+  }
+
   fn mtdf<SS: Fn() -> bool + Sync>(
     &self,
     field: &mut Field,
@@ -496,8 +524,8 @@ impl Minimax {
     best_move: &mut Option<NonZeroPos>,
     should_stop: &SS,
   ) -> i32 {
-    let mut alpha = trajectories_pruning.alpha().unwrap_or_else(|| field.score(player));
-    let mut beta = trajectories_pruning.beta().unwrap_or_else(|| field.score(player));
+    let mut alpha = self.bound(trajectories_pruning.alpha(), field, player);
+    let mut beta = self.bound(trajectories_pruning.beta(), field, player);
     while alpha != beta {
       if let [single_move] = *trajectories_pruning.moves.as_slice() {
         *best_move = NonZeroPos::new(single_move);
@@ -541,8 +569,8 @@ impl Minimax {
     best_move: &mut Option<NonZeroPos>,
     should_stop: &SS,
   ) -> i32 {
-    let alpha = trajectories_pruning.alpha().unwrap_or_else(|| field.score(player));
-    let beta = trajectories_pruning.beta().unwrap_or_else(|| field.score(player));
+    let alpha = self.bound(trajectories_pruning.alpha(), field, player);
+    let beta = self.bound(trajectories_pruning.beta(), field, player);
     self.alpha_beta_parallel(
       field,
       player,
@@ -555,6 +583,8 @@ impl Minimax {
     )
   }
 
+  /// Searches for the best move of `player` to the given depth, returning it
+  /// with its estimation in half points.
   pub fn minimax<SS: Fn() -> bool + Sync>(
     &self,
     field: &mut Field,
@@ -564,7 +594,7 @@ impl Minimax {
   ) -> (Option<NonZeroPos>, i32) {
     info!("Starting minimax with depth {} and player {}.", depth, player);
     if depth == 0 {
-      return (None, field.score(player));
+      return (None, estimation(field.score(player), player, self.komi_x_2));
     }
     let mut empty_board = iter::repeat_n(0u32, field.length()).collect::<Vec<_>>();
     let mut trajectories_pruning = TrajectoriesPruning::new(
@@ -631,6 +661,8 @@ impl Minimax {
     }
   }
 
+  /// Deepens the search until it's stopped, returning the best move of
+  /// `player`, its estimation in half points and the depth reached.
   pub fn minimax_with_time<SS: Fn() -> bool + Sync>(
     &self,
     field: &mut Field,
@@ -655,7 +687,7 @@ impl Minimax {
       MinimaxType::NegaScout => Minimax::nega_scout,
       MinimaxType::Mtdf => Minimax::mtdf,
     };
-    let mut estimation = field.score(player);
+    let mut estimation = estimation(field.score(player), player, self.komi_x_2);
     while !should_stop() {
       estimation = minimax_function(
         self,
@@ -31,7 +31,7 @@ macro_rules! minimax_bench {
       let mut rng = Xoshiro256PlusPlus::seed_from_u64(SEED);
       let field = construct_field(&mut rng, $image.image);
       bencher.iter(|| {
-        let minimax = Minimax::new($config);
+        let minimax = Minimax::new($config, 0);
         let mut local_field = field.clone();
         minimax.minimax(&mut local_field, Player::Red, $depth, &|| false)
       });
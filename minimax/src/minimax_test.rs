@@ -30,7 +30,7 @@ macro_rules! minimax_test {
       env_logger::try_init().ok();
       let mut rng = Xoshiro256PlusPlus::seed_from_u64(SEED);
       let mut field = construct_field(&mut rng, $image.image);
-      let minimax = Minimax::new($config);
+      let minimax = Minimax::new($config, 0);
       let (pos, _) = minimax.minimax(&mut field, Player::Red, $depth, &|| false);
       assert_eq!(pos, NonZeroPos::new(field.to_pos($image.solution.0, $image.solution.1)));
     }
@@ -104,3 +104,28 @@ minimax_test!(mtdf_12, MINIMAX_CONFIG_MTDF, IMAGE_12, 8);
 minimax_test!(mtdf_13, MINIMAX_CONFIG_MTDF, IMAGE_13, 8);
 minimax_test!(mtdf_14, MINIMAX_CONFIG_MTDF, IMAGE_14, 8);
 minimax_test!(mtdf_15, MINIMAX_CONFIG_MTDF, IMAGE_15, 8);
+
+// Red has captured two points and no move can change the score, so the komi
+// alone decides who wins. Estimations are in half points.
+#[test]
+fn komi_flips_the_result() {
+  for config in [MINIMAX_CONFIG_NEGASCOUT, MINIMAX_CONFIG_MTDF] {
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(SEED);
+    let mut field = construct_field(
+      &mut rng,
+      "
+      .a.a.
+      aAaAa
+      .a.a.
+      ",
+    );
+    let estimate = |komi_x_2, player, field: &mut _| {
+      Minimax::new(config.clone(), komi_x_2)
+        .minimax(field, player, 4, &|| false)
+        .1
+    };
+    assert_eq!(estimate(0, Player::Red, &mut field), 4);
+    assert_eq!(estimate(-5, Player::Red, &mut field), -1);
+    assert_eq!(estimate(-5, Player::Black, &mut field), 1);
+  }
+}
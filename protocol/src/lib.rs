@@ -20,10 +20,22 @@ pub enum Constraint {
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(tag = "command")]
 pub enum Request {
-  Init { width: u32, height: u32 },
-  PutPoint { coords: Coords, player: Player },
+  Init {
+    width: u32,
+    height: u32,
+    /// Komi for Red, doubled so that half points are representable.
+    #[serde(default)]
+    komi_x_2: i32,
+  },
+  PutPoint {
+    coords: Coords,
+    player: Player,
+  },
   Undo,
-  Analyze { player: Player, constraint: Constraint },
+  Analyze {
+    player: Player,
+    constraint: Constraint,
+  },
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -36,9 +48,18 @@ pub struct Move {
 #[serde(tag = "command")]
 pub enum Response {
   Init,
-  PutPoint { put: bool },
-  Undo { undone: bool },
-  Analyze { moves: Vec<Move> },
+  PutPoint {
+    put: bool,
+  },
+  Undo {
+    undone: bool,
+  },
+  Analyze {
+    moves: Vec<Move>,
+    /// Probability of winning for the analyzed player, if the engine estimates it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    winrate: Option<f64>,
+  },
 }
 
 #[cfg(test)]
@@ -60,10 +81,27 @@ mod tests {
   from_to_json_test!(
     init_request,
     Request,
-    Request::Init { width: 39, height: 32 },
-    r#"{"command":"Init","width":39,"height":32}"#
+    Request::Init {
+      width: 39,
+      height: 32,
+      komi_x_2: 1
+    },
+    r#"{"command":"Init","width":39,"height":32,"komi_x_2":1}"#
   );
 
+  #[test]
+  fn init_request_without_komi() {
+    let parsed: Request = serde_json::from_str(r#"{"command":"Init","width":39,"height":32}"#).unwrap();
+    assert_eq!(
+      parsed,
+      Request::Init {
+        width: 39,
+        height: 32,
+        komi_x_2: 0
+      }
+    );
+  }
+
   from_to_json_test!(
     put_point_request,
     Request,
@@ -119,8 +157,22 @@ mod tests {
       moves: vec![Move {
         coords: Coords { x: 1, y: 2 },
         weight: 1.0
-      }]
+      }],
+      winrate: None,
     },
     r#"{"command":"Analyze","moves":[{"coords":{"x":1,"y":2},"weight":1.0}]}"#
   );
+
+  from_to_json_test!(
+    analyze_with_winrate_response,
+    Response,
+    Response::Analyze {
+      moves: vec![Move {
+        coords: Coords { x: 1, y: 2 },
+        weight: 1.0
+      }],
+      winrate: Some(0.75),
+    },
+    r#"{"command":"Analyze","moves":[{"coords":{"x":1,"y":2},"weight":1.0}],"winrate":0.75}"#
+  );
 }
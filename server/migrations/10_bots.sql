@@ -0,0 +1 @@
+ALTER TABLE players ADD COLUMN bot boolean NOT NULL DEFAULT false;
@@ -0,0 +1,10 @@
+-- Resignations and grounding wins used to be stored under the name of the
+-- winner, while everything reading them expects the name of the loser.
+UPDATE games
+SET "result" = CASE "result"
+  WHEN 'resignedred' THEN 'resignedblack'::gameresult
+  WHEN 'resignedblack' THEN 'resignedred'::gameresult
+  WHEN 'groundedred' THEN 'groundedblack'::gameresult
+  WHEN 'groundedblack' THEN 'groundedred'::gameresult
+END
+WHERE "result" IN ('resignedred', 'resignedblack', 'groundedred', 'groundedblack');
@@ -0,0 +1,25 @@
+CREATE TABLE IF NOT EXISTS rating_history (
+  game_id uuid NOT NULL REFERENCES games (id),
+  player_id uuid NOT NULL REFERENCES players (id),
+  rating DOUBLE PRECISION NOT NULL,
+  deviation DOUBLE PRECISION NOT NULL,
+  volatility DOUBLE PRECISION NOT NULL,
+  "timestamp" timestamp NOT NULL,
+  PRIMARY KEY (game_id, player_id)
+);
+
+CREATE INDEX rating_history_player ON rating_history (player_id, "timestamp");
+
+-- Game result from a player's point of view. Every result except draws is named after the player who lost.
+CREATE OR REPLACE FUNCTION game_outcome(p_result gameresult, p_red_player_id uuid, p_player_id uuid)
+  RETURNS text AS
+$$
+BEGIN
+  RETURN CASE
+    WHEN p_result IS NULL THEN NULL
+    WHEN p_result IN ('drawagreement', 'drawgrounded') THEN 'draw'
+    WHEN (p_result IN ('resignedred', 'groundedred', 'timeoutred')) = (p_red_player_id = p_player_id) THEN 'loss'
+    ELSE 'win'
+  END;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
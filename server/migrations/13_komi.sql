@@ -0,0 +1 @@
+ALTER TABLE games ADD COLUMN komi_x_2 integer NOT NULL DEFAULT 0;
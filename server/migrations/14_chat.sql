@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS chat_messages (
+  game_id uuid REFERENCES games (id),
+  player_id uuid NOT NULL REFERENCES players (id),
+  "text" varchar(500) NOT NULL,
+  "timestamp" timestamp NOT NULL
+);
+
+CREATE INDEX chat_messages_game ON chat_messages (game_id, "timestamp");
@@ -0,0 +1,35 @@
+CREATE TYPE tournament_format AS ENUM ('round_robin', 'swiss');
+CREATE TYPE tournament_status AS ENUM ('registration', 'running', 'finished', 'cancelled');
+
+CREATE TABLE IF NOT EXISTS tournaments (
+  id uuid PRIMARY KEY,
+  name varchar(50) NOT NULL,
+  creator_id uuid NOT NULL REFERENCES players (id),
+  width integer NOT NULL,
+  height integer NOT NULL,
+  total_time_ms bigint NOT NULL,
+  increment_ms bigint NOT NULL,
+  opening opening NOT NULL,
+  komi_x_2 integer NOT NULL,
+  format tournament_format NOT NULL,
+  rounds integer,
+  start_time timestamp NOT NULL,
+  status tournament_status NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS tournament_players (
+  tournament_id uuid NOT NULL REFERENCES tournaments (id),
+  player_id uuid NOT NULL REFERENCES players (id),
+  registration_time timestamp NOT NULL,
+  PRIMARY KEY (tournament_id, player_id)
+);
+
+CREATE TABLE IF NOT EXISTS tournament_pairings (
+  tournament_id uuid NOT NULL REFERENCES tournaments (id),
+  round integer NOT NULL,
+  red_player_id uuid NOT NULL REFERENCES players (id),
+  black_player_id uuid REFERENCES players (id),
+  game_id uuid REFERENCES games (id)
+);
+
+CREATE INDEX tournament_pairings_tournament ON tournament_pairings (tournament_id, round);
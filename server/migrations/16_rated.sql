@@ -0,0 +1,2 @@
+ALTER TABLE games ADD COLUMN rated boolean NOT NULL DEFAULT true;
+ALTER TABLE tournaments ADD COLUMN rated boolean NOT NULL DEFAULT true;
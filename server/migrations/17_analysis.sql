@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS analysis_queue (
+  game_id uuid PRIMARY KEY REFERENCES games (id),
+  enqueue_time timestamp NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS move_analysis (
+  game_id uuid NOT NULL REFERENCES games (id),
+  "number" smallint NOT NULL,
+  winrate double precision,
+  best_x smallint,
+  best_y smallint,
+  PRIMARY KEY (game_id, "number")
+);
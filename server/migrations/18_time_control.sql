@@ -0,0 +1,13 @@
+CREATE TYPE time_control AS ENUM ('fischer', 'japanese', 'canadian', 'delay');
+
+ALTER TABLE games
+ADD COLUMN time_control time_control NOT NULL DEFAULT 'fischer',
+ADD COLUMN period_ms bigint NOT NULL DEFAULT 0,
+ADD COLUMN periods integer NOT NULL DEFAULT 0,
+ADD COLUMN max_move_time_ms bigint;
+
+ALTER TABLE tournaments
+ADD COLUMN time_control time_control NOT NULL DEFAULT 'fischer',
+ADD COLUMN period_ms bigint NOT NULL DEFAULT 0,
+ADD COLUMN periods integer NOT NULL DEFAULT 0,
+ADD COLUMN max_move_time_ms bigint;
@@ -0,0 +1,33 @@
+ALTER TABLE players ADD COLUMN admin boolean NOT NULL DEFAULT false;
+ALTER TABLE players ADD COLUMN banned boolean NOT NULL DEFAULT false;
+ALTER TABLE players ADD COLUMN muted_until timestamp;
+
+ALTER TYPE gameresult ADD VALUE IF NOT EXISTS 'aborted';
+
+-- Game result from a player's point of view. Every result except draws is named after the player who lost.
+-- Games aborted by moderators have no outcome.
+CREATE OR REPLACE FUNCTION game_outcome(p_result gameresult, p_red_player_id uuid, p_player_id uuid)
+  RETURNS text AS
+$$
+BEGIN
+  RETURN CASE
+    WHEN p_result IS NULL OR p_result::text = 'aborted' THEN NULL
+    WHEN p_result IN ('drawagreement', 'drawgrounded') THEN 'draw'
+    WHEN (p_result IN ('resignedred', 'groundedred', 'timeoutred')) = (p_red_player_id = p_player_id) THEN 'loss'
+    ELSE 'win'
+  END;
+END;
+$$ LANGUAGE plpgsql IMMUTABLE;
+
+CREATE TYPE moderation_action AS ENUM ('ban', 'unban', 'mute', 'unmute', 'close_game', 'rename');
+
+CREATE TABLE IF NOT EXISTS audit_log (
+  admin_id uuid NOT NULL REFERENCES players (id),
+  "action" moderation_action NOT NULL,
+  player_id uuid REFERENCES players (id),
+  game_id uuid REFERENCES games (id),
+  details text,
+  "timestamp" timestamp NOT NULL
+);
+
+CREATE INDEX audit_log_timestamp ON audit_log ("timestamp");
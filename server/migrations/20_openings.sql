@@ -0,0 +1,2 @@
+ALTER TYPE opening ADD VALUE IF NOT EXISTS 'empty';
+ALTER TYPE opening ADD VALUE IF NOT EXISTS 'four_crosses';
@@ -0,0 +1,176 @@
+use crate::{
+  SessionShared,
+  config::AnalysisConfig,
+  db::{self, Db},
+  ids::*,
+  opening_moves_count,
+};
+use anyhow::Result;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures_util::{StreamExt, stream};
+use oppai_client::{Client, Constraint};
+use oppai_field::player::Player;
+use std::{future::Future, io, sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
+
+/// Part of the engine API needed to analyze finished games.
+pub trait Engine: Send + 'static {
+  fn init(&mut self, width: u32, height: u32, komi_x_2: i32) -> impl Future<Output = io::Result<()>> + Send;
+  fn put_point(&mut self, x: u32, y: u32, player: Player) -> impl Future<Output = io::Result<bool>> + Send;
+  /// Candidate moves with the probability of winning for the player if the
+  /// engine estimates it.
+  fn analyze(
+    &mut self,
+    player: Player,
+    constraint: Constraint,
+  ) -> impl Future<Output = io::Result<(Vec<oppai_protocol::Move>, Option<f64>)>> + Send;
+}
+
+impl Engine for Client {
+  fn init(&mut self, width: u32, height: u32, komi_x_2: i32) -> impl Future<Output = io::Result<()>> + Send {
+    Client::init(self, width, height, komi_x_2)
+  }
+
+  fn put_point(&mut self, x: u32, y: u32, player: Player) -> impl Future<Output = io::Result<bool>> + Send {
+    Client::put_point(self, x, y, player)
+  }
+
+  fn analyze(
+    &mut self,
+    player: Player,
+    constraint: Constraint,
+  ) -> impl Future<Output = io::Result<(Vec<oppai_protocol::Move>, Option<f64>)>> + Send {
+    Client::analyze_with_winrate(self, player, constraint)
+  }
+}
+
+/// Replays a game in the engine and analyzes the position before every move
+/// except the opening for the player who made it.
+pub async fn analyze_game<E: Engine>(
+  mut engine: E,
+  game_with_moves: &db::GameWithMoves,
+  time_per_move: Duration,
+) -> io::Result<Vec<db::MoveAnalysis>> {
+  let game = &game_with_moves.game;
+  engine
+    .init(game.width as u32, game.height as u32, game.komi_x_2)
+    .await?;
+
+  let opening_moves = opening_moves_count(game.opening);
+  let mut analysis = Vec::new();
+  for (i, m) in game_with_moves.moves.iter().enumerate() {
+    let player = m.player.into();
+    if i >= opening_moves {
+      let (moves, winrate) = engine.analyze(player, Constraint::Time(time_per_move)).await?;
+      let best_move = moves.into_iter().max_by(|m1, m2| m1.weight.total_cmp(&m2.weight));
+      analysis.push(db::MoveAnalysis {
+        game_id: game.id,
+        number: m.number,
+        winrate,
+        best_x: best_move.as_ref().map(|best_move| best_move.coords.x as i16),
+        best_y: best_move.as_ref().map(|best_move| best_move.coords.y as i16),
+      });
+    }
+    engine.put_point(m.x as u32, m.y as u32, player).await?;
+  }
+
+  Ok(analysis)
+}
+
+async fn analyze_and_store<E: Engine>(
+  shared: &SessionShared,
+  engine: E,
+  game_id: GameId,
+  time_per_move: Duration,
+) -> Result<()> {
+  let game_with_moves = shared.db.get_game(game_id.0).await?;
+  let analysis = analyze_game(engine, &game_with_moves, time_per_move).await?;
+  shared.db.set_game_analysis(game_id.0, analysis).await
+}
+
+/// Analyzes a game with a fresh engine on every attempt, waiting longer after
+/// each failure. A game that fails every attempt is dropped from the queue, so
+/// it doesn't come back after every restart.
+async fn analyze_with_retries<E: Engine>(
+  shared: &SessionShared,
+  config: &AnalysisConfig,
+  spawn_engine: impl Fn() -> io::Result<E>,
+  game_id: GameId,
+) {
+  let mut delay = config.retry_delay;
+  for attempt in 1..=config.attempts {
+    let result = match spawn_engine() {
+      Ok(engine) => analyze_and_store(shared, engine, game_id, config.time_per_move).await,
+      Err(error) => Err(error.into()),
+    };
+    match result {
+      Ok(()) => return,
+      Err(error) => log::warn!("Attempt {} to analyze game {} failed: {}", attempt, game_id, error),
+    }
+    if attempt < config.attempts {
+      tokio::time::sleep(delay).await;
+      delay *= 2;
+    }
+  }
+
+  log::error!(
+    "Dropping game {} from the analysis queue after {} failed attempts",
+    game_id,
+    config.attempts
+  );
+  if let Err(error) = shared.db.drop_analysis(game_id.0).await {
+    log::error!("Failed to drop game {} from the analysis queue: {}", game_id, error);
+  }
+}
+
+/// Analyzes finished games in the background. Games are queued in the database
+/// so the ones left unanalyzed are picked up again after a restart.
+pub struct Analyzer<F> {
+  shared: Arc<SessionShared>,
+  config: AnalysisConfig,
+  games: UnboundedReceiver<GameId>,
+  spawn_engine: F,
+}
+
+impl<E: Engine, F: Fn() -> io::Result<E> + Send + Sync + 'static> Analyzer<F> {
+  pub fn new(
+    shared: Arc<SessionShared>,
+    config: AnalysisConfig,
+    games: UnboundedReceiver<GameId>,
+    spawn_engine: F,
+  ) -> Self {
+    Analyzer {
+      shared,
+      config,
+      games,
+      spawn_engine,
+    }
+  }
+
+  pub async fn run(self) {
+    let semaphore = Arc::new(Semaphore::new(self.config.concurrency));
+    let spawn_engine = Arc::new(self.spawn_engine);
+
+    let queued = self.shared.db.get_analysis_queue().await.unwrap_or_else(|error| {
+      log::error!("Failed to load the analysis queue: {}", error);
+      Vec::new()
+    });
+    let mut games = stream::iter(queued.into_iter().map(GameId)).chain(self.games);
+
+    while let Some(game_id) = games.next().await {
+      let permit = semaphore
+        .clone()
+        .acquire_owned()
+        .await
+        .expect("analysis semaphore is never closed");
+
+      let shared = self.shared.clone();
+      let config = self.config.clone();
+      let spawn_engine = spawn_engine.clone();
+      tokio::spawn(async move {
+        analyze_with_retries(&shared, &config, &*spawn_engine, game_id).await;
+        drop(permit);
+      });
+    }
+  }
+}
@@ -0,0 +1,264 @@
+use crate::{
+  Session, SessionShared,
+  analysis::{Analyzer, Engine},
+  config::AnalysisConfig,
+  db::Db,
+  ids::GameId,
+  message::{Coordinate, FieldSize, GameConfig, GameTime, MoveAnalysis, Opening, Request, Response, TimeControl},
+  state::State,
+  test_utils::{connect, shared_with_analysis, wait_for},
+};
+use futures::channel::mpsc;
+use oppai_client::Constraint;
+use oppai_field::{field::Field, player::Player};
+use oppai_protocol::{Coords, Move};
+use rand::{SeedableRng, rngs::StdRng};
+use std::{
+  future::Future,
+  io,
+  sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+  },
+  time::Duration,
+};
+
+/// Engine that prefers the first empty cell and always favors Black.
+struct FirstMoveEngine {
+  field: Option<Field>,
+}
+
+impl Engine for FirstMoveEngine {
+  fn init(&mut self, width: u32, height: u32, _komi_x_2: i32) -> impl Future<Output = io::Result<()>> + Send {
+    self.field = Some(Field::new_from_rng(width, height, &mut StdRng::seed_from_u64(7)));
+    async { Ok(()) }
+  }
+
+  fn put_point(&mut self, x: u32, y: u32, player: Player) -> impl Future<Output = io::Result<bool>> + Send {
+    let field = self.field.as_mut().unwrap();
+    let pos = field.to_pos(x, y);
+    let put = field.put_point(pos, player);
+    async move { Ok(put) }
+  }
+
+  fn analyze(
+    &mut self,
+    player: Player,
+    _: Constraint,
+  ) -> impl Future<Output = io::Result<(Vec<Move>, Option<f64>)>> + Send {
+    let field = self.field.as_ref().unwrap();
+    let moves = (field.min_pos()..=field.max_pos())
+      .find(|&pos| field.is_putting_allowed(pos))
+      .map(|pos| Move {
+        coords: Coords {
+          x: field.to_x(pos),
+          y: field.to_y(pos),
+        },
+        weight: 1.0,
+      })
+      .into_iter()
+      .collect();
+    let winrate = match player {
+      Player::Red => 0.25,
+      Player::Black => 0.75,
+    };
+    async move { Ok((moves, Some(winrate))) }
+  }
+}
+
+fn analysis_config() -> AnalysisConfig {
+  AnalysisConfig {
+    engine: String::new(),
+    engine_args: Vec::new(),
+    concurrency: 2,
+    time_per_move: Duration::from_millis(10),
+    attempts: 3,
+    retry_delay: Duration::from_millis(10),
+  }
+}
+
+/// Plays a short game where Red resigns after a move of each player.
+async fn play_game(state: &Arc<State>, shared: &Arc<SessionShared>) -> (Session<StdRng>, GameId) {
+  let (mut alice, mut alice_rx) = connect(state, shared, "alice").await;
+  let (mut bob, _bob_rx) = connect(state, shared, "bob").await;
+
+  alice
+    .handle(
+      state,
+      Request::Create {
+        config: GameConfig {
+          size: FieldSize { width: 10, height: 10 },
+          time: GameTime {
+            total: Duration::from_secs(60),
+            control: TimeControl::Fischer {
+              increment: Duration::ZERO,
+            },
+            max_per_move: None,
+          },
+          opening: Opening::Cross,
+          komi_x_2: 0,
+          rated: true,
+        },
+      },
+    )
+    .await
+    .unwrap();
+  let game_id = wait_for(&mut alice_rx, |response| match response {
+    Response::Create { game_id, .. } => Some(game_id),
+    _ => None,
+  })
+  .await;
+  bob.handle(state, Request::Join { game_id }).await.unwrap();
+  alice.handle(state, Request::Subscribe { game_id }).await.unwrap();
+
+  for (session, x, y) in [(&mut alice, 1, 1), (&mut bob, 8, 8)] {
+    session
+      .handle(
+        state,
+        Request::PutPoint {
+          game_id,
+          coordinate: Coordinate { x, y },
+        },
+      )
+      .await
+      .unwrap();
+  }
+  alice.handle(state, Request::Resign { game_id }).await.unwrap();
+  wait_for(&mut alice_rx, |response| match response {
+    Response::GameResult { game_id: id, .. } if id == game_id => Some(()),
+    _ => None,
+  })
+  .await;
+
+  (alice, game_id)
+}
+
+async fn wait_for_analysis(state: &Arc<State>, shared: &Arc<SessionShared>, game_id: GameId) -> Vec<MoveAnalysis> {
+  let (mut session, mut rx) = connect(state, shared, "carol").await;
+  loop {
+    session.handle(state, Request::GetAnalysis { game_id }).await.unwrap();
+    let moves = wait_for(&mut rx, |response| match response {
+      Response::Analysis { game_id: id, moves } if id == game_id => Some(moves),
+      _ => None,
+    })
+    .await;
+    if !moves.is_empty() {
+      return moves;
+    }
+    tokio::time::sleep(Duration::from_millis(10)).await;
+  }
+}
+
+fn expected_analysis() -> Vec<MoveAnalysis> {
+  // The first empty cell stays the same since the game is played in the middle.
+  let best_move = Some(Coordinate { x: 0, y: 0 });
+  vec![
+    MoveAnalysis {
+      number: 4,
+      winrate: Some(0.25),
+      best_move: best_move.clone(),
+    },
+    MoveAnalysis {
+      number: 5,
+      winrate: Some(0.75),
+      best_move,
+    },
+  ]
+}
+
+#[tokio::test]
+async fn finished_games_are_analyzed() {
+  let state = Arc::new(State::default());
+  let (analysis_tx, analysis_rx) = mpsc::unbounded();
+  let shared = shared_with_analysis(Some(analysis_tx));
+
+  let analyzer = Analyzer::new(shared.clone(), analysis_config(), analysis_rx, || {
+    Ok(FirstMoveEngine { field: None })
+  });
+  tokio::spawn(analyzer.run());
+
+  let (_alice, game_id) = play_game(&state, &shared).await;
+  let moves = tokio::time::timeout(Duration::from_secs(10), wait_for_analysis(&state, &shared, game_id))
+    .await
+    .expect("no analysis in time");
+  assert_eq!(moves, expected_analysis());
+  assert!(shared.db.get_analysis_queue().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn analysis_queue_survives_restart() {
+  let state = Arc::new(State::default());
+  let (analysis_tx, analysis_rx) = mpsc::unbounded();
+  let shared = shared_with_analysis(Some(analysis_tx));
+  // The server stops before the worker takes the game.
+  drop(analysis_rx);
+
+  let (_alice, game_id) = play_game(&state, &shared).await;
+  assert_eq!(shared.db.get_analysis_queue().await.unwrap(), vec![game_id.0]);
+
+  let (_analysis_tx, analysis_rx) = mpsc::unbounded();
+  let analyzer = Analyzer::new(shared.clone(), analysis_config(), analysis_rx, || {
+    Ok(FirstMoveEngine { field: None })
+  });
+  tokio::spawn(analyzer.run());
+
+  let moves = tokio::time::timeout(Duration::from_secs(10), wait_for_analysis(&state, &shared, game_id))
+    .await
+    .expect("no analysis in time");
+  assert_eq!(moves, expected_analysis());
+  assert!(shared.db.get_analysis_queue().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn failed_analysis_is_retried() {
+  let state = Arc::new(State::default());
+  let (analysis_tx, analysis_rx) = mpsc::unbounded();
+  let shared = shared_with_analysis(Some(analysis_tx));
+
+  // The engine fails to start the first time.
+  let spawned = AtomicUsize::new(0);
+  let analyzer = Analyzer::new(shared.clone(), analysis_config(), analysis_rx, move || {
+    if spawned.fetch_add(1, Ordering::Relaxed) == 0 {
+      Err(io::Error::other("engine crashed"))
+    } else {
+      Ok(FirstMoveEngine { field: None })
+    }
+  });
+  tokio::spawn(analyzer.run());
+
+  let (_alice, game_id) = play_game(&state, &shared).await;
+  let moves = tokio::time::timeout(Duration::from_secs(10), wait_for_analysis(&state, &shared, game_id))
+    .await
+    .expect("no analysis in time");
+  assert_eq!(moves, expected_analysis());
+  assert!(shared.db.get_analysis_queue().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn failing_analysis_leaves_the_queue() {
+  let state = Arc::new(State::default());
+  let (analysis_tx, analysis_rx) = mpsc::unbounded();
+  let shared = shared_with_analysis(Some(analysis_tx));
+  drop(analysis_rx);
+  play_game(&state, &shared).await;
+
+  let (_analysis_tx, analysis_rx) = mpsc::unbounded();
+  let spawned = Arc::new(AtomicUsize::new(0));
+  let analyzer = Analyzer::new(shared.clone(), analysis_config(), analysis_rx, {
+    let spawned = spawned.clone();
+    move || -> io::Result<FirstMoveEngine> {
+      spawned.fetch_add(1, Ordering::Relaxed);
+      Err(io::Error::other("engine crashed"))
+    }
+  });
+  tokio::spawn(analyzer.run());
+
+  tokio::time::timeout(Duration::from_secs(10), async {
+    while !shared.db.get_analysis_queue().await.unwrap().is_empty() {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+  })
+  .await
+  .expect("the game stayed in the queue");
+  assert_eq!(spawned.load(Ordering::Relaxed), analysis_config().attempts as usize);
+}
@@ -0,0 +1,369 @@
+use crate::{Session, config::BotConfig, ids::*, message, state::State};
+use anyhow::Result;
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures_util::{StreamExt, select};
+use oppai_client::{Client, Constraint};
+use oppai_field::player::Player;
+use rand::Rng;
+use std::{collections::HashMap, future::Future, io, sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+
+/// Minimal time a bot thinks on a move.
+const MIN_THINKING_TIME: Duration = Duration::from_millis(100);
+
+/// Part of the engine API that bots need to play a game.
+pub trait Engine: Send + 'static {
+  fn init(&mut self, width: u32, height: u32, komi_x_2: i32) -> impl Future<Output = io::Result<()>> + Send;
+  fn put_point(&mut self, x: u32, y: u32, player: Player) -> impl Future<Output = io::Result<bool>> + Send;
+  fn analyze(
+    &mut self,
+    player: Player,
+    constraint: Constraint,
+  ) -> impl Future<Output = io::Result<Vec<oppai_protocol::Move>>> + Send;
+}
+
+impl Engine for Client {
+  fn init(&mut self, width: u32, height: u32, komi_x_2: i32) -> impl Future<Output = io::Result<()>> + Send {
+    Client::init(self, width, height, komi_x_2)
+  }
+
+  fn put_point(&mut self, x: u32, y: u32, player: Player) -> impl Future<Output = io::Result<bool>> + Send {
+    Client::put_point(self, x, y, player)
+  }
+
+  fn analyze(
+    &mut self,
+    player: Player,
+    constraint: Constraint,
+  ) -> impl Future<Output = io::Result<Vec<oppai_protocol::Move>>> + Send {
+    Client::analyze(self, player, constraint)
+  }
+}
+
+/// Game updates a bot reacts to.
+enum GameEvent {
+  Init {
+    moves: Vec<message::Move>,
+    time_left: message::TimeLeft,
+  },
+  PutPoint {
+    _move: message::Move,
+    time_left: message::TimeLeft,
+  },
+}
+
+/// Decisions a bot made in its games.
+enum Action {
+  PutPoint {
+    game_id: GameId,
+    coordinate: message::Coordinate,
+  },
+  Resign {
+    game_id: GameId,
+  },
+}
+
+struct BotGame {
+  events: UnboundedSender<GameEvent>,
+  task: JoinHandle<()>,
+}
+
+/// A player driven by an engine. It takes part in the lobby through a regular session,
+/// so it's seen by other players the same way as a human connection.
+pub struct Bot<R: Rng, F> {
+  session: Session<R>,
+  config: BotConfig,
+  spawn_engine: F,
+  open_games: HashMap<GameId, message::OpenGame>,
+  own_open_game: Option<GameId>,
+  creating: bool,
+  games: HashMap<GameId, BotGame>,
+}
+
+fn thinking_time(time: &message::GameTime, main: Duration, period: Option<&message::PeriodLeft>) -> Duration {
+  let thinking_time = match (time.control, period) {
+    (message::TimeControl::Fischer { increment }, _) => (main / 30 + increment / 2).min(main / 2),
+    (message::TimeControl::Delay { delay }, _) => delay + main / 30,
+    // Byo-yomi time is used only when the main time runs out, so keep a margin.
+    (_, Some(&message::PeriodLeft::Japanese { time, .. })) => main / 30 + time / 2,
+    (_, Some(&message::PeriodLeft::Canadian { time, moves })) => main / 30 + time / moves.max(1) / 2,
+    (_, None) => main / 30,
+  };
+  let thinking_time = match time.max_per_move {
+    Some(max_per_move) => thinking_time.min(max_per_move / 2),
+    None => thinking_time,
+  };
+  thinking_time.max(MIN_THINKING_TIME)
+}
+
+async fn play<E: Engine>(
+  mut engine: E,
+  game_id: GameId,
+  color: Player,
+  config: message::GameConfig,
+  mut events: UnboundedReceiver<GameEvent>,
+  actions: UnboundedSender<Action>,
+) -> io::Result<()> {
+  engine
+    .init(config.size.width, config.size.height, config.komi_x_2)
+    .await?;
+
+  let mut last_player = None;
+
+  while let Some(event) = events.next().await {
+    let (moves, time_left) = match event {
+      GameEvent::Init { moves, time_left } => (moves, time_left),
+      GameEvent::PutPoint { _move, time_left } => (vec![_move], time_left),
+    };
+
+    for m in moves {
+      // A move might be reported twice if it was put during the subscription.
+      if engine.put_point(m.coordinate.x, m.coordinate.y, m.player).await? {
+        last_player = Some(m.player);
+      }
+    }
+
+    if last_player.map_or(Player::Red, |player| player.next()) != color {
+      continue;
+    }
+
+    let (main, period) = match color {
+      Player::Red => (time_left.red, time_left.red_period.as_ref()),
+      Player::Black => (time_left.black, time_left.black_period.as_ref()),
+    };
+    let constraint = Constraint::Time(thinking_time(&config.time, main, period));
+    let best_move = engine
+      .analyze(color, constraint)
+      .await?
+      .into_iter()
+      .max_by(|m1, m2| m1.weight.total_cmp(&m2.weight));
+
+    let action = if let Some(best_move) = best_move {
+      Action::PutPoint {
+        game_id,
+        coordinate: message::Coordinate {
+          x: best_move.coords.x,
+          y: best_move.coords.y,
+        },
+      }
+    } else {
+      log::warn!("Bot engine has no moves in game {}", game_id);
+      Action::Resign { game_id }
+    };
+
+    if actions.unbounded_send(action).is_err() {
+      break;
+    }
+  }
+
+  Ok(())
+}
+
+impl<R: Rng + Send, E: Engine, F: FnMut() -> io::Result<E>> Bot<R, F> {
+  pub fn new(session: Session<R>, config: BotConfig, spawn_engine: F) -> Self {
+    Bot {
+      session,
+      config,
+      spawn_engine,
+      open_games: HashMap::new(),
+      own_open_game: None,
+      creating: false,
+      games: HashMap::new(),
+    }
+  }
+
+  async fn request(&mut self, state: &Arc<State>, request: message::Request) {
+    if let Err(error) = self.session.handle(state, request).await {
+      log::warn!("Bot request failed: {}", error);
+    }
+  }
+
+  /// Joins a suitable open game or creates a new one if the bot isn't busy.
+  async fn seek(&mut self, state: &Arc<State>) {
+    let Some(player_id) = self.session.player_id else {
+      return;
+    };
+    if !self.games.is_empty() {
+      return;
+    }
+
+    let game_id = self
+      .open_games
+      .iter()
+      .find(|(_, open_game)| open_game.player_id != player_id && self.config.accepts(&open_game.config))
+      .map(|(&game_id, _)| game_id);
+
+    if let Some(game_id) = game_id {
+      if let Some(own_game_id) = self.own_open_game.take() {
+        self
+          .request(state, message::Request::Close { game_id: own_game_id })
+          .await;
+      }
+      self.request(state, message::Request::Join { game_id }).await;
+    } else if self.own_open_game.is_none()
+      && !self.creating
+      && let Some(config) = self.config.create.clone()
+    {
+      self.creating = true;
+      self.request(state, message::Request::Create { config }).await;
+    }
+  }
+
+  async fn start(
+    &mut self,
+    state: &Arc<State>,
+    game_id: GameId,
+    game: message::Game,
+    actions: &UnboundedSender<Action>,
+  ) {
+    let color = match self.session.player_id {
+      Some(player_id) if player_id == game.red_player_id => Player::Red,
+      Some(player_id) if player_id == game.black_player_id => Player::Black,
+      _ => return,
+    };
+
+    let engine = match (self.spawn_engine)() {
+      Ok(engine) => engine,
+      Err(error) => {
+        log::error!("Failed to spawn bot engine for game {}: {}", game_id, error);
+        self.request(state, message::Request::Resign { game_id }).await;
+        return;
+      }
+    };
+
+    let (events_tx, events_rx) = mpsc::unbounded();
+    let actions = actions.clone();
+    let task = tokio::spawn(async move {
+      if let Err(error) = play(engine, game_id, color, game.config, events_rx, actions.clone()).await {
+        log::error!("Bot engine failed in game {}: {}", game_id, error);
+        let _ = actions.unbounded_send(Action::Resign { game_id });
+      }
+    });
+    self.games.insert(
+      game_id,
+      BotGame {
+        events: events_tx,
+        task,
+      },
+    );
+
+    self.request(state, message::Request::Subscribe { game_id }).await;
+  }
+
+  async fn finish(&mut self, state: &Arc<State>, game_id: GameId) {
+    if let Some(game) = self.games.remove(&game_id) {
+      game.task.abort();
+      self.request(state, message::Request::Unsubscribe { game_id }).await;
+      self.seek(state).await;
+    }
+  }
+
+  fn send_event(&self, game_id: GameId, event: GameEvent) {
+    if let Some(game) = self.games.get(&game_id) {
+      let _ = game.events.unbounded_send(event);
+    }
+  }
+
+  async fn on_response(&mut self, state: &Arc<State>, response: message::Response, actions: &UnboundedSender<Action>) {
+    match response {
+      message::Response::Init { open_games, .. } => {
+        self.open_games = open_games;
+      }
+      message::Response::Auth { .. } => self.seek(state).await,
+      message::Response::Create { game_id, open_game } => {
+        if Some(open_game.player_id) == self.session.player_id {
+          self.creating = false;
+          self.own_open_game = Some(game_id);
+        }
+        self.open_games.insert(game_id, open_game);
+        self.seek(state).await;
+      }
+      message::Response::Close { game_id } => {
+        self.open_games.remove(&game_id);
+        if self.own_open_game == Some(game_id) {
+          self.own_open_game = None;
+          self.seek(state).await;
+        }
+      }
+      message::Response::Start { game_id, game } => {
+        self.open_games.remove(&game_id);
+        if self.own_open_game == Some(game_id) {
+          self.own_open_game = None;
+        }
+        self.start(state, game_id, game, actions).await;
+      }
+      message::Response::GameInit {
+        game_id,
+        moves,
+        time_left,
+        result,
+        ..
+      } => {
+        if result.is_some() {
+          self.finish(state, game_id).await;
+        } else {
+          self.send_event(game_id, GameEvent::Init { moves, time_left });
+        }
+      }
+      message::Response::PutPoint {
+        game_id,
+        _move,
+        time_left,
+        ..
+      } => self.send_event(game_id, GameEvent::PutPoint { _move, time_left }),
+      message::Response::GameResult { game_id, .. } => self.finish(state, game_id).await,
+      _ => {}
+    }
+  }
+
+  async fn on_action(&mut self, state: &Arc<State>, action: Action) {
+    match action {
+      Action::PutPoint { game_id, coordinate } if self.games.contains_key(&game_id) => {
+        self
+          .request(state, message::Request::PutPoint { game_id, coordinate })
+          .await
+      }
+      Action::Resign { game_id } if self.games.contains_key(&game_id) => {
+        self.request(state, message::Request::Resign { game_id }).await
+      }
+      _ => {}
+    }
+  }
+
+  pub async fn run(mut self, state: Arc<State>) -> Result<()> {
+    let (tx, mut rx) = mpsc::channel::<message::Response>(32);
+    let (actions_tx, mut actions_rx) = mpsc::unbounded::<Action>();
+
+    self.session.init(&state, tx).await?;
+    self
+      .session
+      .handle(
+        &state,
+        message::Request::AuthBot {
+          token: self.config.token.clone(),
+        },
+      )
+      .await?;
+
+    loop {
+      select! {
+        response = rx.next() => match response {
+          Some(response) => self.on_response(&state, response, &actions_tx).await,
+          None => break,
+        },
+        action = actions_rx.next() => {
+          if let Some(action) = action {
+            self.on_action(&state, action).await;
+          }
+        },
+      }
+    }
+
+    for (_, game) in self.games.drain() {
+      game.task.abort();
+    }
+    self.session.finalize(&state).await;
+
+    Ok(())
+  }
+}
@@ -0,0 +1,159 @@
+use crate::{
+  Session,
+  bot::{Bot, Engine},
+  config::BotConfig,
+  ids::*,
+  message::{
+    Coordinate, FieldSize, GameConfig, GameResult, GameTime, Opening, Request, Response, TimeControl, WinReason,
+  },
+  state::State,
+  test_utils::{BOT_TOKEN, connect, shared, wait_for},
+};
+use oppai_client::Constraint;
+use oppai_field::{field::Field, player::Player};
+use oppai_protocol::{Coords, Move};
+use rand::{SeedableRng, rngs::StdRng};
+use std::{future::Future, io, sync::Arc, time::Duration};
+
+/// Engine that always plays the first empty cell.
+struct FirstMoveEngine {
+  field: Option<Field>,
+}
+
+impl Engine for FirstMoveEngine {
+  fn init(&mut self, width: u32, height: u32, _komi_x_2: i32) -> impl Future<Output = io::Result<()>> + Send {
+    self.field = Some(Field::new_from_rng(width, height, &mut StdRng::seed_from_u64(7)));
+    async { Ok(()) }
+  }
+
+  fn put_point(&mut self, x: u32, y: u32, player: Player) -> impl Future<Output = io::Result<bool>> + Send {
+    let field = self.field.as_mut().unwrap();
+    let pos = field.to_pos(x, y);
+    let put = field.put_point(pos, player);
+    async move { Ok(put) }
+  }
+
+  fn analyze(&mut self, _: Player, _: Constraint) -> impl Future<Output = io::Result<Vec<Move>>> + Send {
+    let field = self.field.as_ref().unwrap();
+    let moves = (field.min_pos()..=field.max_pos())
+      .find(|&pos| field.is_putting_allowed(pos))
+      .map(|pos| Move {
+        coords: Coords {
+          x: field.to_x(pos),
+          y: field.to_y(pos),
+        },
+        weight: 1.0,
+      })
+      .into_iter()
+      .collect();
+    async move { Ok(moves) }
+  }
+}
+
+fn bot_config() -> BotConfig {
+  BotConfig {
+    token: BOT_TOKEN.to_string(),
+    engine: String::new(),
+    engine_args: Vec::new(),
+    min_size: 10,
+    max_size: 20,
+    min_total_time: Duration::from_secs(10),
+    max_total_time: Duration::from_secs(600),
+    max_increment: Duration::from_secs(10),
+    create: None,
+  }
+}
+
+fn game_config() -> GameConfig {
+  GameConfig {
+    size: FieldSize { width: 10, height: 10 },
+    time: GameTime {
+      total: Duration::from_secs(60),
+      control: TimeControl::Fischer {
+        increment: Duration::ZERO,
+      },
+      max_per_move: None,
+    },
+    opening: Opening::Cross,
+    komi_x_2: 0,
+    rated: true,
+  }
+}
+
+#[tokio::test]
+async fn bot_joins_and_plays() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+
+  let bot = Bot::new(
+    Session::new(shared.clone(), StdRng::seed_from_u64(1)),
+    bot_config(),
+    || Ok(FirstMoveEngine { field: None }),
+  );
+  tokio::spawn(bot.run(state.clone()));
+
+  let (mut human, mut rx) = connect(&state, &shared, "human").await;
+  let human_id = human.player_id.unwrap();
+
+  human
+    .handle(&state, Request::Create { config: game_config() })
+    .await
+    .unwrap();
+
+  let (game_id, game) = wait_for(&mut rx, |response| match response {
+    Response::Start { game_id, game } => Some((game_id, game)),
+    _ => None,
+  })
+  .await;
+  assert_eq!(game.red_player_id, human_id);
+  assert!(!game.red_player.bot);
+  assert!(game.black_player.bot);
+
+  human.handle(&state, Request::Subscribe { game_id }).await.unwrap();
+
+  for x in 0..3 {
+    human
+      .handle(
+        &state,
+        Request::PutPoint {
+          game_id,
+          coordinate: Coordinate { x: x + 1, y: 2 },
+        },
+      )
+      .await
+      .unwrap();
+    let coordinate = wait_for(&mut rx, |response| match response {
+      Response::PutPoint { game_id: id, _move, .. } if id == game_id && _move.player == Player::Black => {
+        Some(_move.coordinate)
+      }
+      _ => None,
+    })
+    .await;
+    assert_eq!(coordinate, Coordinate { x, y: 0 });
+  }
+
+  human.handle(&state, Request::Resign { game_id }).await.unwrap();
+
+  let result = wait_for(&mut rx, |response| match response {
+    Response::GameResult {
+      game_id: id, result, ..
+    } if id == game_id => Some(result),
+    _ => None,
+  })
+  .await;
+  assert_eq!(
+    result,
+    GameResult::Win {
+      winner: Player::Black,
+      reason: WinReason::Resigned,
+    }
+  );
+  assert!(state.games.pin().get(&game_id).is_none());
+  assert!(
+    state
+      .players
+      .pin()
+      .keys()
+      .any(|&player_id: &PlayerId| player_id != human_id)
+  );
+}
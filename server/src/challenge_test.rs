@@ -0,0 +1,95 @@
+use crate::{
+  ids::GameId,
+  message::{
+    Challenge, ChallengeCloseReason, FieldSize, GameConfig, GameTime, Opening, Request, Response, TimeControl,
+  },
+  state::State,
+  test_utils::{connect, shared, wait_for},
+};
+use futures::channel::mpsc::Receiver;
+use oppai_field::player::Player;
+use std::{sync::Arc, time::Duration};
+
+fn game_config() -> GameConfig {
+  GameConfig {
+    size: FieldSize { width: 10, height: 10 },
+    time: GameTime {
+      total: Duration::from_secs(60),
+      control: TimeControl::Fischer {
+        increment: Duration::ZERO,
+      },
+      max_per_move: None,
+    },
+    opening: Opening::Cross,
+    komi_x_2: 0,
+    rated: true,
+  }
+}
+
+async fn challenge(rx: &mut Receiver<Response>) -> (GameId, Challenge) {
+  wait_for(rx, |response| match response {
+    Response::Challenge { game_id, challenge } => Some((game_id, challenge)),
+    _ => None,
+  })
+  .await
+}
+
+#[tokio::test]
+async fn challenge_decline_accept_and_rematch() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+
+  let (mut alice, mut alice_rx) = connect(&state, &shared, "alice").await;
+  let (mut bob, mut bob_rx) = connect(&state, &shared, "bob").await;
+  let alice_id = alice.player_id.unwrap();
+  let bob_id = bob.player_id.unwrap();
+
+  let request = Request::Challenge {
+    player_id: bob_id,
+    config: game_config(),
+  };
+  alice.handle(&state, request.clone()).await.unwrap();
+  let (game_id, received) = challenge(&mut bob_rx).await;
+  assert_eq!(received.player_id, alice_id);
+  assert_eq!(received.opponent_id, bob_id);
+  assert_eq!(received.color, Player::Red);
+  assert_eq!(challenge(&mut alice_rx).await.0, game_id);
+  assert!(state.open_games.pin().is_empty());
+
+  bob.handle(&state, Request::DeclineChallenge { game_id }).await.unwrap();
+  let reason = wait_for(&mut alice_rx, |response| match response {
+    Response::ChallengeClosed { game_id: id, reason } if id == game_id => Some(reason),
+    _ => None,
+  })
+  .await;
+  assert_eq!(reason, ChallengeCloseReason::Declined);
+  assert!(state.challenges.pin().is_empty());
+
+  alice.handle(&state, request).await.unwrap();
+  let (game_id, _) = challenge(&mut bob_rx).await;
+  bob.handle(&state, Request::AcceptChallenge { game_id }).await.unwrap();
+  let game = wait_for(&mut alice_rx, |response| match response {
+    Response::Start { game_id: id, game } if id == game_id => Some(game),
+    _ => None,
+  })
+  .await;
+  assert_eq!(game.red_player_id, alice_id);
+  assert_eq!(game.black_player_id, bob_id);
+
+  alice.handle(&state, Request::Resign { game_id }).await.unwrap();
+
+  alice.handle(&state, Request::Rematch { game_id }).await.unwrap();
+  let (rematch_id, received) = challenge(&mut bob_rx).await;
+  assert_eq!(received.color, Player::Black);
+  assert_eq!(received.rematch, Some(game_id));
+
+  bob.handle(&state, Request::Rematch { game_id }).await.unwrap();
+  let game = wait_for(&mut alice_rx, |response| match response {
+    Response::Start { game_id: id, game } if id == rematch_id => Some(game),
+    _ => None,
+  })
+  .await;
+  assert_eq!(game.red_player_id, bob_id);
+  assert_eq!(game.black_player_id, alice_id);
+  assert!(state.challenges.pin().is_empty());
+}
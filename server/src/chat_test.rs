@@ -0,0 +1,109 @@
+use crate::{
+  CHAT_RATE_LIMIT, MAX_CHAT_MESSAGE_LENGTH,
+  message::{ChatMessage, FieldSize, GameConfig, GameTime, Opening, Request, Response, TimeControl},
+  state::State,
+  test_utils::{connect, shared, wait_for},
+};
+use futures::channel::mpsc::Receiver;
+use std::{sync::Arc, time::Duration};
+
+async fn chat(rx: &mut Receiver<Response>) -> ChatMessage {
+  wait_for(rx, |response| match response {
+    Response::Chat { message, .. } => Some(message),
+    _ => None,
+  })
+  .await
+}
+
+#[tokio::test]
+async fn lobby_and_game_chat() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+
+  let (mut alice, mut alice_rx) = connect(&state, &shared, "alice").await;
+  let (mut bob, mut bob_rx) = connect(&state, &shared, "bob").await;
+
+  let lobby = |text: &str| Request::Chat {
+    game_id: None,
+    text: text.to_string(),
+  };
+  alice.handle(&state, lobby("  hello  ")).await.unwrap();
+  let message = chat(&mut bob_rx).await;
+  assert_eq!(message.nickname, "alice");
+  assert_eq!(message.text, "hello");
+  assert_eq!(chat(&mut alice_rx).await, message);
+
+  assert!(alice.handle(&state, lobby(" ")).await.is_err());
+  assert!(
+    alice
+      .handle(&state, lobby(&"a".repeat(MAX_CHAT_MESSAGE_LENGTH + 1)))
+      .await
+      .is_err()
+  );
+
+  // Messages over the limit are dropped.
+  for i in 1..=CHAT_RATE_LIMIT {
+    alice.handle(&state, lobby(&i.to_string())).await.unwrap();
+  }
+  let (_, mut carol_rx) = connect(&state, &shared, "carol").await;
+  let chat_history = wait_for(&mut carol_rx, |response| match response {
+    Response::Init { chat, .. } => Some(chat),
+    _ => None,
+  })
+  .await;
+  assert_eq!(
+    chat_history.into_iter().map(|message| message.text).collect::<Vec<_>>(),
+    vec!["hello", "1", "2", "3", "4"]
+  );
+
+  alice
+    .handle(
+      &state,
+      Request::Create {
+        config: GameConfig {
+          size: FieldSize { width: 10, height: 10 },
+          time: GameTime {
+            total: Duration::from_secs(60),
+            control: TimeControl::Fischer {
+              increment: Duration::ZERO,
+            },
+            max_per_move: None,
+          },
+          opening: Opening::Cross,
+          komi_x_2: 0,
+          rated: true,
+        },
+      },
+    )
+    .await
+    .unwrap();
+  let game_id = wait_for(&mut bob_rx, |response| match response {
+    Response::Create { game_id, .. } => Some(game_id),
+    _ => None,
+  })
+  .await;
+  bob.handle(&state, Request::Join { game_id }).await.unwrap();
+
+  let in_game = Request::Chat {
+    game_id: Some(game_id),
+    text: "good game".to_string(),
+  };
+  assert!(bob.handle(&state, in_game.clone()).await.is_err());
+  bob.handle(&state, Request::Subscribe { game_id }).await.unwrap();
+  bob.handle(&state, in_game).await.unwrap();
+  let (chat_game_id, message) = wait_for(&mut bob_rx, |response| match response {
+    Response::Chat { game_id, message } => Some((game_id, message)),
+    _ => None,
+  })
+  .await;
+  assert_eq!(chat_game_id, Some(game_id));
+  assert_eq!(message.nickname, "bob");
+
+  alice.handle(&state, Request::Subscribe { game_id }).await.unwrap();
+  let chat_history = wait_for(&mut alice_rx, |response| match response {
+    Response::GameInit { chat, .. } => Some(chat),
+    _ => None,
+  })
+  .await;
+  assert_eq!(chat_history, vec![message]);
+}
@@ -1,7 +1,12 @@
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
 use clap::{Arg, Command};
 use cookie::Key;
+use itertools::Itertools;
 use openidconnect::{ClientId, ClientSecret, IssuerUrl};
 
+use crate::message::{FieldSize, GameConfig, GameTime, Opening, TimeControl};
+
 #[derive(Clone, Debug)]
 pub struct OidcConfig {
   pub issuer_url: IssuerUrl,
@@ -9,12 +14,115 @@ pub struct OidcConfig {
   pub client_secret: Option<ClientSecret>,
 }
 
+#[derive(Clone, Debug)]
+pub struct BotConfig {
+  pub token: String,
+  pub engine: String,
+  pub engine_args: Vec<String>,
+  pub min_size: u32,
+  pub max_size: u32,
+  pub min_total_time: Duration,
+  pub max_total_time: Duration,
+  pub max_increment: Duration,
+  /// Game to create in the lobby when there is no suitable open game.
+  pub create: Option<GameConfig>,
+}
+
+impl BotConfig {
+  pub fn accepts(&self, config: &GameConfig) -> bool {
+    (self.min_size..=self.max_size).contains(&config.size.width)
+      && (self.min_size..=self.max_size).contains(&config.size.height)
+      && (self.min_total_time..=self.max_total_time).contains(&config.time.total)
+      && match config.time.control {
+        TimeControl::Fischer { increment } => increment <= self.max_increment,
+        TimeControl::Delay { delay } => delay <= self.max_increment,
+        // Byo-yomi periods are bounded by the increment limit as well.
+        TimeControl::Japanese { period, .. } | TimeControl::Canadian { period, .. } => period <= self.max_increment,
+      }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnalysisConfig {
+  pub engine: String,
+  pub engine_args: Vec<String>,
+  /// Maximal number of games analyzed at the same time.
+  pub concurrency: usize,
+  pub time_per_move: Duration,
+  /// Number of attempts to analyze a game before it's dropped from the queue.
+  pub attempts: u32,
+  /// Delay before retrying a failed analysis, doubled after every retry.
+  pub retry_delay: Duration,
+}
+
+/// Maximal numbers of requests within a 10 seconds window.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitConfig {
+  pub connection: usize,
+  pub player: usize,
+}
+
 #[derive(Clone, Debug)]
 pub struct Config {
+  /// Address of the WebSocket listener.
+  pub listen: SocketAddr,
+  /// Address of the HTTP listener with health and metrics endpoints.
+  pub metrics_listen: Option<SocketAddr>,
+  /// Time to wait for live games to finish on shutdown.
+  pub shutdown_timeout: Duration,
   pub oidc: OidcConfig,
   #[cfg(not(feature = "in-memory"))]
   pub postgres_socket: String,
   pub cookie_key: Key,
+  /// Maps bot tokens to bot nicknames.
+  pub bot_tokens: HashMap<String, String>,
+  pub bot: Option<BotConfig>,
+  pub analysis: Option<AnalysisConfig>,
+  pub rate_limit: RateLimitConfig,
+}
+
+fn parse_bot_token(s: &str) -> Result<(String, String), String> {
+  s.split_once(':')
+    .filter(|(nickname, token)| !nickname.is_empty() && !token.is_empty())
+    .map(|(nickname, token)| (token.to_string(), nickname.to_string()))
+    .ok_or_else(|| format!("invalid bot token `{}`, expected NICKNAME:TOKEN", s))
+}
+
+fn parse_game_config(s: &str) -> Result<GameConfig, String> {
+  let error = || {
+    format!(
+      "invalid game config `{}`, expected WIDTHxHEIGHT,TOTAL+INCREMENT,OPENING",
+      s
+    )
+  };
+  let (size, time, opening) = s.split(',').collect_tuple().ok_or_else(error)?;
+  let (width, height) = size.split_once('x').ok_or_else(error)?;
+  let (total, increment) = time.split_once('+').ok_or_else(error)?;
+  let opening = match opening {
+    "empty" => Opening::Empty,
+    "cross" => Opening::Cross,
+    "two-crosses" => Opening::TwoCrosses,
+    "triple-cross" => Opening::TripleCross,
+    "four-crosses" => Opening::FourCrosses,
+    _ => return Err(error()),
+  };
+  let config = GameConfig {
+    size: FieldSize {
+      width: width.parse().map_err(|_| error())?,
+      height: height.parse().map_err(|_| error())?,
+    },
+    time: GameTime {
+      total: Duration::from_secs(total.parse().map_err(|_| error())?),
+      control: TimeControl::Fischer {
+        increment: Duration::from_secs(increment.parse().map_err(|_| error())?),
+      },
+      max_per_move: None,
+    },
+    opening,
+    komi_x_2: 0,
+    rated: true,
+  };
+  if config.is_valid() { Ok(config) } else { Err(error()) }
 }
 
 pub fn cli_parse() -> Config {
@@ -22,6 +130,31 @@ pub fn cli_parse() -> Config {
     .version(clap::crate_version!())
     .author(clap::crate_authors!("\n"))
     .about(clap::crate_description!())
+    .arg(
+      Arg::new("listen")
+        .long("listen")
+        .help("Address to accept WebSocket connections on")
+        .num_args(1)
+        .value_parser(clap::value_parser!(SocketAddr))
+        .default_value("127.0.0.1:8080")
+        .env("LISTEN"),
+    )
+    .arg(
+      Arg::new("metrics-listen")
+        .long("metrics-listen")
+        .help("Address to serve health and Prometheus metrics endpoints on")
+        .num_args(1)
+        .value_parser(clap::value_parser!(SocketAddr))
+        .env("METRICS_LISTEN"),
+    )
+    .arg(
+      Arg::new("shutdown-timeout")
+        .long("shutdown-timeout")
+        .help("Time in seconds to wait for live games to finish on shutdown")
+        .num_args(1)
+        .value_parser(clap::value_parser!(u64))
+        .default_value("600"),
+    )
     .arg(
       Arg::new("oidc-issuer-url")
         .long("oidc-issuer-url")
@@ -51,6 +184,147 @@ pub fn cli_parse() -> Config {
         .help("Cookie secret key")
         .num_args(1)
         .env("COOKIE_KEY"),
+    )
+    .arg(
+      Arg::new("bot-tokens")
+        .long("bot-tokens")
+        .help("Tokens bot players can authenticate with, separated by ','")
+        .value_name("NICKNAME:TOKEN")
+        .num_args(1..)
+        .value_delimiter(',')
+        .value_parser(parse_bot_token)
+        .env("BOT_TOKENS"),
+    )
+    .arg(
+      Arg::new("bot-token")
+        .long("bot-token")
+        .help("Token of a bot player driven by an engine within the server")
+        .num_args(1)
+        .requires("bot-engine")
+        .env("BOT_TOKEN"),
+    )
+    .arg(
+      Arg::new("bot-engine")
+        .long("bot-engine")
+        .help("Engine executable the bot plays with")
+        .num_args(1)
+        .requires("bot-token"),
+    )
+    .arg(
+      Arg::new("bot-engine-args")
+        .long("bot-engine-args")
+        .help("Args for the bot engine, separated by ','")
+        .num_args(1..)
+        .value_delimiter(','),
+    )
+    .arg(
+      Arg::new("bot-min-size")
+        .long("bot-min-size")
+        .help("Minimal field width and height of games the bot joins")
+        .num_args(1)
+        .value_parser(clap::value_parser!(u32))
+        .default_value("10"),
+    )
+    .arg(
+      Arg::new("bot-max-size")
+        .long("bot-max-size")
+        .help("Maximal field width and height of games the bot joins")
+        .num_args(1)
+        .value_parser(clap::value_parser!(u32))
+        .default_value("50"),
+    )
+    .arg(
+      Arg::new("bot-min-time")
+        .long("bot-min-time")
+        .help("Minimal total time in seconds of games the bot joins")
+        .num_args(1)
+        .value_parser(clap::value_parser!(u64))
+        .default_value("60"),
+    )
+    .arg(
+      Arg::new("bot-max-time")
+        .long("bot-max-time")
+        .help("Maximal total time in seconds of games the bot joins")
+        .num_args(1)
+        .value_parser(clap::value_parser!(u64))
+        .default_value("1800"),
+    )
+    .arg(
+      Arg::new("bot-max-increment")
+        .long("bot-max-increment")
+        .help("Maximal time increment in seconds of games the bot joins")
+        .num_args(1)
+        .value_parser(clap::value_parser!(u64))
+        .default_value("30"),
+    )
+    .arg(
+      Arg::new("bot-create")
+        .long("bot-create")
+        .help("Game the bot creates when there is no suitable open game, e.g. 39x32,300+5,cross")
+        .value_name("WIDTHxHEIGHT,TOTAL+INCREMENT,OPENING")
+        .num_args(1)
+        .value_parser(parse_game_config),
+    )
+    .arg(
+      Arg::new("analysis-engine")
+        .long("analysis-engine")
+        .help("Engine executable finished games are analyzed with")
+        .num_args(1),
+    )
+    .arg(
+      Arg::new("analysis-engine-args")
+        .long("analysis-engine-args")
+        .help("Args for the analysis engine, separated by ','")
+        .num_args(1..)
+        .value_delimiter(','),
+    )
+    .arg(
+      Arg::new("analysis-concurrency")
+        .long("analysis-concurrency")
+        .help("Maximal number of games analyzed at the same time")
+        .num_args(1)
+        .value_parser(clap::value_parser!(u32).range(1..))
+        .default_value("1"),
+    )
+    .arg(
+      Arg::new("analysis-time")
+        .long("analysis-time")
+        .help("Time in milliseconds the analysis engine spends on a move")
+        .num_args(1)
+        .value_parser(clap::value_parser!(u64))
+        .default_value("1000"),
+    )
+    .arg(
+      Arg::new("analysis-attempts")
+        .long("analysis-attempts")
+        .help("Number of attempts to analyze a game before giving up on it")
+        .num_args(1)
+        .value_parser(clap::value_parser!(u32).range(1..))
+        .default_value("3"),
+    )
+    .arg(
+      Arg::new("analysis-retry-delay")
+        .long("analysis-retry-delay")
+        .help("Time in milliseconds before retrying a failed analysis, doubled after every retry")
+        .num_args(1)
+        .value_parser(clap::value_parser!(u64))
+        .default_value("10000"),
+    )
+    .arg(
+      Arg::new("connection-rate-limit")
+        .long("connection-rate-limit")
+        .help("Maximal number of requests a connection can make within 10 seconds")
+        .num_args(1)
+        .value_parser(clap::value_parser!(usize))
+        .default_value("100"),
+    )
+    .arg(
+      Arg::new("player-rate-limit")
+        .long("player-rate-limit")
+        .help("Maximal number of requests a player can make from all connections within 10 seconds")
+        .num_args(1)
+        .value_parser(clap::value_parser!(usize))
+        .default_value("200"),
     );
   #[cfg(not(feature = "in-memory"))]
   let command = command.arg(
@@ -75,10 +349,93 @@ pub fn cli_parse() -> Config {
   let cookie_key = matches.get_one::<String>("cookie-key").map_or_else(Key::generate, |s| {
     Key::from(hex::decode(s.as_str()).unwrap().as_slice())
   });
+  let bot_tokens = matches
+    .get_many::<(String, String)>("bot-tokens")
+    .map(|tokens| tokens.cloned().collect())
+    .unwrap_or_default();
+
+  let bot = matches.get_one::<String>("bot-token").map(|token| BotConfig {
+    token: token.clone(),
+    engine: matches
+      .get_one::<String>("bot-engine")
+      .cloned()
+      .expect("`bot-engine` is required by `bot-token`"),
+    engine_args: matches
+      .get_many::<String>("bot-engine-args")
+      .map(|args| args.cloned().collect())
+      .unwrap_or_default(),
+    min_size: *matches
+      .get_one::<u32>("bot-min-size")
+      .expect("`bot-min-size` has a default"),
+    max_size: *matches
+      .get_one::<u32>("bot-max-size")
+      .expect("`bot-max-size` has a default"),
+    min_total_time: Duration::from_secs(
+      *matches
+        .get_one::<u64>("bot-min-time")
+        .expect("`bot-min-time` has a default"),
+    ),
+    max_total_time: Duration::from_secs(
+      *matches
+        .get_one::<u64>("bot-max-time")
+        .expect("`bot-max-time` has a default"),
+    ),
+    max_increment: Duration::from_secs(
+      *matches
+        .get_one::<u64>("bot-max-increment")
+        .expect("`bot-max-increment` has a default"),
+    ),
+    create: matches.get_one::<GameConfig>("bot-create").cloned(),
+  });
+
+  let analysis = matches
+    .get_one::<String>("analysis-engine")
+    .map(|engine| AnalysisConfig {
+      engine: engine.clone(),
+      engine_args: matches
+        .get_many::<String>("analysis-engine-args")
+        .map(|args| args.cloned().collect())
+        .unwrap_or_default(),
+      concurrency: *matches
+        .get_one::<u32>("analysis-concurrency")
+        .expect("`analysis-concurrency` has a default") as usize,
+      time_per_move: Duration::from_millis(
+        *matches
+          .get_one::<u64>("analysis-time")
+          .expect("`analysis-time` has a default"),
+      ),
+      attempts: *matches
+        .get_one::<u32>("analysis-attempts")
+        .expect("`analysis-attempts` has a default"),
+      retry_delay: Duration::from_millis(
+        *matches
+          .get_one::<u64>("analysis-retry-delay")
+          .expect("`analysis-retry-delay` has a default"),
+      ),
+    });
+
   Config {
+    listen: *matches.get_one("listen").expect("`listen` has a default"),
+    metrics_listen: matches.get_one("metrics-listen").copied(),
+    shutdown_timeout: Duration::from_secs(
+      *matches
+        .get_one::<u64>("shutdown-timeout")
+        .expect("`shutdown-timeout` has a default"),
+    ),
     oidc,
     #[cfg(not(feature = "in-memory"))]
     postgres_socket: matches.get_one("postgres-socket").cloned().unwrap(),
     cookie_key,
+    bot_tokens,
+    bot,
+    analysis,
+    rate_limit: RateLimitConfig {
+      connection: *matches
+        .get_one("connection-rate-limit")
+        .expect("`connection-rate-limit` has a default"),
+      player: *matches
+        .get_one("player-rate-limit")
+        .expect("`player-rate-limit` has a default"),
+    },
   }
 }
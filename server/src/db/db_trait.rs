@@ -11,6 +11,18 @@ pub enum Opening {
   Cross,
   TwoCrosses,
   TripleCross,
+  Empty,
+  FourCrosses,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "time_control")]
+#[sqlx(rename_all = "lowercase")]
+pub enum TimeControl {
+  Fischer,
+  Japanese,
+  Canadian,
+  Delay,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
@@ -46,6 +58,11 @@ pub struct Player {
   pub rating: f64,
   pub deviation: f64,
   pub volatility: f64,
+  pub bot: bool,
+  pub admin: bool,
+  pub banned: bool,
+  /// The player can't chat until this time.
+  pub muted_until: Option<PrimitiveDateTime>,
 }
 
 pub struct OidcPlayer {
@@ -89,6 +106,7 @@ impl OidcPlayer {
   }
 }
 
+/// The player a decisive result names is the one who lost the game.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
 #[sqlx(type_name = "provider")]
 #[sqlx(rename_all = "lowercase")]
@@ -101,6 +119,26 @@ pub enum GameResult {
   TimeOutBlack,
   DrawAgreement,
   DrawGrounded,
+  /// Closed by a moderator without a winner.
+  Aborted,
+}
+
+/// Game result from a player's point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+  Win,
+  Loss,
+  Draw,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GamesFilter {
+  pub outcome: Option<Outcome>,
+  pub opponent_id: Option<Uuid>,
+  pub width: Option<i32>,
+  pub height: Option<i32>,
+  pub from: Option<PrimitiveDateTime>,
+  pub to: Option<PrimitiveDateTime>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
@@ -112,8 +150,16 @@ pub struct Game {
   pub width: i32,
   pub height: i32,
   pub total_time_ms: i64,
+  /// Increment for Fischer time control or delay for simple delay.
   pub increment_ms: i64,
+  pub time_control: TimeControl,
+  pub period_ms: i64,
+  /// Number of Japanese byo-yomi periods or moves per Canadian byo-yomi period.
+  pub periods: i32,
+  pub max_move_time_ms: Option<i64>,
   pub opening: Opening,
+  pub komi_x_2: i32,
+  pub rated: bool,
   pub finish_time: Option<PrimitiveDateTime>,
   pub result: Option<GameResult>,
 }
@@ -136,6 +182,130 @@ pub struct DrawOffer {
   pub timestamp: PrimitiveDateTime,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "tournament_format")]
+#[sqlx(rename_all = "snake_case")]
+pub enum TournamentFormat {
+  RoundRobin,
+  Swiss,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "tournament_status")]
+#[sqlx(rename_all = "lowercase")]
+pub enum TournamentStatus {
+  Registration,
+  Running,
+  Finished,
+  Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Tournament {
+  pub id: Uuid,
+  pub name: String,
+  pub creator_id: Uuid,
+  pub width: i32,
+  pub height: i32,
+  pub total_time_ms: i64,
+  /// Increment for Fischer time control or delay for simple delay.
+  pub increment_ms: i64,
+  pub time_control: TimeControl,
+  pub period_ms: i64,
+  /// Number of Japanese byo-yomi periods or moves per Canadian byo-yomi period.
+  pub periods: i32,
+  pub max_move_time_ms: Option<i64>,
+  pub opening: Opening,
+  pub komi_x_2: i32,
+  pub rated: bool,
+  pub format: TournamentFormat,
+  /// Number of rounds for Swiss tournaments.
+  pub rounds: Option<i32>,
+  pub start_time: PrimitiveDateTime,
+  pub status: TournamentStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct TournamentPairing {
+  pub tournament_id: Uuid,
+  pub round: i32,
+  pub red_player_id: Uuid,
+  /// No opponent means a bye.
+  pub black_player_id: Option<Uuid>,
+  pub game_id: Option<Uuid>,
+}
+
+/// Chat message in a game or in the lobby if there is no game.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct ChatMessage {
+  pub game_id: Option<Uuid>,
+  pub player_id: Uuid,
+  pub text: String,
+  pub timestamp: PrimitiveDateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "moderation_action")]
+#[sqlx(rename_all = "snake_case")]
+pub enum ModerationAction {
+  Ban,
+  Unban,
+  Mute,
+  Unmute,
+  CloseGame,
+  Rename,
+}
+
+/// Action of an admin recorded in the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct AuditLogEntry {
+  pub admin_id: Uuid,
+  pub action: ModerationAction,
+  pub player_id: Option<Uuid>,
+  pub game_id: Option<Uuid>,
+  pub details: Option<String>,
+  pub timestamp: PrimitiveDateTime,
+}
+
+/// Rating of a player right after a rated game.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct RatingHistoryEntry {
+  pub game_id: Uuid,
+  pub player_id: Uuid,
+  pub rating: f64,
+  pub deviation: f64,
+  pub volatility: f64,
+  pub timestamp: PrimitiveDateTime,
+}
+
+/// Engine analysis of the position before a move.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct MoveAnalysis {
+  pub game_id: Uuid,
+  pub number: i16,
+  /// Probability of winning for the player making the move.
+  pub winrate: Option<f64>,
+  pub best_x: Option<i16>,
+  pub best_y: Option<i16>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, sqlx::FromRow)]
+pub struct PlayerStats {
+  pub wins: i64,
+  pub losses: i64,
+  pub draws: i64,
+}
+
+/// Results of a player against one opponent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::FromRow)]
+pub struct OpponentStats {
+  pub opponent_id: Uuid,
+  pub games: i64,
+  pub wins: i64,
+  pub losses: i64,
+  pub draws: i64,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GameWithMoves {
   pub game: Game,
@@ -143,21 +313,51 @@ pub struct GameWithMoves {
 }
 
 pub trait Db {
+  /// Checks that the database is reachable.
+  async fn ping(&self) -> Result<()>;
   async fn get_or_create_player<R: Rng>(&self, oidc_player: OidcPlayer, rng: &mut R) -> Result<Player>;
   #[cfg(feature = "test")]
   async fn get_or_create_test_player(&self, name: String) -> Result<Player>;
+  async fn get_or_create_bot_player(&self, name: String) -> Result<Player>;
   async fn get_player(&self, player_id: Uuid) -> Result<Player>;
   async fn get_players(&self, player_ids: &[Uuid]) -> Result<Vec<Player>>;
   async fn create_game(&self, game: Game, opening_moves: Vec<Move>) -> Result<()>;
   async fn create_move(&self, m: Move) -> Result<()>;
   async fn create_move_and_set_result(&self, m: Move, result: GameResult) -> Result<()>;
+  /// Deletes moves of a game starting from the given number after a takeback.
+  async fn delete_moves(&self, game_id: Uuid, from_number: i16) -> Result<()>;
   async fn create_draw_offer(&self, draw_offer: DrawOffer) -> Result<()>;
+  async fn create_tournament(&self, tournament: Tournament) -> Result<()>;
+  async fn add_tournament_player(
+    &self,
+    tournament_id: Uuid,
+    player_id: Uuid,
+    registration_time: PrimitiveDateTime,
+  ) -> Result<()>;
+  async fn remove_tournament_player(&self, tournament_id: Uuid, player_id: Uuid) -> Result<()>;
+  async fn create_tournament_pairings(&self, pairings: Vec<TournamentPairing>) -> Result<()>;
+  async fn set_tournament_status(&self, tournament_id: Uuid, status: TournamentStatus) -> Result<()>;
+  async fn create_chat_message(&self, chat_message: ChatMessage) -> Result<()>;
+  /// The most recent chat messages of a game or the lobby, the oldest first.
+  async fn get_chat_messages(&self, game_id: Option<Uuid>, limit: i64) -> Result<Vec<ChatMessage>>;
   async fn set_result(&self, game_id: Uuid, finish_time: PrimitiveDateTime, result: GameResult) -> Result<()>;
   async fn update_player_nickname(&self, player_id: Uuid, nickname: String) -> Result<()>;
+  #[cfg(all(test, feature = "test", feature = "in-memory"))]
+  async fn set_player_admin(&self, player_id: Uuid, admin: bool) -> Result<()>;
+  async fn set_player_banned(&self, player_id: Uuid, banned: bool) -> Result<()>;
+  async fn set_player_muted_until(&self, player_id: Uuid, muted_until: Option<PrimitiveDateTime>) -> Result<()>;
+  async fn add_audit_log_entry(&self, entry: AuditLogEntry) -> Result<()>;
+  /// The most recent audit log entries, the newest first.
+  async fn get_audit_log(&self, offset: i64, limit: i64) -> Result<Vec<AuditLogEntry>>;
   async fn is_nickname_available(&self, nickname: String) -> Result<bool>;
   async fn get_game(&self, game_id: Uuid) -> Result<GameWithMoves>;
+  /// Finished games of a player matching the filter, the most recent first.
+  async fn get_player_games(&self, player_id: Uuid, filter: GamesFilter, offset: i64, limit: i64) -> Result<Vec<Game>>;
+  /// Updates ratings of both players of a game and records them in the rating history.
   async fn update_ratings(
     &self,
+    game_id: Uuid,
+    timestamp: PrimitiveDateTime,
     player1_id: Uuid,
     player1_rating: f64,
     player1_deviation: f64,
@@ -167,4 +367,21 @@ pub trait Db {
     player2_deviation: f64,
     player2_volatility: f64,
   ) -> Result<()>;
+  /// Players with at least one rated game, the highest rated first.
+  async fn get_leaderboard(&self, offset: i64, limit: i64) -> Result<Vec<Player>>;
+  /// Rating history of a player, the oldest first.
+  async fn get_rating_history(&self, player_id: Uuid) -> Result<Vec<RatingHistoryEntry>>;
+  async fn get_player_stats(&self, player_id: Uuid) -> Result<PlayerStats>;
+  /// Opponents a player has finished the most games with.
+  async fn get_top_opponents(&self, player_id: Uuid, limit: i64) -> Result<Vec<OpponentStats>>;
+  /// Adds a finished game to the queue of games waiting for engine analysis.
+  async fn enqueue_analysis(&self, game_id: Uuid, enqueue_time: PrimitiveDateTime) -> Result<()>;
+  /// Games waiting for engine analysis, the oldest first.
+  async fn get_analysis_queue(&self) -> Result<Vec<Uuid>>;
+  /// Stores the analysis of a game and removes it from the queue.
+  async fn set_game_analysis(&self, game_id: Uuid, analysis: Vec<MoveAnalysis>) -> Result<()>;
+  /// Removes a game the engine failed to analyze from the queue.
+  async fn drop_analysis(&self, game_id: Uuid) -> Result<()>;
+  /// Analysis of a game ordered by move number, empty if it's not analyzed.
+  async fn get_game_analysis(&self, game_id: Uuid) -> Result<Vec<MoveAnalysis>>;
 }
@@ -2,6 +2,7 @@ use super::*;
 
 use anyhow::{Result, anyhow};
 use rand::Rng;
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::sync::Arc;
 use time::PrimitiveDateTime;
@@ -21,6 +22,52 @@ struct DbState {
   moves: HashMap<Uuid, Vec<Move>>,
   /// Maps Game ID -> List of Draw Offers
   draw_offers: HashMap<Uuid, Vec<DrawOffer>>,
+  /// Maps Tournament ID -> Tournament Data
+  tournaments: HashMap<Uuid, Tournament>,
+  /// Maps Tournament ID -> List of registered Player IDs
+  tournament_players: HashMap<Uuid, Vec<Uuid>>,
+  /// Maps Tournament ID -> List of Pairings
+  tournament_pairings: HashMap<Uuid, Vec<TournamentPairing>>,
+  /// Chat messages in the order they were sent
+  chat_messages: Vec<ChatMessage>,
+  /// Admin actions in the order they were made
+  audit_log: Vec<AuditLogEntry>,
+  /// Ratings after every rated game in the order they were recorded
+  rating_history: Vec<RatingHistoryEntry>,
+  /// Games waiting for analysis with their enqueue time
+  analysis_queue: HashMap<Uuid, PrimitiveDateTime>,
+  /// Maps Game ID -> Analysis of its moves
+  move_analysis: HashMap<Uuid, Vec<MoveAnalysis>>,
+}
+
+fn outcome(game: &Game, player_id: Uuid) -> Option<Outcome> {
+  // Every result except draws is named after the player who lost.
+  let red_won = match game.result? {
+    GameResult::ResignedRed | GameResult::GroundedRed | GameResult::TimeOutRed => false,
+    GameResult::ResignedBlack | GameResult::GroundedBlack | GameResult::TimeOutBlack => true,
+    GameResult::DrawAgreement | GameResult::DrawGrounded => return Some(Outcome::Draw),
+    GameResult::Aborted => return None,
+  };
+  Some(if red_won == (game.red_player_id == player_id) {
+    Outcome::Win
+  } else {
+    Outcome::Loss
+  })
+}
+
+fn matches(game: &Game, player_id: Uuid, filter: &GamesFilter) -> bool {
+  (game.red_player_id == player_id || game.black_player_id == player_id)
+    && game.result.is_some()
+    && filter
+      .outcome
+      .is_none_or(|expected| outcome(game, player_id) == Some(expected))
+    && filter
+      .opponent_id
+      .is_none_or(|opponent_id| game.red_player_id == opponent_id || game.black_player_id == opponent_id)
+    && filter.width.is_none_or(|width| game.width == width)
+    && filter.height.is_none_or(|height| game.height == height)
+    && filter.from.is_none_or(|from| game.start_time >= from)
+    && filter.to.is_none_or(|to| game.start_time < to)
 }
 
 #[derive(Clone, Default)]
@@ -29,6 +76,10 @@ pub struct InMemoryDb {
 }
 
 impl Db for InMemoryDb {
+  async fn ping(&self) -> Result<()> {
+    Ok(())
+  }
+
   async fn get_or_create_player<R: Rng>(&self, oidc_player: OidcPlayer, rng: &mut R) -> Result<Player> {
     let mut state = self.state.write().await;
 
@@ -46,6 +97,10 @@ impl Db for InMemoryDb {
       rating: 1500.0,
       deviation: 350.0,
       volatility: 0.06,
+      bot: false,
+      admin: false,
+      banned: false,
+      muted_until: None,
     };
 
     state.oidc_lookup.insert(oidc_player.subject.clone(), id);
@@ -69,6 +124,39 @@ impl Db for InMemoryDb {
       rating: 1500.0,
       deviation: 350.0,
       volatility: 0.06,
+      bot: false,
+      admin: false,
+      banned: false,
+      muted_until: None,
+    };
+
+    state.players.insert(id, player.clone());
+
+    Ok(player)
+  }
+
+  async fn get_or_create_bot_player(&self, name: String) -> Result<Player> {
+    let mut state = self.state.write().await;
+
+    if let Some(player) = state.players.values().find(|player| player.nickname == name) {
+      return if player.bot {
+        Ok(player.clone())
+      } else {
+        Err(anyhow!("Nickname {} is taken by a human player", name))
+      };
+    }
+
+    let id = Uuid::new_v4();
+    let player = Player {
+      id,
+      nickname: name,
+      rating: 1500.0,
+      deviation: 350.0,
+      volatility: 0.06,
+      bot: true,
+      admin: false,
+      banned: false,
+      muted_until: None,
     };
 
     state.players.insert(id, player.clone());
@@ -125,6 +213,16 @@ impl Db for InMemoryDb {
     Ok(())
   }
 
+  async fn delete_moves(&self, game_id: Uuid, from_number: i16) -> Result<()> {
+    let mut state = self.state.write().await;
+
+    if let Some(moves) = state.moves.get_mut(&game_id) {
+      moves.retain(|m| m.number < from_number);
+    }
+
+    Ok(())
+  }
+
   async fn create_move_and_set_result(&self, m: Move, result: GameResult) -> Result<()> {
     let mut state = self.state.write().await;
 
@@ -162,6 +260,112 @@ impl Db for InMemoryDb {
     Ok(())
   }
 
+  async fn create_tournament(&self, tournament: Tournament) -> Result<()> {
+    let mut state = self.state.write().await;
+
+    if !state.players.contains_key(&tournament.creator_id) {
+      return Err(anyhow!("Creator ID {} not found", tournament.creator_id));
+    }
+
+    state.tournaments.insert(tournament.id, tournament);
+
+    Ok(())
+  }
+
+  async fn add_tournament_player(
+    &self,
+    tournament_id: Uuid,
+    player_id: Uuid,
+    _registration_time: PrimitiveDateTime,
+  ) -> Result<()> {
+    let mut state = self.state.write().await;
+
+    if !state.tournaments.contains_key(&tournament_id) {
+      return Err(anyhow!("Tournament ID {} not found", tournament_id));
+    }
+    if !state.players.contains_key(&player_id) {
+      return Err(anyhow!("Player ID {} not found", player_id));
+    }
+
+    let players = state.tournament_players.entry(tournament_id).or_default();
+    if players.contains(&player_id) {
+      return Err(anyhow!(
+        "Player {} is already registered for tournament {}",
+        player_id,
+        tournament_id
+      ));
+    }
+    players.push(player_id);
+
+    Ok(())
+  }
+
+  async fn remove_tournament_player(&self, tournament_id: Uuid, player_id: Uuid) -> Result<()> {
+    let mut state = self.state.write().await;
+
+    if let Some(players) = state.tournament_players.get_mut(&tournament_id) {
+      players.retain(|&id| id != player_id);
+    }
+
+    Ok(())
+  }
+
+  async fn create_tournament_pairings(&self, pairings: Vec<TournamentPairing>) -> Result<()> {
+    let mut state = self.state.write().await;
+
+    for pairing in pairings {
+      if !state.tournaments.contains_key(&pairing.tournament_id) {
+        return Err(anyhow!("Tournament ID {} not found", pairing.tournament_id));
+      }
+      state
+        .tournament_pairings
+        .entry(pairing.tournament_id)
+        .or_default()
+        .push(pairing);
+    }
+
+    Ok(())
+  }
+
+  async fn set_tournament_status(&self, tournament_id: Uuid, status: TournamentStatus) -> Result<()> {
+    let mut state = self.state.write().await;
+
+    if let Some(tournament) = state.tournaments.get_mut(&tournament_id) {
+      tournament.status = status;
+      Ok(())
+    } else {
+      Err(anyhow!("Tournament ID {} not found", tournament_id))
+    }
+  }
+
+  async fn create_chat_message(&self, chat_message: ChatMessage) -> Result<()> {
+    let mut state = self.state.write().await;
+
+    if let Some(game_id) = chat_message.game_id
+      && !state.games.contains_key(&game_id)
+    {
+      return Err(anyhow!("Game ID {} not found for chat message", game_id));
+    }
+
+    state.chat_messages.push(chat_message);
+
+    Ok(())
+  }
+
+  async fn get_chat_messages(&self, game_id: Option<Uuid>, limit: i64) -> Result<Vec<ChatMessage>> {
+    let state = self.state.read().await;
+    let mut chat_messages: Vec<ChatMessage> = state
+      .chat_messages
+      .iter()
+      .rev()
+      .filter(|chat_message| chat_message.game_id == game_id)
+      .take(limit.max(0) as usize)
+      .cloned()
+      .collect();
+    chat_messages.reverse();
+    Ok(chat_messages)
+  }
+
   async fn set_result(&self, game_id: Uuid, finish_time: PrimitiveDateTime, result: GameResult) -> Result<()> {
     let mut state = self.state.write().await;
 
@@ -190,6 +394,60 @@ impl Db for InMemoryDb {
     }
   }
 
+  #[cfg(all(test, feature = "test", feature = "in-memory"))]
+  async fn set_player_admin(&self, player_id: Uuid, admin: bool) -> Result<()> {
+    let mut state = self.state.write().await;
+
+    if let Some(player) = state.players.get_mut(&player_id) {
+      player.admin = admin;
+      Ok(())
+    } else {
+      Err(anyhow!("Player with ID {} not found", player_id))
+    }
+  }
+
+  async fn set_player_banned(&self, player_id: Uuid, banned: bool) -> Result<()> {
+    let mut state = self.state.write().await;
+
+    if let Some(player) = state.players.get_mut(&player_id) {
+      player.banned = banned;
+      Ok(())
+    } else {
+      Err(anyhow!("Player with ID {} not found", player_id))
+    }
+  }
+
+  async fn set_player_muted_until(&self, player_id: Uuid, muted_until: Option<PrimitiveDateTime>) -> Result<()> {
+    let mut state = self.state.write().await;
+
+    if let Some(player) = state.players.get_mut(&player_id) {
+      player.muted_until = muted_until;
+      Ok(())
+    } else {
+      Err(anyhow!("Player with ID {} not found", player_id))
+    }
+  }
+
+  async fn add_audit_log_entry(&self, entry: AuditLogEntry) -> Result<()> {
+    let mut state = self.state.write().await;
+    state.audit_log.push(entry);
+    Ok(())
+  }
+
+  async fn get_audit_log(&self, offset: i64, limit: i64) -> Result<Vec<AuditLogEntry>> {
+    let state = self.state.read().await;
+    Ok(
+      state
+        .audit_log
+        .iter()
+        .rev()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .cloned()
+        .collect(),
+    )
+  }
+
   async fn is_nickname_available(&self, nickname: String) -> Result<bool> {
     let state = self.state.read().await;
     Ok(!state.players.values().any(|player| player.nickname == nickname))
@@ -210,8 +468,30 @@ impl Db for InMemoryDb {
     }
   }
 
+  async fn get_player_games(&self, player_id: Uuid, filter: GamesFilter, offset: i64, limit: i64) -> Result<Vec<Game>> {
+    let state = self.state.read().await;
+
+    let mut games = state
+      .games
+      .values()
+      .filter(|game| matches(game, player_id, &filter))
+      .cloned()
+      .collect::<Vec<_>>();
+    games.sort_by_key(|game| Reverse(game.start_time));
+
+    Ok(
+      games
+        .into_iter()
+        .skip(offset.max(0) as usize)
+        .take(limit.max(0) as usize)
+        .collect(),
+    )
+  }
+
   async fn update_ratings(
     &self,
+    game_id: Uuid,
+    timestamp: PrimitiveDateTime,
     player1_id: Uuid,
     player1_rating: f64,
     player1_deviation: f64,
@@ -239,6 +519,144 @@ impl Db for InMemoryDb {
       return Err(anyhow!("Player with ID {} not found", player2_id));
     }
 
+    state.rating_history.push(RatingHistoryEntry {
+      game_id,
+      player_id: player1_id,
+      rating: player1_rating,
+      deviation: player1_deviation,
+      volatility: player1_volatility,
+      timestamp,
+    });
+    state.rating_history.push(RatingHistoryEntry {
+      game_id,
+      player_id: player2_id,
+      rating: player2_rating,
+      deviation: player2_deviation,
+      volatility: player2_volatility,
+      timestamp,
+    });
+
+    Ok(())
+  }
+
+  async fn get_leaderboard(&self, offset: i64, limit: i64) -> Result<Vec<Player>> {
+    let state = self.state.read().await;
+    let mut players: Vec<Player> = state
+      .players
+      .values()
+      .filter(|player| state.rating_history.iter().any(|entry| entry.player_id == player.id))
+      .cloned()
+      .collect();
+    players.sort_by(|p1, p2| p2.rating.total_cmp(&p1.rating).then(p1.id.cmp(&p2.id)));
+    Ok(
+      players
+        .into_iter()
+        .skip(offset.max(0) as usize)
+        .take(limit.max(0) as usize)
+        .collect(),
+    )
+  }
+
+  async fn get_rating_history(&self, player_id: Uuid) -> Result<Vec<RatingHistoryEntry>> {
+    let state = self.state.read().await;
+    let mut history: Vec<RatingHistoryEntry> = state
+      .rating_history
+      .iter()
+      .filter(|entry| entry.player_id == player_id)
+      .cloned()
+      .collect();
+    history.sort_by_key(|entry| entry.timestamp);
+    Ok(history)
+  }
+
+  async fn get_player_stats(&self, player_id: Uuid) -> Result<PlayerStats> {
+    let state = self.state.read().await;
+    let mut stats = PlayerStats::default();
+    for game in state.games.values() {
+      if game.red_player_id != player_id && game.black_player_id != player_id {
+        continue;
+      }
+      match outcome(game, player_id) {
+        Some(Outcome::Win) => stats.wins += 1,
+        Some(Outcome::Loss) => stats.losses += 1,
+        Some(Outcome::Draw) => stats.draws += 1,
+        None => {}
+      }
+    }
+    Ok(stats)
+  }
+
+  async fn get_top_opponents(&self, player_id: Uuid, limit: i64) -> Result<Vec<OpponentStats>> {
+    let state = self.state.read().await;
+    let mut opponents: HashMap<Uuid, OpponentStats> = HashMap::new();
+    for game in state.games.values() {
+      let opponent_id = if game.red_player_id == player_id {
+        game.black_player_id
+      } else if game.black_player_id == player_id {
+        game.red_player_id
+      } else {
+        continue;
+      };
+      let Some(outcome) = outcome(game, player_id) else {
+        continue;
+      };
+      let stats = opponents.entry(opponent_id).or_insert(OpponentStats {
+        opponent_id,
+        games: 0,
+        wins: 0,
+        losses: 0,
+        draws: 0,
+      });
+      stats.games += 1;
+      match outcome {
+        Outcome::Win => stats.wins += 1,
+        Outcome::Loss => stats.losses += 1,
+        Outcome::Draw => stats.draws += 1,
+      }
+    }
+    let mut opponents: Vec<OpponentStats> = opponents.into_values().collect();
+    opponents.sort_by(|o1, o2| o2.games.cmp(&o1.games).then(o1.opponent_id.cmp(&o2.opponent_id)));
+    opponents.truncate(limit.max(0) as usize);
+    Ok(opponents)
+  }
+
+  async fn enqueue_analysis(&self, game_id: Uuid, enqueue_time: PrimitiveDateTime) -> Result<()> {
+    let mut state = self.state.write().await;
+
+    if !state.games.contains_key(&game_id) {
+      return Err(anyhow!("Game ID {} not found for analysis", game_id));
+    }
+
+    state.analysis_queue.entry(game_id).or_insert(enqueue_time);
+
     Ok(())
   }
+
+  async fn get_analysis_queue(&self) -> Result<Vec<Uuid>> {
+    let state = self.state.read().await;
+    let mut queue: Vec<(Uuid, PrimitiveDateTime)> =
+      state.analysis_queue.iter().map(|(&id, &time)| (id, time)).collect();
+    queue.sort_by_key(|&(id, time)| (time, id));
+    Ok(queue.into_iter().map(|(id, _)| id).collect())
+  }
+
+  async fn set_game_analysis(&self, game_id: Uuid, mut analysis: Vec<MoveAnalysis>) -> Result<()> {
+    let mut state = self.state.write().await;
+
+    analysis.sort_by_key(|move_analysis| move_analysis.number);
+    state.move_analysis.insert(game_id, analysis);
+    state.analysis_queue.remove(&game_id);
+
+    Ok(())
+  }
+
+  async fn drop_analysis(&self, game_id: Uuid) -> Result<()> {
+    self.state.write().await.analysis_queue.remove(&game_id);
+    Ok(())
+  }
+
+  async fn get_game_analysis(&self, game_id: Uuid) -> Result<Vec<MoveAnalysis>> {
+    let state = self.state.read().await;
+    Ok(state.move_analysis.get(&game_id).cloned().unwrap_or_default())
+  }
 }
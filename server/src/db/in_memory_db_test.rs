@@ -0,0 +1,171 @@
+use super::*;
+
+use time::{Date, Month, PrimitiveDateTime, Time};
+use uuid::Uuid;
+
+fn time(day: u8) -> PrimitiveDateTime {
+  PrimitiveDateTime::new(
+    Date::from_calendar_date(2025, Month::January, day).unwrap(),
+    Time::MIDNIGHT,
+  )
+}
+
+async fn create_game(db: &InMemoryDb, red: Uuid, black: Uuid, day: u8, width: i32, result: Option<GameResult>) -> Uuid {
+  let id = Uuid::new_v4();
+  db.create_game(
+    Game {
+      id,
+      red_player_id: red,
+      black_player_id: black,
+      start_time: time(day),
+      width,
+      height: width,
+      total_time_ms: 300_000,
+      increment_ms: 5_000,
+      time_control: TimeControl::Fischer,
+      period_ms: 0,
+      periods: 0,
+      max_move_time_ms: None,
+      opening: Opening::Cross,
+      komi_x_2: 0,
+      rated: true,
+      finish_time: None,
+      result: None,
+    },
+    Vec::new(),
+  )
+  .await
+  .unwrap();
+  if let Some(result) = result {
+    db.set_result(id, time(day), result).await.unwrap();
+  }
+  id
+}
+
+#[tokio::test]
+async fn player_games_filter_and_pagination() {
+  let db = InMemoryDb::default();
+  let alice = db.get_or_create_bot_player("alice".to_string()).await.unwrap().id;
+  let bob = db.get_or_create_bot_player("bob".to_string()).await.unwrap().id;
+  let carol = db.get_or_create_bot_player("carol".to_string()).await.unwrap().id;
+
+  let won = create_game(&db, alice, bob, 1, 20, Some(GameResult::ResignedBlack)).await;
+  let lost = create_game(&db, bob, alice, 2, 20, Some(GameResult::TimeOutBlack)).await;
+  let drawn = create_game(&db, carol, alice, 3, 30, Some(GameResult::DrawAgreement)).await;
+  create_game(&db, alice, carol, 4, 30, None).await;
+  create_game(&db, bob, carol, 5, 30, Some(GameResult::GroundedRed)).await;
+
+  let ids = |games: Vec<Game>| games.into_iter().map(|game| game.id).collect::<Vec<_>>();
+
+  let all = db.get_player_games(alice, GamesFilter::default(), 0, 10).await.unwrap();
+  assert_eq!(ids(all), vec![drawn, lost, won]);
+
+  let page = db.get_player_games(alice, GamesFilter::default(), 1, 1).await.unwrap();
+  assert_eq!(ids(page), vec![lost]);
+
+  let filter = |outcome| GamesFilter {
+    outcome: Some(outcome),
+    ..GamesFilter::default()
+  };
+  assert_eq!(
+    ids(db.get_player_games(alice, filter(Outcome::Win), 0, 10).await.unwrap()),
+    vec![won]
+  );
+  assert_eq!(
+    ids(db.get_player_games(alice, filter(Outcome::Loss), 0, 10).await.unwrap()),
+    vec![lost]
+  );
+  assert_eq!(
+    ids(db.get_player_games(alice, filter(Outcome::Draw), 0, 10).await.unwrap()),
+    vec![drawn]
+  );
+
+  let filter = GamesFilter {
+    opponent_id: Some(bob),
+    from: Some(time(2)),
+    ..GamesFilter::default()
+  };
+  assert_eq!(
+    ids(db.get_player_games(alice, filter, 0, 10).await.unwrap()),
+    vec![lost]
+  );
+
+  let filter = GamesFilter {
+    width: Some(30),
+    height: Some(30),
+    to: Some(time(5)),
+    ..GamesFilter::default()
+  };
+  assert_eq!(
+    ids(db.get_player_games(alice, filter, 0, 10).await.unwrap()),
+    vec![drawn]
+  );
+}
+
+#[tokio::test]
+async fn leaderboard_and_profile() {
+  let db = InMemoryDb::default();
+  let alice = db.get_or_create_bot_player("alice".to_string()).await.unwrap().id;
+  let bob = db.get_or_create_bot_player("bob".to_string()).await.unwrap().id;
+  let carol = db.get_or_create_bot_player("carol".to_string()).await.unwrap().id;
+  db.get_or_create_bot_player("dave".to_string()).await.unwrap();
+
+  let first = create_game(&db, alice, bob, 1, 20, Some(GameResult::ResignedBlack)).await;
+  db.update_ratings(first, time(1), alice, 1662.0, 290.0, 0.06, bob, 1338.0, 290.0, 0.06)
+    .await
+    .unwrap();
+  let second = create_game(&db, carol, alice, 2, 20, Some(GameResult::GroundedRed)).await;
+  db.update_ratings(second, time(2), carol, 1400.0, 280.0, 0.06, alice, 1700.0, 250.0, 0.06)
+    .await
+    .unwrap();
+  create_game(&db, bob, alice, 3, 20, Some(GameResult::DrawGrounded)).await;
+  create_game(&db, alice, carol, 4, 20, None).await;
+
+  let leaderboard = db.get_leaderboard(0, 10).await.unwrap();
+  assert_eq!(
+    leaderboard.iter().map(|player| player.id).collect::<Vec<_>>(),
+    vec![alice, carol, bob]
+  );
+  let page = db.get_leaderboard(1, 1).await.unwrap();
+  assert_eq!(page.iter().map(|player| player.id).collect::<Vec<_>>(), vec![carol]);
+
+  let history = db.get_rating_history(alice).await.unwrap();
+  assert_eq!(
+    history
+      .iter()
+      .map(|entry| (entry.game_id, entry.rating))
+      .collect::<Vec<_>>(),
+    vec![(first, 1662.0), (second, 1700.0)]
+  );
+
+  assert_eq!(
+    db.get_player_stats(alice).await.unwrap(),
+    PlayerStats {
+      wins: 2,
+      losses: 0,
+      draws: 1,
+    }
+  );
+
+  let opponents = db.get_top_opponents(alice, 10).await.unwrap();
+  assert_eq!(
+    opponents,
+    vec![
+      OpponentStats {
+        opponent_id: bob,
+        games: 2,
+        wins: 1,
+        losses: 0,
+        draws: 1,
+      },
+      OpponentStats {
+        opponent_id: carol,
+        games: 1,
+        wins: 1,
+        losses: 0,
+        draws: 0,
+      },
+    ]
+  );
+  assert_eq!(db.get_top_opponents(alice, 1).await.unwrap().len(), 1);
+}
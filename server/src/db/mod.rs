@@ -1,6 +1,8 @@
 mod db_trait;
 #[cfg(feature = "in-memory")]
 mod in_memory_db;
+#[cfg(all(test, feature = "in-memory"))]
+mod in_memory_db_test;
 #[cfg(not(feature = "in-memory"))]
 mod sqlx_db;
 
@@ -13,6 +13,11 @@ pub struct SqlxDb {
 }
 
 impl Db for SqlxDb {
+  async fn ping(&self) -> Result<()> {
+    sqlx::query("SELECT 1").execute(&self.pool).await?;
+    Ok(())
+  }
+
   async fn get_or_create_player<R: Rng>(&self, oidc_player: OidcPlayer, rng: &mut R) -> Result<Player> {
     let mut tx = self.pool.begin().await?;
 
@@ -24,7 +29,8 @@ WITH updated AS (
   WHERE subject = $6
   RETURNING player_id
 )
-SELECT players.id, players.nickname, players.rating, players.deviation, players.volatility FROM updated
+SELECT players.id, players.nickname, players.rating, players.deviation, players.volatility, players.bot, players.admin, players.banned,
+  players.muted_until FROM updated
 JOIN players ON updated.player_id = players.id
 ",
     )
@@ -46,7 +52,8 @@ JOIN players ON updated.player_id = players.id
       if oidc_player.email_verified == Some(true) {
         sqlx::query_as(
           "
-SELECT players.id, players.nickname, players.rating, players.deviation, players.volatility FROM oidc_players
+SELECT players.id, players.nickname, players.rating, players.deviation, players.volatility, players.bot, players.admin, players.banned,
+  players.muted_until FROM oidc_players
 JOIN players ON oidc_players.player_id = players.id
 WHERE oidc_players.email = $1
 LIMIT 1
@@ -71,7 +78,7 @@ LIMIT 1
         "
 INSERT INTO players (id, nickname, registration_time)
 VALUES (gen_random_uuid(), unique_nickname($1), now())
-RETURNING id, nickname, rating, deviation, volatility
+RETURNING id, nickname, rating, deviation, volatility, bot, admin, banned, muted_until
 ",
       )
       .bind(nickname)
@@ -122,10 +129,46 @@ ON CONFLICT DO NOTHING
     })
   }
 
+  async fn get_or_create_bot_player(&self, name: String) -> Result<Player> {
+    let mut tx = self.pool.begin().await?;
+
+    let player: Option<Player> = sqlx::query_as(
+      "
+SELECT id, nickname, rating, deviation, volatility, bot, admin, banned, muted_until
+FROM players
+WHERE nickname = $1
+",
+    )
+    .bind(&name)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let player = match player {
+      Some(player) if player.bot => player,
+      Some(_) => anyhow::bail!("nickname {} is taken by a human player", name),
+      None => {
+        sqlx::query_as(
+          "
+INSERT INTO players (id, nickname, registration_time, bot)
+VALUES (gen_random_uuid(), $1, now(), true)
+RETURNING id, nickname, rating, deviation, volatility, bot, admin, banned, muted_until
+",
+        )
+        .bind(&name)
+        .fetch_one(&mut *tx)
+        .await?
+      }
+    };
+
+    tx.commit().await?;
+
+    Ok(player)
+  }
+
   async fn get_player(&self, player_id: Uuid) -> Result<Player> {
     sqlx::query_as(
       "
-SELECT id, nickname, rating, deviation, volatility
+SELECT id, nickname, rating, deviation, volatility, bot, admin, banned, muted_until
 FROM players
 WHERE id = $1
 ",
@@ -139,7 +182,7 @@ WHERE id = $1
   async fn get_players(&self, player_ids: &[Uuid]) -> Result<Vec<Player>> {
     sqlx::query_as(
       "
-SELECT id, nickname, rating, deviation, volatility
+SELECT id, nickname, rating, deviation, volatility, bot, admin, banned, muted_until
 FROM players
 WHERE id IN (SELECT unnest($1::uuid[]))
 ",
@@ -155,8 +198,8 @@ WHERE id IN (SELECT unnest($1::uuid[]))
 
     sqlx::query(
       "
-INSERT INTO games (id, red_player_id, black_player_id, start_time, width, height, total_time_ms, increment_ms, opening)
-VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+INSERT INTO games (id, red_player_id, black_player_id, start_time, width, height, total_time_ms, increment_ms, time_control, period_ms, periods, max_move_time_ms, opening, komi_x_2, rated)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
 ",
     )
     .bind(game.id)
@@ -167,7 +210,13 @@ VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
     .bind(game.height)
     .bind(game.total_time_ms)
     .bind(game.increment_ms)
+    .bind(game.time_control)
+    .bind(game.period_ms)
+    .bind(game.periods)
+    .bind(game.max_move_time_ms)
     .bind(game.opening)
+    .bind(game.komi_x_2)
+    .bind(game.rated)
     .execute(&mut *tx)
     .await?;
 
@@ -193,6 +242,16 @@ VALUES ($1, $2, $3, $4, $5, $6)
     Ok(())
   }
 
+  async fn delete_moves(&self, game_id: Uuid, from_number: i16) -> Result<()> {
+    sqlx::query("DELETE FROM moves WHERE game_id = $1 AND \"number\" >= $2")
+      .bind(game_id)
+      .bind(from_number)
+      .execute(&self.pool)
+      .await
+      .map_err(From::from)
+      .map(|_| ())
+  }
+
   async fn create_move_and_set_result(&self, m: Move, result: GameResult) -> Result<()> {
     let mut tx = self.pool.begin().await?;
 
@@ -264,6 +323,140 @@ VALUES ($1, $2, $3, $4)
     .map(|_| ())
   }
 
+  async fn create_tournament(&self, tournament: Tournament) -> Result<()> {
+    sqlx::query(
+      "
+INSERT INTO tournaments (id, name, creator_id, width, height, total_time_ms, increment_ms, time_control, period_ms, periods, max_move_time_ms, opening, komi_x_2, rated, format, rounds, start_time, status)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)
+",
+    )
+    .bind(tournament.id)
+    .bind(tournament.name)
+    .bind(tournament.creator_id)
+    .bind(tournament.width)
+    .bind(tournament.height)
+    .bind(tournament.total_time_ms)
+    .bind(tournament.increment_ms)
+    .bind(tournament.time_control)
+    .bind(tournament.period_ms)
+    .bind(tournament.periods)
+    .bind(tournament.max_move_time_ms)
+    .bind(tournament.opening)
+    .bind(tournament.komi_x_2)
+    .bind(tournament.rated)
+    .bind(tournament.format)
+    .bind(tournament.rounds)
+    .bind(tournament.start_time)
+    .bind(tournament.status)
+    .execute(&self.pool)
+    .await
+    .map_err(From::from)
+    .map(|_| ())
+  }
+
+  async fn add_tournament_player(
+    &self,
+    tournament_id: Uuid,
+    player_id: Uuid,
+    registration_time: PrimitiveDateTime,
+  ) -> Result<()> {
+    sqlx::query(
+      "
+INSERT INTO tournament_players (tournament_id, player_id, registration_time)
+VALUES ($1, $2, $3)
+",
+    )
+    .bind(tournament_id)
+    .bind(player_id)
+    .bind(registration_time)
+    .execute(&self.pool)
+    .await
+    .map_err(From::from)
+    .map(|_| ())
+  }
+
+  async fn remove_tournament_player(&self, tournament_id: Uuid, player_id: Uuid) -> Result<()> {
+    sqlx::query("DELETE FROM tournament_players WHERE tournament_id = $1 AND player_id = $2")
+      .bind(tournament_id)
+      .bind(player_id)
+      .execute(&self.pool)
+      .await
+      .map_err(From::from)
+      .map(|_| ())
+  }
+
+  async fn create_tournament_pairings(&self, pairings: Vec<TournamentPairing>) -> Result<()> {
+    let mut tx = self.pool.begin().await?;
+
+    for pairing in pairings {
+      sqlx::query(
+        "
+INSERT INTO tournament_pairings (tournament_id, round, red_player_id, black_player_id, game_id)
+VALUES ($1, $2, $3, $4, $5)
+",
+      )
+      .bind(pairing.tournament_id)
+      .bind(pairing.round)
+      .bind(pairing.red_player_id)
+      .bind(pairing.black_player_id)
+      .bind(pairing.game_id)
+      .execute(&mut *tx)
+      .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+  }
+
+  async fn set_tournament_status(&self, tournament_id: Uuid, status: TournamentStatus) -> Result<()> {
+    sqlx::query("UPDATE tournaments SET status = $1 WHERE id = $2")
+      .bind(status)
+      .bind(tournament_id)
+      .execute(&self.pool)
+      .await
+      .map_err(From::from)
+      .map(|_| ())
+  }
+
+  async fn create_chat_message(&self, chat_message: ChatMessage) -> Result<()> {
+    sqlx::query(
+      "
+INSERT INTO chat_messages (game_id, player_id, \"text\", \"timestamp\")
+VALUES ($1, $2, $3, $4)
+",
+    )
+    .bind(chat_message.game_id)
+    .bind(chat_message.player_id)
+    .bind(chat_message.text)
+    .bind(chat_message.timestamp)
+    .execute(&self.pool)
+    .await
+    .map_err(From::from)
+    .map(|_| ())
+  }
+
+  async fn get_chat_messages(&self, game_id: Option<Uuid>, limit: i64) -> Result<Vec<ChatMessage>> {
+    sqlx::query_as(
+      "
+SELECT game_id, player_id, \"text\", \"timestamp\"
+FROM (
+  SELECT game_id, player_id, \"text\", \"timestamp\"
+  FROM chat_messages
+  WHERE game_id IS NOT DISTINCT FROM $1
+  ORDER BY \"timestamp\" DESC
+  LIMIT $2
+) AS recent
+ORDER BY \"timestamp\"
+",
+    )
+    .bind(game_id)
+    .bind(limit)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(From::from)
+  }
+
   async fn set_result(&self, game_id: Uuid, finish_time: PrimitiveDateTime, result: GameResult) -> Result<()> {
     sqlx::query(
       "
@@ -295,6 +488,88 @@ WHERE id = $2
     .map(|_| ())
   }
 
+  #[cfg(all(test, feature = "test", feature = "in-memory"))]
+  async fn set_player_admin(&self, player_id: Uuid, admin: bool) -> Result<()> {
+    sqlx::query(
+      "
+UPDATE players SET admin = $1
+WHERE id = $2
+",
+    )
+    .bind(admin)
+    .bind(player_id)
+    .execute(&self.pool)
+    .await
+    .map_err(From::from)
+    .map(|_| ())
+  }
+
+  async fn set_player_banned(&self, player_id: Uuid, banned: bool) -> Result<()> {
+    sqlx::query(
+      "
+UPDATE players SET banned = $1
+WHERE id = $2
+",
+    )
+    .bind(banned)
+    .bind(player_id)
+    .execute(&self.pool)
+    .await
+    .map_err(From::from)
+    .map(|_| ())
+  }
+
+  async fn set_player_muted_until(&self, player_id: Uuid, muted_until: Option<PrimitiveDateTime>) -> Result<()> {
+    sqlx::query(
+      "
+UPDATE players SET muted_until = $1
+WHERE id = $2
+",
+    )
+    .bind(muted_until)
+    .bind(player_id)
+    .execute(&self.pool)
+    .await
+    .map_err(From::from)
+    .map(|_| ())
+  }
+
+  async fn add_audit_log_entry(&self, entry: AuditLogEntry) -> Result<()> {
+    sqlx::query(
+      "
+INSERT INTO audit_log (admin_id, \"action\", player_id, game_id, details, \"timestamp\")
+VALUES ($1, $2, $3, $4, $5, $6)
+",
+    )
+    .bind(entry.admin_id)
+    .bind(entry.action)
+    .bind(entry.player_id)
+    .bind(entry.game_id)
+    .bind(entry.details)
+    .bind(entry.timestamp)
+    .execute(&self.pool)
+    .await
+    .map_err(From::from)
+    .map(|_| ())
+  }
+
+  async fn get_audit_log(&self, offset: i64, limit: i64) -> Result<Vec<AuditLogEntry>> {
+    sqlx::query_as(
+      "
+SELECT admin_id, \"action\", player_id, game_id, details, \"timestamp\"
+FROM audit_log
+ORDER BY \"timestamp\" DESC
+OFFSET $1
+LIMIT $2
+",
+    )
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(From::from)
+  }
+
   async fn is_nickname_available(&self, nickname: String) -> Result<bool> {
     let count: i64 = sqlx::query_scalar(
       "
@@ -309,7 +584,7 @@ SELECT COUNT(*) FROM players WHERE nickname = $1
 
   async fn get_game(&self, game_id: Uuid) -> Result<GameWithMoves> {
     let game = sqlx::query_as::<_, Game>(
-      "SELECT id, red_player_id, black_player_id, start_time, width, height, total_time_ms, increment_ms, opening, result, finish_time FROM games WHERE id = $1"
+      "SELECT id, red_player_id, black_player_id, start_time, width, height, total_time_ms, increment_ms, time_control, period_ms, periods, max_move_time_ms, opening, komi_x_2, rated, result, finish_time FROM games WHERE id = $1"
     )
     .bind(game_id)
     .fetch_one(&self.pool)
@@ -325,8 +600,45 @@ SELECT COUNT(*) FROM players WHERE nickname = $1
     Ok(GameWithMoves { game, moves })
   }
 
+  async fn get_player_games(&self, player_id: Uuid, filter: GamesFilter, offset: i64, limit: i64) -> Result<Vec<Game>> {
+    sqlx::query_as(
+      "
+SELECT id, red_player_id, black_player_id, start_time, width, height, total_time_ms, increment_ms, time_control, period_ms, periods, max_move_time_ms, opening, komi_x_2, rated, result, finish_time
+FROM games
+WHERE (red_player_id = $1 OR black_player_id = $1)
+  AND \"result\" IS NOT NULL
+  AND ($2::text IS NULL OR $2 = game_outcome(\"result\", red_player_id, $1))
+  AND ($3::uuid IS NULL OR red_player_id = $3 OR black_player_id = $3)
+  AND ($4::integer IS NULL OR width = $4)
+  AND ($5::integer IS NULL OR height = $5)
+  AND ($6::timestamp IS NULL OR start_time >= $6)
+  AND ($7::timestamp IS NULL OR start_time < $7)
+ORDER BY start_time DESC
+LIMIT $8 OFFSET $9
+",
+    )
+    .bind(player_id)
+    .bind(filter.outcome.map(|outcome| match outcome {
+      Outcome::Win => "win",
+      Outcome::Loss => "loss",
+      Outcome::Draw => "draw",
+    }))
+    .bind(filter.opponent_id)
+    .bind(filter.width)
+    .bind(filter.height)
+    .bind(filter.from)
+    .bind(filter.to)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(From::from)
+  }
+
   async fn update_ratings(
     &self,
+    game_id: Uuid,
+    timestamp: PrimitiveDateTime,
     player1_id: Uuid,
     player1_rating: f64,
     player1_deviation: f64,
@@ -354,8 +666,185 @@ SELECT COUNT(*) FROM players WHERE nickname = $1
       .execute(&mut *tx)
       .await?;
 
+    sqlx::query(
+      "
+INSERT INTO rating_history (game_id, player_id, rating, deviation, volatility, \"timestamp\")
+VALUES ($1, $3, $4, $5, $6, $2), ($1, $7, $8, $9, $10, $2)
+",
+    )
+    .bind(game_id)
+    .bind(timestamp)
+    .bind(player1_id)
+    .bind(player1_rating)
+    .bind(player1_deviation)
+    .bind(player1_volatility)
+    .bind(player2_id)
+    .bind(player2_rating)
+    .bind(player2_deviation)
+    .bind(player2_volatility)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+  }
+
+  async fn get_leaderboard(&self, offset: i64, limit: i64) -> Result<Vec<Player>> {
+    sqlx::query_as(
+      "
+SELECT id, nickname, rating, deviation, volatility, bot, admin, banned, muted_until
+FROM players
+WHERE EXISTS (SELECT 1 FROM rating_history WHERE player_id = players.id)
+ORDER BY rating DESC, id
+OFFSET $1
+LIMIT $2
+",
+    )
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(From::from)
+  }
+
+  async fn get_rating_history(&self, player_id: Uuid) -> Result<Vec<RatingHistoryEntry>> {
+    sqlx::query_as(
+      "
+SELECT game_id, player_id, rating, deviation, volatility, \"timestamp\"
+FROM rating_history
+WHERE player_id = $1
+ORDER BY \"timestamp\"
+",
+    )
+    .bind(player_id)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(From::from)
+  }
+
+  async fn get_player_stats(&self, player_id: Uuid) -> Result<PlayerStats> {
+    sqlx::query_as(
+      "
+SELECT
+  COUNT(*) FILTER (WHERE outcome = 'win') AS wins,
+  COUNT(*) FILTER (WHERE outcome = 'loss') AS losses,
+  COUNT(*) FILTER (WHERE outcome = 'draw') AS draws
+FROM (
+  SELECT game_outcome(\"result\", red_player_id, $1) AS outcome
+  FROM games
+  WHERE red_player_id = $1 OR black_player_id = $1
+) AS outcomes
+",
+    )
+    .bind(player_id)
+    .fetch_one(&self.pool)
+    .await
+    .map_err(From::from)
+  }
+
+  async fn get_top_opponents(&self, player_id: Uuid, limit: i64) -> Result<Vec<OpponentStats>> {
+    sqlx::query_as(
+      "
+SELECT
+  opponent_id,
+  COUNT(*) AS games,
+  COUNT(*) FILTER (WHERE outcome = 'win') AS wins,
+  COUNT(*) FILTER (WHERE outcome = 'loss') AS losses,
+  COUNT(*) FILTER (WHERE outcome = 'draw') AS draws
+FROM (
+  SELECT
+    CASE WHEN red_player_id = $1 THEN black_player_id ELSE red_player_id END AS opponent_id,
+    game_outcome(\"result\", red_player_id, $1) AS outcome
+  FROM games
+  WHERE (red_player_id = $1 OR black_player_id = $1) AND \"result\" IS NOT NULL
+) AS outcomes
+GROUP BY opponent_id
+ORDER BY games DESC, opponent_id
+LIMIT $2
+",
+    )
+    .bind(player_id)
+    .bind(limit)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(From::from)
+  }
+
+  async fn enqueue_analysis(&self, game_id: Uuid, enqueue_time: PrimitiveDateTime) -> Result<()> {
+    sqlx::query(
+      "
+INSERT INTO analysis_queue (game_id, enqueue_time)
+VALUES ($1, $2)
+ON CONFLICT DO NOTHING
+",
+    )
+    .bind(game_id)
+    .bind(enqueue_time)
+    .execute(&self.pool)
+    .await
+    .map_err(From::from)
+    .map(|_| ())
+  }
+
+  async fn get_analysis_queue(&self) -> Result<Vec<Uuid>> {
+    sqlx::query_scalar("SELECT game_id FROM analysis_queue ORDER BY enqueue_time, game_id")
+      .fetch_all(&self.pool)
+      .await
+      .map_err(From::from)
+  }
+
+  async fn set_game_analysis(&self, game_id: Uuid, analysis: Vec<MoveAnalysis>) -> Result<()> {
+    let mut tx = self.pool.begin().await?;
+
+    for move_analysis in analysis {
+      sqlx::query(
+        "
+INSERT INTO move_analysis (game_id, \"number\", winrate, best_x, best_y)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (game_id, \"number\") DO UPDATE
+SET winrate = EXCLUDED.winrate, best_x = EXCLUDED.best_x, best_y = EXCLUDED.best_y
+",
+      )
+      .bind(move_analysis.game_id)
+      .bind(move_analysis.number)
+      .bind(move_analysis.winrate)
+      .bind(move_analysis.best_x)
+      .bind(move_analysis.best_y)
+      .execute(&mut *tx)
+      .await?;
+    }
+
+    sqlx::query("DELETE FROM analysis_queue WHERE game_id = $1")
+      .bind(game_id)
+      .execute(&mut *tx)
+      .await?;
+
     tx.commit().await?;
 
     Ok(())
   }
+
+  async fn drop_analysis(&self, game_id: Uuid) -> Result<()> {
+    sqlx::query("DELETE FROM analysis_queue WHERE game_id = $1")
+      .bind(game_id)
+      .execute(&self.pool)
+      .await?;
+    Ok(())
+  }
+
+  async fn get_game_analysis(&self, game_id: Uuid) -> Result<Vec<MoveAnalysis>> {
+    sqlx::query_as(
+      "
+SELECT game_id, \"number\", winrate, best_x, best_y
+FROM move_analysis
+WHERE game_id = $1
+ORDER BY \"number\"
+",
+    )
+    .bind(game_id)
+    .fetch_all(&self.pool)
+    .await
+    .map_err(From::from)
+  }
 }
@@ -0,0 +1,279 @@
+use crate::{
+  Session,
+  db::{self, Db},
+  ids::GameId,
+  message::{
+    Coordinate, FieldSize, GameConfig, GameResult, GameTime, Opening, Request, Response, TimeControl, WinReason,
+  },
+  state::{GameState, State},
+  test_utils::{connect, shared, wait_for},
+};
+use futures::channel::mpsc::Receiver;
+use oppai_field::player::Player;
+use rand::rngs::StdRng;
+use std::{sync::Arc, time::Duration};
+
+fn game_config(komi_x_2: i32, rated: bool) -> GameConfig {
+  GameConfig {
+    size: FieldSize { width: 10, height: 10 },
+    time: GameTime {
+      total: Duration::from_secs(60),
+      control: TimeControl::Fischer {
+        increment: Duration::ZERO,
+      },
+      max_per_move: None,
+    },
+    opening: Opening::Cross,
+    komi_x_2,
+    rated,
+  }
+}
+
+/// Red grounds right after the opening, conceding its two opening points.
+async fn ground_after_opening(komi_x_2: i32) -> GameResult {
+  let state = Arc::new(State::default());
+  let shared = shared();
+
+  let (mut alice, mut alice_rx) = connect(&state, &shared, "alice").await;
+  let (mut bob, _bob_rx) = connect(&state, &shared, "bob").await;
+
+  alice
+    .handle(
+      &state,
+      Request::Create {
+        config: game_config(komi_x_2, true),
+      },
+    )
+    .await
+    .unwrap();
+  let game_id = wait_for(&mut alice_rx, |response| match response {
+    Response::Create { game_id, .. } => Some(game_id),
+    _ => None,
+  })
+  .await;
+  bob.handle(&state, Request::Join { game_id }).await.unwrap();
+  alice.handle(&state, Request::Subscribe { game_id }).await.unwrap();
+
+  assert!(bob.handle(&state, Request::Ground { game_id }).await.is_err());
+  alice.handle(&state, Request::Ground { game_id }).await.unwrap();
+
+  let result = wait_for(&mut alice_rx, |response| match response {
+    Response::GameResult {
+      game_id: id, result, ..
+    } if id == game_id => Some(result),
+    _ => None,
+  })
+  .await;
+  assert!(state.games.pin().get(&game_id).is_none());
+  let stored = shared.db.get_game(game_id.0).await.unwrap().game.result.unwrap();
+  assert_eq!(GameResult::from(stored), result);
+  result
+}
+
+#[tokio::test]
+async fn ground_concedes_non_grounded_points() {
+  assert_eq!(
+    ground_after_opening(3).await,
+    GameResult::Win {
+      winner: Player::Black,
+      reason: WinReason::Grounded,
+    }
+  );
+  assert_eq!(
+    ground_after_opening(5).await,
+    GameResult::Win {
+      winner: Player::Red,
+      reason: WinReason::Grounded,
+    }
+  );
+}
+
+// The stored result names the player who resigned, and reads back as the announced one.
+#[tokio::test]
+async fn resign_stores_the_loser() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+
+  let (mut alice, mut alice_rx) = connect(&state, &shared, "alice").await;
+  let (mut bob, _bob_rx) = connect(&state, &shared, "bob").await;
+
+  alice
+    .handle(
+      &state,
+      Request::Create {
+        config: game_config(0, true),
+      },
+    )
+    .await
+    .unwrap();
+  let game_id = wait_for(&mut alice_rx, |response| match response {
+    Response::Create { game_id, .. } => Some(game_id),
+    _ => None,
+  })
+  .await;
+  bob.handle(&state, Request::Join { game_id }).await.unwrap();
+  alice.handle(&state, Request::Subscribe { game_id }).await.unwrap();
+  alice.handle(&state, Request::Resign { game_id }).await.unwrap();
+
+  let result = wait_for(&mut alice_rx, |response| match response {
+    Response::GameResult {
+      game_id: id, result, ..
+    } if id == game_id => Some(result),
+    _ => None,
+  })
+  .await;
+  assert_eq!(
+    result,
+    GameResult::Win {
+      winner: Player::Black,
+      reason: WinReason::Resigned,
+    }
+  );
+  let stored = shared.db.get_game(game_id.0).await.unwrap().game.result.unwrap();
+  assert_eq!(stored, db::GameResult::ResignedRed);
+  assert_eq!(GameResult::from(stored), result);
+}
+
+async fn put_point(session: &mut Session<StdRng>, state: &Arc<State>, game_id: GameId, x: u32, y: u32) {
+  session
+    .handle(
+      state,
+      Request::PutPoint {
+        game_id,
+        coordinate: Coordinate { x, y },
+      },
+    )
+    .await
+    .unwrap();
+}
+
+async fn undone_moves(rx: &mut Receiver<Response>) -> u32 {
+  wait_for(rx, |response| match response {
+    Response::Undo { moves, .. } => Some(moves),
+    _ => None,
+  })
+  .await
+}
+
+#[tokio::test]
+async fn takeback_in_unrated_game() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+
+  let (mut alice, mut alice_rx) = connect(&state, &shared, "alice").await;
+  let (mut bob, _bob_rx) = connect(&state, &shared, "bob").await;
+  let alice_id = alice.player_id.unwrap();
+
+  alice
+    .handle(
+      &state,
+      Request::Create {
+        config: game_config(0, false),
+      },
+    )
+    .await
+    .unwrap();
+  let game_id = wait_for(&mut alice_rx, |response| match response {
+    Response::Create { game_id, .. } => Some(game_id),
+    _ => None,
+  })
+  .await;
+  bob.handle(&state, Request::Join { game_id }).await.unwrap();
+  alice.handle(&state, Request::Subscribe { game_id }).await.unwrap();
+  let game_state = state.games.pin().get(&game_id).unwrap().state.clone();
+  let opening_moves = game_state.read().await.opening_moves;
+
+  // Opening moves can't be taken back.
+  alice.handle(&state, Request::Takeback { game_id }).await.unwrap();
+  assert_eq!(game_state.read().await.takeback_offer, None);
+
+  put_point(&mut alice, &state, game_id, 1, 1).await;
+  put_point(&mut bob, &state, game_id, 8, 8).await;
+
+  alice.handle(&state, Request::Takeback { game_id }).await.unwrap();
+  let (player, offer) = wait_for(&mut alice_rx, |response| match response {
+    Response::Takeback { player, offer, .. } => Some((player, offer)),
+    _ => None,
+  })
+  .await;
+  assert_eq!((player, offer), (Player::Red, true));
+  bob.handle(&state, Request::Takeback { game_id }).await.unwrap();
+  assert_eq!(undone_moves(&mut alice_rx).await, 2);
+  assert_eq!(game_state.read().await.field.moves_count(), opening_moves);
+
+  put_point(&mut alice, &state, game_id, 1, 2).await;
+  alice.handle(&state, Request::Takeback { game_id }).await.unwrap();
+  bob.handle(&state, Request::Takeback { game_id }).await.unwrap();
+  assert_eq!(undone_moves(&mut alice_rx).await, 1);
+  assert_eq!(game_state.read().await.field.moves_count(), opening_moves);
+  assert_eq!(shared.db.get_game(game_id.0).await.unwrap().moves.len(), opening_moves);
+
+  alice.handle(&state, Request::Resign { game_id }).await.unwrap();
+  wait_for(&mut alice_rx, |response| match response {
+    Response::GameResult { game_id: id, .. } if id == game_id => Some(()),
+    _ => None,
+  })
+  .await;
+  assert!(shared.db.get_rating_history(alice_id.0).await.unwrap().is_empty());
+
+  alice
+    .handle(
+      &state,
+      Request::Create {
+        config: game_config(0, true),
+      },
+    )
+    .await
+    .unwrap();
+  let game_id = wait_for(&mut alice_rx, |response| match response {
+    Response::Create { game_id, .. } => Some(game_id),
+    _ => None,
+  })
+  .await;
+  bob.handle(&state, Request::Join { game_id }).await.unwrap();
+  put_point(&mut alice, &state, game_id, 1, 1).await;
+  assert!(alice.handle(&state, Request::Takeback { game_id }).await.is_err());
+}
+
+#[tokio::test]
+async fn takeback_restores_clocks() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+
+  let (mut alice, mut alice_rx) = connect(&state, &shared, "alice").await;
+  let (mut bob, _bob_rx) = connect(&state, &shared, "bob").await;
+
+  let mut config = game_config(0, false);
+  config.time.control = TimeControl::Fischer {
+    increment: Duration::from_secs(5),
+  };
+  alice.handle(&state, Request::Create { config }).await.unwrap();
+  let game_id = wait_for(&mut alice_rx, |response| match response {
+    Response::Create { game_id, .. } => Some(game_id),
+    _ => None,
+  })
+  .await;
+  bob.handle(&state, Request::Join { game_id }).await.unwrap();
+  alice.handle(&state, Request::Subscribe { game_id }).await.unwrap();
+  let game = state.games.pin().get(&game_id).unwrap().clone();
+  let initial_clock = game.config.time.clock();
+  let non_grounded = |game_state: &GameState| (game_state.field.non_grounded_red, game_state.field.non_grounded_black);
+  let initial_non_grounded = non_grounded(&*game.state.read().await);
+
+  put_point(&mut alice, &state, game_id, 1, 1).await;
+  put_point(&mut bob, &state, game_id, 8, 8).await;
+  assert!(game.state.read().await.red_clock.main > initial_clock.main);
+
+  alice.handle(&state, Request::Takeback { game_id }).await.unwrap();
+  bob.handle(&state, Request::Takeback { game_id }).await.unwrap();
+  assert_eq!(undone_moves(&mut alice_rx).await, 2);
+
+  // Both increments are gone, and only the time Alice spent since Bob's move
+  // is charged to her.
+  let game_state = game.state.read().await;
+  assert_eq!(game_state.black_clock, initial_clock);
+  assert!(game_state.red_clock.main <= initial_clock.main);
+  assert!(game_state.red_clock.main > initial_clock.main - Duration::from_secs(5));
+  assert!(game_state.move_clocks.is_empty());
+  assert_eq!(non_grounded(&game_state), initial_non_grounded);
+}
@@ -13,3 +13,7 @@ pub struct PlayerId(pub Uuid);
 #[derive(Debug, Display, PartialEq, Eq, Clone, Copy, Hash, Default, From, Into, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct GameId(pub Uuid);
+
+#[derive(Debug, Display, PartialEq, Eq, Clone, Copy, Hash, Default, From, Into, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TournamentId(pub Uuid);
@@ -2,7 +2,7 @@ use crate::db::Db;
 use anyhow::{Error, Result};
 use cookie::time::{Duration as CookieDuration, OffsetDateTime};
 use cookie::{Cookie, CookieJar, Expiration, Key, SameSite};
-use futures::channel::mpsc::{self, Sender};
+use futures::channel::mpsc::{self, Sender, UnboundedSender};
 use futures_util::{FutureExt, SinkExt, StreamExt, select};
 use ids::*;
 use itertools::Itertools;
@@ -11,8 +11,9 @@ use openidconnect::{
   OAuth2TokenResponse, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, TokenResponse,
   core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata},
 };
+use oppai_client::Client;
 use oppai_field::{field::Field, player::Player};
-use oppai_initial::initial::InitialPosition;
+use oppai_initial::opening::{Opening, Placement};
 use rand::make_rng;
 use rand::{Rng, RngExt, SeedableRng, rngs::StdRng};
 use serde::{Deserialize, Serialize};
@@ -22,8 +23,11 @@ use skillratings::{
 };
 #[cfg(not(feature = "in-memory"))]
 use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
-use state::{FieldSize, Game, GameConfig, GameState, GameTime, OpenGame, State};
-use std::collections::{HashMap, HashSet};
+use state::{
+  Challenge, FieldSize, Game, GameConfig, GameState, GameTime, MatchPreferences, OpenGame, QueueEntry, State,
+};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 use time::PrimitiveDateTime;
@@ -36,11 +40,41 @@ use tokio_tungstenite::tungstenite::Message;
 use tokio_tungstenite::tungstenite::handshake::server::Request;
 use uuid::Builder;
 
+mod analysis;
+#[cfg(all(test, feature = "test", feature = "in-memory"))]
+mod analysis_test;
+mod bot;
+#[cfg(all(test, feature = "test", feature = "in-memory"))]
+mod bot_test;
+#[cfg(all(test, feature = "test", feature = "in-memory"))]
+mod challenge_test;
+#[cfg(all(test, feature = "test", feature = "in-memory"))]
+mod chat_test;
 mod config;
 mod db;
+#[cfg(all(test, feature = "test", feature = "in-memory"))]
+mod game_test;
 mod ids;
+mod matchmaking;
+#[cfg(all(test, feature = "test", feature = "in-memory"))]
+mod matchmaking_test;
 mod message;
+mod metrics;
+#[cfg(all(test, feature = "test", feature = "in-memory"))]
+mod metrics_test;
+#[cfg(all(test, feature = "test", feature = "in-memory"))]
+mod moderation_test;
+mod sgf;
+#[cfg(test)]
+mod sgf_test;
 mod state;
+#[cfg(all(test, feature = "test", feature = "in-memory"))]
+mod test_utils;
+#[cfg(test)]
+mod time_control_test;
+mod tournament;
+#[cfg(all(test, feature = "test", feature = "in-memory"))]
+mod tournament_test;
 
 impl From<message::Opening> for db::Opening {
   fn from(opening: message::Opening) -> Self {
@@ -48,6 +82,8 @@ impl From<message::Opening> for db::Opening {
       message::Opening::Cross => db::Opening::Cross,
       message::Opening::TwoCrosses => db::Opening::TwoCrosses,
       message::Opening::TripleCross => db::Opening::TripleCross,
+      message::Opening::Empty => db::Opening::Empty,
+      message::Opening::FourCrosses => db::Opening::FourCrosses,
     }
   }
 }
@@ -58,18 +94,185 @@ impl From<db::Opening> for message::Opening {
       db::Opening::Cross => message::Opening::Cross,
       db::Opening::TwoCrosses => message::Opening::TwoCrosses,
       db::Opening::TripleCross => message::Opening::TripleCross,
+      db::Opening::Empty => message::Opening::Empty,
+      db::Opening::FourCrosses => message::Opening::FourCrosses,
+    }
+  }
+}
+
+impl From<db::GameResult> for message::GameResult {
+  fn from(result: db::GameResult) -> Self {
+    match result {
+      db::GameResult::ResignedRed => message::GameResult::Win {
+        winner: Player::Black,
+        reason: message::WinReason::Resigned,
+      },
+      db::GameResult::ResignedBlack => message::GameResult::Win {
+        winner: Player::Red,
+        reason: message::WinReason::Resigned,
+      },
+      db::GameResult::GroundedRed => message::GameResult::Win {
+        winner: Player::Black,
+        reason: message::WinReason::Grounded,
+      },
+      db::GameResult::GroundedBlack => message::GameResult::Win {
+        winner: Player::Red,
+        reason: message::WinReason::Grounded,
+      },
+      db::GameResult::TimeOutRed => message::GameResult::Win {
+        winner: Player::Black,
+        reason: message::WinReason::TimeOut,
+      },
+      db::GameResult::TimeOutBlack => message::GameResult::Win {
+        winner: Player::Red,
+        reason: message::WinReason::TimeOut,
+      },
+      db::GameResult::DrawAgreement => message::GameResult::Draw {
+        reason: message::DrawReason::Agreement,
+      },
+      db::GameResult::DrawGrounded => message::GameResult::Draw {
+        reason: message::DrawReason::Grounded,
+      },
+      db::GameResult::Aborted => message::GameResult::Aborted,
+    }
+  }
+}
+
+impl From<db::ModerationAction> for message::ModerationAction {
+  fn from(action: db::ModerationAction) -> Self {
+    match action {
+      db::ModerationAction::Ban => message::ModerationAction::Ban,
+      db::ModerationAction::Unban => message::ModerationAction::Unban,
+      db::ModerationAction::Mute => message::ModerationAction::Mute,
+      db::ModerationAction::Unmute => message::ModerationAction::Unmute,
+      db::ModerationAction::CloseGame => message::ModerationAction::CloseGame,
+      db::ModerationAction::Rename => message::ModerationAction::Rename,
+    }
+  }
+}
+
+impl From<db::Color> for Player {
+  fn from(color: db::Color) -> Self {
+    match color {
+      db::Color::Red => Player::Red,
+      db::Color::Black => Player::Black,
+    }
+  }
+}
+
+impl From<message::Outcome> for db::Outcome {
+  fn from(outcome: message::Outcome) -> Self {
+    match outcome {
+      message::Outcome::Win => db::Outcome::Win,
+      message::Outcome::Loss => db::Outcome::Loss,
+      message::Outcome::Draw => db::Outcome::Draw,
+    }
+  }
+}
+
+fn to_epoch(time: PrimitiveDateTime) -> Duration {
+  (time.assume_utc() - OffsetDateTime::UNIX_EPOCH)
+    .try_into()
+    .unwrap_or_default()
+}
+
+fn from_epoch(duration: Duration) -> PrimitiveDateTime {
+  let time = OffsetDateTime::from(SystemTime::UNIX_EPOCH + duration);
+  PrimitiveDateTime::new(time.date(), time.time())
+}
+
+fn to_game_config(game: &db::Game) -> message::GameConfig {
+  message::GameConfig {
+    size: message::FieldSize {
+      width: game.width as u32,
+      height: game.height as u32,
+    },
+    time: message::GameTime {
+      total: Duration::from_millis(game.total_time_ms as u64),
+      control: match game.time_control {
+        db::TimeControl::Fischer => message::TimeControl::Fischer {
+          increment: Duration::from_millis(game.increment_ms as u64),
+        },
+        db::TimeControl::Japanese => message::TimeControl::Japanese {
+          period: Duration::from_millis(game.period_ms as u64),
+          periods: game.periods as u32,
+        },
+        db::TimeControl::Canadian => message::TimeControl::Canadian {
+          period: Duration::from_millis(game.period_ms as u64),
+          moves: game.periods as u32,
+        },
+        db::TimeControl::Delay => message::TimeControl::Delay {
+          delay: Duration::from_millis(game.increment_ms as u64),
+        },
+      },
+      max_per_move: game
+        .max_move_time_ms
+        .map(|max_move_time_ms| Duration::from_millis(max_move_time_ms as u64)),
+    },
+    opening: game.opening.into(),
+    komi_x_2: game.komi_x_2,
+    rated: game.rated,
+  }
+}
+
+/// DB columns of a time control: its kind, increment or delay, period and number
+/// of periods or moves per period.
+fn to_db_time_control(control: message::TimeControl) -> (db::TimeControl, i64, i64, i32) {
+  match control {
+    message::TimeControl::Fischer { increment } => (db::TimeControl::Fischer, increment.as_millis() as i64, 0, 0),
+    message::TimeControl::Japanese { period, periods } => {
+      (db::TimeControl::Japanese, 0, period.as_millis() as i64, periods as i32)
+    }
+    message::TimeControl::Canadian { period, moves } => {
+      (db::TimeControl::Canadian, 0, period.as_millis() as i64, moves as i32)
     }
+    message::TimeControl::Delay { delay } => (db::TimeControl::Delay, delay.as_millis() as i64, 0, 0),
   }
 }
 
-fn to_initial_position(opening: message::Opening) -> InitialPosition {
-  match opening {
-    message::Opening::Cross => InitialPosition::Cross,
-    message::Opening::TwoCrosses => InitialPosition::TwoCrosses,
-    message::Opening::TripleCross => InitialPosition::TripleCross,
+/// Result of a game ended by grounding. The grounding player, if any, concedes all their non-grounded points.
+fn grounded_result(
+  field: &Field,
+  komi_x_2: i32,
+  grounding: Option<Player>,
+) -> (db::GameResult, message::GameResult, Outcomes) {
+  let conceded_x_2 = match grounding {
+    Some(Player::Red) => -(field.non_grounded_red as i32) * 2,
+    Some(Player::Black) => field.non_grounded_black as i32 * 2,
+    None => 0,
+  };
+  match (field.score(Player::Red) * 2 + komi_x_2 + conceded_x_2).cmp(&0) {
+    Ordering::Greater => (
+      db::GameResult::GroundedBlack,
+      message::GameResult::Win {
+        winner: Player::Red,
+        reason: message::WinReason::Grounded,
+      },
+      Outcomes::WIN,
+    ),
+    Ordering::Less => (
+      db::GameResult::GroundedRed,
+      message::GameResult::Win {
+        winner: Player::Black,
+        reason: message::WinReason::Grounded,
+      },
+      Outcomes::LOSS,
+    ),
+    Ordering::Equal => (
+      db::GameResult::DrawGrounded,
+      message::GameResult::Draw {
+        reason: message::DrawReason::Grounded,
+      },
+      Outcomes::DRAW,
+    ),
   }
 }
 
+/// Number of moves an opening places before the game starts.
+fn opening_moves_count(opening: db::Opening) -> usize {
+  message::Opening::from(opening).pattern().moves.len()
+}
+
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 struct CookieData {
   player_id: PlayerId,
@@ -95,15 +298,43 @@ struct SessionShared {
   http_client: reqwest::Client,
   cookie_key: Key,
   oidc: config::OidcConfig,
+  /// Maps bot tokens to bot nicknames.
+  bot_tokens: HashMap<String, String>,
+  /// Finished games for the analysis worker if it's enabled.
+  analysis: Option<UnboundedSender<GameId>>,
+  metrics: metrics::Metrics,
+  rate_limit: config::RateLimitConfig,
 }
 
 impl SessionShared {
+  /// Queues a finished game for engine analysis. Failures are only logged since
+  /// they shouldn't affect the game itself.
+  async fn enqueue_analysis(&self, game_id: GameId, timestamp: PrimitiveDateTime) {
+    let Some(analysis) = &self.analysis else {
+      return;
+    };
+    if let Err(error) = self.db.enqueue_analysis(game_id.0, timestamp).await {
+      log::error!("Failed to queue game {} for analysis: {}", game_id, error);
+      return;
+    }
+    let _ = analysis.unbounded_send(game_id);
+  }
+
+  /// Updates ratings of players after a game. Returns `None` for unrated games,
+  /// which leave ratings unchanged.
   async fn update_ratings(
     &self,
+    game_id: GameId,
+    rated: bool,
+    timestamp: PrimitiveDateTime,
     red_player_id: PlayerId,
     black_player_id: PlayerId,
     outcome: Outcomes,
-  ) -> Result<(message::Player, message::Player)> {
+  ) -> Result<Option<(message::Player, message::Player)>> {
+    if !rated {
+      return Ok(None);
+    }
+
     let [player_1, player_2] = self
       .db
       .get_players(&[red_player_id.0, black_player_id.0])
@@ -133,6 +364,8 @@ impl SessionShared {
     self
       .db
       .update_ratings(
+        game_id.0,
+        timestamp,
         red_player_id.0,
         new_red.rating,
         new_red.deviation,
@@ -149,24 +382,80 @@ impl SessionShared {
       rating: new_red.rating,
       deviation: new_red.deviation,
       volatility: new_red.volatility,
+      bot: red_player.bot,
     };
     let updated_black = message::Player {
       nickname: black_player.nickname,
       rating: new_black.rating,
       deviation: new_black.deviation,
       volatility: new_black.volatility,
+      bot: black_player.bot,
     };
 
-    Ok((updated_red, updated_black))
+    Ok(Some((updated_red, updated_black)))
   }
 }
 
+/// Time after which an unanswered challenge is closed.
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Maximum length of a chat message in characters.
+const MAX_CHAT_MESSAGE_LENGTH: usize = 500;
+
+/// Number of chat messages a connection can send within `CHAT_RATE_PERIOD`.
+const CHAT_RATE_LIMIT: usize = 5;
+
+const CHAT_RATE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Window in which requests are counted for rate limiting.
+const REQUEST_RATE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Maximum time a player can be muted for.
+const MAX_MUTE_DURATION: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Number of entries in a page of the audit log.
+const AUDIT_LOG_PAGE_SIZE: i64 = 50;
+
+/// Number of recent chat messages sent on initialization.
+const CHAT_HISTORY_SIZE: i64 = 50;
+
+/// Number of games in a page of player's games list.
+const GAMES_PAGE_SIZE: i64 = 20;
+
+/// Number of players in a page of the leaderboard.
+const LEADERBOARD_PAGE_SIZE: i64 = 50;
+
+/// Number of most played opponents in a player's profile.
+const TOP_OPPONENTS_COUNT: i64 = 10;
+
+/// Maximum length of a tournament name in characters.
+const MAX_TOURNAMENT_NAME_LENGTH: usize = 50;
+
 struct Session<R: Rng> {
   shared: Arc<SessionShared>,
   rng: R,
   connection_id: ConnectionId,
   player_id: Option<PlayerId>,
   watching: HashSet<GameId>,
+  /// Times of recently sent chat messages for rate limiting.
+  chat_times: VecDeque<SystemTime>,
+  /// Times of recent requests for rate limiting.
+  request_times: VecDeque<SystemTime>,
+}
+
+/// Records an event at `now` unless `limit` events already happened within `period`.
+fn within_rate_limit(times: &mut VecDeque<SystemTime>, now: SystemTime, limit: usize, period: Duration) -> bool {
+  while times
+    .front()
+    .is_some_and(|&time| now.duration_since(time).unwrap_or_default() >= period)
+  {
+    times.pop_front();
+  }
+  if times.len() >= limit {
+    return false;
+  }
+  times.push_back(now);
+  true
 }
 
 impl<R: Rng> Session<R> {
@@ -178,6 +467,8 @@ impl<R: Rng> Session<R> {
       connection_id,
       player_id: None,
       watching: HashSet::new(),
+      chat_times: VecDeque::new(),
+      request_times: VecDeque::new(),
     }
   }
 
@@ -307,6 +598,10 @@ impl<R: Rng> Session<R> {
       .await?;
     let player_id = PlayerId(player.id);
 
+    if player.banned {
+      anyhow::bail!("banned player {} attempted to sign in", player_id);
+    }
+
     self.player_id = Some(player_id);
     state.insert_players_connection(player_id, self.connection_id);
 
@@ -318,6 +613,7 @@ impl<R: Rng> Session<R> {
           rating: player.rating,
           deviation: player.deviation,
           volatility: player.volatility,
+          bot: player.bot,
         },
       })
       .await;
@@ -365,6 +661,10 @@ impl<R: Rng> Session<R> {
     let player = self.shared.db.get_or_create_test_player(name).await?;
     let player_id = PlayerId(player.id);
 
+    if player.banned {
+      anyhow::bail!("banned player {} attempted to sign in", player_id);
+    }
+
     self.player_id = Some(player_id);
     state.insert_players_connection(player_id, self.connection_id);
 
@@ -376,6 +676,7 @@ impl<R: Rng> Session<R> {
           rating: player.rating,
           deviation: player.deviation,
           volatility: player.volatility,
+          bot: player.bot,
         },
       })
       .await;
@@ -409,6 +710,65 @@ impl<R: Rng> Session<R> {
     Ok(())
   }
 
+  async fn auth_bot(&mut self, state: &State, token: String) -> Result<()> {
+    let nickname = self
+      .shared
+      .bot_tokens
+      .get(&token)
+      .cloned()
+      .ok_or_else(|| anyhow::anyhow!("invalid bot token from connection {}", self.connection_id))?;
+    let player = self.shared.db.get_or_create_bot_player(nickname).await?;
+    let player_id = PlayerId(player.id);
+
+    if player.banned {
+      anyhow::bail!("banned player {} attempted to sign in", player_id);
+    }
+
+    self.player_id = Some(player_id);
+    state.insert_players_connection(player_id, self.connection_id);
+
+    state
+      .send_to_all(message::Response::PlayerJoined {
+        player_id,
+        player: message::Player {
+          nickname: player.nickname,
+          rating: player.rating,
+          deviation: player.deviation,
+          volatility: player.volatility,
+          bot: player.bot,
+        },
+      })
+      .await;
+
+    let mut jar = CookieJar::new();
+    let cookie = Cookie::build((
+      "kropki",
+      serde_json::to_string(&CookieData {
+        player_id,
+        expires_at: SystemTime::now() + CookieDuration::days(1),
+      })
+      .unwrap(),
+    ))
+    .path("/")
+    .expires(Expiration::Session)
+    .same_site(SameSite::Strict)
+    .secure(true)
+    .build();
+    jar.private_mut(&self.shared.cookie_key).add(cookie);
+
+    state
+      .send_to_connection(
+        self.connection_id,
+        message::Response::Auth {
+          player_id,
+          cookie: jar.get("kropki").unwrap().to_string(),
+        },
+      )
+      .await?;
+
+    Ok(())
+  }
+
   async fn init(&self, state: &State, tx: Sender<message::Response>) -> Result<()> {
     let player = if let Some(player_id) = self.player_id {
       Some(self.shared.db.get_player(player_id.0).await?)
@@ -416,6 +776,12 @@ impl<R: Rng> Session<R> {
       None
     };
 
+    if let Some(player) = player.as_ref()
+      && player.banned
+    {
+      anyhow::bail!("banned player {} attempted to connect", player.id);
+    }
+
     // lock connection before inserting so we can be sure we send init message before any update
     let connection = Arc::new(Mutex::new(tx));
     let connection_c = connection.clone();
@@ -436,6 +802,7 @@ impl<R: Rng> Session<R> {
               rating: player.rating,
               deviation: player.deviation,
               volatility: player.volatility,
+              bot: player.bot,
             },
           },
         )
@@ -447,6 +814,14 @@ impl<R: Rng> Session<R> {
       .pin()
       .keys()
       .chain(state.open_games.pin().values().map(|open_game| &open_game.player_id))
+      .chain(
+        state
+          .challenges
+          .pin()
+          .values()
+          .filter(|challenge| self.player_id.is_some_and(|player_id| challenge.involves(player_id)))
+          .flat_map(|challenge| [&challenge.player_id, &challenge.opponent_id].into_iter()),
+      )
       .chain(
         state
           .games
@@ -471,6 +846,7 @@ impl<R: Rng> Session<R> {
             rating: player.rating,
             deviation: player.deviation,
             volatility: player.volatility,
+            bot: player.bot,
           },
         )
       })
@@ -493,6 +869,7 @@ impl<R: Rng> Session<R> {
                   rating: player.rating,
                   deviation: player.deviation,
                   volatility: player.volatility,
+                  bot: player.bot,
                 },
                 config: message::GameConfig {
                   size: message::FieldSize {
@@ -501,10 +878,52 @@ impl<R: Rng> Session<R> {
                   },
                   time: message::GameTime {
                     total: open_game.config.time.total,
-                    increment: open_game.config.time.increment,
+                    control: open_game.config.time.control,
+                    max_per_move: open_game.config.time.max_per_move,
                   },
                   opening: open_game.config.opening,
+                  komi_x_2: open_game.config.komi_x_2,
+                  rated: open_game.config.rated,
+                },
+              },
+            )
+          })
+          .into_iter()
+      })
+      .collect();
+    let challenges = state
+      .challenges
+      .pin()
+      .iter()
+      .filter(|(_, challenge)| self.player_id.is_some_and(|player_id| challenge.involves(player_id)))
+      .flat_map(|(&game_id, challenge)| {
+        players
+          .get(&challenge.player_id.0)
+          .zip(players.get(&challenge.opponent_id.0))
+          .map(|(player, opponent)| {
+            (
+              game_id,
+              message::Challenge {
+                player_id: challenge.player_id,
+                player: player.clone(),
+                opponent_id: challenge.opponent_id,
+                opponent: opponent.clone(),
+                color: challenge.color,
+                config: message::GameConfig {
+                  size: message::FieldSize {
+                    width: challenge.config.size.width,
+                    height: challenge.config.size.height,
+                  },
+                  time: message::GameTime {
+                    total: challenge.config.time.total,
+                    control: challenge.config.time.control,
+                    max_per_move: challenge.config.time.max_per_move,
+                  },
+                  opening: challenge.config.opening,
+                  komi_x_2: challenge.config.komi_x_2,
+                  rated: challenge.config.rated,
                 },
+                rematch: challenge.rematch,
               },
             )
           })
@@ -530,12 +949,14 @@ impl<R: Rng> Session<R> {
                   rating: red_player.rating,
                   deviation: red_player.deviation,
                   volatility: red_player.volatility,
+                  bot: red_player.bot,
                 },
                 black_player: message::Player {
                   nickname: black_player.nickname.clone(),
                   rating: black_player.rating,
                   deviation: black_player.deviation,
                   volatility: black_player.volatility,
+                  bot: black_player.bot,
                 },
                 config: message::GameConfig {
                   size: message::FieldSize {
@@ -544,9 +965,12 @@ impl<R: Rng> Session<R> {
                   },
                   time: message::GameTime {
                     total: game.config.time.total,
-                    increment: game.config.time.increment,
+                    control: game.config.time.control,
+                    max_per_move: game.config.time.max_per_move,
                   },
                   opening: game.config.opening,
+                  komi_x_2: game.config.komi_x_2,
+                  rated: game.config.rated,
                 },
               },
             )
@@ -566,11 +990,51 @@ impl<R: Rng> Session<R> {
       })
       .collect();
 
+    let chat = self.chat_messages(None).await?;
+
+    let tournaments = state
+      .tournaments
+      .pin()
+      .iter()
+      .map(|(&tournament_id, tournament)| (tournament_id, tournament.clone()))
+      .collect::<Vec<_>>();
+    let mut tournaments_messages = HashMap::with_capacity(tournaments.len());
+    for (tournament_id, tournament) in tournaments {
+      let tournament_message = tournament::to_message(&tournament, &*tournament.state.read().await);
+      tournaments_messages.insert(tournament_id, tournament_message);
+    }
+
+    let queue = self
+      .player_id
+      .and_then(|player_id| state.queue.pin().get(&player_id).map(|entry| entry.preferences.clone()))
+      .map(|preferences| message::MatchPreferences {
+        min_size: message::FieldSize {
+          width: preferences.min_size.width,
+          height: preferences.min_size.height,
+        },
+        max_size: message::FieldSize {
+          width: preferences.max_size.width,
+          height: preferences.max_size.height,
+        },
+        time: message::GameTime {
+          total: preferences.time.total,
+          control: preferences.time.control,
+          max_per_move: preferences.time.max_per_move,
+        },
+        opening: preferences.opening,
+        komi_x_2: preferences.komi_x_2,
+        rated: preferences.rated,
+      });
+
     let init = message::Response::Init {
       player_id: self.player_id,
       players,
       open_games,
+      challenges,
       games,
+      chat,
+      tournaments: tournaments_messages,
+      queue,
     };
     connection_c_lock.send(init).await?;
 
@@ -587,6 +1051,8 @@ impl<R: Rng> Session<R> {
     if let Some(player_id) = self.player_id
       && state.remove_players_connection(player_id, self.connection_id)
     {
+      state.queue.pin().remove(&player_id);
+      state.player_request_times.pin().remove(&player_id);
       state.send_to_all(message::Response::PlayerLeft { player_id }).await;
     }
   }
@@ -595,6 +1061,8 @@ impl<R: Rng> Session<R> {
     if let Some(player_id) = self.player_id {
       self.player_id = None;
       if state.remove_players_connection(player_id, self.connection_id) {
+        state.queue.pin().remove(&player_id);
+        state.player_request_times.pin().remove(&player_id);
         state.send_to_all(message::Response::PlayerLeft { player_id }).await;
       }
     }
@@ -627,6 +1095,11 @@ impl<R: Rng> Session<R> {
       anyhow::bail!("too many open games for player {}", player_id);
     }
 
+    if state.is_shutting_down() {
+      log::warn!("Player {} attempted to create a game during shutdown", player_id);
+      return Ok(());
+    }
+
     let game_id = GameId(Builder::from_random_bytes(self.rng.random()).into_uuid());
     let open_game = OpenGame {
       player_id,
@@ -637,9 +1110,12 @@ impl<R: Rng> Session<R> {
         },
         time: GameTime {
           total: config.time.total,
-          increment: config.time.increment,
+          control: config.time.control,
+          max_per_move: config.time.max_per_move,
         },
         opening: config.opening,
+        komi_x_2: config.komi_x_2,
+        rated: config.rated,
       },
     };
 
@@ -657,6 +1133,7 @@ impl<R: Rng> Session<R> {
             rating: player.rating,
             deviation: player.deviation,
             volatility: player.volatility,
+            bot: player.bot,
           },
           config,
         },
@@ -706,120 +1183,508 @@ impl<R: Rng> Session<R> {
       anyhow::bail!("attempt to join own game from player {}", player_id);
     }
 
-    let now = SystemTime::now();
-    let now_offset = OffsetDateTime::from(now);
-    let now_primitive = PrimitiveDateTime::new(now_offset.date(), now_offset.time());
+    self
+      .start_game(state, game_id, open_game.player_id, player_id, open_game.config)
+      .await
+  }
 
-    // Create the field and play opening moves.
-    let mut field = Field::new_from_rng(open_game.config.size.width, open_game.config.size.height, &mut self.rng);
-    let initial_position = to_initial_position(open_game.config.opening);
-    let mut opening_db_moves = Vec::new();
-    for (i, (pos, player)) in initial_position
-      .points(open_game.config.size.width, open_game.config.size.height, Player::Red)
-      .enumerate()
+  /// Registers a challenge and notifies both players about it.
+  async fn send_challenge(&mut self, state: &Arc<State>, challenge: Challenge) -> Result<()> {
+    if challenge.player_id == challenge.opponent_id {
+      anyhow::bail!("attempt to challenge themselves from player {}", challenge.player_id);
+    }
+    if !state.players.pin().contains_key(&challenge.opponent_id) {
+      anyhow::bail!("attempt to challenge an offline player {}", challenge.opponent_id);
+    }
+    if state.is_shutting_down() {
+      log::warn!("Player {} attempted to challenge during shutdown", challenge.player_id);
+      return Ok(());
+    }
+    if state
+      .challenges
+      .pin()
+      .values()
+      .any(|other| other.player_id == challenge.player_id && other.opponent_id == challenge.opponent_id)
     {
-      if field.put_point(pos, player) {
-        opening_db_moves.push(db::Move {
-          game_id: game_id.0,
-          player: player.into(),
-          number: i as i16,
-          x: field.to_x(pos) as i16,
-          y: field.to_y(pos) as i16,
-          timestamp: now_primitive,
-        });
-      }
+      anyhow::bail!(
+        "player {} already challenged player {}",
+        challenge.player_id,
+        challenge.opponent_id
+      );
     }
 
-    self
-      .shared
-      .db
-      .create_game(
-        db::Game {
-          id: game_id.0,
-          red_player_id: open_game.player_id.0,
-          black_player_id: player_id.0,
-          start_time: now_primitive,
-          width: open_game.config.size.width as i32,
-          height: open_game.config.size.height as i32,
-          total_time_ms: open_game.config.time.total.as_millis() as i64,
-          increment_ms: open_game.config.time.increment.as_millis() as i64,
-          opening: open_game.config.opening.into(),
-          finish_time: None,
-          result: None,
-        },
-        opening_db_moves,
-      )
-      .await?;
-
-    let timer = Self::spawn_timeout_task(
-      state.clone(),
-      self.shared.clone(),
-      game_id,
-      Player::Red,
-      open_game.config.time.total,
-    );
-
-    let game_state = GameState {
-      field,
-      red_time: open_game.config.time.total,
-      black_time: open_game.config.time.total,
-      last_move_time: now,
-      draw_offer: None,
-      timer,
-    };
-    let game = Game {
-      red_player_id: open_game.player_id,
-      black_player_id: player_id,
-      config: open_game.config.clone(),
-      state: Arc::new(RwLock::new(game_state)),
-    };
-
-    state.games.pin().insert(game_id, game);
-
     let [player_1, player_2] = self
       .shared
       .db
-      .get_players(&[open_game.player_id.0, player_id.0])
+      .get_players(&[challenge.player_id.0, challenge.opponent_id.0])
       .await?
       .try_into()
-      .map_err(|_| anyhow::anyhow!("can't find players {} and {}", open_game.player_id.0, player_id.0))?;
-    let [red_player, black_player] = if player_1.id == open_game.player_id.0 {
+      .map_err(|_| {
+        anyhow::anyhow!(
+          "can't find players {} and {}",
+          challenge.player_id,
+          challenge.opponent_id
+        )
+      })?;
+    let [player, opponent] = if player_1.id == challenge.player_id.0 {
       [player_1, player_2]
     } else {
       [player_2, player_1]
     };
 
-    state
-      .send_to_all(message::Response::Start {
-        game_id,
-        game: message::Game {
-          red_player_id: PlayerId(red_player.id),
-          black_player_id: PlayerId(black_player.id),
-          red_player: message::Player {
-            nickname: red_player.nickname,
-            rating: red_player.rating,
-            deviation: red_player.deviation,
-            volatility: red_player.volatility,
-          },
-          black_player: message::Player {
-            nickname: black_player.nickname,
-            rating: black_player.rating,
-            deviation: black_player.deviation,
-            volatility: black_player.volatility,
+    let game_id = GameId(Builder::from_random_bytes(self.rng.random()).into_uuid());
+    let response = message::Response::Challenge {
+      game_id,
+      challenge: message::Challenge {
+        player_id: challenge.player_id,
+        player: message::Player {
+          nickname: player.nickname,
+          rating: player.rating,
+          deviation: player.deviation,
+          volatility: player.volatility,
+          bot: player.bot,
+        },
+        opponent_id: challenge.opponent_id,
+        opponent: message::Player {
+          nickname: opponent.nickname,
+          rating: opponent.rating,
+          deviation: opponent.deviation,
+          volatility: opponent.volatility,
+          bot: opponent.bot,
+        },
+        color: challenge.color,
+        config: message::GameConfig {
+          size: message::FieldSize {
+            width: challenge.config.size.width,
+            height: challenge.config.size.height,
           },
-          config: message::GameConfig {
-            size: message::FieldSize {
-              width: open_game.config.size.width,
-              height: open_game.config.size.height,
-            },
-            time: message::GameTime {
-              total: open_game.config.time.total,
-              increment: open_game.config.time.increment,
-            },
-            opening: open_game.config.opening,
+          time: message::GameTime {
+            total: challenge.config.time.total,
+            control: challenge.config.time.control,
+            max_per_move: challenge.config.time.max_per_move,
           },
+          opening: challenge.config.opening,
+          komi_x_2: challenge.config.komi_x_2,
+          rated: challenge.config.rated,
         },
-      })
+        rematch: challenge.rematch,
+      },
+    };
+
+    let player_id = challenge.player_id;
+    let opponent_id = challenge.opponent_id;
+    state.challenges.pin().insert(game_id, challenge);
+
+    let state_c = state.clone();
+    tokio::spawn(async move {
+      tokio::time::sleep(CHALLENGE_TIMEOUT).await;
+      let challenge = state_c.challenges.pin().remove(&game_id).cloned();
+      if let Some(challenge) = challenge {
+        close_challenge(&state_c, game_id, &challenge, message::ChallengeCloseReason::Expired).await;
+      }
+    });
+
+    state.send_to_player(player_id, response.clone()).await;
+    state.send_to_player(opponent_id, response).await;
+
+    Ok(())
+  }
+
+  async fn challenge(&mut self, state: &Arc<State>, opponent_id: PlayerId, config: message::GameConfig) -> Result<()> {
+    if !config.is_valid() {
+      anyhow::bail!(
+        "invalid game config {:?} from connection {}",
+        config,
+        self.connection_id
+      );
+    }
+
+    let player_id = self.player_id()?;
+
+    self
+      .send_challenge(
+        state,
+        Challenge {
+          player_id,
+          opponent_id,
+          color: Player::Red,
+          config: GameConfig {
+            size: FieldSize {
+              width: config.size.width,
+              height: config.size.height,
+            },
+            time: GameTime {
+              total: config.time.total,
+              control: config.time.control,
+              max_per_move: config.time.max_per_move,
+            },
+            opening: config.opening,
+            komi_x_2: config.komi_x_2,
+            rated: config.rated,
+          },
+          rematch: None,
+        },
+      )
+      .await
+  }
+
+  async fn rematch(&mut self, state: &Arc<State>, game_id: GameId) -> Result<()> {
+    let player_id = self.player_id()?;
+
+    let game = self.shared.db.get_game(game_id.0).await?.game;
+    if game.result.is_none() {
+      anyhow::bail!(
+        "attempt to rematch an unfinished game {} from player {}",
+        game_id,
+        player_id
+      );
+    }
+    // Colors are swapped in a rematch.
+    let (color, opponent_id) = if game.red_player_id == player_id.0 {
+      (Player::Black, PlayerId(game.black_player_id))
+    } else if game.black_player_id == player_id.0 {
+      (Player::Red, PlayerId(game.red_player_id))
+    } else {
+      anyhow::bail!(
+        "attempt to rematch a foreign game {} from player {}",
+        game_id,
+        player_id
+      );
+    };
+
+    // Both players asked for a rematch, so it can start right away.
+    let counter_offer = state
+      .challenges
+      .pin()
+      .iter()
+      .find(|(_, challenge)| challenge.rematch == Some(game_id) && challenge.player_id == opponent_id)
+      .map(|(&challenge_id, _)| challenge_id);
+    if let Some(challenge_id) = counter_offer {
+      return self.accept_challenge(state, challenge_id).await;
+    }
+
+    let config = to_game_config(&game);
+    self
+      .send_challenge(
+        state,
+        Challenge {
+          player_id,
+          opponent_id,
+          color,
+          config: GameConfig {
+            size: FieldSize {
+              width: config.size.width,
+              height: config.size.height,
+            },
+            time: GameTime {
+              total: config.time.total,
+              control: config.time.control,
+              max_per_move: config.time.max_per_move,
+            },
+            opening: config.opening,
+            komi_x_2: config.komi_x_2,
+            rated: config.rated,
+          },
+          rematch: Some(game_id),
+        },
+      )
+      .await
+  }
+
+  async fn enqueue(&self, state: &State, preferences: message::MatchPreferences) -> Result<()> {
+    if !preferences.is_valid() {
+      anyhow::bail!(
+        "invalid match preferences {:?} from connection {}",
+        preferences,
+        self.connection_id
+      );
+    }
+
+    let player_id = self.player_id()?;
+
+    if state.is_shutting_down() {
+      log::warn!("Player {} attempted to enqueue during shutdown", player_id);
+      return Ok(());
+    }
+
+    let player = self
+      .shared
+      .db
+      .get_players(&[player_id.0])
+      .await?
+      .pop()
+      .ok_or_else(|| anyhow::anyhow!("can't find player {}", player_id))?;
+    if player.banned {
+      anyhow::bail!("banned player {} attempted to enqueue", player_id);
+    }
+    if state.is_playing(player_id) {
+      anyhow::bail!("player {} attempted to enqueue while playing", player_id);
+    }
+
+    let entry = QueueEntry {
+      preferences: MatchPreferences {
+        min_size: FieldSize {
+          width: preferences.min_size.width,
+          height: preferences.min_size.height,
+        },
+        max_size: FieldSize {
+          width: preferences.max_size.width,
+          height: preferences.max_size.height,
+        },
+        time: GameTime {
+          total: preferences.time.total,
+          control: preferences.time.control,
+          max_per_move: preferences.time.max_per_move,
+        },
+        opening: preferences.opening,
+        komi_x_2: preferences.komi_x_2,
+        rated: preferences.rated,
+      },
+      rating: Glicko2Rating {
+        rating: player.rating,
+        deviation: player.deviation,
+        volatility: player.volatility,
+      },
+      enqueue_time: SystemTime::now(),
+    };
+    state.queue.pin().insert(player_id, entry);
+
+    state
+      .send_to_player(player_id, message::Response::Enqueued { preferences })
+      .await;
+
+    Ok(())
+  }
+
+  async fn dequeue(&self, state: &State) -> Result<()> {
+    let player_id = self.player_id()?;
+
+    if state.queue.pin().remove(&player_id).is_some() {
+      state.send_to_player(player_id, message::Response::Dequeued).await;
+    }
+
+    Ok(())
+  }
+
+  async fn accept_challenge(&mut self, state: &Arc<State>, game_id: GameId) -> Result<()> {
+    let player_id = self.player_id()?;
+
+    if state.is_shutting_down() {
+      log::warn!("Player {} attempted to accept a challenge during shutdown", player_id);
+      return Ok(());
+    }
+
+    if let Some(challenge) = state.challenges.pin().get(&game_id) {
+      if challenge.opponent_id != player_id {
+        anyhow::bail!(
+          "attempt to accept a wrong challenge {} from player {}",
+          game_id,
+          player_id
+        );
+      }
+    } else {
+      log::warn!(
+        "Player {} attempted to accept a challenge {} which dosn't exist",
+        player_id,
+        game_id
+      );
+      return Ok(());
+    }
+
+    let challenge = if let Some(challenge) = state.challenges.pin().remove(&game_id) {
+      challenge.clone()
+    } else {
+      return Ok(());
+    };
+
+    let (red_player_id, black_player_id) = match challenge.color {
+      Player::Red => (challenge.player_id, challenge.opponent_id),
+      Player::Black => (challenge.opponent_id, challenge.player_id),
+    };
+
+    self
+      .start_game(state, game_id, red_player_id, black_player_id, challenge.config)
+      .await
+  }
+
+  async fn decline_challenge(&self, state: &State, game_id: GameId) -> Result<()> {
+    let player_id = self.player_id()?;
+
+    let reason = if let Some(challenge) = state.challenges.pin().get(&game_id) {
+      if challenge.opponent_id == player_id {
+        message::ChallengeCloseReason::Declined
+      } else if challenge.player_id == player_id {
+        message::ChallengeCloseReason::Cancelled
+      } else {
+        anyhow::bail!(
+          "attempt to decline a wrong challenge {} from player {}",
+          game_id,
+          player_id
+        );
+      }
+    } else {
+      return Ok(());
+    };
+
+    let challenge = state.challenges.pin().remove(&game_id).cloned();
+    if let Some(challenge) = challenge {
+      close_challenge(state, game_id, &challenge, reason).await;
+    }
+
+    Ok(())
+  }
+
+  /// Creates a game and notifies everyone that it started.
+  async fn start_game(
+    &mut self,
+    state: &Arc<State>,
+    game_id: GameId,
+    red_player_id: PlayerId,
+    black_player_id: PlayerId,
+    config: GameConfig,
+  ) -> Result<()> {
+    if state.is_shutting_down() {
+      anyhow::bail!("attempt to start a game {} during shutdown", game_id);
+    }
+
+    let now = SystemTime::now();
+    let now_offset = OffsetDateTime::from(now);
+    let now_primitive = PrimitiveDateTime::new(now_offset.date(), now_offset.time());
+
+    // Create the field and play opening moves.
+    let mut field = Field::new_from_rng(config.size.width, config.size.height, &mut self.rng);
+    let opening = Opening::from(config.opening).points(
+      config.size.width,
+      config.size.height,
+      Player::Red,
+      Placement::Centred,
+      &mut self.rng,
+    );
+    let mut opening_db_moves = Vec::new();
+    for (i, (pos, player)) in opening.into_iter().enumerate() {
+      if field.put_point(pos, player) {
+        field.update_grounded();
+        opening_db_moves.push(db::Move {
+          game_id: game_id.0,
+          player: player.into(),
+          number: i as i16,
+          x: field.to_x(pos) as i16,
+          y: field.to_y(pos) as i16,
+          timestamp: now_primitive,
+        });
+      }
+    }
+
+    let (time_control, increment_ms, period_ms, periods) = to_db_time_control(config.time.control);
+    self
+      .shared
+      .db
+      .create_game(
+        db::Game {
+          id: game_id.0,
+          red_player_id: red_player_id.0,
+          black_player_id: black_player_id.0,
+          start_time: now_primitive,
+          width: config.size.width as i32,
+          height: config.size.height as i32,
+          total_time_ms: config.time.total.as_millis() as i64,
+          increment_ms,
+          time_control,
+          period_ms,
+          periods,
+          max_move_time_ms: config
+            .time
+            .max_per_move
+            .map(|max_per_move| max_per_move.as_millis() as i64),
+          opening: config.opening.into(),
+          komi_x_2: config.komi_x_2,
+          rated: config.rated,
+          finish_time: None,
+          result: None,
+        },
+        opening_db_moves,
+      )
+      .await?;
+
+    let clock = config.time.clock();
+    let timer = Self::spawn_timeout_task(
+      state.clone(),
+      self.shared.clone(),
+      game_id,
+      Player::Red,
+      config.time.remaining(&clock),
+    );
+
+    let opening_moves = field.moves_count();
+    let game_state = GameState {
+      field,
+      red_clock: clock,
+      black_clock: clock,
+      last_move_time: now,
+      draw_offer: None,
+      takeback_offer: None,
+      opening_moves,
+      move_clocks: Vec::new(),
+      timer,
+    };
+    let game = Game {
+      red_player_id,
+      black_player_id,
+      config: config.clone(),
+      state: Arc::new(RwLock::new(game_state)),
+    };
+
+    state.games.pin().insert(game_id, game);
+
+    let [player_1, player_2] = self
+      .shared
+      .db
+      .get_players(&[red_player_id.0, black_player_id.0])
+      .await?
+      .try_into()
+      .map_err(|_| anyhow::anyhow!("can't find players {} and {}", red_player_id, black_player_id))?;
+    let [red_player, black_player] = if player_1.id == red_player_id.0 {
+      [player_1, player_2]
+    } else {
+      [player_2, player_1]
+    };
+
+    state
+      .send_to_all(message::Response::Start {
+        game_id,
+        game: message::Game {
+          red_player_id: PlayerId(red_player.id),
+          black_player_id: PlayerId(black_player.id),
+          red_player: message::Player {
+            nickname: red_player.nickname,
+            rating: red_player.rating,
+            deviation: red_player.deviation,
+            volatility: red_player.volatility,
+            bot: red_player.bot,
+          },
+          black_player: message::Player {
+            nickname: black_player.nickname,
+            rating: black_player.rating,
+            deviation: black_player.deviation,
+            volatility: black_player.volatility,
+            bot: black_player.bot,
+          },
+          config: message::GameConfig {
+            size: message::FieldSize {
+              width: config.size.width,
+              height: config.size.height,
+            },
+            time: message::GameTime {
+              total: config.time.total,
+              control: config.time.control,
+              max_per_move: config.time.max_per_move,
+            },
+            opening: config.opening,
+            komi_x_2: config.komi_x_2,
+            rated: config.rated,
+          },
+        },
+      })
       .await;
 
     Ok(())
@@ -839,6 +1704,8 @@ impl<R: Rng> Session<R> {
 
     state.subscribe(self.connection_id, game_id);
 
+    let chat = self.chat_messages(Some(game_id)).await?;
+
     let game = state.games.pin().get(&game_id).map(|game| {
       (
         game.state.clone(),
@@ -866,22 +1733,8 @@ impl<R: Rng> Session<R> {
 
       let now = SystemTime::now();
       let now_epoch = now.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
-      let elapsed = now.duration_since(game_state.last_move_time).unwrap_or_default();
 
-      let time_left = match game_state
-        .field
-        .last_player()
-        .map_or(Player::Red, |player| player.next())
-      {
-        Player::Red => message::TimeLeft {
-          red: game_state.red_time.saturating_sub(elapsed),
-          black: game_state.black_time,
-        },
-        Player::Black => message::TimeLeft {
-          red: game_state.red_time,
-          black: game_state.black_time.saturating_sub(elapsed),
-        },
-      };
+      let time_left = game_state.time_left(&config.time, now);
 
       drop(game_state);
 
@@ -908,12 +1761,14 @@ impl<R: Rng> Session<R> {
             rating: red_player.rating,
             deviation: red_player.deviation,
             volatility: red_player.volatility,
+            bot: red_player.bot,
           },
           black_player: message::Player {
             nickname: black_player.nickname,
             rating: black_player.rating,
             deviation: black_player.deviation,
             volatility: black_player.volatility,
+            bot: black_player.bot,
           },
           config: message::GameConfig {
             size: message::FieldSize {
@@ -922,9 +1777,12 @@ impl<R: Rng> Session<R> {
             },
             time: message::GameTime {
               total: config.time.total,
-              increment: config.time.increment,
+              control: config.time.control,
+              max_per_move: config.time.max_per_move,
             },
             opening: config.opening,
+            komi_x_2: config.komi_x_2,
+            rated: config.rated,
           },
         },
         moves,
@@ -932,6 +1790,7 @@ impl<R: Rng> Session<R> {
         time_left,
         draw_offer,
         result: None,
+        chat,
       }
     } else {
       let game_with_moves = self.shared.db.get_game(game_id.0).await?;
@@ -961,71 +1820,39 @@ impl<R: Rng> Session<R> {
             x: m.x as u32,
             y: m.y as u32,
           },
-          player: match m.player {
-            db::Color::Red => Player::Red,
-            db::Color::Black => Player::Black,
-          },
+          player: m.player.into(),
         })
         .collect();
 
-      let result = game_with_moves.game.result.map(|res| match res {
-        db::GameResult::ResignedRed => message::GameResult::Win {
-          winner: Player::Black,
-          reason: message::WinReason::Resigned,
-        },
-        db::GameResult::ResignedBlack => message::GameResult::Win {
-          winner: Player::Red,
-          reason: message::WinReason::Resigned,
-        },
-        db::GameResult::GroundedRed => message::GameResult::Win {
-          winner: Player::Black,
-          reason: message::WinReason::Grounded,
-        },
-        db::GameResult::GroundedBlack => message::GameResult::Win {
-          winner: Player::Red,
-          reason: message::WinReason::Grounded,
-        },
-        db::GameResult::TimeOutRed => message::GameResult::Win {
-          winner: Player::Black,
-          reason: message::WinReason::TimeOut,
-        },
-        db::GameResult::TimeOutBlack => message::GameResult::Win {
-          winner: Player::Red,
-          reason: message::WinReason::TimeOut,
-        },
-        db::GameResult::DrawAgreement => message::GameResult::Draw {
-          reason: message::DrawReason::Agreement,
-        },
-        db::GameResult::DrawGrounded => message::GameResult::Draw {
-          reason: message::DrawReason::Grounded,
-        },
-      });
+      let result = game_with_moves.game.result.map(From::from);
 
       let now = SystemTime::now();
       let now_epoch = now.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
 
-      let start_total = time::Duration::milliseconds(game_with_moves.game.total_time_ms);
-      let increment = time::Duration::milliseconds(game_with_moves.game.increment_ms);
+      let config = to_game_config(&game_with_moves.game);
+      let time = GameTime {
+        total: config.time.total,
+        control: config.time.control,
+        max_per_move: config.time.max_per_move,
+      };
+      let elapsed = |from: PrimitiveDateTime, to: PrimitiveDateTime| (to - from).try_into().unwrap_or_default();
 
-      let mut red_time = start_total;
-      let mut black_time = start_total;
+      let mut red_clock = time.clock();
+      let mut black_clock = time.clock();
       let mut last_move_timestamp = game_with_moves.game.start_time;
 
       // Opening moves are pre-placed before the clocks start; skip them.
-      let opening_moves_count = match game_with_moves.game.opening {
-        db::Opening::Cross => 4,
-        db::Opening::TwoCrosses | db::Opening::TripleCross => 8,
-      };
+      let opening_moves_count = opening_moves_count(game_with_moves.game.opening);
 
       for (i, m) in game_with_moves.moves.iter().enumerate() {
-        let elapsed = m.timestamp - last_move_timestamp;
+        let elapsed = elapsed(last_move_timestamp, m.timestamp);
         last_move_timestamp = m.timestamp;
         if i < opening_moves_count {
           continue;
         }
         match m.player {
-          db::Color::Red => red_time = (red_time - elapsed).max(time::Duration::ZERO) + increment,
-          db::Color::Black => black_time = (black_time - elapsed).max(time::Duration::ZERO) + increment,
+          db::Color::Red => time.charge(&mut red_clock, elapsed),
+          db::Color::Black => time.charge(&mut black_clock, elapsed),
         }
       }
 
@@ -1035,17 +1862,14 @@ impl<R: Rng> Session<R> {
           .last()
           .map(|m| m.player.next())
           .unwrap_or(db::Color::Red);
-        let elapsed = finish_time - last_move_timestamp;
+        let elapsed = elapsed(last_move_timestamp, finish_time);
         match player {
-          db::Color::Red => red_time = (red_time - elapsed).max(time::Duration::ZERO),
-          db::Color::Black => black_time = (black_time - elapsed).max(time::Duration::ZERO),
+          db::Color::Red => time.spend(&mut red_clock, elapsed),
+          db::Color::Black => time.spend(&mut black_clock, elapsed),
         }
       }
 
-      let time_left = message::TimeLeft {
-        red: red_time.try_into().unwrap_or(Duration::ZERO),
-        black: black_time.try_into().unwrap_or(Duration::ZERO),
-      };
+      let time_left = time.time_left(&red_clock, &black_clock);
 
       message::Response::GameInit {
         game_id,
@@ -1057,30 +1881,23 @@ impl<R: Rng> Session<R> {
             rating: red_player.rating,
             deviation: red_player.deviation,
             volatility: red_player.volatility,
+            bot: red_player.bot,
           },
           black_player: message::Player {
             nickname: black_player.nickname,
             rating: black_player.rating,
             deviation: black_player.deviation,
             volatility: black_player.volatility,
+            bot: black_player.bot,
           },
-          config: message::GameConfig {
-            size: message::FieldSize {
-              width: game_with_moves.game.width as u32,
-              height: game_with_moves.game.height as u32,
-            },
-            time: message::GameTime {
-              total: Duration::from_millis(game_with_moves.game.total_time_ms as u64),
-              increment: Duration::from_millis(game_with_moves.game.increment_ms as u64),
-            },
-            opening: game_with_moves.game.opening.into(),
-          },
+          config,
         },
         moves,
         init_time: now_epoch,
         time_left,
         draw_offer: None,
         result,
+        chat,
       }
     };
 
@@ -1111,11 +1928,18 @@ impl<R: Rng> Session<R> {
 
       // We attempt to remove the game. If it's already gone (resigned, drawn, or finished),
       // this returns None and we do nothing.
-      let (game_state, red_player_id, black_player_id) = if let Some(game) = state.games.pin().remove(&game_id) {
-        (game.state.clone(), game.red_player_id, game.black_player_id)
-      } else {
-        return;
-      };
+      let (game_state, red_player_id, black_player_id, time, rated) =
+        if let Some(game) = state.games.pin().remove(&game_id) {
+          (
+            game.state.clone(),
+            game.red_player_id,
+            game.black_player_id,
+            game.config.time.clone(),
+            game.config.rated,
+          )
+        } else {
+          return;
+        };
 
       log::info!("Game {} timed out for {:?}", game_id, player);
 
@@ -1128,18 +1952,7 @@ impl<R: Rng> Session<R> {
         Player::Black => db::GameResult::TimeOutBlack,
       };
 
-      let game_state = game_state.read().await;
-      let time_left = match player {
-        Player::Red => message::TimeLeft {
-          red: Duration::ZERO,
-          black: game_state.black_time,
-        },
-        Player::Black => message::TimeLeft {
-          red: game_state.red_time,
-          black: Duration::ZERO,
-        },
-      };
-      drop(game_state);
+      let time_left = game_state.read().await.time_left(&time, now);
 
       if let Err(e) = shared.db.set_result(game_id.0, now_primitive, result).await {
         log::error!("Failed to set game result on timeout: {}", e);
@@ -1150,7 +1963,10 @@ impl<R: Rng> Session<R> {
         Player::Red => Outcomes::LOSS,
         Player::Black => Outcomes::WIN,
       };
-      let ratings = shared.update_ratings(red_player_id, black_player_id, outcome).await;
+      shared.enqueue_analysis(game_id, now_primitive).await;
+      let ratings = shared
+        .update_ratings(game_id, rated, now_primitive, red_player_id, black_player_id, outcome)
+        .await;
 
       state
         .send_to_all(message::Response::GameResult {
@@ -1164,7 +1980,8 @@ impl<R: Rng> Session<R> {
         .await;
 
       match ratings {
-        Ok((red_player, black_player)) => {
+        Ok(None) => {}
+        Ok(Some((red_player, black_player))) => {
           state
             .send_to_all(message::Response::RatingsUpdated {
               game_id,
@@ -1183,7 +2000,7 @@ impl<R: Rng> Session<R> {
   async fn put_point(&self, state: &Arc<State>, game_id: GameId, coordinate: message::Coordinate) -> Result<()> {
     let player_id = self.player_id()?;
 
-    let (game_state, player, increment, red_player_id, black_player_id) =
+    let (game_state, player, time, komi_x_2, rated, red_player_id, black_player_id) =
       if let Some(game) = state.games.pin().get(&game_id) {
         let player = if let Some(player) = game.color(player_id) {
           player
@@ -1197,7 +2014,9 @@ impl<R: Rng> Session<R> {
         (
           game.state.clone(),
           player,
-          game.config.time.increment,
+          game.config.time.clone(),
+          game.config.komi_x_2,
+          game.config.rated,
           game.red_player_id,
           game.black_player_id,
         )
@@ -1236,6 +2055,7 @@ impl<R: Rng> Session<R> {
       );
     }
     game_state.field.update_grounded();
+    game_state.takeback_offer = None;
 
     game_state.timer.abort();
 
@@ -1245,38 +2065,13 @@ impl<R: Rng> Session<R> {
     let now_epoch = now.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
 
     let elapsed = now.duration_since(game_state.last_move_time).unwrap_or_default();
-    match player {
-      Player::Red => game_state.red_time = game_state.red_time.saturating_sub(elapsed) + increment,
-      Player::Black => game_state.black_time = game_state.black_time.saturating_sub(elapsed) + increment,
-    }
+    let clock = *game_state.clock(player);
+    time.charge(game_state.clock_mut(player), elapsed);
 
-    let result = if game_state.field.is_game_over(0) {
+    let result = if game_state.field.is_game_over(komi_x_2) {
       state.games.pin().remove(&game_id);
 
-      let (db_result, result, outcome) = if game_state.field.score_red != game_state.field.score_black {
-        (
-          match player {
-            Player::Red => db::GameResult::GroundedRed,
-            Player::Black => db::GameResult::GroundedBlack,
-          },
-          message::GameResult::Win {
-            winner: player,
-            reason: message::WinReason::Grounded,
-          },
-          match player {
-            Player::Red => Outcomes::WIN,
-            Player::Black => Outcomes::LOSS,
-          },
-        )
-      } else {
-        (
-          db::GameResult::DrawGrounded,
-          message::GameResult::Draw {
-            reason: message::DrawReason::Grounded,
-          },
-          Outcomes::DRAW,
-        )
-      };
+      let (db_result, result, outcome) = grounded_result(&game_state.field, komi_x_2, None);
 
       self
         .shared
@@ -1297,33 +2092,17 @@ impl<R: Rng> Session<R> {
           game_state.field.undo();
         })?;
 
+      self.shared.enqueue_analysis(game_id, now_primitive).await;
       let ratings = self
         .shared
-        .update_ratings(red_player_id, black_player_id, outcome)
+        .update_ratings(game_id, rated, now_primitive, red_player_id, black_player_id, outcome)
         .await?;
 
       Some((result, ratings))
     } else {
-      match player {
-        Player::Red => {
-          game_state.timer = Self::spawn_timeout_task(
-            state.clone(),
-            self.shared.clone(),
-            game_id,
-            Player::Black,
-            game_state.black_time,
-          );
-        }
-        Player::Black => {
-          game_state.timer = Self::spawn_timeout_task(
-            state.clone(),
-            self.shared.clone(),
-            game_id,
-            Player::Red,
-            game_state.red_time,
-          );
-        }
-      }
+      let remaining = time.remaining(game_state.clock(player.next()));
+      game_state.timer =
+        Self::spawn_timeout_task(state.clone(), self.shared.clone(), game_id, player.next(), remaining);
 
       game_state.last_move_time = now;
 
@@ -1342,17 +2121,17 @@ impl<R: Rng> Session<R> {
         .inspect_err(|_| {
           game_state.field.undo();
         })?;
+      game_state.move_clocks.push(clock);
 
       None
     };
 
-    let time_left = message::TimeLeft {
-      red: game_state.red_time,
-      black: game_state.black_time,
-    };
+    let time_left = time.time_left(&game_state.red_clock, &game_state.black_clock);
 
     drop(game_state);
 
+    self.shared.metrics.record_move();
+
     state
       .send_to_watchers(
         game_id,
@@ -1365,7 +2144,7 @@ impl<R: Rng> Session<R> {
       )
       .await;
 
-    if let Some((result, (red_player, black_player))) = result {
+    if let Some((result, ratings)) = result {
       state
         .send_to_watchers(
           game_id,
@@ -1377,15 +2156,17 @@ impl<R: Rng> Session<R> {
         )
         .await;
 
-      state
-        .send_to_all(message::Response::RatingsUpdated {
-          game_id,
-          red_player_id,
-          red_player,
-          black_player_id,
-          black_player,
-        })
-        .await;
+      if let Some((red_player, black_player)) = ratings {
+        state
+          .send_to_all(message::Response::RatingsUpdated {
+            game_id,
+            red_player_id,
+            red_player,
+            black_player_id,
+            black_player,
+          })
+          .await;
+      }
     }
 
     Ok(())
@@ -1394,14 +2175,19 @@ impl<R: Rng> Session<R> {
   async fn resign(&self, state: &State, game_id: GameId) -> Result<()> {
     let player_id = self.player_id()?;
 
-    let (player, red_player_id, black_player_id, game_state) = {
+    let (player, red_player_id, black_player_id, rated, game_state, time) = {
       let pin = state.games.pin();
 
-      let (player, red_player_id, black_player_id) = if let Some(game) = pin.get(&game_id) {
+      let (player, red_player_id, black_player_id, rated) = if let Some(game) = pin.get(&game_id) {
         if player_id == game.red_player_id {
-          (Player::Red, game.red_player_id, game.black_player_id)
+          (Player::Red, game.red_player_id, game.black_player_id, game.config.rated)
         } else if player_id == game.black_player_id {
-          (Player::Black, game.red_player_id, game.black_player_id)
+          (
+            Player::Black,
+            game.red_player_id,
+            game.black_player_id,
+            game.config.rated,
+          )
         } else {
           anyhow::bail!("player {} attempted to resign in a wrong game {}", player_id, game_id,)
         }
@@ -1415,35 +2201,21 @@ impl<R: Rng> Session<R> {
         return Ok(());
       };
 
-      let game_state = if let Some(game) = pin.remove(&game_id) {
-        game.state.clone()
+      let (game_state, time) = if let Some(game) = pin.remove(&game_id) {
+        (game.state.clone(), game.config.time.clone())
       } else {
         log::warn!("Game {} is already finished", game_id);
         return Ok(());
       };
 
-      (player, red_player_id, black_player_id, game_state)
+      (player, red_player_id, black_player_id, rated, game_state, time)
     };
 
     let now = SystemTime::now();
     let now_offset = OffsetDateTime::from(now);
     let now_primitive = PrimitiveDateTime::new(now_offset.date(), now_offset.time());
 
-    let game_state = game_state.read().await;
-    let elapsed = now.duration_since(game_state.last_move_time).unwrap_or_default();
-    let current_turn = game_state.field.last_player().map_or(Player::Red, |p| p.next());
-    let time_left = if current_turn == Player::Red {
-      message::TimeLeft {
-        red: game_state.red_time.saturating_sub(elapsed),
-        black: game_state.black_time,
-      }
-    } else {
-      message::TimeLeft {
-        red: game_state.red_time,
-        black: game_state.black_time.saturating_sub(elapsed),
-      }
-    };
-    drop(game_state);
+    let time_left = game_state.read().await.time_left(&time, now);
 
     self
       .shared
@@ -1452,8 +2224,8 @@ impl<R: Rng> Session<R> {
         game_id.0,
         now_primitive,
         match player {
-          Player::Red => db::GameResult::ResignedBlack,
-          Player::Black => db::GameResult::ResignedRed,
+          Player::Red => db::GameResult::ResignedRed,
+          Player::Black => db::GameResult::ResignedBlack,
         },
       )
       .await?;
@@ -1463,9 +2235,10 @@ impl<R: Rng> Session<R> {
       Player::Red => Outcomes::LOSS,
       Player::Black => Outcomes::WIN,
     };
-    let (red_player, black_player) = self
+    self.shared.enqueue_analysis(game_id, now_primitive).await;
+    let ratings = self
       .shared
-      .update_ratings(red_player_id, black_player_id, outcome)
+      .update_ratings(game_id, rated, now_primitive, red_player_id, black_player_id, outcome)
       .await?;
 
     state
@@ -1482,38 +2255,142 @@ impl<R: Rng> Session<R> {
       )
       .await;
 
-    state
-      .send_to_all(message::Response::RatingsUpdated {
-        game_id,
-        red_player_id,
-        red_player,
-        black_player_id,
-        black_player,
-      })
-      .await;
+    if let Some((red_player, black_player)) = ratings {
+      state
+        .send_to_all(message::Response::RatingsUpdated {
+          game_id,
+          red_player_id,
+          red_player,
+          black_player_id,
+          black_player,
+        })
+        .await;
+    }
 
     Ok(())
   }
 
-  async fn draw(&self, state: &State, game_id: GameId) -> Result<()> {
+  async fn ground(&self, state: &State, game_id: GameId) -> Result<()> {
     let player_id = self.player_id()?;
 
-    let (game_state, player, red_player_id, black_player_id) = if let Some(game) = state.games.pin().get(&game_id) {
-      let player = if let Some(player) = game.color(player_id) {
-        player
+    let (game_state, player, time, komi_x_2, rated, red_player_id, black_player_id) =
+      if let Some(game) = state.games.pin().get(&game_id) {
+        let player = if let Some(player) = game.color(player_id) {
+          player
+        } else {
+          anyhow::bail!("player {} attempted to ground in a wrong game {}", player_id, game_id);
+        };
+        (
+          game.state.clone(),
+          player,
+          game.config.time.clone(),
+          game.config.komi_x_2,
+          game.config.rated,
+          game.red_player_id,
+          game.black_player_id,
+        )
       } else {
-        anyhow::bail!("player {} attempted to draw in a wrong game {}", player_id, game_id,);
+        log::warn!(
+          "player {} attempted to ground in a game {} that don't exist",
+          player_id,
+          game_id,
+        );
+        return Ok(());
       };
-      (game.state.clone(), player, game.red_player_id, game.black_player_id)
-    } else {
-      log::warn!(
-        "player {} attempted to draw in a game {} that don't exist",
+
+    let game_state = game_state.write().await;
+
+    if game_state
+      .field
+      .last_player()
+      .map_or(Player::Red, |player| player.next())
+      != player
+    {
+      anyhow::bail!(
+        "player {} attempted to ground on opponent's turn in a game {}",
         player_id,
         game_id,
       );
+    }
 
+    if state.games.pin().remove(&game_id).is_none() {
+      log::warn!("Game {} is already finished", game_id);
       return Ok(());
-    };
+    }
+
+    game_state.timer.abort();
+
+    let now = SystemTime::now();
+    let now_offset = OffsetDateTime::from(now);
+    let now_primitive = PrimitiveDateTime::new(now_offset.date(), now_offset.time());
+
+    let time_left = game_state.time_left(&time, now);
+
+    // The grounding player concedes all their points that aren't grounded yet.
+    let (db_result, result, outcome) = grounded_result(&game_state.field, komi_x_2, Some(player));
+    drop(game_state);
+
+    self.shared.db.set_result(game_id.0, now_primitive, db_result).await?;
+
+    self.shared.enqueue_analysis(game_id, now_primitive).await;
+    let ratings = self
+      .shared
+      .update_ratings(game_id, rated, now_primitive, red_player_id, black_player_id, outcome)
+      .await?;
+
+    state
+      .send_to_watchers(
+        game_id,
+        message::Response::GameResult {
+          game_id,
+          time_left,
+          result,
+        },
+      )
+      .await;
+
+    if let Some((red_player, black_player)) = ratings {
+      state
+        .send_to_all(message::Response::RatingsUpdated {
+          game_id,
+          red_player_id,
+          red_player,
+          black_player_id,
+          black_player,
+        })
+        .await;
+    }
+
+    Ok(())
+  }
+
+  async fn draw(&self, state: &State, game_id: GameId) -> Result<()> {
+    let player_id = self.player_id()?;
+
+    let (game_state, player, time, rated, red_player_id, black_player_id) =
+      if let Some(game) = state.games.pin().get(&game_id) {
+        let player = if let Some(player) = game.color(player_id) {
+          player
+        } else {
+          anyhow::bail!("player {} attempted to draw in a wrong game {}", player_id, game_id,);
+        };
+        (
+          game.state.clone(),
+          player,
+          game.config.time.clone(),
+          game.config.rated,
+          game.red_player_id,
+          game.black_player_id,
+        )
+      } else {
+        log::warn!(
+          "player {} attempted to draw in a game {} that don't exist",
+          player_id,
+          game_id,
+        );
+
+        return Ok(());
+      };
 
     let mut game_state = game_state.write().await;
 
@@ -1582,119 +2459,1161 @@ impl<R: Rng> Session<R> {
             return Ok(());
           };
 
-          let now = SystemTime::now();
-          let now_offset = OffsetDateTime::from(now);
-          let now_primitive = PrimitiveDateTime::new(now_offset.date(), now_offset.time());
+          let now = SystemTime::now();
+          let now_offset = OffsetDateTime::from(now);
+          let now_primitive = PrimitiveDateTime::new(now_offset.date(), now_offset.time());
+
+          let time_left = game_state.time_left(&time, now);
+
+          drop(game_state);
+
+          self
+            .shared
+            .db
+            .set_result(game_id.0, now_primitive, db::GameResult::DrawAgreement)
+            .await?;
+
+          self.shared.enqueue_analysis(game_id, now_primitive).await;
+          let ratings = self
+            .shared
+            .update_ratings(
+              game_id,
+              rated,
+              now_primitive,
+              red_player_id,
+              black_player_id,
+              Outcomes::DRAW,
+            )
+            .await?;
+
+          state
+            .send_to_watchers(
+              game_id,
+              message::Response::GameResult {
+                game_id,
+                time_left,
+                result: message::GameResult::Draw {
+                  reason: message::DrawReason::Agreement,
+                },
+              },
+            )
+            .await;
+
+          if let Some((red_player, black_player)) = ratings {
+            state
+              .send_to_all(message::Response::RatingsUpdated {
+                game_id,
+                red_player_id,
+                red_player,
+                black_player_id,
+                black_player,
+              })
+              .await;
+          }
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  async fn takeback(&self, state: &Arc<State>, game_id: GameId) -> Result<()> {
+    let player_id = self.player_id()?;
+
+    let (game_state, player, time) = if let Some(game) = state.games.pin().get(&game_id) {
+      let player = if let Some(player) = game.color(player_id) {
+        player
+      } else {
+        anyhow::bail!(
+          "player {} attempted to take back in a wrong game {}",
+          player_id,
+          game_id
+        );
+      };
+      if game.config.rated {
+        anyhow::bail!(
+          "player {} attempted to take back in a rated game {}",
+          player_id,
+          game_id
+        );
+      }
+      (game.state.clone(), player, game.config.time.clone())
+    } else {
+      log::warn!(
+        "player {} attempted to take back in a game {} that don't exist",
+        player_id,
+        game_id,
+      );
+
+      return Ok(());
+    };
+
+    let mut game_state = game_state.write().await;
+
+    let offer = match game_state.takeback_offer {
+      None => {
+        if !game_state
+          .field
+          .colored_moves()
+          .skip(game_state.opening_moves)
+          .any(|(_, p)| p == player)
+        {
+          log::warn!("player {} has no moves to take back in a game {}", player_id, game_id);
+          return Ok(());
+        }
+        game_state.takeback_offer = Some(player);
+        true
+      }
+      Some(takeback_offer) if takeback_offer == player => {
+        game_state.takeback_offer = None;
+        false
+      }
+      Some(takeback_offer) => {
+        game_state.takeback_offer = None;
+
+        // Moves are taken back until it's the requesting player's turn again.
+        let moves = if game_state.field.last_player() == Some(takeback_offer) {
+          1
+        } else {
+          2
+        };
+        let from_number = game_state.field.moves_count() - moves;
+        self.shared.db.delete_moves(game_id.0, from_number as i16).await?;
+
+        // Undone moves give back the time spent on them along with increments or
+        // byo-yomi moves they earned. Undoing restores the grounded state too.
+        let now = SystemTime::now();
+        let elapsed = now.duration_since(game_state.last_move_time).unwrap_or_default();
+        let player_on_turn = game_state.player_on_turn();
+        for _ in 0..moves {
+          if let (Some(player), Some(clock)) = (game_state.field.last_player(), game_state.move_clocks.pop()) {
+            *game_state.clock_mut(player) = clock;
+          }
+          game_state.field.undo();
+        }
+
+        // Time spent on the current turn is still charged to the player on turn.
+        time.spend(game_state.clock_mut(player_on_turn), elapsed);
+        game_state.last_move_time = now;
+
+        game_state.timer.abort();
+        let remaining = time.remaining(game_state.clock(takeback_offer));
+        game_state.timer =
+          Self::spawn_timeout_task(state.clone(), self.shared.clone(), game_id, takeback_offer, remaining);
+
+        let time_left = time.time_left(&game_state.red_clock, &game_state.black_clock);
+
+        drop(game_state);
+
+        state
+          .send_to_watchers(
+            game_id,
+            message::Response::Undo {
+              game_id,
+              moves: moves as u32,
+              time_left,
+            },
+          )
+          .await;
+
+        return Ok(());
+      }
+    };
+
+    drop(game_state);
+
+    state
+      .send_to_watchers(game_id, message::Response::Takeback { game_id, player, offer })
+      .await;
+
+    Ok(())
+  }
+
+  /// Recent chat messages of a game or the lobby with nicknames of their authors.
+  async fn chat_messages(&self, game_id: Option<GameId>) -> Result<Vec<message::ChatMessage>> {
+    let chat_messages = self
+      .shared
+      .db
+      .get_chat_messages(game_id.map(|game_id| game_id.0), CHAT_HISTORY_SIZE)
+      .await?;
+    let player_ids = chat_messages
+      .iter()
+      .map(|chat_message| chat_message.player_id)
+      .unique()
+      .collect::<Vec<_>>();
+    let nicknames = self
+      .shared
+      .db
+      .get_players(&player_ids)
+      .await?
+      .into_iter()
+      .map(|player| (player.id, player.nickname))
+      .collect::<HashMap<_, _>>();
+
+    Ok(
+      chat_messages
+        .into_iter()
+        .filter_map(|chat_message| {
+          Some(message::ChatMessage {
+            player_id: PlayerId(chat_message.player_id),
+            nickname: nicknames.get(&chat_message.player_id)?.clone(),
+            text: chat_message.text,
+            timestamp: to_epoch(chat_message.timestamp),
+          })
+        })
+        .collect(),
+    )
+  }
+
+  async fn create_tournament(
+    &mut self,
+    state: &Arc<State>,
+    name: String,
+    config: message::GameConfig,
+    format: message::TournamentFormat,
+    start_time: Duration,
+  ) -> Result<()> {
+    let player_id = self.player_id()?;
+
+    if state.is_shutting_down() {
+      log::warn!("Player {} attempted to create a tournament during shutdown", player_id);
+      return Ok(());
+    }
+
+    let name = name.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_TOURNAMENT_NAME_LENGTH {
+      anyhow::bail!("invalid tournament name from connection {}", self.connection_id);
+    }
+    if !config.is_valid() {
+      anyhow::bail!(
+        "invalid game config {:?} from connection {}",
+        config,
+        self.connection_id
+      );
+    }
+    if let message::TournamentFormat::Swiss { rounds } = format
+      && !(1..=tournament::MAX_SWISS_ROUNDS).contains(&rounds)
+    {
+      anyhow::bail!(
+        "invalid number of Swiss rounds {} from connection {}",
+        rounds,
+        self.connection_id
+      );
+    }
+    let start_time = SystemTime::UNIX_EPOCH + start_time;
+    if !tournament::is_start_time_valid(start_time) {
+      anyhow::bail!("invalid tournament start time from connection {}", self.connection_id);
+    }
+
+    let tournament_id = TournamentId(Builder::from_random_bytes(self.rng.random()).into_uuid());
+    let config = GameConfig {
+      size: FieldSize {
+        width: config.size.width,
+        height: config.size.height,
+      },
+      time: GameTime {
+        total: config.time.total,
+        control: config.time.control,
+        max_per_move: config.time.max_per_move,
+      },
+      opening: config.opening,
+      komi_x_2: config.komi_x_2,
+      rated: config.rated,
+    };
+
+    let (time_control, increment_ms, period_ms, periods) = to_db_time_control(config.time.control);
+    self
+      .shared
+      .db
+      .create_tournament(db::Tournament {
+        id: tournament_id.0,
+        name: name.clone(),
+        creator_id: player_id.0,
+        width: config.size.width as i32,
+        height: config.size.height as i32,
+        total_time_ms: config.time.total.as_millis() as i64,
+        increment_ms,
+        time_control,
+        period_ms,
+        periods,
+        max_move_time_ms: config
+          .time
+          .max_per_move
+          .map(|max_per_move| max_per_move.as_millis() as i64),
+        opening: config.opening.into(),
+        komi_x_2: config.komi_x_2,
+        rated: config.rated,
+        format: format.into(),
+        rounds: match format {
+          message::TournamentFormat::RoundRobin => None,
+          message::TournamentFormat::Swiss { rounds } => Some(rounds as i32),
+        },
+        start_time: from_epoch(start_time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default()),
+        status: db::TournamentStatus::Registration,
+      })
+      .await?;
+
+    let tournament = tournament::Tournament {
+      name,
+      creator_id: player_id,
+      config,
+      format,
+      start_time,
+      state: Arc::new(RwLock::new(tournament::TournamentState {
+        status: message::TournamentStatus::Registration,
+        players: Vec::new(),
+        rounds: Vec::new(),
+      })),
+    };
+    state.tournaments.pin().insert(tournament_id, tournament.clone());
+
+    let director = tournament::Director::new(
+      Session::new(self.shared.clone(), StdRng::from_rng(&mut self.rng)),
+      tournament_id,
+      tournament.clone(),
+    );
+    tokio::spawn(director.run(state.clone()).map(move |result| {
+      if let Err(error) = result {
+        log::error!("Tournament {} failed: {}", tournament_id, error);
+      }
+    }));
+
+    tournament::broadcast(state, tournament_id, &tournament).await;
+
+    Ok(())
+  }
+
+  async fn register_tournament(&self, state: &State, tournament_id: TournamentId) -> Result<()> {
+    let player_id = self.player_id()?;
+
+    let Some(tournament) = state.tournaments.pin().get(&tournament_id).cloned() else {
+      log::warn!(
+        "Player {} attempted to register for a tournament {} which doesn't exist",
+        player_id,
+        tournament_id
+      );
+      return Ok(());
+    };
+
+    let player = self
+      .shared
+      .db
+      .get_players(&[player_id.0])
+      .await?
+      .pop()
+      .ok_or_else(|| anyhow::anyhow!("can't find player {}", player_id))?;
+
+    let mut tournament_state = tournament.state.write().await;
+    if tournament_state.status != message::TournamentStatus::Registration {
+      log::warn!(
+        "Player {} attempted to register for a tournament {} after registration closed",
+        player_id,
+        tournament_id
+      );
+      return Ok(());
+    }
+    if tournament_state
+      .players
+      .iter()
+      .any(|tournament_player| tournament_player.player_id == player_id)
+    {
+      return Ok(());
+    }
+    if tournament_state.players.len() >= tournament::MAX_PLAYERS {
+      log::warn!(
+        "Player {} attempted to register for a full tournament {}",
+        player_id,
+        tournament_id
+      );
+      return Ok(());
+    }
+
+    let now = from_epoch(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?);
+    self
+      .shared
+      .db
+      .add_tournament_player(tournament_id.0, player_id.0, now)
+      .await?;
+    tournament_state.players.push(message::TournamentPlayer {
+      player_id,
+      player: message::Player {
+        nickname: player.nickname,
+        rating: player.rating,
+        deviation: player.deviation,
+        volatility: player.volatility,
+        bot: player.bot,
+      },
+    });
+    drop(tournament_state);
+
+    tournament::broadcast(state, tournament_id, &tournament).await;
+
+    Ok(())
+  }
+
+  async fn unregister_tournament(&self, state: &State, tournament_id: TournamentId) -> Result<()> {
+    let player_id = self.player_id()?;
+
+    let Some(tournament) = state.tournaments.pin().get(&tournament_id).cloned() else {
+      return Ok(());
+    };
+
+    let mut tournament_state = tournament.state.write().await;
+    if tournament_state.status != message::TournamentStatus::Registration {
+      log::warn!(
+        "Player {} attempted to unregister from a tournament {} after registration closed",
+        player_id,
+        tournament_id
+      );
+      return Ok(());
+    }
+    let Some(index) = tournament_state
+      .players
+      .iter()
+      .position(|tournament_player| tournament_player.player_id == player_id)
+    else {
+      return Ok(());
+    };
+
+    self
+      .shared
+      .db
+      .remove_tournament_player(tournament_id.0, player_id.0)
+      .await?;
+    tournament_state.players.remove(index);
+    drop(tournament_state);
+
+    tournament::broadcast(state, tournament_id, &tournament).await;
+
+    Ok(())
+  }
+
+  async fn chat(&mut self, state: &State, game_id: Option<GameId>, text: String) -> Result<()> {
+    let player_id = self.player_id()?;
+
+    let text = text.trim();
+    if text.is_empty() || text.chars().count() > MAX_CHAT_MESSAGE_LENGTH {
+      anyhow::bail!("invalid chat message from connection {}", self.connection_id);
+    }
+
+    if let Some(game_id) = game_id
+      && !self.watching.contains(&game_id)
+    {
+      anyhow::bail!(
+        "connection {} attempted to chat in a game {} it doesn't watch",
+        self.connection_id,
+        game_id
+      );
+    }
+
+    let now = SystemTime::now();
+    let now_offset = OffsetDateTime::from(now);
+    let now_primitive = PrimitiveDateTime::new(now_offset.date(), now_offset.time());
+
+    let player = self.shared.db.get_player(player_id.0).await?;
+    if let Some(muted_until) = player.muted_until
+      && muted_until > now_primitive
+    {
+      log::warn!("Muted player {} attempted to chat", player_id);
+      state
+        .send_to_connection(
+          self.connection_id,
+          message::Response::Muted {
+            until: to_epoch(muted_until),
+          },
+        )
+        .await?;
+      return Ok(());
+    }
+
+    if !within_rate_limit(&mut self.chat_times, now, CHAT_RATE_LIMIT, CHAT_RATE_PERIOD) {
+      log::warn!("Chat rate limit exceeded by connection {}", self.connection_id);
+      return Ok(());
+    }
+
+    self
+      .shared
+      .db
+      .create_chat_message(db::ChatMessage {
+        game_id: game_id.map(|game_id| game_id.0),
+        player_id: player_id.0,
+        text: text.to_string(),
+        timestamp: now_primitive,
+      })
+      .await?;
+
+    let response = message::Response::Chat {
+      game_id,
+      message: message::ChatMessage {
+        player_id,
+        nickname: player.nickname,
+        text: text.to_string(),
+        timestamp: now.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default(),
+      },
+    };
+
+    if let Some(game_id) = game_id {
+      state.send_to_watchers(game_id, response).await;
+    } else {
+      state.send_to_all(response).await;
+    }
+
+    Ok(())
+  }
+
+  fn is_nickname_valid(nickname: &str) -> bool {
+    if nickname.len() < 3 || nickname.len() > 32 {
+      return false;
+    }
+
+    if !nickname.chars().all(|c| c.is_alphanumeric() || c == '_') {
+      return false;
+    }
+
+    true
+  }
+
+  async fn change_nickname(&mut self, state: &State, nickname: String) -> Result<()> {
+    if !Self::is_nickname_valid(&nickname) {
+      anyhow::bail!("Invalid nickname format");
+    }
+
+    let player_id = self.player_id()?;
+
+    self
+      .shared
+      .db
+      .update_player_nickname(player_id.0, nickname.clone())
+      .await?;
+
+    log::info!("Player {} changed nickname to '{}'", player_id.0, nickname);
+
+    self.send_nickname_changed(state, player_id).await
+  }
+
+  async fn send_nickname_changed(&self, state: &State, player_id: PlayerId) -> Result<()> {
+    let player = self.shared.db.get_player(player_id.0).await?;
+
+    state
+      .send_to_all(message::Response::NicknameChanged {
+        player_id,
+        player: message::Player {
+          nickname: player.nickname,
+          rating: player.rating,
+          deviation: player.deviation,
+          volatility: player.volatility,
+          bot: player.bot,
+        },
+      })
+      .await;
+
+    Ok(())
+  }
+
+  async fn check_nickname(&self, state: &State, nickname: String) -> Result<()> {
+    let available =
+      Self::is_nickname_valid(&nickname) && self.shared.db.is_nickname_available(nickname.clone()).await?;
+
+    state
+      .send_to_connection(
+        self.connection_id,
+        message::Response::NicknameAvailable { nickname, available },
+      )
+      .await?;
+
+    Ok(())
+  }
+
+  /// Returns the id of the authorized player if they are an admin.
+  async fn admin_id(&self) -> Result<PlayerId> {
+    let player_id = self.player_id()?;
+    if !self.shared.db.get_player(player_id.0).await?.admin {
+      anyhow::bail!(
+        "player {} attempted a moderation request without admin rights",
+        player_id
+      );
+    }
+    Ok(player_id)
+  }
+
+  async fn audit(
+    &self,
+    admin_id: PlayerId,
+    action: db::ModerationAction,
+    player_id: Option<PlayerId>,
+    game_id: Option<GameId>,
+    details: Option<String>,
+  ) -> Result<()> {
+    log::info!(
+      "Admin {} performed {:?} on player {:?} and game {:?}",
+      admin_id,
+      action,
+      player_id,
+      game_id
+    );
+
+    let now = OffsetDateTime::now_utc();
+    self
+      .shared
+      .db
+      .add_audit_log_entry(db::AuditLogEntry {
+        admin_id: admin_id.0,
+        action,
+        player_id: player_id.map(|player_id| player_id.0),
+        game_id: game_id.map(|game_id| game_id.0),
+        details,
+        timestamp: PrimitiveDateTime::new(now.date(), now.time()),
+      })
+      .await
+  }
+
+  async fn ban(&self, state: &State, player_id: PlayerId) -> Result<()> {
+    let admin_id = self.admin_id().await?;
+    if player_id == admin_id {
+      anyhow::bail!("admin {} attempted to ban themselves", admin_id);
+    }
+
+    self.shared.db.set_player_banned(player_id.0, true).await?;
+    self
+      .audit(admin_id, db::ModerationAction::Ban, Some(player_id), None, None)
+      .await?;
+
+    state.queue.pin().remove(&player_id);
+    let open_games = state
+      .open_games
+      .pin()
+      .iter()
+      .filter(|(_, open_game)| open_game.player_id == player_id)
+      .map(|(&game_id, _)| game_id)
+      .collect::<Vec<_>>();
+    for game_id in open_games {
+      if state.open_games.pin().remove(&game_id).is_some() {
+        state.send_to_all(message::Response::Close { game_id }).await;
+      }
+    }
+
+    state.send_to_player(player_id, message::Response::Banned).await;
+    // Closing the channels closes the connections once the sent messages are delivered.
+    let connections = state
+      .players
+      .pin()
+      .get(&player_id)
+      .into_iter()
+      .flatten()
+      .filter_map(|connection_id| state.connections.pin().remove(connection_id).cloned())
+      .collect::<Vec<_>>();
+    for connection in connections {
+      connection.lock().await.close_channel();
+    }
+
+    Ok(())
+  }
+
+  async fn unban(&self, player_id: PlayerId) -> Result<()> {
+    let admin_id = self.admin_id().await?;
+    self.shared.db.set_player_banned(player_id.0, false).await?;
+    self
+      .audit(admin_id, db::ModerationAction::Unban, Some(player_id), None, None)
+      .await
+  }
+
+  async fn mute(&self, state: &State, player_id: PlayerId, duration: Duration) -> Result<()> {
+    let admin_id = self.admin_id().await?;
+    if duration.is_zero() || duration > MAX_MUTE_DURATION {
+      anyhow::bail!("invalid mute duration {:?} from admin {}", duration, admin_id);
+    }
+
+    let until = OffsetDateTime::now_utc() + duration;
+    let until = PrimitiveDateTime::new(until.date(), until.time());
+    self.shared.db.set_player_muted_until(player_id.0, Some(until)).await?;
+    self
+      .audit(
+        admin_id,
+        db::ModerationAction::Mute,
+        Some(player_id),
+        None,
+        Some(format!("{} seconds", duration.as_secs())),
+      )
+      .await?;
+
+    state
+      .send_to_player(player_id, message::Response::Muted { until: to_epoch(until) })
+      .await;
+
+    Ok(())
+  }
+
+  async fn unmute(&self, player_id: PlayerId) -> Result<()> {
+    let admin_id = self.admin_id().await?;
+    self.shared.db.set_player_muted_until(player_id.0, None).await?;
+    self
+      .audit(admin_id, db::ModerationAction::Unmute, Some(player_id), None, None)
+      .await
+  }
+
+  async fn force_close(&self, state: &State, game_id: GameId) -> Result<()> {
+    let admin_id = self.admin_id().await?;
+
+    let game = state
+      .games
+      .pin()
+      .remove(&game_id)
+      .map(|game| (game.state.clone(), game.config.time.clone()));
+    if state.open_games.pin().remove(&game_id).is_some() {
+      state.send_to_all(message::Response::Close { game_id }).await;
+    } else if let Some((game_state, time)) = game {
+      let now = SystemTime::now();
+      let now_offset = OffsetDateTime::from(now);
+      let now_primitive = PrimitiveDateTime::new(now_offset.date(), now_offset.time());
+
+      let time_left = game_state.read().await.time_left(&time, now);
+
+      // Aborted games affect neither ratings nor statistics and aren't analyzed.
+      self
+        .shared
+        .db
+        .set_result(game_id.0, now_primitive, db::GameResult::Aborted)
+        .await?;
+
+      state
+        .send_to_watchers(
+          game_id,
+          message::Response::GameResult {
+            game_id,
+            time_left,
+            result: message::GameResult::Aborted,
+          },
+        )
+        .await;
+    } else {
+      log::warn!(
+        "Admin {} attempted to close a game {} that doesn't exist",
+        admin_id,
+        game_id
+      );
+      return Ok(());
+    }
 
-          let elapsed = now.duration_since(game_state.last_move_time).unwrap_or_default();
-          let current_turn = game_state.field.last_player().map_or(Player::Red, |p| p.next());
-
-          let time_left = if current_turn == Player::Red {
-            message::TimeLeft {
-              red: game_state.red_time.saturating_sub(elapsed),
-              black: game_state.black_time,
-            }
-          } else {
-            message::TimeLeft {
-              red: game_state.red_time,
-              black: game_state.black_time.saturating_sub(elapsed),
-            }
-          };
+    self
+      .audit(admin_id, db::ModerationAction::CloseGame, None, Some(game_id), None)
+      .await
+  }
 
-          drop(game_state);
+  async fn rename(&self, state: &State, player_id: PlayerId, nickname: String) -> Result<()> {
+    let admin_id = self.admin_id().await?;
+    if !Self::is_nickname_valid(&nickname) {
+      anyhow::bail!("invalid nickname format from admin {}", admin_id);
+    }
 
-          self
-            .shared
-            .db
-            .set_result(game_id.0, now_primitive, db::GameResult::DrawAgreement)
-            .await?;
+    let old_nickname = self.shared.db.get_player(player_id.0).await?.nickname;
+    self
+      .shared
+      .db
+      .update_player_nickname(player_id.0, nickname.clone())
+      .await?;
+    self
+      .audit(
+        admin_id,
+        db::ModerationAction::Rename,
+        Some(player_id),
+        None,
+        Some(format!("'{}' -> '{}'", old_nickname, nickname)),
+      )
+      .await?;
 
-          let (red_player, black_player) = self
-            .shared
-            .update_ratings(red_player_id, black_player_id, Outcomes::DRAW)
-            .await?;
+    self.send_nickname_changed(state, player_id).await
+  }
 
-          state
-            .send_to_watchers(
-              game_id,
-              message::Response::GameResult {
-                game_id,
-                time_left,
-                result: message::GameResult::Draw {
-                  reason: message::DrawReason::Agreement,
-                },
-              },
-            )
-            .await;
+  async fn get_audit_log(&self, state: &State, page: u32) -> Result<()> {
+    self.admin_id().await?;
 
-          state
-            .send_to_all(message::Response::RatingsUpdated {
-              game_id,
-              red_player_id,
-              red_player,
-              black_player_id,
-              black_player,
-            })
-            .await;
-        }
-      }
-    }
+    let entries = self
+      .shared
+      .db
+      .get_audit_log(page as i64 * AUDIT_LOG_PAGE_SIZE, AUDIT_LOG_PAGE_SIZE)
+      .await?
+      .into_iter()
+      .map(|entry| message::AuditLogEntry {
+        admin_id: PlayerId(entry.admin_id),
+        action: entry.action.into(),
+        player_id: entry.player_id.map(PlayerId),
+        game_id: entry.game_id.map(GameId),
+        details: entry.details,
+        timestamp: to_epoch(entry.timestamp),
+      })
+      .collect();
+
+    state
+      .send_to_connection(self.connection_id, message::Response::AuditLog { page, entries })
+      .await?;
 
     Ok(())
   }
 
-  fn is_nickname_valid(nickname: &str) -> bool {
-    if nickname.len() < 3 || nickname.len() > 32 {
+  /// Checks and records a request against the connection and the player limits.
+  fn within_request_rate_limit(&mut self, state: &State) -> bool {
+    let now = SystemTime::now();
+    let limit = self.shared.rate_limit;
+    if !within_rate_limit(&mut self.request_times, now, limit.connection, REQUEST_RATE_PERIOD) {
+      log::warn!("Request rate limit exceeded by connection {}", self.connection_id);
       return false;
     }
-
-    if !nickname.chars().all(|c| c.is_alphanumeric() || c == '_') {
-      return false;
+    if let Some(player_id) = self.player_id {
+      let times = state
+        .player_request_times
+        .pin()
+        .get_or_insert_with(player_id, Default::default)
+        .clone();
+      if !within_rate_limit(&mut times.lock().unwrap(), now, limit.player, REQUEST_RATE_PERIOD) {
+        log::warn!("Request rate limit exceeded by player {}", player_id);
+        return false;
+      }
     }
-
     true
   }
 
-  async fn change_nickname(&mut self, state: &State, nickname: String) -> Result<()> {
-    if !Self::is_nickname_valid(&nickname) {
-      anyhow::bail!("Invalid nickname format");
+  /// Handles a request of a websocket client. Requests over the limit are
+  /// dropped like chat messages over the chat limit. Sessions run by the server
+  /// itself, like bots, call `handle` directly and aren't limited.
+  async fn handle_client_request(&mut self, state: &Arc<State>, request: message::Request) -> Result<()> {
+    if !self.within_request_rate_limit(state) {
+      return Ok(());
     }
+    self.handle(state, request).await
+  }
 
-    let player_id = self.player_id()?;
+  async fn handle(&mut self, state: &Arc<State>, request: message::Request) -> Result<()> {
+    match request {
+      message::Request::GetAuthUrl { remember_me } => self.get_auth_url(state, remember_me).await,
+      message::Request::Auth {
+        code: oidc_code,
+        state: oidc_state,
+        auth_cookie,
+      } => self
+        .auth(state, oidc_code, oidc_state, auth_cookie)
+        .await
+        .inspect_err(|_| self.shared.metrics.record_auth_failure()),
+      #[cfg(feature = "test")]
+      message::Request::AuthTest { name } => self.auth_test(state, name).await,
+      message::Request::AuthBot { token } => self
+        .auth_bot(state, token)
+        .await
+        .inspect_err(|_| self.shared.metrics.record_auth_failure()),
+      message::Request::SignOut => {
+        self.sign_out(state).await;
+        Ok(())
+      }
+      message::Request::Create { config } => self.create(state, config).await,
+      message::Request::Close { game_id } => self.close(state, game_id).await,
+      message::Request::Join { game_id } => self.join(state, game_id).await,
+      message::Request::Challenge { player_id, config } => self.challenge(state, player_id, config).await,
+      message::Request::Rematch { game_id } => self.rematch(state, game_id).await,
+      message::Request::Enqueue { preferences } => self.enqueue(state, preferences).await,
+      message::Request::Dequeue => self.dequeue(state).await,
+      message::Request::AcceptChallenge { game_id } => self.accept_challenge(state, game_id).await,
+      message::Request::DeclineChallenge { game_id } => self.decline_challenge(state, game_id).await,
+      message::Request::Subscribe { game_id } => self.subscribe(state, game_id).await,
+      message::Request::Unsubscribe { game_id } => self.unsubscribe(state, game_id),
+      message::Request::PutPoint { game_id, coordinate } => self.put_point(state, game_id, coordinate).await,
+      message::Request::Resign { game_id } => self.resign(state, game_id).await,
+      message::Request::Ground { game_id } => self.ground(state, game_id).await,
+      message::Request::Draw { game_id } => self.draw(state, game_id).await,
+      message::Request::Takeback { game_id } => self.takeback(state, game_id).await,
+      message::Request::CreateTournament {
+        name,
+        config,
+        format,
+        start_time,
+      } => self.create_tournament(state, name, config, format, start_time).await,
+      message::Request::RegisterTournament { tournament_id } => self.register_tournament(state, tournament_id).await,
+      message::Request::UnregisterTournament { tournament_id } => {
+        self.unregister_tournament(state, tournament_id).await
+      }
+      message::Request::Chat { game_id, text } => self.chat(state, game_id, text).await,
+      message::Request::ChangeNickname { nickname } => self.change_nickname(state, nickname).await,
+      message::Request::CheckNickname { nickname } => self.check_nickname(state, nickname).await,
+      message::Request::GetGames {
+        player_id,
+        filter,
+        page,
+      } => self.get_games(state, player_id, filter, page).await,
+      message::Request::GetGame { game_id } => self.get_game(state, game_id).await,
+      message::Request::GetSgf { game_id } => self.get_sgf(state, game_id).await,
+      message::Request::GetLeaderboard { page } => self.get_leaderboard(state, page).await,
+      message::Request::GetProfile { player_id } => self.get_profile(state, player_id).await,
+      message::Request::GetAnalysis { game_id } => self.get_analysis(state, game_id).await,
+      message::Request::Ban { player_id } => self.ban(state, player_id).await,
+      message::Request::Unban { player_id } => self.unban(player_id).await,
+      message::Request::Mute { player_id, duration } => self.mute(state, player_id, duration).await,
+      message::Request::Unmute { player_id } => self.unmute(player_id).await,
+      message::Request::ForceClose { game_id } => self.force_close(state, game_id).await,
+      message::Request::Rename { player_id, nickname } => self.rename(state, player_id, nickname).await,
+      message::Request::GetAuditLog { page } => self.get_audit_log(state, page).await,
+    }
+  }
 
-    self
+  async fn finished_games(&self, games: Vec<db::Game>) -> Result<Vec<message::FinishedGame>> {
+    let player_ids = games
+      .iter()
+      .flat_map(|game| [game.red_player_id, game.black_player_id])
+      .unique()
+      .collect::<Vec<_>>();
+    let players = self
       .shared
       .db
-      .update_player_nickname(player_id.0, nickname.clone())
+      .get_players(&player_ids)
+      .await?
+      .into_iter()
+      .map(|player| (player.id, player))
+      .collect::<HashMap<_, _>>();
+
+    games
+      .into_iter()
+      .map(|game| {
+        let (Some(result), Some(finish_time)) = (game.result, game.finish_time) else {
+          anyhow::bail!("game {} is not finished", game.id);
+        };
+        let (Some(red_player), Some(black_player)) =
+          (players.get(&game.red_player_id), players.get(&game.black_player_id))
+        else {
+          anyhow::bail!("can't find players {} and {}", game.red_player_id, game.black_player_id);
+        };
+        Ok(message::FinishedGame {
+          game_id: GameId(game.id),
+          game: message::Game {
+            red_player_id: PlayerId(game.red_player_id),
+            black_player_id: PlayerId(game.black_player_id),
+            red_player: message::Player {
+              nickname: red_player.nickname.clone(),
+              rating: red_player.rating,
+              deviation: red_player.deviation,
+              volatility: red_player.volatility,
+              bot: red_player.bot,
+            },
+            black_player: message::Player {
+              nickname: black_player.nickname.clone(),
+              rating: black_player.rating,
+              deviation: black_player.deviation,
+              volatility: black_player.volatility,
+              bot: black_player.bot,
+            },
+            config: to_game_config(&game),
+          },
+          start_time: to_epoch(game.start_time),
+          finish_time: to_epoch(finish_time),
+          result: result.into(),
+        })
+      })
+      .collect()
+  }
+
+  async fn get_games(&self, state: &State, player_id: PlayerId, filter: message::GamesFilter, page: u32) -> Result<()> {
+    let games = self
+      .shared
+      .db
+      .get_player_games(
+        player_id.0,
+        db::GamesFilter {
+          outcome: filter.outcome.map(From::from),
+          opponent_id: filter.opponent_id.map(|opponent_id| opponent_id.0),
+          width: filter.size.as_ref().map(|size| size.width as i32),
+          height: filter.size.as_ref().map(|size| size.height as i32),
+          from: filter.from.map(from_epoch),
+          to: filter.to.map(from_epoch),
+        },
+        page as i64 * GAMES_PAGE_SIZE,
+        GAMES_PAGE_SIZE,
+      )
       .await?;
+    let games = self.finished_games(games).await?;
 
-    log::info!("Player {} changed nickname to '{}'", player_id.0, nickname);
+    state
+      .send_to_connection(self.connection_id, message::Response::Games { player_id, page, games })
+      .await?;
 
-    let player = self.shared.db.get_player(player_id.0).await?;
+    Ok(())
+  }
+
+  async fn get_game(&self, state: &State, game_id: GameId) -> Result<()> {
+    let game_with_moves = self.shared.db.get_game(game_id.0).await?;
+
+    let moves = game_with_moves
+      .moves
+      .iter()
+      .map(|m| message::TimedMove {
+        coordinate: message::Coordinate {
+          x: m.x as u32,
+          y: m.y as u32,
+        },
+        player: m.player.into(),
+        timestamp: to_epoch(m.timestamp),
+      })
+      .collect();
+    let game = self
+      .finished_games(vec![game_with_moves.game])
+      .await?
+      .pop()
+      .ok_or_else(|| anyhow::anyhow!("can't find game {}", game_id))?;
 
     state
-      .send_to_all(message::Response::NicknameChanged {
-        player_id,
+      .send_to_connection(self.connection_id, message::Response::Game { game, moves })
+      .await?;
+
+    Ok(())
+  }
+
+  async fn get_sgf(&mut self, state: &State, game_id: GameId) -> Result<()> {
+    let game_with_moves = self.shared.db.get_game(game_id.0).await?;
+
+    let red_player_id = game_with_moves.game.red_player_id;
+    let black_player_id = game_with_moves.game.black_player_id;
+    let [player_1, player_2] = self
+      .shared
+      .db
+      .get_players(&[red_player_id, black_player_id])
+      .await?
+      .try_into()
+      .map_err(|_| anyhow::anyhow!("can't find players {} and {}", red_player_id, black_player_id))?;
+    let [red_player, black_player] = if player_1.id == red_player_id {
+      [player_1, player_2]
+    } else {
+      [player_2, player_1]
+    };
+
+    let sgf = sgf::to_sgf_str(&game_with_moves, &red_player, &black_player, &mut self.rng)
+      .ok_or_else(|| anyhow::anyhow!("can't export game {} to SGF", game_id))?;
+
+    state
+      .send_to_connection(self.connection_id, message::Response::Sgf { game_id, sgf })
+      .await?;
+
+    Ok(())
+  }
+
+  async fn get_analysis(&self, state: &State, game_id: GameId) -> Result<()> {
+    let moves = self
+      .shared
+      .db
+      .get_game_analysis(game_id.0)
+      .await?
+      .into_iter()
+      .map(|move_analysis| message::MoveAnalysis {
+        number: move_analysis.number as u32,
+        winrate: move_analysis.winrate,
+        best_move: move_analysis
+          .best_x
+          .zip(move_analysis.best_y)
+          .map(|(x, y)| message::Coordinate {
+            x: x as u32,
+            y: y as u32,
+          }),
+      })
+      .collect();
+
+    state
+      .send_to_connection(self.connection_id, message::Response::Analysis { game_id, moves })
+      .await?;
+
+    Ok(())
+  }
+
+  async fn get_leaderboard(&self, state: &State, page: u32) -> Result<()> {
+    let players = self
+      .shared
+      .db
+      .get_leaderboard(page as i64 * LEADERBOARD_PAGE_SIZE, LEADERBOARD_PAGE_SIZE)
+      .await?
+      .into_iter()
+      .map(|player| message::LeaderboardEntry {
+        player_id: PlayerId(player.id),
         player: message::Player {
           nickname: player.nickname,
           rating: player.rating,
           deviation: player.deviation,
           volatility: player.volatility,
+          bot: player.bot,
         },
       })
-      .await;
+      .collect();
+
+    state
+      .send_to_connection(self.connection_id, message::Response::Leaderboard { page, players })
+      .await?;
 
     Ok(())
   }
 
-  async fn check_nickname(&self, state: &State, nickname: String) -> Result<()> {
-    let available =
-      Self::is_nickname_valid(&nickname) && self.shared.db.is_nickname_available(nickname.clone()).await?;
+  async fn get_profile(&self, state: &State, player_id: PlayerId) -> Result<()> {
+    let player = self.shared.db.get_player(player_id.0).await?;
+    let rating_history = self
+      .shared
+      .db
+      .get_rating_history(player_id.0)
+      .await?
+      .into_iter()
+      .map(|entry| message::RatingChange {
+        game_id: GameId(entry.game_id),
+        rating: entry.rating,
+        deviation: entry.deviation,
+        volatility: entry.volatility,
+        timestamp: to_epoch(entry.timestamp),
+      })
+      .collect();
+    let stats = self.shared.db.get_player_stats(player_id.0).await?;
+
+    let top_opponents = self
+      .shared
+      .db
+      .get_top_opponents(player_id.0, TOP_OPPONENTS_COUNT)
+      .await?;
+    let opponent_ids = top_opponents.iter().map(|stats| stats.opponent_id).collect::<Vec<_>>();
+    let opponents = self
+      .shared
+      .db
+      .get_players(&opponent_ids)
+      .await?
+      .into_iter()
+      .map(|player| (player.id, player))
+      .collect::<HashMap<_, _>>();
+    let opponents = top_opponents
+      .into_iter()
+      .filter_map(|stats| {
+        let opponent = opponents.get(&stats.opponent_id)?;
+        Some(message::OpponentStats {
+          player_id: PlayerId(stats.opponent_id),
+          player: message::Player {
+            nickname: opponent.nickname.clone(),
+            rating: opponent.rating,
+            deviation: opponent.deviation,
+            volatility: opponent.volatility,
+            bot: opponent.bot,
+          },
+          games: stats.games as u64,
+          wins: stats.wins as u64,
+          losses: stats.losses as u64,
+          draws: stats.draws as u64,
+        })
+      })
+      .collect();
 
     state
       .send_to_connection(
         self.connection_id,
-        message::Response::NicknameAvailable { nickname, available },
+        message::Response::Profile {
+          player_id,
+          player: message::Player {
+            nickname: player.nickname,
+            rating: player.rating,
+            deviation: player.deviation,
+            volatility: player.volatility,
+            bot: player.bot,
+          },
+          rating_history,
+          wins: stats.wins as u64,
+          losses: stats.losses as u64,
+          draws: stats.draws as u64,
+          opponents,
+        },
       )
       .await?;
 
@@ -1747,27 +3666,7 @@ impl<R: Rng> Session<R> {
       while let Some(message) = rx_ws.next().await {
         if let Message::Text(message) = message? {
           let message: message::Request = serde_json::from_str(message.as_str())?;
-          match message {
-            message::Request::GetAuthUrl { remember_me } => self.get_auth_url(&state, remember_me).await?,
-            message::Request::Auth {
-              code: oidc_code,
-              state: oidc_state,
-              auth_cookie,
-            } => self.auth(&state, oidc_code, oidc_state, auth_cookie).await?,
-            #[cfg(feature = "test")]
-            message::Request::AuthTest { name } => self.auth_test(&state, name).await?,
-            message::Request::SignOut => self.sign_out(&state).await,
-            message::Request::Create { config } => self.create(&state, config).await?,
-            message::Request::Close { game_id } => self.close(&state, game_id).await?,
-            message::Request::Join { game_id } => self.join(&state, game_id).await?,
-            message::Request::Subscribe { game_id } => self.subscribe(&state, game_id).await?,
-            message::Request::Unsubscribe { game_id } => self.unsubscribe(&state, game_id)?,
-            message::Request::PutPoint { game_id, coordinate } => self.put_point(&state, game_id, coordinate).await?,
-            message::Request::Resign { game_id } => self.resign(&state, game_id).await?,
-            message::Request::Draw { game_id } => self.draw(&state, game_id).await?,
-            message::Request::ChangeNickname { nickname } => self.change_nickname(&state, nickname).await?,
-            message::Request::CheckNickname { nickname } => self.check_nickname(&state, nickname).await?,
-          }
+          self.handle_client_request(&state, message).await?;
         }
       }
 
@@ -1785,6 +3684,12 @@ impl<R: Rng> Session<R> {
   }
 }
 
+async fn close_challenge(state: &State, game_id: GameId, challenge: &Challenge, reason: message::ChallengeCloseReason) {
+  let response = message::Response::ChallengeClosed { game_id, reason };
+  state.send_to_player(challenge.player_id, response.clone()).await;
+  state.send_to_player(challenge.opponent_id, response).await;
+}
+
 async fn close_open_games(state: &State) {
   let mut candidates: HashMap<GameId, u32> = HashMap::new();
   let mut removed = Vec::new();
@@ -1820,6 +3725,63 @@ async fn close_open_games(state: &State) {
   }
 }
 
+async fn shutdown_signal() -> std::io::Result<()> {
+  #[cfg(unix)]
+  {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    select! {
+      r = tokio::signal::ctrl_c().fuse() => r,
+      _ = terminate.recv().fuse() => Ok(()),
+    }
+  }
+  #[cfg(not(unix))]
+  tokio::signal::ctrl_c().await
+}
+
+/// Stops starting new games, notifies clients and waits for live games to
+/// finish. Moves are stored as they are made, so games still live after the
+/// timeout stay in the database without a result.
+async fn shutdown(state: &State, timeout: Duration) {
+  state.shutting_down.store(true, std::sync::atomic::Ordering::SeqCst);
+
+  let open_games = state.open_games.pin().keys().copied().collect::<Vec<_>>();
+  for game_id in open_games {
+    if state.open_games.pin().remove(&game_id).is_some() {
+      state.send_to_all(message::Response::Close { game_id }).await;
+    }
+  }
+  let challenges = state
+    .challenges
+    .pin()
+    .iter()
+    .map(|(&game_id, challenge)| (game_id, challenge.clone()))
+    .collect::<Vec<_>>();
+  for (game_id, challenge) in challenges {
+    if state.challenges.pin().remove(&game_id).is_some() {
+      close_challenge(state, game_id, &challenge, message::ChallengeCloseReason::Cancelled).await;
+    }
+  }
+  let queued = state.queue.pin().keys().copied().collect::<Vec<_>>();
+  for player_id in queued {
+    if state.queue.pin().remove(&player_id).is_some() {
+      state.send_to_player(player_id, message::Response::Dequeued).await;
+    }
+  }
+
+  state.send_to_all(message::Response::Shutdown).await;
+
+  let live_games_finished = async {
+    let mut wakeups = tokio::time::interval(Duration::from_secs(1));
+    while !state.games.is_empty() {
+      log::info!("Waiting for {} live games to finish", state.games.len());
+      wakeups.tick().await;
+    }
+  };
+  if tokio::time::timeout(timeout, live_games_finished).await.is_err() {
+    log::warn!("Shutting down with {} unfinished games", state.games.len());
+  }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
   let env = env_logger::Env::default().filter_or("RUST_LOG", "info");
@@ -1827,7 +3789,7 @@ async fn main() -> Result<()> {
 
   let config = config::cli_parse();
 
-  let listener = TcpListener::bind("127.0.0.1:8080").await?;
+  let listener = TcpListener::bind(config.listen).await?;
   let state = Arc::new(State::default());
 
   let mut rng = make_rng::<StdRng>();
@@ -1843,6 +3805,7 @@ async fn main() -> Result<()> {
     .redirect(reqwest::redirect::Policy::none()) // Following redirects opens the client up to SSRF vulnerabilities.
     .build()?;
 
+  let (analysis_tx, analysis_rx) = mpsc::unbounded();
   let session_shared = Arc::new(SessionShared {
     #[cfg(not(feature = "in-memory"))]
     db: db::SqlxDb::from(pool),
@@ -1851,8 +3814,50 @@ async fn main() -> Result<()> {
     http_client,
     cookie_key: config.cookie_key,
     oidc: config.oidc,
+    bot_tokens: config.bot_tokens,
+    analysis: config.analysis.is_some().then_some(analysis_tx),
+    metrics: Default::default(),
+    rate_limit: config.rate_limit,
   });
 
+  if let Some(metrics_listen) = config.metrics_listen {
+    let metrics_listener = TcpListener::bind(metrics_listen).await?;
+    tokio::spawn(
+      metrics::serve(metrics_listener, state.clone(), session_shared.clone()).map(|result| {
+        if let Err(error) = result {
+          log::error!("Metrics listener stopped with an error: {}", error);
+        }
+      }),
+    );
+  }
+
+  if let Some(analysis_config) = config.analysis {
+    let engine = analysis_config.engine.clone();
+    let engine_args = analysis_config.engine_args.clone();
+    let analyzer = analysis::Analyzer::new(session_shared.clone(), analysis_config, analysis_rx, move || {
+      Client::spawn(engine.clone(), engine_args.clone())
+    });
+    tokio::spawn(analyzer.run());
+  }
+
+  let matchmaker = matchmaking::Matchmaker::new(Session::new(session_shared.clone(), StdRng::from_rng(&mut rng)));
+  tokio::spawn(matchmaker.run(state.clone()));
+
+  if let Some(bot_config) = config.bot {
+    let engine = bot_config.engine.clone();
+    let engine_args = bot_config.engine_args.clone();
+    let bot = bot::Bot::new(
+      Session::new(session_shared.clone(), StdRng::from_rng(&mut rng)),
+      bot_config,
+      move || Client::spawn(engine.clone(), engine_args.clone()),
+    );
+    tokio::spawn(bot.run(state.clone()).map(|result| {
+      if let Err(error) = result {
+        log::error!("Bot stopped with an error: {}", error);
+      }
+    }));
+  }
+
   let future_1 = async {
     loop {
       let (stream, addr) = listener.accept().await?;
@@ -1867,8 +3872,18 @@ async fn main() -> Result<()> {
 
   let future_2 = close_open_games(&state);
 
+  // Connections are still accepted during shutdown so that players can
+  // reconnect to finish their games.
+  let future_3 = async {
+    shutdown_signal().await?;
+    log::info!("Shutting down");
+    shutdown(&state, config.shutdown_timeout).await;
+    Ok::<(), Error>(())
+  };
+
   select! {
     r = future_1.fuse() => r  ,
     _ = future_2.fuse() => Ok(()),
+    r = future_3.fuse() => r,
   }
 }
@@ -0,0 +1,191 @@
+use crate::{
+  Session,
+  db::Db,
+  ids::*,
+  message,
+  state::{FieldSize, GameConfig, QueueEntry, State},
+};
+use anyhow::Result;
+use rand::{Rng, RngExt};
+use skillratings::glicko2::expected_score;
+use std::{
+  collections::HashSet,
+  sync::Arc,
+  time::{Duration, SystemTime},
+};
+use uuid::Builder;
+
+/// How often waiting players are matched.
+pub const MATCHMAKING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Allowed deviation of the expected score from an even game right after enqueueing.
+const INITIAL_WINDOW: f64 = 0.1;
+
+/// Growth of the allowed deviation per second of waiting.
+const WINDOW_GROWTH: f64 = 0.005;
+
+/// Allowed deviation of the expected score from 0.5 after waiting for `waited`.
+/// Eventually anyone is accepted.
+pub fn window(waited: Duration) -> f64 {
+  (INITIAL_WINDOW + WINDOW_GROWTH * waited.as_secs_f64()).min(0.5)
+}
+
+fn common_range(min_1: u32, max_1: u32, min_2: u32, max_2: u32) -> Option<u32> {
+  let max = max_1.min(max_2);
+  (min_1.max(min_2) <= max).then_some(max)
+}
+
+/// The largest game both players accept, if any.
+pub fn common_config(entry_1: &QueueEntry, entry_2: &QueueEntry) -> Option<GameConfig> {
+  let (p1, p2) = (&entry_1.preferences, &entry_2.preferences);
+  if p1.time != p2.time || p1.opening != p2.opening || p1.komi_x_2 != p2.komi_x_2 || p1.rated != p2.rated {
+    return None;
+  }
+  let width = common_range(
+    p1.min_size.width,
+    p1.max_size.width,
+    p2.min_size.width,
+    p2.max_size.width,
+  )?;
+  let height = common_range(
+    p1.min_size.height,
+    p1.max_size.height,
+    p2.min_size.height,
+    p2.max_size.height,
+  )?;
+  Some(GameConfig {
+    size: FieldSize { width, height },
+    time: p1.time.clone(),
+    opening: p1.opening,
+    komi_x_2: p1.komi_x_2,
+    rated: p1.rated,
+  })
+}
+
+/// Deviation of the expected score of the first player from an even game.
+fn imbalance(entry_1: &QueueEntry, entry_2: &QueueEntry) -> f64 {
+  (expected_score(&entry_1.rating, &entry_2.rating).0 - 0.5).abs()
+}
+
+/// Pairs waiting players. Players who wait longer are matched first, each with
+/// the closest compatible opponent within the window of either of them.
+pub fn find_matches(entries: &[(PlayerId, QueueEntry)], now: SystemTime) -> Vec<(PlayerId, PlayerId, GameConfig)> {
+  let mut entries = entries.iter().collect::<Vec<_>>();
+  entries.sort_by_key(|(_, entry)| entry.enqueue_time);
+  let waited = |entry: &QueueEntry| now.duration_since(entry.enqueue_time).unwrap_or_default();
+
+  let mut matched = HashSet::new();
+  let mut matches = Vec::new();
+  for (i, (player_id, entry)) in entries.iter().enumerate() {
+    if matched.contains(player_id) {
+      continue;
+    }
+    let opponent = entries[i + 1..]
+      .iter()
+      .filter(|(opponent_id, _)| !matched.contains(opponent_id))
+      .filter(|(_, opponent)| imbalance(entry, opponent) <= window(waited(entry).max(waited(opponent))))
+      .filter_map(|(opponent_id, opponent)| {
+        common_config(entry, opponent).map(|config| (*opponent_id, imbalance(entry, opponent), config))
+      })
+      .min_by(|(_, imbalance_1, _), (_, imbalance_2, _)| imbalance_1.total_cmp(imbalance_2));
+    if let Some((opponent_id, _, config)) = opponent {
+      matched.insert(*player_id);
+      matched.insert(opponent_id);
+      matches.push((*player_id, opponent_id, config));
+    }
+  }
+
+  matches
+}
+
+/// Periodically matches players from the queue and starts their games.
+pub struct Matchmaker<R: Rng> {
+  session: Session<R>,
+}
+
+impl<R: Rng + Send> Matchmaker<R> {
+  pub fn new(session: Session<R>) -> Self {
+    Matchmaker { session }
+  }
+
+  async fn start(
+    &mut self,
+    state: &Arc<State>,
+    player_id: PlayerId,
+    opponent_id: PlayerId,
+    config: GameConfig,
+  ) -> Result<()> {
+    // Players might have started another game or been banned since they were
+    // enqueued. They leave the queue, while their opponent keeps waiting.
+    let players = self
+      .session
+      .shared
+      .db
+      .get_players(&[player_id.0, opponent_id.0])
+      .await?;
+    let mut eligible = true;
+    for id in [player_id, opponent_id] {
+      let banned = players
+        .iter()
+        .find(|player| player.id == id.0)
+        .is_none_or(|player| player.banned);
+      if banned || state.is_playing(id) {
+        eligible = false;
+        if state.queue.pin().remove(&id).is_some() {
+          state.send_to_player(id, message::Response::Dequeued).await;
+        }
+      }
+    }
+    if !eligible {
+      return Ok(());
+    }
+
+    // Players might leave the queue concurrently.
+    let Some(entry) = state.queue.pin().remove(&player_id).cloned() else {
+      return Ok(());
+    };
+    if state.queue.pin().remove(&opponent_id).is_none() {
+      state.queue.pin().insert(player_id, entry);
+      return Ok(());
+    }
+
+    state.send_to_player(player_id, message::Response::Dequeued).await;
+    state.send_to_player(opponent_id, message::Response::Dequeued).await;
+
+    let (red_player_id, black_player_id) = if self.session.rng.random() {
+      (player_id, opponent_id)
+    } else {
+      (opponent_id, player_id)
+    };
+    let game_id = GameId(Builder::from_random_bytes(self.session.rng.random()).into_uuid());
+
+    self
+      .session
+      .start_game(state, game_id, red_player_id, black_player_id, config)
+      .await
+  }
+
+  pub async fn run(mut self, state: Arc<State>) {
+    let mut interval = tokio::time::interval(MATCHMAKING_INTERVAL);
+    loop {
+      interval.tick().await;
+
+      let entries = state
+        .queue
+        .pin()
+        .iter()
+        .map(|(&player_id, entry)| (player_id, entry.clone()))
+        .collect::<Vec<_>>();
+      for (player_id, opponent_id, config) in find_matches(&entries, SystemTime::now()) {
+        if let Err(error) = self.start(&state, player_id, opponent_id, config).await {
+          log::error!(
+            "Failed to start a matched game of players {} and {}: {}",
+            player_id,
+            opponent_id,
+            error
+          );
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,284 @@
+use crate::{
+  Session,
+  ids::*,
+  matchmaking::{Matchmaker, find_matches},
+  message::{FieldSize, GameConfig, GameTime, MatchPreferences, Opening, Request, Response, TimeControl},
+  state::{self, QueueEntry, State},
+  test_utils::{connect, shared, wait_for},
+};
+use rand::{SeedableRng, rngs::StdRng};
+use skillratings::glicko2::Glicko2Rating;
+use std::{
+  sync::Arc,
+  time::{Duration, SystemTime},
+};
+use uuid::Uuid;
+
+fn preferences(min_size: u32, max_size: u32) -> MatchPreferences {
+  MatchPreferences {
+    min_size: FieldSize {
+      width: min_size,
+      height: min_size,
+    },
+    max_size: FieldSize {
+      width: max_size,
+      height: max_size,
+    },
+    time: GameTime {
+      total: Duration::from_secs(60),
+      control: TimeControl::Fischer {
+        increment: Duration::from_secs(1),
+      },
+      max_per_move: None,
+    },
+    opening: Opening::Cross,
+    komi_x_2: 0,
+    rated: true,
+  }
+}
+
+fn entry(min_size: u32, max_size: u32, rating: f64, enqueue_time: SystemTime) -> QueueEntry {
+  let preferences = preferences(min_size, max_size);
+  QueueEntry {
+    preferences: state::MatchPreferences {
+      min_size: state::FieldSize {
+        width: preferences.min_size.width,
+        height: preferences.min_size.height,
+      },
+      max_size: state::FieldSize {
+        width: preferences.max_size.width,
+        height: preferences.max_size.height,
+      },
+      time: state::GameTime {
+        total: preferences.time.total,
+        control: preferences.time.control,
+        max_per_move: preferences.time.max_per_move,
+      },
+      opening: preferences.opening,
+      komi_x_2: preferences.komi_x_2,
+      rated: preferences.rated,
+    },
+    rating: Glicko2Rating {
+      rating,
+      deviation: 50.0,
+      volatility: 0.06,
+    },
+    enqueue_time,
+  }
+}
+
+#[test]
+fn rating_window_widens_over_time() {
+  let now = SystemTime::now();
+  let strong = PlayerId(Uuid::from_u128(1));
+  let weak = PlayerId(Uuid::from_u128(2));
+  let entries = [(strong, entry(10, 30, 1900.0, now)), (weak, entry(20, 40, 1500.0, now))];
+
+  assert!(find_matches(&entries, now).is_empty());
+  assert!(find_matches(&entries, now + Duration::from_secs(30)).is_empty());
+
+  let matches = find_matches(&entries, now + Duration::from_secs(90));
+  assert_eq!(matches.len(), 1);
+  let (player_id, opponent_id, config) = &matches[0];
+  assert_eq!((*player_id, *opponent_id), (strong, weak));
+  assert_eq!(config.size, state::FieldSize { width: 30, height: 30 });
+}
+
+#[test]
+fn closest_compatible_opponent_is_preferred() {
+  let now = SystemTime::now();
+  let player = PlayerId(Uuid::from_u128(1));
+  let close = PlayerId(Uuid::from_u128(2));
+  let far = PlayerId(Uuid::from_u128(3));
+  let incompatible = PlayerId(Uuid::from_u128(4));
+  let entries = [
+    (player, entry(10, 20, 1500.0, now)),
+    (far, entry(10, 20, 1560.0, now + Duration::from_secs(1))),
+    (close, entry(20, 20, 1520.0, now + Duration::from_secs(2))),
+    (incompatible, entry(25, 30, 1500.0, now + Duration::from_secs(3))),
+  ];
+
+  let matches = find_matches(&entries, now + Duration::from_secs(3));
+  assert_eq!(matches.len(), 1);
+  assert_eq!((matches[0].0, matches[0].1), (player, close));
+}
+
+#[test]
+fn komi_and_rating_come_from_the_preferences() {
+  let now = SystemTime::now();
+  let player = PlayerId(Uuid::from_u128(1));
+  let opponent = PlayerId(Uuid::from_u128(2));
+  let mut player_entry = entry(10, 20, 1500.0, now);
+  player_entry.preferences.komi_x_2 = 1;
+  player_entry.preferences.rated = false;
+  let mut opponent_entry = entry(10, 20, 1500.0, now);
+
+  let entries = [(player, player_entry.clone()), (opponent, opponent_entry.clone())];
+  assert!(find_matches(&entries, now).is_empty());
+
+  opponent_entry.preferences.komi_x_2 = 1;
+  let entries = [(player, player_entry.clone()), (opponent, opponent_entry.clone())];
+  assert!(find_matches(&entries, now).is_empty());
+
+  opponent_entry.preferences.rated = false;
+  let entries = [(player, player_entry), (opponent, opponent_entry)];
+  let matches = find_matches(&entries, now);
+  assert_eq!(matches.len(), 1);
+  assert_eq!((matches[0].2.komi_x_2, matches[0].2.rated), (1, false));
+}
+
+#[tokio::test]
+async fn queued_players_are_matched() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+
+  let matchmaker = Matchmaker::new(Session::new(shared.clone(), StdRng::seed_from_u64(1)));
+  tokio::spawn(matchmaker.run(state.clone()));
+
+  let (mut alice, mut alice_rx) = connect(&state, &shared, "alice").await;
+  let (mut bob, mut bob_rx) = connect(&state, &shared, "bob").await;
+  let (mut carol, mut carol_rx) = connect(&state, &shared, "carol").await;
+  let alice_id = alice.player_id.unwrap();
+  let bob_id = bob.player_id.unwrap();
+
+  alice
+    .handle(
+      &state,
+      Request::Enqueue {
+        preferences: preferences(10, 20),
+      },
+    )
+    .await
+    .unwrap();
+  wait_for(&mut alice_rx, |response| match response {
+    Response::Enqueued { preferences } => Some(preferences),
+    _ => None,
+  })
+  .await;
+
+  // Carol waits for a different time control and stays in the queue.
+  let mut carol_preferences = preferences(10, 20);
+  carol_preferences.time.total = Duration::from_secs(300);
+  carol
+    .handle(
+      &state,
+      Request::Enqueue {
+        preferences: carol_preferences,
+      },
+    )
+    .await
+    .unwrap();
+
+  bob
+    .handle(
+      &state,
+      Request::Enqueue {
+        preferences: preferences(15, 30),
+      },
+    )
+    .await
+    .unwrap();
+
+  for rx in [&mut alice_rx, &mut bob_rx] {
+    wait_for(rx, |response| matches!(response, Response::Dequeued).then_some(())).await;
+  }
+  let game = wait_for(&mut alice_rx, |response| match response {
+    Response::Start { game, .. } => Some(game),
+    _ => None,
+  })
+  .await;
+  let mut player_ids = [game.red_player_id, game.black_player_id];
+  player_ids.sort_by_key(|player_id| player_id.0);
+  let mut expected = [alice_id, bob_id];
+  expected.sort_by_key(|player_id| player_id.0);
+  assert_eq!(player_ids, expected);
+  assert_eq!(game.config.size, FieldSize { width: 20, height: 20 });
+  assert_eq!(game.config.time, preferences(10, 20).time);
+
+  assert_eq!(
+    state.queue.pin().keys().copied().collect::<Vec<_>>(),
+    vec![carol.player_id.unwrap()]
+  );
+  carol.handle(&state, Request::Dequeue).await.unwrap();
+  wait_for(&mut carol_rx, |response| {
+    matches!(response, Response::Dequeued).then_some(())
+  })
+  .await;
+  assert!(state.queue.pin().is_empty());
+}
+
+#[tokio::test]
+async fn players_in_a_live_game_are_not_matched() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+
+  let (mut alice, mut alice_rx) = connect(&state, &shared, "alice").await;
+  let (mut bob, _bob_rx) = connect(&state, &shared, "bob").await;
+  let (mut carol, mut carol_rx) = connect(&state, &shared, "carol").await;
+  let alice_id = alice.player_id.unwrap();
+  let carol_id = carol.player_id.unwrap();
+
+  alice
+    .handle(
+      &state,
+      Request::Create {
+        config: GameConfig {
+          size: FieldSize { width: 10, height: 10 },
+          time: preferences(10, 20).time,
+          opening: Opening::Cross,
+          komi_x_2: 0,
+          rated: true,
+        },
+      },
+    )
+    .await
+    .unwrap();
+  let game_id = wait_for(&mut alice_rx, |response| match response {
+    Response::Create { game_id, .. } => Some(game_id),
+    _ => None,
+  })
+  .await;
+  bob.handle(&state, Request::Join { game_id }).await.unwrap();
+
+  assert!(
+    alice
+      .handle(
+        &state,
+        Request::Enqueue {
+          preferences: preferences(10, 20),
+        },
+      )
+      .await
+      .is_err()
+  );
+
+  // Alice got into the queue before her game started.
+  state
+    .queue
+    .pin()
+    .insert(alice_id, entry(10, 20, 1500.0, SystemTime::now()));
+  carol
+    .handle(
+      &state,
+      Request::Enqueue {
+        preferences: preferences(10, 20),
+      },
+    )
+    .await
+    .unwrap();
+
+  let matchmaker = Matchmaker::new(Session::new(shared.clone(), StdRng::seed_from_u64(1)));
+  tokio::spawn(matchmaker.run(state.clone()));
+
+  wait_for(&mut alice_rx, |response| {
+    matches!(response, Response::Dequeued).then_some(())
+  })
+  .await;
+  assert_eq!(state.queue.pin().keys().copied().collect::<Vec<_>>(), vec![carol_id]);
+  assert!(!state.is_playing(carol_id));
+  carol.handle(&state, Request::Dequeue).await.unwrap();
+  wait_for(&mut carol_rx, |response| {
+    matches!(response, Response::Dequeued).then_some(())
+  })
+  .await;
+}
@@ -24,41 +24,155 @@ impl FieldSize {
   }
 }
 
+/// What happens after the main time runs out.
+#[serde_as]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all_fields = "camelCase")]
+pub enum TimeControl {
+  /// The increment is added after every move.
+  Fischer {
+    #[serde_as(as = "DurationSeconds")]
+    increment: Duration,
+  },
+  /// Every move can take up to a period. Exceeding it uses up the period.
+  Japanese {
+    #[serde_as(as = "DurationSeconds")]
+    period: Duration,
+    periods: u32,
+  },
+  /// A number of moves has to be made within every period.
+  Canadian {
+    #[serde_as(as = "DurationSeconds")]
+    period: Duration,
+    moves: u32,
+  },
+  /// The clock starts running only after the delay on every move.
+  Delay {
+    #[serde_as(as = "DurationSeconds")]
+    delay: Duration,
+  },
+}
+
+impl TimeControl {
+  const MIN_PERIOD: Duration = Duration::from_secs(1);
+  const MAX_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+  const MAX_PERIODS: u32 = 30;
+  const MAX_PERIOD_MOVES: u32 = 100;
+
+  pub fn is_valid(&self) -> bool {
+    match *self {
+      TimeControl::Fischer { increment } => increment <= Self::MAX_PERIOD,
+      TimeControl::Japanese { period, periods } => {
+        (Self::MIN_PERIOD..=Self::MAX_PERIOD).contains(&period) && (1..=Self::MAX_PERIODS).contains(&periods)
+      }
+      TimeControl::Canadian { period, moves } => {
+        (Self::MIN_PERIOD..=Self::MAX_PERIOD).contains(&period) && (1..=Self::MAX_PERIOD_MOVES).contains(&moves)
+      }
+      TimeControl::Delay { delay } => delay <= Self::MAX_PERIOD,
+    }
+  }
+
+  /// Byo-yomi is enough to play without main time.
+  fn has_periods(&self) -> bool {
+    matches!(self, TimeControl::Japanese { .. } | TimeControl::Canadian { .. })
+  }
+}
+
 #[serde_as]
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GameTime {
+  /// Main time.
   #[serde_as(as = "DurationSeconds")]
   pub total: Duration,
-  #[serde_as(as = "DurationSeconds")]
-  pub increment: Duration,
+  pub control: TimeControl,
+  /// Maximal time of a single move, mostly for long games.
+  #[serde_as(as = "Option<DurationSeconds>")]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub max_per_move: Option<Duration>,
+}
+
+/// State of byo-yomi periods of a player.
+#[serde_as]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all_fields = "camelCase")]
+pub enum PeriodLeft {
+  Japanese {
+    /// Time left in the current period.
+    #[serde_as(as = "DurationMilliSeconds")]
+    time: Duration,
+    /// Periods left including the current one.
+    periods: u32,
+  },
+  Canadian {
+    /// Time left in the current period.
+    #[serde_as(as = "DurationMilliSeconds")]
+    time: Duration,
+    /// Moves left to make in the current period.
+    moves: u32,
+  },
 }
 
 #[serde_as]
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TimeLeft {
+  /// Main time left.
   #[serde_as(as = "DurationMilliSeconds")]
   pub red: Duration,
   #[serde_as(as = "DurationMilliSeconds")]
   pub black: Duration,
+  /// Byo-yomi state, absent for time controls without periods.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub red_period: Option<PeriodLeft>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub black_period: Option<PeriodLeft>,
 }
 
 impl GameTime {
   const MIN_TOTAL: Duration = Duration::from_secs(10);
   const MAX_TOTAL: Duration = Duration::from_secs(5 * 24 * 60 * 60);
-  const MAX_INCREMENT: Duration = Duration::from_secs(24 * 60 * 60);
+  const MIN_PER_MOVE: Duration = Duration::from_secs(1);
 
   pub fn is_valid(&self) -> bool {
-    self.total >= Self::MIN_TOTAL && self.total <= Self::MAX_TOTAL && self.increment <= Self::MAX_INCREMENT
+    (self.total >= Self::MIN_TOTAL || self.control.has_periods())
+      && self.total <= Self::MAX_TOTAL
+      && self.control.is_valid()
+      && self
+        .max_per_move
+        .is_none_or(|max_per_move| (Self::MIN_PER_MOVE..=Self::MAX_TOTAL).contains(&max_per_move))
   }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
-pub enum Opening {
-  Cross,
-  TwoCrosses,
-  TripleCross,
+pub use oppai_initial::initial::InitialPosition as Opening;
+
+/// Games a player is ready to play when matched automatically.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchPreferences {
+  pub min_size: FieldSize,
+  pub max_size: FieldSize,
+  pub time: GameTime,
+  pub opening: Opening,
+  /// Doubled komi added to red's score, so that half-point komi is possible.
+  #[serde(default)]
+  pub komi_x_2: i32,
+  /// Whether the game result affects ratings.
+  #[serde(default = "rated_by_default")]
+  pub rated: bool,
+}
+
+impl MatchPreferences {
+  pub fn is_valid(&self) -> bool {
+    self.min_size.is_valid()
+      && self.max_size.is_valid()
+      && self.min_size.width <= self.max_size.width
+      && self.min_size.height <= self.max_size.height
+      && self.time.is_valid()
+      && self.komi_x_2.unsigned_abs() <= GameConfig::MAX_KOMI_X_2
+  }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
@@ -67,11 +181,23 @@ pub struct GameConfig {
   pub size: FieldSize,
   pub time: GameTime,
   pub opening: Opening,
+  /// Doubled komi added to red's score, so that half-point komi is possible.
+  #[serde(default)]
+  pub komi_x_2: i32,
+  /// Whether the game result affects ratings. Takebacks are allowed only in unrated games.
+  #[serde(default = "rated_by_default")]
+  pub rated: bool,
+}
+
+fn rated_by_default() -> bool {
+  true
 }
 
 impl GameConfig {
+  const MAX_KOMI_X_2: u32 = 100;
+
   pub fn is_valid(&self) -> bool {
-    self.size.is_valid() && self.time.is_valid()
+    self.size.is_valid() && self.time.is_valid() && self.komi_x_2.unsigned_abs() <= Self::MAX_KOMI_X_2
   }
 }
 
@@ -94,6 +220,7 @@ pub struct Player {
   pub rating: f64,
   pub deviation: f64,
   pub volatility: f64,
+  pub bot: bool,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -104,6 +231,27 @@ pub struct OpenGame {
   pub config: GameConfig,
 }
 
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Challenge {
+  pub player_id: PlayerId,
+  pub player: Player,
+  pub opponent_id: PlayerId,
+  pub opponent: Player,
+  /// Color of the challenging player.
+  pub color: Color,
+  pub config: GameConfig,
+  /// Finished game this challenge is a rematch of.
+  pub rematch: Option<GameId>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ChallengeCloseReason {
+  Declined,
+  Cancelled,
+  Expired,
+}
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Game {
@@ -131,10 +279,227 @@ pub enum WinReason {
 #[serde(tag = "type")]
 #[serde(rename_all_fields = "camelCase")]
 pub enum GameResult {
-  Win { winner: Color, reason: WinReason },
-  Draw { reason: DrawReason },
+  Win {
+    winner: Color,
+    reason: WinReason,
+  },
+  Draw {
+    reason: DrawReason,
+  },
+  /// Closed by a moderator without a winner.
+  Aborted,
+}
+
+/// Game result from a player's point of view.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Outcome {
+  Win,
+  Loss,
+  Draw,
+}
+
+#[serde_as]
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GamesFilter {
+  #[serde(default)]
+  pub outcome: Option<Outcome>,
+  #[serde(default)]
+  pub opponent_id: Option<PlayerId>,
+  #[serde(default)]
+  pub size: Option<FieldSize>,
+  /// Games started at or after this time since the epoch.
+  #[serde_as(as = "Option<DurationMilliSeconds>")]
+  #[serde(default)]
+  pub from: Option<Duration>,
+  /// Games started before this time since the epoch.
+  #[serde_as(as = "Option<DurationMilliSeconds>")]
+  #[serde(default)]
+  pub to: Option<Duration>,
+}
+
+#[serde_as]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FinishedGame {
+  pub game_id: GameId,
+  pub game: Game,
+  #[serde_as(as = "DurationMilliSeconds")]
+  pub start_time: Duration,
+  #[serde_as(as = "DurationMilliSeconds")]
+  pub finish_time: Duration,
+  pub result: GameResult,
+}
+
+#[serde_as]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct TimedMove {
+  pub coordinate: Coordinate,
+  pub player: Color,
+  #[serde_as(as = "DurationMilliSeconds")]
+  pub timestamp: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all_fields = "camelCase")]
+pub enum TournamentFormat {
+  /// Everyone plays everyone once.
+  RoundRobin,
+  /// Players with similar scores are paired for a fixed number of rounds.
+  Swiss { rounds: u32 },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum TournamentStatus {
+  Registration,
+  Running,
+  Finished,
+  Cancelled,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all_fields = "camelCase")]
+pub enum TournamentResult {
+  Win {
+    winner: Color,
+  },
+  Draw,
+  /// The game was closed by a moderator. It counts for neither player.
+  Void,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TournamentPairing {
+  pub red_player_id: PlayerId,
+  /// No opponent means a bye for the red player.
+  pub black_player_id: Option<PlayerId>,
+  pub game_id: Option<GameId>,
+  pub result: Option<TournamentResult>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Standing {
+  pub player_id: PlayerId,
+  pub points: f64,
+  /// Sum of opponents' points.
+  pub buchholz: f64,
+  /// Sum of points of beaten opponents and half of points of drawn opponents.
+  pub sonneborn_berger: f64,
+  pub wins: u32,
+  pub draws: u32,
+  pub losses: u32,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TournamentPlayer {
+  pub player_id: PlayerId,
+  pub player: Player,
+}
+
+#[serde_as]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tournament {
+  pub name: String,
+  pub creator_id: PlayerId,
+  pub config: GameConfig,
+  pub format: TournamentFormat,
+  /// Time since the epoch when registration closes and the first round starts.
+  #[serde_as(as = "DurationMilliSeconds")]
+  pub start_time: Duration,
+  pub status: TournamentStatus,
+  /// Registered players in the order of registration.
+  pub players: Vec<TournamentPlayer>,
+  pub rounds: Vec<Vec<TournamentPairing>>,
+  /// Players sorted by their place.
+  pub standings: Vec<Standing>,
+}
+
+#[serde_as]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+  pub player_id: PlayerId,
+  pub nickname: String,
+  pub text: String,
+  #[serde_as(as = "DurationMilliSeconds")]
+  pub timestamp: Duration,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardEntry {
+  pub player_id: PlayerId,
+  pub player: Player,
+}
+
+/// Player's rating right after a rated game.
+#[serde_as]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RatingChange {
+  pub game_id: GameId,
+  pub rating: f64,
+  pub deviation: f64,
+  pub volatility: f64,
+  #[serde_as(as = "DurationMilliSeconds")]
+  pub timestamp: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ModerationAction {
+  Ban,
+  Unban,
+  Mute,
+  Unmute,
+  CloseGame,
+  Rename,
+}
+
+/// Action of an admin.
+#[serde_as]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogEntry {
+  pub admin_id: PlayerId,
+  pub action: ModerationAction,
+  pub player_id: Option<PlayerId>,
+  pub game_id: Option<GameId>,
+  pub details: Option<String>,
+  /// Time since the epoch.
+  #[serde_as(as = "DurationMilliSeconds")]
+  pub timestamp: Duration,
 }
 
+/// Engine analysis of the position before a move.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveAnalysis {
+  /// Index of the move in the game including the opening.
+  pub number: u32,
+  /// Probability of winning for the player making the move.
+  pub winrate: Option<f64>,
+  /// The move the engine prefers.
+  pub best_move: Option<Coordinate>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpponentStats {
+  pub player_id: PlayerId,
+  pub player: Player,
+  pub games: u64,
+  pub wins: u64,
+  pub losses: u64,
+  pub draws: u64,
+}
+
+#[serde_as]
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(tag = "command")]
 #[serde(rename_all_fields = "camelCase")]
@@ -151,6 +516,10 @@ pub enum Request {
   AuthTest {
     name: String,
   },
+  /// Authenticate as a bot player with a token from the server config.
+  AuthBot {
+    token: String,
+  },
   SignOut,
   /// Create a new game in a lobby.
   Create {
@@ -164,6 +533,29 @@ pub enum Request {
   Join {
     game_id: GameId,
   },
+  /// Offer a game to a specific player.
+  Challenge {
+    player_id: PlayerId,
+    config: GameConfig,
+  },
+  /// Wait for an automatically matched opponent.
+  Enqueue {
+    preferences: MatchPreferences,
+  },
+  /// Stop waiting for an opponent.
+  Dequeue,
+  /// Offer a game with swapped colors to the opponent of a finished game.
+  Rematch {
+    game_id: GameId,
+  },
+  /// Accept a challenge and start the game.
+  AcceptChallenge {
+    game_id: GameId,
+  },
+  /// Decline a received challenge or cancel a sent one.
+  DeclineChallenge {
+    game_id: GameId,
+  },
   /// Subscribe to game moves.
   Subscribe {
     game_id: GameId,
@@ -181,10 +573,41 @@ pub enum Request {
   Resign {
     game_id: GameId,
   },
+  /// End a game by grounding, conceding all own points that aren't grounded.
+  Ground {
+    game_id: GameId,
+  },
   /// Offer or accept a draw.
   Draw {
     game_id: GameId,
   },
+  /// Ask to take back the last own move in an unrated game, cancel the request
+  /// or accept the opponent's one.
+  Takeback {
+    game_id: GameId,
+  },
+  /// Create a tournament open for registration until its start time.
+  CreateTournament {
+    name: String,
+    config: GameConfig,
+    format: TournamentFormat,
+    #[serde_as(as = "DurationMilliSeconds")]
+    start_time: Duration,
+  },
+  /// Register for a tournament.
+  RegisterTournament {
+    tournament_id: TournamentId,
+  },
+  /// Cancel a tournament registration.
+  UnregisterTournament {
+    tournament_id: TournamentId,
+  },
+  /// Send a chat message to a game or to the lobby.
+  Chat {
+    #[serde(default)]
+    game_id: Option<GameId>,
+    text: String,
+  },
   /// Change user's own nickname.
   ChangeNickname {
     nickname: String,
@@ -193,6 +616,64 @@ pub enum Request {
   CheckNickname {
     nickname: String,
   },
+  /// List finished games of a player, the most recent first.
+  GetGames {
+    player_id: PlayerId,
+    #[serde(default)]
+    filter: GamesFilter,
+    page: u32,
+  },
+  /// Get a finished game with all its moves.
+  GetGame {
+    game_id: GameId,
+  },
+  /// Export a game to SGF.
+  GetSgf {
+    game_id: GameId,
+  },
+  /// List rated players, the highest rated first.
+  GetLeaderboard {
+    page: u32,
+  },
+  /// Get rating history and statistics of a player.
+  GetProfile {
+    player_id: PlayerId,
+  },
+  /// Get engine analysis of a finished game.
+  GetAnalysis {
+    game_id: GameId,
+  },
+  /// Ban a player and close their connections. Admins only.
+  Ban {
+    player_id: PlayerId,
+  },
+  /// Lift a ban. Admins only.
+  Unban {
+    player_id: PlayerId,
+  },
+  /// Forbid a player to chat for some time. Admins only.
+  Mute {
+    player_id: PlayerId,
+    #[serde_as(as = "DurationSeconds")]
+    duration: Duration,
+  },
+  /// Allow a muted player to chat again. Admins only.
+  Unmute {
+    player_id: PlayerId,
+  },
+  /// Close an open or a live game without a result. Admins only.
+  ForceClose {
+    game_id: GameId,
+  },
+  /// Replace an abusive nickname. Admins only.
+  Rename {
+    player_id: PlayerId,
+    nickname: String,
+  },
+  /// Get the most recent admin actions. Admins only.
+  GetAuditLog {
+    page: u32,
+  },
 }
 
 #[serde_as]
@@ -205,7 +686,15 @@ pub enum Response {
     player_id: Option<PlayerId>,
     players: HashMap<PlayerId, Player>,
     open_games: HashMap<GameId, OpenGame>,
+    /// Challenges sent by or to the player.
+    challenges: HashMap<GameId, Challenge>,
     games: HashMap<GameId, Game>,
+    /// Recent lobby chat messages, the oldest first.
+    chat: Vec<ChatMessage>,
+    /// Tournaments that aren't finished yet.
+    tournaments: HashMap<TournamentId, Tournament>,
+    /// Match preferences if the player waits for an opponent.
+    queue: Option<MatchPreferences>,
   },
   /// First message after subscription.
   GameInit {
@@ -217,6 +706,8 @@ pub enum Response {
     time_left: TimeLeft,
     draw_offer: Option<Color>,
     result: Option<GameResult>,
+    /// Recent game chat messages, the oldest first.
+    chat: Vec<ChatMessage>,
   },
   AuthUrl {
     url: String,
@@ -242,6 +733,22 @@ pub enum Response {
   Close {
     game_id: GameId,
   },
+  /// A challenge was sent by or to the player.
+  Challenge {
+    game_id: GameId,
+    challenge: Challenge,
+  },
+  /// The player is waiting for an opponent.
+  Enqueued {
+    preferences: MatchPreferences,
+  },
+  /// The player stopped waiting for an opponent because of a request or a match.
+  Dequeued,
+  /// A challenge was closed without starting a game.
+  ChallengeClosed {
+    game_id: GameId,
+    reason: ChallengeCloseReason,
+  },
   /// A new game started.
   Start {
     game_id: GameId,
@@ -262,11 +769,34 @@ pub enum Response {
     player: Color,
     offer: bool,
   },
+  /// Ask for or cancel a takeback.
+  Takeback {
+    game_id: GameId,
+    player: Color,
+    offer: bool,
+  },
+  /// Last moves were taken back after the opponent accepted a takeback.
+  Undo {
+    game_id: GameId,
+    /// Number of removed moves.
+    moves: u32,
+    time_left: TimeLeft,
+  },
   GameResult {
     game_id: GameId,
     time_left: TimeLeft,
     result: GameResult,
   },
+  /// A tournament was created or changed.
+  Tournament {
+    tournament_id: TournamentId,
+    tournament: Tournament,
+  },
+  /// A chat message in a game or in the lobby.
+  Chat {
+    game_id: Option<GameId>,
+    message: ChatMessage,
+  },
   /// A player changed their nickname.
   NicknameChanged {
     player_id: PlayerId,
@@ -285,4 +815,56 @@ pub enum Response {
     black_player_id: PlayerId,
     black_player: Player,
   },
+  /// A page of player's finished games.
+  Games {
+    player_id: PlayerId,
+    page: u32,
+    games: Vec<FinishedGame>,
+  },
+  /// A finished game with all its moves.
+  Game {
+    game: FinishedGame,
+    moves: Vec<TimedMove>,
+  },
+  /// A game exported to SGF.
+  Sgf {
+    game_id: GameId,
+    sgf: String,
+  },
+  /// A page of the leaderboard.
+  Leaderboard {
+    page: u32,
+    players: Vec<LeaderboardEntry>,
+  },
+  /// Player's profile.
+  Profile {
+    player_id: PlayerId,
+    player: Player,
+    rating_history: Vec<RatingChange>,
+    wins: u64,
+    losses: u64,
+    draws: u64,
+    /// Opponents with the most finished games against the player.
+    opponents: Vec<OpponentStats>,
+  },
+  /// Engine analysis of a finished game, empty if it's not analyzed yet.
+  Analysis {
+    game_id: GameId,
+    moves: Vec<MoveAnalysis>,
+  },
+  /// The server is shutting down: new games can't be started while the live
+  /// ones are played out.
+  Shutdown,
+  /// The player was banned, the connection is closed.
+  Banned,
+  /// The player can't chat until the time since the epoch.
+  Muted {
+    #[serde_as(as = "DurationMilliSeconds")]
+    until: Duration,
+  },
+  /// Page of the audit log, the newest actions first.
+  AuditLog {
+    page: u32,
+    entries: Vec<AuditLogEntry>,
+  },
 }
@@ -0,0 +1,204 @@
+use crate::{SessionShared, db::Db, state::State};
+use std::{
+  collections::VecDeque,
+  fmt::Write as _,
+  io,
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, Ordering},
+  },
+  time::{Duration, Instant},
+};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+  net::{TcpListener, TcpStream},
+};
+
+/// Window moves per minute are counted in.
+const MOVES_WINDOW: Duration = Duration::from_secs(60);
+/// Maximal size of an HTTP request head.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Counters updated by sessions. Gauges like the number of live games are read
+/// from the state on scrape instead.
+#[derive(Default, Debug)]
+pub struct Metrics {
+  moves: AtomicU64,
+  recent_moves: Mutex<VecDeque<Instant>>,
+  auth_failures: AtomicU64,
+}
+
+impl Metrics {
+  pub fn record_move(&self) {
+    self.moves.fetch_add(1, Ordering::Relaxed);
+    let now = Instant::now();
+    let mut recent_moves = self.recent_moves.lock().unwrap();
+    recent_moves.push_back(now);
+    Self::forget_old_moves(&mut recent_moves, now);
+  }
+
+  pub fn record_auth_failure(&self) {
+    self.auth_failures.fetch_add(1, Ordering::Relaxed);
+  }
+
+  fn forget_old_moves(recent_moves: &mut VecDeque<Instant>, now: Instant) {
+    while recent_moves
+      .front()
+      .is_some_and(|&time| now.duration_since(time) > MOVES_WINDOW)
+    {
+      recent_moves.pop_front();
+    }
+  }
+
+  fn moves_per_minute(&self) -> usize {
+    let mut recent_moves = self.recent_moves.lock().unwrap();
+    Self::forget_old_moves(&mut recent_moves, Instant::now());
+    recent_moves.len()
+  }
+}
+
+fn metric(output: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+  writeln!(output, "# HELP {} {}", name, help).unwrap();
+  writeln!(output, "# TYPE {} {}", name, kind).unwrap();
+  writeln!(output, "{} {}", name, value).unwrap();
+}
+
+/// Renders metrics in the Prometheus text format.
+pub async fn render(state: &State, shared: &SessionShared) -> String {
+  let start = Instant::now();
+  let db_up = shared.db.ping().await.is_ok();
+  let db_latency = start.elapsed();
+
+  let mut output = String::new();
+  metric(
+    &mut output,
+    "kropki_sessions",
+    "gauge",
+    "Connected sessions.",
+    state.connections.len(),
+  );
+  metric(
+    &mut output,
+    "kropki_open_games",
+    "gauge",
+    "Games waiting for an opponent.",
+    state.open_games.len(),
+  );
+  metric(
+    &mut output,
+    "kropki_live_games",
+    "gauge",
+    "Games being played.",
+    state.games.len(),
+  );
+  metric(
+    &mut output,
+    "kropki_moves_total",
+    "counter",
+    "Moves made since the start.",
+    shared.metrics.moves.load(Ordering::Relaxed),
+  );
+  metric(
+    &mut output,
+    "kropki_moves_per_minute",
+    "gauge",
+    "Moves made during the last minute.",
+    shared.metrics.moves_per_minute(),
+  );
+  metric(
+    &mut output,
+    "kropki_auth_failures_total",
+    "counter",
+    "Failed authentication attempts.",
+    shared.metrics.auth_failures.load(Ordering::Relaxed),
+  );
+  metric(
+    &mut output,
+    "kropki_db_up",
+    "gauge",
+    "Whether the database responds.",
+    db_up as u8,
+  );
+  metric(
+    &mut output,
+    "kropki_db_latency_seconds",
+    "gauge",
+    "Round trip time of a database query.",
+    db_latency.as_secs_f64(),
+  );
+  metric(
+    &mut output,
+    "kropki_shutting_down",
+    "gauge",
+    "Whether the server is shutting down.",
+    state.is_shutting_down() as u8,
+  );
+  output
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+  let response = format!(
+    "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+    status,
+    content_type,
+    body.len(),
+    body
+  );
+  stream.write_all(response.as_bytes()).await?;
+  stream.shutdown().await
+}
+
+async fn handle_connection(mut stream: TcpStream, state: &State, shared: &SessionShared) -> io::Result<()> {
+  let mut reader = BufReader::new(&mut stream).take(MAX_REQUEST_SIZE as u64);
+  let mut request_line = String::new();
+  reader.read_line(&mut request_line).await?;
+  // Skip headers since they are not used.
+  let mut line = String::new();
+  while reader.read_line(&mut line).await? > 0 && !line.trim_end().is_empty() {
+    line.clear();
+  }
+  if reader.limit() == 0 {
+    return respond(&mut stream, "431 Request Header Fields Too Large", "text/plain", "").await;
+  }
+
+  let mut parts = request_line.split_whitespace();
+  match (parts.next(), parts.next()) {
+    (Some("GET"), Some("/health")) => {
+      // Report unhealthy while shutting down so that load balancers stop routing new clients.
+      if state.is_shutting_down() {
+        respond(&mut stream, "503 Service Unavailable", "text/plain", "shutting down\n").await
+      } else if let Err(error) = shared.db.ping().await {
+        log::warn!("Health check failed: {}", error);
+        respond(
+          &mut stream,
+          "503 Service Unavailable",
+          "text/plain",
+          "database unavailable\n",
+        )
+        .await
+      } else {
+        respond(&mut stream, "200 OK", "text/plain", "ok\n").await
+      }
+    }
+    (Some("GET"), Some("/metrics")) => {
+      let body = render(state, shared).await;
+      respond(&mut stream, "200 OK", "text/plain; version=0.0.4", &body).await
+    }
+    (Some(_), Some(_)) => respond(&mut stream, "404 Not Found", "text/plain", "").await,
+    _ => respond(&mut stream, "400 Bad Request", "text/plain", "").await,
+  }
+}
+
+/// Serves health and metrics endpoints over plain HTTP.
+pub async fn serve(listener: TcpListener, state: Arc<State>, shared: Arc<SessionShared>) -> io::Result<()> {
+  loop {
+    let (stream, addr) = listener.accept().await?;
+    let state = state.clone();
+    let shared = shared.clone();
+    tokio::spawn(async move {
+      if let Err(error) = handle_connection(stream, &state, &shared).await {
+        log::debug!("Failed to serve metrics to {}: {}", addr, error);
+      }
+    });
+  }
+}
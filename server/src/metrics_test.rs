@@ -0,0 +1,170 @@
+use crate::{
+  Session, SessionShared,
+  message::{Coordinate, FieldSize, GameConfig, GameTime, Opening, Request, Response, TimeControl},
+  metrics, shutdown,
+  state::State,
+  test_utils::{connect, shared, wait_for},
+};
+use futures::channel::mpsc;
+use rand::{make_rng, rngs::StdRng};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::net::TcpListener;
+
+fn game_config() -> GameConfig {
+  GameConfig {
+    size: FieldSize { width: 10, height: 10 },
+    time: GameTime {
+      total: Duration::from_secs(60),
+      control: TimeControl::Fischer {
+        increment: Duration::ZERO,
+      },
+      max_per_move: None,
+    },
+    opening: Opening::Cross,
+    komi_x_2: 0,
+    rated: false,
+  }
+}
+
+async fn serve_metrics(state: &Arc<State>, shared: &Arc<SessionShared>) -> SocketAddr {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+  tokio::spawn(metrics::serve(listener, state.clone(), shared.clone()));
+  addr
+}
+
+async fn get(addr: SocketAddr, path: &str) -> (u16, String) {
+  let response = reqwest::get(format!("http://{}{}", addr, path)).await.unwrap();
+  (response.status().as_u16(), response.text().await.unwrap())
+}
+
+fn metric_value(metrics: &str, name: &str) -> f64 {
+  metrics
+    .lines()
+    .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+    .unwrap_or_else(|| panic!("no metric {}", name))
+    .parse()
+    .unwrap()
+}
+
+#[tokio::test]
+async fn metrics_reflect_server_activity() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+  let addr = serve_metrics(&state, &shared).await;
+
+  let (mut alice, mut alice_rx) = connect(&state, &shared, "alice").await;
+  let (mut bob, _bob_rx) = connect(&state, &shared, "bob").await;
+
+  let mut intruder = Session::new(shared.clone(), make_rng::<StdRng>());
+  let (tx, _intruder_rx) = mpsc::channel(32);
+  intruder.init(&state, tx).await.unwrap();
+  assert!(
+    intruder
+      .handle(
+        &state,
+        Request::AuthBot {
+          token: "wrong".to_string()
+        }
+      )
+      .await
+      .is_err()
+  );
+
+  alice
+    .handle(&state, Request::Create { config: game_config() })
+    .await
+    .unwrap();
+  let game_id = wait_for(&mut alice_rx, |response| match response {
+    Response::Create { game_id, .. } => Some(game_id),
+    _ => None,
+  })
+  .await;
+
+  let (_, body) = get(addr, "/metrics").await;
+  assert_eq!(metric_value(&body, "kropki_sessions"), 3.0);
+  assert_eq!(metric_value(&body, "kropki_open_games"), 1.0);
+  assert_eq!(metric_value(&body, "kropki_live_games"), 0.0);
+  assert_eq!(metric_value(&body, "kropki_auth_failures_total"), 1.0);
+
+  bob.handle(&state, Request::Join { game_id }).await.unwrap();
+  alice
+    .handle(
+      &state,
+      Request::PutPoint {
+        game_id,
+        coordinate: Coordinate { x: 1, y: 1 },
+      },
+    )
+    .await
+    .unwrap();
+
+  let (status, body) = get(addr, "/metrics").await;
+  assert_eq!(status, 200);
+  assert_eq!(metric_value(&body, "kropki_open_games"), 0.0);
+  assert_eq!(metric_value(&body, "kropki_live_games"), 1.0);
+  assert_eq!(metric_value(&body, "kropki_moves_total"), 1.0);
+  assert_eq!(metric_value(&body, "kropki_moves_per_minute"), 1.0);
+  assert_eq!(metric_value(&body, "kropki_db_up"), 1.0);
+
+  assert_eq!(get(addr, "/health").await, (200, "ok\n".to_string()));
+  assert_eq!(get(addr, "/unknown").await.0, 404);
+}
+
+#[tokio::test]
+async fn shutdown_waits_for_live_games() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+  let addr = serve_metrics(&state, &shared).await;
+
+  let (mut alice, mut alice_rx) = connect(&state, &shared, "alice").await;
+  let (mut bob, _bob_rx) = connect(&state, &shared, "bob").await;
+  let (mut carol, mut carol_rx) = connect(&state, &shared, "carol").await;
+
+  alice
+    .handle(&state, Request::Create { config: game_config() })
+    .await
+    .unwrap();
+  let game_id = wait_for(&mut alice_rx, |response| match response {
+    Response::Create { game_id, .. } => Some(game_id),
+    _ => None,
+  })
+  .await;
+  bob.handle(&state, Request::Join { game_id }).await.unwrap();
+  carol
+    .handle(&state, Request::Create { config: game_config() })
+    .await
+    .unwrap();
+  let open_game_id = *state.open_games.pin().keys().next().unwrap();
+
+  let shutting_down = tokio::spawn({
+    let state = state.clone();
+    async move { shutdown(&state, Duration::from_secs(10)).await }
+  });
+
+  wait_for(&mut carol_rx, |response| match response {
+    Response::Close { game_id } if game_id == open_game_id => Some(()),
+    _ => None,
+  })
+  .await;
+  wait_for(&mut carol_rx, |response| match response {
+    Response::Shutdown => Some(()),
+    _ => None,
+  })
+  .await;
+  assert_eq!(get(addr, "/health").await.0, 503);
+
+  // New games are not created while shutting down.
+  carol
+    .handle(&state, Request::Create { config: game_config() })
+    .await
+    .unwrap();
+  assert!(state.open_games.is_empty());
+  assert!(!shutting_down.is_finished());
+
+  alice.handle(&state, Request::Resign { game_id }).await.unwrap();
+  tokio::time::timeout(Duration::from_secs(5), shutting_down)
+    .await
+    .expect("shutdown didn't finish after the last game")
+    .unwrap();
+}
@@ -0,0 +1,280 @@
+use crate::{
+  Session, SessionShared,
+  config::RateLimitConfig,
+  db::{self, Db},
+  message::{FieldSize, GameConfig, GameResult, GameTime, ModerationAction, Opening, Request, Response, TimeControl},
+  state::State,
+  test_utils::{connect, next, session_shared, shared, wait_for},
+};
+use futures::channel::mpsc::{self, Receiver};
+use rand::{make_rng, rngs::StdRng};
+use std::{sync::Arc, time::Duration};
+
+fn game_config() -> GameConfig {
+  GameConfig {
+    size: FieldSize { width: 10, height: 10 },
+    time: GameTime {
+      total: Duration::from_secs(60),
+      control: TimeControl::Fischer {
+        increment: Duration::ZERO,
+      },
+      max_per_move: None,
+    },
+    opening: Opening::Cross,
+    komi_x_2: 0,
+    rated: true,
+  }
+}
+
+async fn connect_admin(
+  state: &Arc<State>,
+  shared: &Arc<SessionShared>,
+  name: &str,
+) -> (Session<StdRng>, Receiver<Response>) {
+  let (admin, admin_rx) = connect(state, shared, name).await;
+  shared
+    .db
+    .set_player_admin(admin.player_id.unwrap().0, true)
+    .await
+    .unwrap();
+  (admin, admin_rx)
+}
+
+async fn audit_log(
+  state: &Arc<State>,
+  admin: &mut Session<StdRng>,
+  rx: &mut Receiver<Response>,
+) -> Vec<ModerationAction> {
+  admin.handle(state, Request::GetAuditLog { page: 0 }).await.unwrap();
+  wait_for(rx, |response| match response {
+    Response::AuditLog { entries, .. } => Some(entries.into_iter().map(|entry| entry.action).collect()),
+    _ => None,
+  })
+  .await
+}
+
+#[tokio::test]
+async fn banned_players_are_disconnected_and_rejected() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+  let (mut admin, mut admin_rx) = connect_admin(&state, &shared, "admin").await;
+  let (mut bob, mut bob_rx) = connect(&state, &shared, "bob").await;
+  let bob_id = bob.player_id.unwrap();
+
+  // Only admins can moderate.
+  assert!(
+    bob
+      .handle(
+        &state,
+        Request::Ban {
+          player_id: admin.player_id.unwrap()
+        }
+      )
+      .await
+      .is_err()
+  );
+
+  bob
+    .handle(&state, Request::Create { config: game_config() })
+    .await
+    .unwrap();
+  let game_id = wait_for(&mut admin_rx, |response| match response {
+    Response::Create { game_id, .. } => Some(game_id),
+    _ => None,
+  })
+  .await;
+
+  admin.handle(&state, Request::Ban { player_id: bob_id }).await.unwrap();
+  wait_for(&mut admin_rx, |response| match response {
+    Response::Close { game_id: id } if id == game_id => Some(()),
+    _ => None,
+  })
+  .await;
+  wait_for(&mut bob_rx, |response| match response {
+    Response::Banned => Some(()),
+    _ => None,
+  })
+  .await;
+  // The connection is closed after the ban.
+  while tokio::time::timeout(Duration::from_secs(10), futures_util::StreamExt::next(&mut bob_rx))
+    .await
+    .expect("connection isn't closed")
+    .is_some()
+  {}
+
+  let mut session = Session::new(shared.clone(), make_rng::<StdRng>());
+  let (tx, _rx) = mpsc::channel(32);
+  session.init(&state, tx).await.unwrap();
+  assert!(
+    session
+      .handle(
+        &state,
+        Request::AuthTest {
+          name: "bob".to_string()
+        }
+      )
+      .await
+      .is_err()
+  );
+
+  // Players signed in with a cookie are rejected on connection.
+  let mut session = Session::new(shared.clone(), make_rng::<StdRng>());
+  session.player_id = Some(bob_id);
+  let (tx, _rx) = mpsc::channel(32);
+  assert!(session.init(&state, tx).await.is_err());
+
+  admin
+    .handle(&state, Request::Unban { player_id: bob_id })
+    .await
+    .unwrap();
+  connect(&state, &shared, "bob").await;
+
+  assert_eq!(
+    audit_log(&state, &mut admin, &mut admin_rx).await,
+    vec![ModerationAction::Unban, ModerationAction::Ban]
+  );
+}
+
+#[tokio::test]
+async fn muted_players_cant_chat() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+  let (mut admin, mut admin_rx) = connect_admin(&state, &shared, "admin").await;
+  let (mut bob, mut bob_rx) = connect(&state, &shared, "bob").await;
+  let bob_id = bob.player_id.unwrap();
+
+  admin
+    .handle(
+      &state,
+      Request::Mute {
+        player_id: bob_id,
+        duration: Duration::from_secs(60),
+      },
+    )
+    .await
+    .unwrap();
+  wait_for(&mut bob_rx, |response| match response {
+    Response::Muted { .. } => Some(()),
+    _ => None,
+  })
+  .await;
+
+  let chat = |text: &str| Request::Chat {
+    game_id: None,
+    text: text.to_string(),
+  };
+  bob.handle(&state, chat("spam")).await.unwrap();
+  assert!(matches!(next(&mut bob_rx).await, Response::Muted { .. }));
+
+  admin
+    .handle(&state, Request::Unmute { player_id: bob_id })
+    .await
+    .unwrap();
+  bob.handle(&state, chat("hello")).await.unwrap();
+  let text = wait_for(&mut admin_rx, |response| match response {
+    Response::Chat { message, .. } => Some(message.text),
+    _ => None,
+  })
+  .await;
+  assert_eq!(text, "hello");
+}
+
+#[tokio::test]
+async fn admins_close_games_and_rename_players() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+  let (mut admin, mut admin_rx) = connect_admin(&state, &shared, "admin").await;
+  let (mut alice, mut alice_rx) = connect(&state, &shared, "alice").await;
+  let (mut bob, _bob_rx) = connect(&state, &shared, "bob").await;
+  let bob_id = bob.player_id.unwrap();
+
+  alice
+    .handle(&state, Request::Create { config: game_config() })
+    .await
+    .unwrap();
+  let game_id = wait_for(&mut alice_rx, |response| match response {
+    Response::Create { game_id, .. } => Some(game_id),
+    _ => None,
+  })
+  .await;
+  bob.handle(&state, Request::Join { game_id }).await.unwrap();
+  alice.handle(&state, Request::Subscribe { game_id }).await.unwrap();
+
+  admin.handle(&state, Request::ForceClose { game_id }).await.unwrap();
+  let result = wait_for(&mut alice_rx, |response| match response {
+    Response::GameResult {
+      game_id: id, result, ..
+    } if id == game_id => Some(result),
+    _ => None,
+  })
+  .await;
+  assert_eq!(result, GameResult::Aborted);
+  assert!(state.games.is_empty());
+  let game = shared.db.get_game(game_id.0).await.unwrap().game;
+  assert_eq!(game.result, Some(db::GameResult::Aborted));
+  // Aborted games don't change ratings.
+  assert_eq!(shared.db.get_player(bob_id.0).await.unwrap().rating, 1500.0);
+
+  admin
+    .handle(
+      &state,
+      Request::Rename {
+        player_id: bob_id,
+        nickname: "renamed".to_string(),
+      },
+    )
+    .await
+    .unwrap();
+  let nickname = wait_for(&mut alice_rx, |response| match response {
+    Response::NicknameChanged { player_id, player } if player_id == bob_id => Some(player.nickname),
+    _ => None,
+  })
+  .await;
+  assert_eq!(nickname, "renamed");
+
+  assert_eq!(
+    audit_log(&state, &mut admin, &mut admin_rx).await,
+    vec![ModerationAction::Rename, ModerationAction::CloseGame]
+  );
+}
+
+#[tokio::test]
+async fn requests_over_the_limit_are_dropped() {
+  let state = Arc::new(State::default());
+  let shared = Arc::new(SessionShared {
+    rate_limit: RateLimitConfig {
+      connection: 3,
+      player: 3,
+    },
+    ..session_shared()
+  });
+
+  let check = || Request::CheckNickname {
+    nickname: "carol".to_string(),
+  };
+  let responses = |rx: &mut Receiver<Response>| {
+    std::iter::from_fn(|| rx.try_recv().ok())
+      .filter(|response| matches!(response, Response::NicknameAvailable { .. }))
+      .count()
+  };
+
+  // Authentication is a request as well.
+  let (mut bob1, mut bob1_rx) = connect(&state, &shared, "bob").await;
+  for _ in 0..3 {
+    bob1.handle_client_request(&state, check()).await.unwrap();
+  }
+  assert_eq!(responses(&mut bob1_rx), 2);
+
+  // The player limit is shared between connections.
+  let (mut bob2, mut bob2_rx) = connect(&state, &shared, "bob").await;
+  for _ in 0..3 {
+    bob2.handle_client_request(&state, check()).await.unwrap();
+  }
+  assert_eq!(responses(&mut bob2_rx), 1);
+
+  // Sessions of the server itself, like bots, aren't limited.
+  for _ in 0..3 {
+    bob2.handle(&state, check()).await.unwrap();
+  }
+  assert_eq!(responses(&mut bob2_rx), 3);
+}
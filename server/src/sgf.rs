@@ -0,0 +1,102 @@
+use crate::{db, to_epoch};
+use oppai_field::{extended_field::ExtendedField, player::Player};
+use rand::Rng;
+use sgf_parse::{GameTree, SimpleText, Text, serialize, unknown_game::Prop};
+use std::iter;
+
+fn opening_name(opening: db::Opening) -> &'static str {
+  match opening {
+    db::Opening::Cross => "cross",
+    db::Opening::TwoCrosses => "two crosses",
+    db::Opening::TripleCross => "triple cross",
+    db::Opening::Empty => "empty",
+    db::Opening::FourCrosses => "four crosses",
+  }
+}
+
+/// SGF result. Red plays white and black plays black.
+fn result(result: db::GameResult, field: &ExtendedField, komi_x_2: i32) -> String {
+  let score = (field.field.score(Player::Red) * 2 + komi_x_2).abs() as f64 / 2.0;
+  match result {
+    db::GameResult::ResignedRed => "B+R".to_string(),
+    db::GameResult::ResignedBlack => "W+R".to_string(),
+    db::GameResult::TimeOutRed => "B+T".to_string(),
+    db::GameResult::TimeOutBlack => "W+T".to_string(),
+    db::GameResult::GroundedRed => format!("B+{}", score),
+    db::GameResult::GroundedBlack => format!("W+{}", score),
+    db::GameResult::DrawAgreement | db::GameResult::DrawGrounded => "0".to_string(),
+    db::GameResult::Aborted => "Void".to_string(),
+  }
+}
+
+fn simple_text(text: String) -> SimpleText {
+  SimpleText { text }
+}
+
+/// Exports a game with its players to SGF. Moves are annotated with their
+/// timestamps in milliseconds since the epoch in the non-standard `TS` property.
+pub fn to_sgf_str<R: Rng>(
+  game_with_moves: &db::GameWithMoves,
+  red_player: &db::Player,
+  black_player: &db::Player,
+  rng: &mut R,
+) -> Option<String> {
+  let game = &game_with_moves.game;
+  let mut field = ExtendedField::new_from_rng(game.width as u32, game.height as u32, rng);
+  let mut timestamps = Vec::with_capacity(game_with_moves.moves.len());
+  for m in &game_with_moves.moves {
+    let pos = field.field.to_pos(m.x as u32, m.y as u32);
+    if field.put_players_point(pos, m.player.into()) {
+      timestamps.push(m.timestamp);
+    } else {
+      log::warn!("Invalid move {} in game {}", m.number, game.id);
+    }
+  }
+
+  let mut root = oppai_sgf::to_sgf(&field)?;
+
+  root.properties.extend([
+    Prop::PW(simple_text(red_player.nickname.clone())),
+    Prop::PB(simple_text(black_player.nickname.clone())),
+    Prop::WR(simple_text(format!("{:.0}", red_player.rating))),
+    Prop::BR(simple_text(format!("{:.0}", black_player.rating))),
+    Prop::DT(simple_text(game.start_time.date().to_string())),
+    Prop::TM(game.total_time_ms as f64 / 1000.0),
+    Prop::OT(simple_text(overtime(game))),
+    Prop::GC(Text {
+      text: format!("{} opening", opening_name(game.opening)),
+    }),
+  ]);
+  if game.komi_x_2 != 0 {
+    root.properties.push(Prop::Unknown(
+      "KM".to_string(),
+      vec![(game.komi_x_2 as f64 / 2.0).to_string()],
+    ));
+  }
+  if let Some(game_result) = game.result {
+    root
+      .properties
+      .push(Prop::RE(simple_text(result(game_result, &field, game.komi_x_2))));
+  }
+
+  let mut node = &mut root;
+  for timestamp in timestamps {
+    node = node.children.first_mut()?;
+    node.properties.push(Prop::Unknown(
+      "TS".to_string(),
+      vec![to_epoch(timestamp).as_millis().to_string()],
+    ));
+  }
+
+  Some(serialize(iter::once(&GameTree::Unknown(root))))
+}
+
+fn overtime(game: &db::Game) -> String {
+  let seconds = |ms: i64| ms as f64 / 1000.0;
+  match game.time_control {
+    db::TimeControl::Fischer => format!("{} fischer", seconds(game.increment_ms)),
+    db::TimeControl::Japanese => format!("{}x{} byo-yomi", game.periods, seconds(game.period_ms)),
+    db::TimeControl::Canadian => format!("{}/{} canadian", game.periods, seconds(game.period_ms)),
+    db::TimeControl::Delay => format!("{} delay", seconds(game.increment_ms)),
+  }
+}
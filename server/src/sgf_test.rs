@@ -0,0 +1,82 @@
+use crate::{db, sgf::to_sgf_str};
+use oppai_field::field::Field;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use time::{Date, Month, PrimitiveDateTime, Time};
+use uuid::Uuid;
+
+fn player(nickname: &str, rating: f64) -> db::Player {
+  db::Player {
+    id: Uuid::nil(),
+    nickname: nickname.to_string(),
+    rating,
+    deviation: 350.0,
+    volatility: 0.06,
+    bot: false,
+    admin: false,
+    banned: false,
+    muted_until: None,
+  }
+}
+
+#[test]
+fn finished_game() {
+  let start_time = PrimitiveDateTime::new(
+    Date::from_calendar_date(2025, Month::March, 14).unwrap(),
+    Time::from_hms(12, 0, 0).unwrap(),
+  );
+  let moves = [(4, 4), (4, 5), (5, 5), (5, 4), (0, 0)]
+    .into_iter()
+    .enumerate()
+    .map(|(i, (x, y))| db::Move {
+      game_id: Uuid::nil(),
+      player: if i % 2 == 0 { db::Color::Red } else { db::Color::Black },
+      number: i as i16,
+      x,
+      y,
+      timestamp: start_time + time::Duration::seconds(i as i64),
+    })
+    .collect();
+  let game_with_moves = db::GameWithMoves {
+    game: db::Game {
+      id: Uuid::nil(),
+      red_player_id: Uuid::nil(),
+      black_player_id: Uuid::nil(),
+      start_time,
+      width: 10,
+      height: 10,
+      total_time_ms: 300_000,
+      increment_ms: 5_000,
+      time_control: db::TimeControl::Fischer,
+      period_ms: 0,
+      periods: 0,
+      max_move_time_ms: None,
+      opening: db::Opening::Cross,
+      komi_x_2: 1,
+      rated: true,
+      finish_time: Some(start_time + time::Duration::seconds(10)),
+      result: Some(db::GameResult::ResignedBlack),
+    },
+    moves,
+  };
+
+  let mut rng = StdRng::seed_from_u64(7);
+  let sgf = to_sgf_str(
+    &game_with_moves,
+    &player("red", 1612.4),
+    &player("black", 1500.0),
+    &mut rng,
+  )
+  .unwrap();
+
+  assert_eq!(
+    sgf,
+    "(;GM[40]SZ[10:10]RU[russian]PW[red]PB[black]WR[1612]BR[1500]DT[2025-03-14]TM[300]OT[5 fischer]\
+     GC[cross opening]KM[0.5]RE[W+R]\
+     ;W[ee]TS[1741953600000];B[ef]TS[1741953601000];W[ff]TS[1741953602000];B[fe]TS[1741953603000]\
+     ;W[aa]TS[1741953604000])"
+  );
+
+  let field: Field = oppai_sgf::from_sgf_str(&sgf, &mut rng).unwrap();
+  assert_eq!(field.moves_count(), 5);
+}
@@ -1,14 +1,20 @@
 use crate::{
   ids::*,
-  message::{Opening, Response},
+  message::{Opening, PeriodLeft, Response, TimeControl, TimeLeft},
+  tournament::Tournament,
 };
 use anyhow::Result;
 use futures::channel::mpsc::Sender;
 use imbl::HashSet as ImHashSet;
 use oppai_field::{field::Field, player::Player};
 use papaya::{Compute, HashMap, Operation};
+use skillratings::glicko2::Glicko2Rating;
 use std::{
-  sync::Arc,
+  collections::VecDeque,
+  sync::{
+    Arc,
+    atomic::{self, AtomicBool},
+  },
   time::{Duration, SystemTime},
 };
 use tokio::{
@@ -25,7 +31,127 @@ pub struct FieldSize {
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct GameTime {
   pub total: Duration,
-  pub increment: Duration,
+  pub control: TimeControl,
+  pub max_per_move: Option<Duration>,
+}
+
+/// Time a player has left.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Clock {
+  pub main: Duration,
+  /// Time left in the current byo-yomi period.
+  pub period: Duration,
+  /// Japanese byo-yomi periods left including the current one.
+  pub periods: u32,
+  /// Moves left to make in the current Canadian byo-yomi period.
+  pub moves: u32,
+}
+
+impl GameTime {
+  pub fn clock(&self) -> Clock {
+    let (period, periods, moves) = match self.control {
+      TimeControl::Japanese { period, periods } => (period, periods, 0),
+      TimeControl::Canadian { period, moves } => (period, 0, moves),
+      TimeControl::Fischer { .. } | TimeControl::Delay { .. } => (Duration::ZERO, 0, 0),
+    };
+    Clock {
+      main: self.total,
+      period,
+      periods,
+      moves,
+    }
+  }
+
+  /// Time a player on turn has until the timeout.
+  pub fn remaining(&self, clock: &Clock) -> Duration {
+    let extra = match self.control {
+      TimeControl::Fischer { .. } => Duration::ZERO,
+      TimeControl::Delay { delay } => delay,
+      TimeControl::Japanese { period, .. } => match clock.periods {
+        0 => Duration::ZERO,
+        periods => clock.period + period * (periods - 1),
+      },
+      TimeControl::Canadian { .. } => clock.period,
+    };
+    let remaining = clock.main + extra;
+    self
+      .max_per_move
+      .map_or(remaining, |max_per_move| remaining.min(max_per_move))
+  }
+
+  /// Spends time of a turn: the main time first and then byo-yomi periods.
+  pub fn spend(&self, clock: &mut Clock, elapsed: Duration) {
+    let elapsed = match self.control {
+      TimeControl::Delay { delay } => elapsed.saturating_sub(delay),
+      _ => elapsed,
+    };
+    let from_main = elapsed.min(clock.main);
+    clock.main -= from_main;
+    let mut overtime = elapsed - from_main;
+
+    match self.control {
+      TimeControl::Fischer { .. } | TimeControl::Delay { .. } => {}
+      TimeControl::Japanese { period, .. } => {
+        while !overtime.is_zero() && clock.periods > 0 {
+          if overtime < clock.period {
+            clock.period -= overtime;
+            break;
+          }
+          overtime -= clock.period;
+          clock.periods -= 1;
+          clock.period = if clock.periods > 0 { period } else { Duration::ZERO };
+        }
+      }
+      TimeControl::Canadian { .. } => clock.period = clock.period.saturating_sub(overtime),
+    }
+  }
+
+  /// Charges a player for a move made after `elapsed` time on turn.
+  pub fn charge(&self, clock: &mut Clock, elapsed: Duration) {
+    self.spend(clock, elapsed);
+
+    match self.control {
+      TimeControl::Fischer { increment } => clock.main += increment,
+      TimeControl::Delay { .. } => {}
+      TimeControl::Japanese { period, .. } => {
+        if clock.main.is_zero() && clock.periods > 0 {
+          clock.period = period;
+        }
+      }
+      TimeControl::Canadian { period, moves } => {
+        if clock.main.is_zero() {
+          clock.moves = clock.moves.saturating_sub(1);
+          if clock.moves == 0 {
+            clock.period = period;
+            clock.moves = moves;
+          }
+        }
+      }
+    }
+  }
+
+  fn period_left(&self, clock: &Clock) -> Option<PeriodLeft> {
+    match self.control {
+      TimeControl::Fischer { .. } | TimeControl::Delay { .. } => None,
+      TimeControl::Japanese { .. } => Some(PeriodLeft::Japanese {
+        time: clock.period,
+        periods: clock.periods,
+      }),
+      TimeControl::Canadian { .. } => Some(PeriodLeft::Canadian {
+        time: clock.period,
+        moves: clock.moves,
+      }),
+    }
+  }
+
+  pub fn time_left(&self, red: &Clock, black: &Clock) -> TimeLeft {
+    TimeLeft {
+      red: red.main,
+      black: black.main,
+      red_period: self.period_left(red),
+      black_period: self.period_left(black),
+    }
+  }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -33,6 +159,27 @@ pub struct GameConfig {
   pub size: FieldSize,
   pub time: GameTime,
   pub opening: Opening,
+  /// Doubled komi added to red's score.
+  pub komi_x_2: i32,
+  pub rated: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MatchPreferences {
+  pub min_size: FieldSize,
+  pub max_size: FieldSize,
+  pub time: GameTime,
+  pub opening: Opening,
+  pub komi_x_2: i32,
+  pub rated: bool,
+}
+
+/// A player waiting for an automatically matched game.
+#[derive(Debug, Clone)]
+pub struct QueueEntry {
+  pub preferences: MatchPreferences,
+  pub rating: Glicko2Rating,
+  pub enqueue_time: SystemTime,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -41,16 +188,73 @@ pub struct OpenGame {
   pub config: GameConfig,
 }
 
+/// A game offered directly to another player. It isn't visible in the lobby.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Challenge {
+  pub player_id: PlayerId,
+  pub opponent_id: PlayerId,
+  /// Color of the challenging player.
+  pub color: Player,
+  pub config: GameConfig,
+  /// Finished game this challenge is a rematch of.
+  pub rematch: Option<GameId>,
+}
+
+impl Challenge {
+  pub fn involves(&self, player_id: PlayerId) -> bool {
+    self.player_id == player_id || self.opponent_id == player_id
+  }
+}
+
 #[derive(Debug)]
 pub struct GameState {
   pub field: Field,
-  pub red_time: Duration,
-  pub black_time: Duration,
+  pub red_clock: Clock,
+  pub black_clock: Clock,
   pub last_move_time: SystemTime,
   pub draw_offer: Option<Player>,
+  /// Player who asked to take back their last move. Any new move cancels the request.
+  pub takeback_offer: Option<Player>,
+  /// Number of moves of the opening which can't be taken back.
+  pub opening_moves: usize,
+  /// Clock of the moving player before each move after the opening, restored
+  /// when the move is taken back.
+  pub move_clocks: Vec<Clock>,
   pub timer: JoinHandle<()>,
 }
 
+impl GameState {
+  pub fn player_on_turn(&self) -> Player {
+    self.field.last_player().map_or(Player::Red, |player| player.next())
+  }
+
+  pub fn clock(&self, player: Player) -> &Clock {
+    match player {
+      Player::Red => &self.red_clock,
+      Player::Black => &self.black_clock,
+    }
+  }
+
+  pub fn clock_mut(&mut self, player: Player) -> &mut Clock {
+    match player {
+      Player::Red => &mut self.red_clock,
+      Player::Black => &mut self.black_clock,
+    }
+  }
+
+  /// Time left of both players at the moment `now`, the player on turn spending
+  /// the time since the last move.
+  pub fn time_left(&self, time: &GameTime, now: SystemTime) -> TimeLeft {
+    let elapsed = now.duration_since(self.last_move_time).unwrap_or_default();
+    let (mut red_clock, mut black_clock) = (self.red_clock, self.black_clock);
+    match self.player_on_turn() {
+      Player::Red => time.spend(&mut red_clock, elapsed),
+      Player::Black => time.spend(&mut black_clock, elapsed),
+    }
+    time.time_left(&red_clock, &black_clock)
+  }
+}
+
 #[derive(Debug, Clone)]
 pub struct Game {
   pub red_player_id: PlayerId,
@@ -71,7 +275,7 @@ impl Game {
   }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Default)]
 pub struct State {
   /// Sender is behind a lock since we need exclusive access to send a message.
   /// Also it's useful to make sure we don't send any updates before initial message is sent
@@ -81,13 +285,32 @@ pub struct State {
   pub players: HashMap<PlayerId, ImHashSet<ConnectionId>>,
   /// Open games are never mutated, they can be only created or removed.
   pub open_games: HashMap<GameId, OpenGame>,
+  /// Challenges are never mutated either. They are keyed by the id of the game they start.
+  pub challenges: HashMap<GameId, Challenge>,
   /// Games have mutable state inside.
   pub games: HashMap<GameId, Game>,
   /// Immutable set just allows to avoid lock here.
   pub watchers: HashMap<GameId, ImHashSet<ConnectionId>>,
+  /// Players waiting for automatically matched games.
+  pub queue: HashMap<PlayerId, QueueEntry>,
+  /// Tournaments that aren't finished yet. They have mutable state inside.
+  pub tournaments: HashMap<TournamentId, Tournament>,
+  /// Times of recent requests of online players from all their connections.
+  pub player_request_times: HashMap<PlayerId, Arc<std::sync::Mutex<VecDeque<SystemTime>>>>,
+  /// Set on shutdown to stop starting new games.
+  pub shutting_down: AtomicBool,
 }
 
 impl State {
+  pub fn is_shutting_down(&self) -> bool {
+    self.shutting_down.load(atomic::Ordering::SeqCst)
+  }
+
+  /// Whether the player takes part in a live game.
+  pub fn is_playing(&self, player_id: PlayerId) -> bool {
+    self.games.pin().values().any(|game| game.color(player_id).is_some())
+  }
+
   pub fn insert_players_connection(&self, player_id: PlayerId, connection_id: ConnectionId) {
     self.players.pin().compute(player_id, |entry| match entry {
       Some((_, connections)) if connections.contains(&connection_id) => Operation::Abort(()),
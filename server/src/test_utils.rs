@@ -0,0 +1,78 @@
+use crate::{
+  Session, SessionShared,
+  config::{OidcConfig, RateLimitConfig},
+  db::InMemoryDb,
+  ids::GameId,
+  message::{Request, Response},
+  state::State,
+};
+use cookie::Key;
+use futures::channel::mpsc::{self, Receiver, UnboundedSender};
+use futures_util::StreamExt;
+use openidconnect::{ClientId, IssuerUrl};
+use rand::{make_rng, rngs::StdRng};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+pub const BOT_TOKEN: &str = "secret";
+
+pub fn shared() -> Arc<SessionShared> {
+  shared_with_analysis(None)
+}
+
+pub fn shared_with_analysis(analysis: Option<UnboundedSender<GameId>>) -> Arc<SessionShared> {
+  Arc::new(SessionShared {
+    analysis,
+    ..session_shared()
+  })
+}
+
+pub fn session_shared() -> SessionShared {
+  SessionShared {
+    db: InMemoryDb::default(),
+    http_client: reqwest::Client::new(),
+    cookie_key: Key::generate(),
+    oidc: OidcConfig {
+      issuer_url: IssuerUrl::new("https://example.org".to_string()).unwrap(),
+      client_id: ClientId::new("kropki".to_string()),
+      client_secret: None,
+    },
+    bot_tokens: HashMap::from([(BOT_TOKEN.to_string(), "bot".to_string())]),
+    analysis: None,
+    metrics: Default::default(),
+    rate_limit: RateLimitConfig {
+      connection: 1000,
+      player: 1000,
+    },
+  }
+}
+
+pub async fn next(rx: &mut Receiver<Response>) -> Response {
+  tokio::time::timeout(Duration::from_secs(10), rx.next())
+    .await
+    .expect("no response in time")
+    .expect("connection closed")
+}
+
+pub async fn wait_for<T>(rx: &mut Receiver<Response>, mut f: impl FnMut(Response) -> Option<T>) -> T {
+  loop {
+    if let Some(result) = f(next(rx).await) {
+      return result;
+    }
+  }
+}
+
+/// Connects a new session and authenticates it as a test player.
+pub async fn connect(
+  state: &Arc<State>,
+  shared: &Arc<SessionShared>,
+  name: &str,
+) -> (Session<StdRng>, Receiver<Response>) {
+  let mut session = Session::new(shared.clone(), make_rng::<StdRng>());
+  let (tx, rx) = mpsc::channel(32);
+  session.init(state, tx).await.unwrap();
+  session
+    .handle_client_request(state, Request::AuthTest { name: name.to_string() })
+    .await
+    .unwrap();
+  (session, rx)
+}
@@ -0,0 +1,107 @@
+use crate::{
+  message::{PeriodLeft, TimeControl},
+  state::GameTime,
+};
+use std::time::Duration;
+
+fn secs(secs: u64) -> Duration {
+  Duration::from_secs(secs)
+}
+
+fn game_time(total: u64, control: TimeControl) -> GameTime {
+  GameTime {
+    total: secs(total),
+    control,
+    max_per_move: None,
+  }
+}
+
+#[test]
+fn fischer_adds_increment() {
+  let time = game_time(60, TimeControl::Fischer { increment: secs(5) });
+  let mut clock = time.clock();
+  time.charge(&mut clock, secs(10));
+  assert_eq!(clock.main, secs(55));
+  assert_eq!(time.remaining(&clock), secs(55));
+}
+
+#[test]
+fn delay_is_not_charged() {
+  let time = game_time(60, TimeControl::Delay { delay: secs(5) });
+  let mut clock = time.clock();
+  time.charge(&mut clock, secs(3));
+  assert_eq!(clock.main, secs(60));
+  time.charge(&mut clock, secs(8));
+  assert_eq!(clock.main, secs(57));
+  assert_eq!(time.remaining(&clock), secs(62));
+}
+
+#[test]
+fn japanese_byo_yomi_spends_periods() {
+  let time = game_time(
+    10,
+    TimeControl::Japanese {
+      period: secs(5),
+      periods: 3,
+    },
+  );
+  let mut clock = time.clock();
+  assert_eq!(time.remaining(&clock), secs(25));
+
+  // A move within a period doesn't spend it.
+  time.charge(&mut clock, secs(14));
+  assert_eq!(clock.main, Duration::ZERO);
+  assert_eq!(clock.periods, 3);
+  assert_eq!(clock.period, secs(5));
+
+  // Exceeding a period spends it.
+  time.charge(&mut clock, secs(7));
+  assert_eq!(clock.periods, 2);
+  assert_eq!(clock.period, secs(5));
+  assert_eq!(time.remaining(&clock), secs(10));
+
+  assert_eq!(
+    time.time_left(&clock, &time.clock()).red_period,
+    Some(PeriodLeft::Japanese {
+      time: secs(5),
+      periods: 2,
+    })
+  );
+
+  time.spend(&mut clock, secs(10));
+  assert_eq!(clock.periods, 0);
+  assert_eq!(time.remaining(&clock), Duration::ZERO);
+}
+
+#[test]
+fn canadian_byo_yomi_resets_after_moves() {
+  let time = game_time(
+    0,
+    TimeControl::Canadian {
+      period: secs(20),
+      moves: 2,
+    },
+  );
+  let mut clock = time.clock();
+  time.charge(&mut clock, secs(8));
+  assert_eq!(clock.period, secs(12));
+  assert_eq!(clock.moves, 1);
+  time.charge(&mut clock, secs(10));
+  assert_eq!(clock.period, secs(20));
+  assert_eq!(clock.moves, 2);
+
+  time.spend(&mut clock, secs(25));
+  assert_eq!(time.remaining(&clock), Duration::ZERO);
+}
+
+#[test]
+fn max_per_move_limits_remaining_time() {
+  let time = GameTime {
+    max_per_move: Some(secs(30)),
+    ..game_time(300, TimeControl::Fischer { increment: secs(5) })
+  };
+  let mut clock = time.clock();
+  assert_eq!(time.remaining(&clock), secs(30));
+  time.charge(&mut clock, secs(290));
+  assert_eq!(time.remaining(&clock), secs(15));
+}
@@ -0,0 +1,514 @@
+use crate::{
+  Session, db,
+  db::Db,
+  ids::*,
+  message::{
+    self, Standing, TournamentFormat, TournamentPairing, TournamentPlayer, TournamentResult, TournamentStatus,
+  },
+  state::{GameConfig, State},
+};
+use anyhow::Result;
+use futures::channel::mpsc;
+use futures_util::StreamExt;
+use oppai_field::player::Player;
+use rand::{Rng, RngExt};
+use std::{
+  collections::{HashMap, HashSet},
+  sync::Arc,
+  time::{Duration, SystemTime},
+};
+use tokio::sync::RwLock;
+use uuid::Builder;
+
+/// Maximum number of players registered for a tournament.
+pub const MAX_PLAYERS: usize = 64;
+
+pub const MAX_SWISS_ROUNDS: u32 = 20;
+
+/// Maximum number of attempts to pair a Swiss round without rematches.
+const PAIRING_BUDGET: u32 = 100_000;
+
+#[derive(Debug)]
+pub struct TournamentState {
+  pub status: TournamentStatus,
+  /// Registered players in the order of registration.
+  pub players: Vec<TournamentPlayer>,
+  pub rounds: Vec<Vec<TournamentPairing>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tournament {
+  pub name: String,
+  pub creator_id: PlayerId,
+  pub config: GameConfig,
+  pub format: TournamentFormat,
+  pub start_time: SystemTime,
+  pub state: Arc<RwLock<TournamentState>>,
+}
+
+impl From<TournamentFormat> for db::TournamentFormat {
+  fn from(format: TournamentFormat) -> Self {
+    match format {
+      TournamentFormat::RoundRobin => db::TournamentFormat::RoundRobin,
+      TournamentFormat::Swiss { .. } => db::TournamentFormat::Swiss,
+    }
+  }
+}
+
+impl From<TournamentStatus> for db::TournamentStatus {
+  fn from(status: TournamentStatus) -> Self {
+    match status {
+      TournamentStatus::Registration => db::TournamentStatus::Registration,
+      TournamentStatus::Running => db::TournamentStatus::Running,
+      TournamentStatus::Finished => db::TournamentStatus::Finished,
+      TournamentStatus::Cancelled => db::TournamentStatus::Cancelled,
+    }
+  }
+}
+
+fn is_finished(pairing: &TournamentPairing) -> bool {
+  pairing.black_player_id.is_none() || pairing.result.is_some()
+}
+
+/// Points of both players of a finished pairing. A bye is worth a win in Swiss
+/// tournaments and nothing in round robin ones where everyone gets one.
+fn points(format: TournamentFormat, pairing: &TournamentPairing) -> Option<(f64, f64)> {
+  match (pairing.black_player_id, pairing.result) {
+    (None, _) => match format {
+      TournamentFormat::RoundRobin => None,
+      TournamentFormat::Swiss { .. } => Some((1.0, 0.0)),
+    },
+    (Some(_), Some(TournamentResult::Win { winner: Player::Red })) => Some((1.0, 0.0)),
+    (Some(_), Some(TournamentResult::Win { winner: Player::Black })) => Some((0.0, 1.0)),
+    (Some(_), Some(TournamentResult::Draw)) => Some((0.5, 0.5)),
+    (Some(_), Some(TournamentResult::Void) | None) => None,
+  }
+}
+
+/// Players sorted by points and then by tie-breaks. Sonneborn-Berger is preferred
+/// in round robin tournaments and Buchholz in Swiss ones.
+pub fn standings(format: TournamentFormat, players: &[PlayerId], rounds: &[Vec<TournamentPairing>]) -> Vec<Standing> {
+  let mut standings = players
+    .iter()
+    .map(|&player_id| Standing {
+      player_id,
+      points: 0.0,
+      buchholz: 0.0,
+      sonneborn_berger: 0.0,
+      wins: 0,
+      draws: 0,
+      losses: 0,
+    })
+    .collect::<Vec<_>>();
+  let index = players
+    .iter()
+    .enumerate()
+    .map(|(i, &player_id)| (player_id, i))
+    .collect::<HashMap<_, _>>();
+
+  let mut record = |player_id: PlayerId, points: f64| {
+    if let Some(standing) = index.get(&player_id).map(|&i| &mut standings[i]) {
+      standing.points += points;
+      if points == 1.0 {
+        standing.wins += 1;
+      } else if points == 0.5 {
+        standing.draws += 1;
+      } else {
+        standing.losses += 1;
+      }
+    }
+  };
+  for pairing in rounds.iter().flatten() {
+    if let Some((red_points, black_points)) = points(format, pairing) {
+      record(pairing.red_player_id, red_points);
+      if let Some(black_player_id) = pairing.black_player_id {
+        record(black_player_id, black_points);
+      }
+    }
+  }
+
+  let total_points = standings
+    .iter()
+    .map(|standing| (standing.player_id, standing.points))
+    .collect::<HashMap<_, _>>();
+  for pairing in rounds.iter().flatten() {
+    let (Some(black_player_id), Some((red_points, black_points))) = (pairing.black_player_id, points(format, pairing))
+    else {
+      continue;
+    };
+    for (player_id, opponent_id, points) in [
+      (pairing.red_player_id, black_player_id, red_points),
+      (black_player_id, pairing.red_player_id, black_points),
+    ] {
+      let (Some(&i), Some(&opponent_points)) = (index.get(&player_id), total_points.get(&opponent_id)) else {
+        continue;
+      };
+      standings[i].buchholz += opponent_points;
+      standings[i].sonneborn_berger += opponent_points * points;
+    }
+  }
+
+  let key = |standing: &Standing| match format {
+    TournamentFormat::RoundRobin => (standing.points, standing.sonneborn_berger, standing.buchholz),
+    TournamentFormat::Swiss { .. } => (standing.points, standing.buchholz, standing.sonneborn_berger),
+  };
+  standings.sort_by(|s1, s2| {
+    let (k1, k2) = (key(s1), key(s2));
+    k2.0
+      .total_cmp(&k1.0)
+      .then(k2.1.total_cmp(&k1.1))
+      .then(k2.2.total_cmp(&k1.2))
+  });
+
+  standings
+}
+
+fn pairing(red_player_id: PlayerId, black_player_id: Option<PlayerId>) -> TournamentPairing {
+  TournamentPairing {
+    red_player_id,
+    black_player_id,
+    game_id: None,
+    result: None,
+  }
+}
+
+/// Pairings of a round robin round by the circle method, or `None` if all rounds
+/// were played. With an odd number of players one of them gets a bye every round.
+pub fn round_robin_round(players: &[PlayerId], round: usize) -> Option<Vec<TournamentPairing>> {
+  let n = players.len() + players.len() % 2;
+  if n < 2 || round >= n - 1 {
+    return None;
+  }
+
+  // The first player stays in place while the others rotate.
+  let player = |i: usize| {
+    let slot = if i == 0 { 0 } else { (i - 1 + round) % (n - 1) + 1 };
+    players.get(slot).copied()
+  };
+
+  Some(
+    (0..n / 2)
+      .filter_map(|i| {
+        let (first, second) = if (round + i).is_multiple_of(2) {
+          (player(i), player(n - 1 - i))
+        } else {
+          (player(n - 1 - i), player(i))
+        };
+        match (first, second) {
+          (Some(red), black) => Some(pairing(red, black)),
+          (None, Some(red)) => Some(pairing(red, None)),
+          (None, None) => None,
+        }
+      })
+      .collect(),
+  )
+}
+
+/// Pairs players so that nobody meets the same opponent twice, preferring
+/// opponents with close standings. Gives up after `budget` attempts.
+fn pair_without_repeats(
+  players: &[PlayerId],
+  played: &HashSet<(PlayerId, PlayerId)>,
+  pairs: &mut Vec<(PlayerId, PlayerId)>,
+  budget: &mut u32,
+) -> bool {
+  let Some((&first, rest)) = players.split_first() else {
+    return true;
+  };
+  for (i, &second) in rest.iter().enumerate() {
+    if played.contains(&(first, second)) {
+      continue;
+    }
+    if *budget == 0 {
+      return false;
+    }
+    *budget -= 1;
+    let remaining = rest
+      .iter()
+      .enumerate()
+      .filter(|&(j, _)| j != i)
+      .map(|(_, &player_id)| player_id)
+      .collect::<Vec<_>>();
+    pairs.push((first, second));
+    if pair_without_repeats(&remaining, played, pairs, budget) {
+      return true;
+    }
+    pairs.pop();
+  }
+  false
+}
+
+/// Pairings of a Swiss round. `ranked` players are sorted by their standings.
+pub fn swiss_round(ranked: &[PlayerId], rounds: &[Vec<TournamentPairing>]) -> Vec<TournamentPairing> {
+  let mut ranked = ranked.to_vec();
+  let mut pairings = Vec::new();
+
+  if ranked.len() % 2 == 1 {
+    let had_bye = rounds
+      .iter()
+      .flatten()
+      .filter(|pairing| pairing.black_player_id.is_none())
+      .map(|pairing| pairing.red_player_id)
+      .collect::<HashSet<_>>();
+    let i = ranked
+      .iter()
+      .rposition(|player_id| !had_bye.contains(player_id))
+      .unwrap_or(ranked.len() - 1);
+    pairings.push(pairing(ranked.remove(i), None));
+  }
+
+  // Void games weren't played, so their players may still meet.
+  let mut played = HashSet::new();
+  let mut red_games = HashMap::new();
+  for pairing in rounds
+    .iter()
+    .flatten()
+    .filter(|pairing| pairing.result != Some(TournamentResult::Void))
+  {
+    if let Some(black_player_id) = pairing.black_player_id {
+      played.insert((pairing.red_player_id, black_player_id));
+      played.insert((black_player_id, pairing.red_player_id));
+      *red_games.entry(pairing.red_player_id).or_insert(0) += 1;
+    }
+  }
+
+  let mut pairs = Vec::new();
+  let mut budget = PAIRING_BUDGET;
+  if !pair_without_repeats(&ranked, &played, &mut pairs, &mut budget) {
+    // Everyone has already met, so repeats are unavoidable.
+    pairs = ranked.chunks_exact(2).map(|chunk| (chunk[0], chunk[1])).collect();
+  }
+
+  // The player who had red less often gets it.
+  let red_count = |player_id: &PlayerId| red_games.get(player_id).copied().unwrap_or(0);
+  pairings.extend(pairs.into_iter().map(|(first, second)| {
+    if red_count(&second) < red_count(&first) {
+      pairing(second, Some(first))
+    } else {
+      pairing(first, Some(second))
+    }
+  }));
+
+  pairings
+}
+
+/// Pairings of the next round, or `None` if the tournament is over.
+pub fn next_round(format: TournamentFormat, state: &TournamentState) -> Option<Vec<TournamentPairing>> {
+  let players = state.players.iter().map(|player| player.player_id).collect::<Vec<_>>();
+  match format {
+    TournamentFormat::RoundRobin => round_robin_round(&players, state.rounds.len()),
+    TournamentFormat::Swiss { rounds } => {
+      if state.rounds.len() >= rounds as usize {
+        return None;
+      }
+      let ranked = standings(format, &players, &state.rounds)
+        .into_iter()
+        .map(|standing| standing.player_id)
+        .collect::<Vec<_>>();
+      Some(swiss_round(&ranked, &state.rounds))
+    }
+  }
+}
+
+pub fn to_message(tournament: &Tournament, state: &TournamentState) -> message::Tournament {
+  let players = state.players.iter().map(|player| player.player_id).collect::<Vec<_>>();
+  message::Tournament {
+    name: tournament.name.clone(),
+    creator_id: tournament.creator_id,
+    config: message::GameConfig {
+      size: message::FieldSize {
+        width: tournament.config.size.width,
+        height: tournament.config.size.height,
+      },
+      time: message::GameTime {
+        total: tournament.config.time.total,
+        control: tournament.config.time.control,
+        max_per_move: tournament.config.time.max_per_move,
+      },
+      opening: tournament.config.opening,
+      komi_x_2: tournament.config.komi_x_2,
+      rated: tournament.config.rated,
+    },
+    format: tournament.format,
+    start_time: tournament
+      .start_time
+      .duration_since(SystemTime::UNIX_EPOCH)
+      .unwrap_or_default(),
+    status: state.status,
+    players: state.players.clone(),
+    rounds: state.rounds.clone(),
+    standings: standings(tournament.format, &players, &state.rounds),
+  }
+}
+
+pub async fn broadcast(state: &State, tournament_id: TournamentId, tournament: &Tournament) {
+  let tournament_message = to_message(tournament, &*tournament.state.read().await);
+  state
+    .send_to_all(message::Response::Tournament {
+      tournament_id,
+      tournament: tournament_message,
+    })
+    .await;
+}
+
+/// Runs a tournament: closes registration at the start time, pairs rounds, starts
+/// their games and records results as the games finish.
+pub struct Director<R: Rng> {
+  session: Session<R>,
+  tournament_id: TournamentId,
+  tournament: Tournament,
+}
+
+impl<R: Rng + Send> Director<R> {
+  pub fn new(session: Session<R>, tournament_id: TournamentId, tournament: Tournament) -> Self {
+    Director {
+      session,
+      tournament_id,
+      tournament,
+    }
+  }
+
+  async fn set_status(&self, state: &State, status: TournamentStatus) -> Result<()> {
+    self.tournament.state.write().await.status = status;
+    self
+      .session
+      .shared
+      .db
+      .set_tournament_status(self.tournament_id.0, status.into())
+      .await?;
+    broadcast(state, self.tournament_id, &self.tournament).await;
+    Ok(())
+  }
+
+  async fn start_round(&mut self, state: &Arc<State>, mut pairings: Vec<TournamentPairing>) -> Result<()> {
+    let round = self.tournament.state.read().await.rounds.len();
+
+    for pairing in &mut pairings {
+      let Some(black_player_id) = pairing.black_player_id else {
+        continue;
+      };
+      let game_id = GameId(Builder::from_random_bytes(self.session.rng.random()).into_uuid());
+      // Watch the game before it starts so that its result can't be missed.
+      self.session.watching.insert(game_id);
+      state.subscribe(self.session.connection_id, game_id);
+      self
+        .session
+        .start_game(
+          state,
+          game_id,
+          pairing.red_player_id,
+          black_player_id,
+          self.tournament.config.clone(),
+        )
+        .await?;
+      pairing.game_id = Some(game_id);
+    }
+
+    self
+      .session
+      .shared
+      .db
+      .create_tournament_pairings(
+        pairings
+          .iter()
+          .map(|pairing| db::TournamentPairing {
+            tournament_id: self.tournament_id.0,
+            round: round as i32,
+            red_player_id: pairing.red_player_id.0,
+            black_player_id: pairing.black_player_id.map(|player_id| player_id.0),
+            game_id: pairing.game_id.map(|game_id| game_id.0),
+          })
+          .collect(),
+      )
+      .await?;
+
+    self.tournament.state.write().await.rounds.push(pairings);
+    broadcast(state, self.tournament_id, &self.tournament).await;
+
+    Ok(())
+  }
+
+  /// Records a game result. Returns `true` if the current round is finished.
+  async fn record(&mut self, state: &State, game_id: GameId, result: message::GameResult) -> bool {
+    let mut tournament_state = self.tournament.state.write().await;
+    let Some(round) = tournament_state.rounds.last_mut() else {
+      return false;
+    };
+    let Some(pairing) = round.iter_mut().find(|pairing| pairing.game_id == Some(game_id)) else {
+      return false;
+    };
+    pairing.result = Some(match result {
+      message::GameResult::Win { winner, .. } => TournamentResult::Win { winner },
+      message::GameResult::Draw { .. } => TournamentResult::Draw,
+      // A game closed by a moderator shouldn't block the round, nor score.
+      message::GameResult::Aborted => TournamentResult::Void,
+    });
+    let finished = round.iter().all(is_finished);
+    drop(tournament_state);
+
+    self.session.watching.remove(&game_id);
+    state.unsubscribe(self.session.connection_id, game_id);
+    broadcast(state, self.tournament_id, &self.tournament).await;
+
+    finished
+  }
+
+  async fn direct(&mut self, state: &Arc<State>) -> Result<()> {
+    if let Ok(delay) = self.tournament.start_time.duration_since(SystemTime::now()) {
+      tokio::time::sleep(delay).await;
+    }
+
+    // Responses are forwarded to an unbounded channel so that starting many games
+    // at once doesn't overflow the connection.
+    let (tx, rx) = mpsc::channel(32);
+    let (events_tx, mut events) = mpsc::unbounded();
+    tokio::spawn(rx.map(Ok).forward(events_tx));
+    self.session.init(state, tx).await?;
+
+    if self.tournament.state.read().await.players.len() < 2 {
+      return self.set_status(state, TournamentStatus::Cancelled).await;
+    }
+    self.set_status(state, TournamentStatus::Running).await?;
+
+    loop {
+      let pairings = next_round(self.tournament.format, &*self.tournament.state.read().await);
+      let Some(pairings) = pairings else {
+        break;
+      };
+      let finished = pairings.iter().all(is_finished);
+      self.start_round(state, pairings).await?;
+
+      if !finished {
+        loop {
+          let Some(response) = events.next().await else {
+            anyhow::bail!("tournament {} connection closed", self.tournament_id);
+          };
+          if let message::Response::GameResult { game_id, result, .. } = response
+            && self.record(state, game_id, result).await
+          {
+            break;
+          }
+        }
+      }
+    }
+
+    self.set_status(state, TournamentStatus::Finished).await
+  }
+
+  pub async fn run(mut self, state: Arc<State>) -> Result<()> {
+    let result = self.direct(&state).await;
+    self.session.finalize(&state).await;
+    state.tournaments.pin().remove(&self.tournament_id);
+    result
+  }
+}
+
+pub fn is_start_time_valid(start_time: SystemTime) -> bool {
+  const MAX_DELAY: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+  start_time
+    .duration_since(SystemTime::now())
+    .is_ok_and(|delay| delay <= MAX_DELAY)
+    || SystemTime::now()
+      .duration_since(start_time)
+      .is_ok_and(|delay| delay < Duration::from_secs(60))
+}
@@ -0,0 +1,243 @@
+use crate::{
+  ids::*,
+  message::{
+    FieldSize, GameConfig, GameTime, Opening, Request, Response, TimeControl, Tournament, TournamentFormat,
+    TournamentPairing, TournamentResult, TournamentStatus,
+  },
+  state::State,
+  test_utils::{connect, next, shared},
+  tournament::{round_robin_round, standings, swiss_round},
+};
+use oppai_field::player::Player;
+use std::{
+  collections::HashSet,
+  sync::Arc,
+  time::{Duration, SystemTime},
+};
+use uuid::Uuid;
+
+fn game_config() -> GameConfig {
+  GameConfig {
+    size: FieldSize { width: 10, height: 10 },
+    time: GameTime {
+      total: Duration::from_secs(60),
+      control: TimeControl::Fischer {
+        increment: Duration::ZERO,
+      },
+      max_per_move: None,
+    },
+    opening: Opening::Cross,
+    komi_x_2: 0,
+    rated: true,
+  }
+}
+
+fn player_ids(count: u128) -> Vec<PlayerId> {
+  (1..=count).map(|i| PlayerId(Uuid::from_u128(i))).collect()
+}
+
+fn games(rounds: &[Vec<TournamentPairing>]) -> Vec<(PlayerId, PlayerId)> {
+  rounds
+    .iter()
+    .flatten()
+    .filter_map(|pairing| {
+      pairing
+        .black_player_id
+        .map(|black_player_id| (pairing.red_player_id, black_player_id))
+    })
+    .collect()
+}
+
+/// Unordered pair of players.
+fn pair(player_id_1: PlayerId, player_id_2: PlayerId) -> (Uuid, Uuid) {
+  (player_id_1.0.min(player_id_2.0), player_id_1.0.max(player_id_2.0))
+}
+
+#[test]
+fn round_robin_pairs_everyone_once() {
+  for count in 2..=9 {
+    let players = player_ids(count);
+    let rounds = (0..)
+      .map_while(|round| round_robin_round(&players, round))
+      .collect::<Vec<_>>();
+    assert_eq!(rounds.len() as u128, count + count % 2 - 1);
+
+    let mut met = HashSet::new();
+    for (red_player_id, black_player_id) in games(&rounds) {
+      assert_ne!(red_player_id, black_player_id);
+      assert!(met.insert(pair(red_player_id, black_player_id)));
+    }
+    assert_eq!(met.len() as u128, count * (count - 1) / 2);
+
+    for round in &rounds {
+      let round_players = round
+        .iter()
+        .flat_map(|pairing| [Some(pairing.red_player_id), pairing.black_player_id])
+        .flatten()
+        .collect::<HashSet<_>>();
+      assert_eq!(round_players.len() as u128, count);
+    }
+  }
+}
+
+#[test]
+fn swiss_avoids_rematches_and_repeated_byes() {
+  let players = player_ids(7);
+  let format = TournamentFormat::Swiss { rounds: 5 };
+  let mut rounds = Vec::<Vec<TournamentPairing>>::new();
+  for _ in 0..5 {
+    let ranked = standings(format, &players, &rounds)
+      .into_iter()
+      .map(|standing| standing.player_id)
+      .collect::<Vec<_>>();
+    let mut round = swiss_round(&ranked, &rounds);
+    assert_eq!(round.len(), 4);
+    for pairing in &mut round {
+      // The player with the lower id always wins.
+      if let Some(black_player_id) = pairing.black_player_id {
+        let winner = if pairing.red_player_id.0 < black_player_id.0 {
+          Player::Red
+        } else {
+          Player::Black
+        };
+        pairing.result = Some(TournamentResult::Win { winner });
+      }
+    }
+    rounds.push(round);
+  }
+
+  let mut met = HashSet::new();
+  for (red_player_id, black_player_id) in games(&rounds) {
+    assert!(met.insert(pair(red_player_id, black_player_id)));
+  }
+  let byes = rounds
+    .iter()
+    .flatten()
+    .filter(|pairing| pairing.black_player_id.is_none())
+    .map(|pairing| pairing.red_player_id)
+    .collect::<HashSet<_>>();
+  assert_eq!(byes.len(), 5);
+
+  let final_standings = standings(format, &players, &rounds);
+  assert_eq!(final_standings[0].player_id, players[0]);
+  assert_eq!(final_standings[0].points, 5.0);
+  assert_eq!(
+    final_standings.iter().map(|standing| standing.points).sum::<f64>(),
+    // Every game and every bye gives one point.
+    5.0 * 4.0
+  );
+}
+
+// A game closed by a moderator scores for neither player, and in Swiss
+// tournaments its players may be paired again.
+#[test]
+fn void_games_do_not_count() {
+  let players = player_ids(4);
+  let format = TournamentFormat::Swiss { rounds: 2 };
+  let mut round = swiss_round(&players, &[]);
+  for pairing in &mut round {
+    pairing.result = Some(TournamentResult::Void);
+  }
+  let rounds = vec![round];
+
+  let ranked = standings(format, &players, &rounds);
+  for standing in &ranked {
+    assert_eq!(standing.points, 0.0);
+    assert_eq!((standing.wins, standing.draws, standing.losses), (0, 0, 0));
+  }
+  let ranked = ranked
+    .into_iter()
+    .map(|standing| standing.player_id)
+    .collect::<Vec<_>>();
+  let met = |rounds: &[Vec<TournamentPairing>]| {
+    games(rounds)
+      .into_iter()
+      .map(|(red, black)| pair(red, black))
+      .collect::<HashSet<_>>()
+  };
+  assert_eq!(met(&[swiss_round(&ranked, &rounds)]), met(&rounds));
+}
+
+#[tokio::test]
+async fn round_robin_tournament_runs_to_the_end() {
+  let state = Arc::new(State::default());
+  let shared = shared();
+
+  let mut tasks = Vec::new();
+  let mut player_ids = Vec::new();
+  let (mut creator, mut creator_rx) = connect(&state, &shared, "creator").await;
+  let start_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap() + Duration::from_millis(500);
+  creator
+    .handle(
+      &state,
+      Request::CreateTournament {
+        name: "Cup".to_string(),
+        config: game_config(),
+        format: TournamentFormat::RoundRobin,
+        start_time,
+      },
+    )
+    .await
+    .unwrap();
+  let tournament_id = loop {
+    if let Response::Tournament { tournament_id, .. } = next(&mut creator_rx).await {
+      break tournament_id;
+    }
+  };
+
+  for name in ["alice", "bob", "carol"] {
+    let (mut session, mut rx) = connect(&state, &shared, name).await;
+    let player_id = session.player_id.unwrap();
+    player_ids.push(player_id);
+    session
+      .handle(&state, Request::RegisterTournament { tournament_id })
+      .await
+      .unwrap();
+
+    // Red always resigns.
+    let state = state.clone();
+    tasks.push(tokio::spawn(async move {
+      loop {
+        match next(&mut rx).await {
+          Response::Start { game_id, game } if game.red_player_id == player_id => {
+            session.handle(&state, Request::Resign { game_id }).await.unwrap();
+          }
+          Response::Tournament { tournament, .. } if tournament.status == TournamentStatus::Finished => {
+            return tournament;
+          }
+          _ => {}
+        }
+      }
+    }));
+  }
+  drop(creator);
+  drop(creator_rx);
+
+  let mut tournaments = Vec::<Tournament>::new();
+  for task in tasks {
+    tournaments.push(task.await.unwrap());
+  }
+  let tournament = &tournaments[0];
+  assert!(tournaments.iter().all(|other| other == tournament));
+
+  assert_eq!(tournament.players.len(), 3);
+  assert_eq!(tournament.rounds.len(), 3);
+  for pairing in tournament.rounds.iter().flatten() {
+    if pairing.black_player_id.is_some() {
+      assert!(pairing.game_id.is_some());
+      assert_eq!(pairing.result, Some(TournamentResult::Win { winner: Player::Black }));
+    }
+  }
+  assert_eq!(
+    tournament.standings.iter().map(|standing| standing.wins).sum::<u32>(),
+    3
+  );
+  assert_eq!(
+    tournament
+      .standings
+      .iter()
+      .map(|standing| standing.player_id)
+      .collect::<HashSet<_>>(),
+    player_ids.into_iter().collect::<HashSet<_>>()
+  );
+}
@@ -193,6 +193,9 @@ pub struct UctRoot {
   moves_count: usize,
   hash: Hash,
   wave_pruning: WavePruning,
+  /// Komi of the game for Red, doubled. Unlike the dynamic `komi`, which is a
+  /// search heuristic, it's a rule of the game.
+  komi_x_2: i32,
   komi: AtomicI32,
   komi_visits: AtomicU32,
   komi_wins: AtomicU32,
@@ -208,6 +211,7 @@ impl Clone for UctRoot {
       moves_count: self.moves_count,
       hash: self.hash,
       wave_pruning: self.wave_pruning.clone(),
+      komi_x_2: self.komi_x_2,
       komi: AtomicI32::new(self.komi.load(Ordering::SeqCst)),
       komi_visits: AtomicU32::new(self.komi_visits.load(Ordering::SeqCst)),
       komi_wins: AtomicU32::new(self.komi_wins.load(Ordering::SeqCst)),
@@ -236,7 +240,7 @@ impl UctRoot {
     self.moves_count = field.moves_count();
     self.hash = field.hash();
     if self.config.komi_type != UctKomiType::None {
-      self.komi = AtomicI32::new(field.score(player));
+      self.komi = AtomicI32::new(self.score(field, player));
     }
     self.wave_pruning.init(field, self.config.radius);
   }
@@ -280,7 +284,7 @@ impl UctRoot {
             );
             UctRoot::expand_node(node, &mut added_moves, rng);
             match self.config.komi_type {
-              UctKomiType::Static => self.komi = AtomicI32::new(field.score(self.player)),
+              UctKomiType::Static => self.komi = AtomicI32::new(self.score(field, self.player)),
               UctKomiType::Dynamic => {
                 self.komi_visits = AtomicU32::new(node.get_visits());
                 self.komi_wins = AtomicU32::new(node.get_wins());
@@ -331,7 +335,7 @@ impl UctRoot {
     }
   }
 
-  pub fn new(config: UctConfig, length: Pos) -> UctRoot {
+  pub fn new(config: UctConfig, length: Pos, komi_x_2: i32) -> UctRoot {
     UctRoot {
       config,
       node: None,
@@ -339,6 +343,7 @@ impl UctRoot {
       moves_count: 0,
       hash: 0,
       wave_pruning: WavePruning::new(length),
+      komi_x_2,
       komi: AtomicI32::new(0),
       komi_visits: AtomicU32::new(0),
       komi_wins: AtomicU32::new(0),
@@ -346,11 +351,21 @@ impl UctRoot {
     }
   }
 
-  fn random_result(field: &Field, player: Player, komi: i32) -> Option<Player> {
+  /// Score of `player` with the game komi, rounded down to whole points.
+  fn score(&self, field: &Field, player: Player) -> i32 {
+    let komi_x_2 = if player == Player::Red {
+      self.komi_x_2
+    } else {
+      -self.komi_x_2
+    };
+    (field.score(player) * 2 + komi_x_2).div_euclid(2)
+  }
+
+  fn random_result(field: &Field, player: Player, komi: i32, komi_x_2: i32) -> Option<Player> {
     use std::cmp::Ordering;
     let red_komi = if player == Player::Red { komi } else { -komi };
-    let red_score = field.score(Player::Red);
-    match red_score.cmp(&red_komi) {
+    let red_score_x_2 = field.score(Player::Red) * 2 + komi_x_2;
+    match red_score_x_2.cmp(&(red_komi * 2)) {
       Ordering::Greater => Some(Player::Red),
       Ordering::Less => Some(Player::Black),
       Ordering::Equal => None,
@@ -363,6 +378,7 @@ impl UctRoot {
     rng: &mut R,
     possible_moves: &mut [Pos],
     komi: i32,
+    komi_x_2: i32,
   ) -> Option<Player> {
     possible_moves.shuffle(rng);
     let mut cur_player = player;
@@ -378,7 +394,7 @@ impl UctRoot {
         }
       }
     }
-    UctRoot::random_result(field, player, komi)
+    UctRoot::random_result(field, player, komi, komi_x_2)
   }
 
   fn ucb(&self, parent_visits_ln: f64, node: &UctNode, ucb_type: UcbType) -> f64 {
@@ -459,7 +475,7 @@ impl UctRoot {
   ) -> Option<Player> {
     let random_result = if node.get_visits() < self.config.when_create_children || depth == self.config.depth {
       node.add_visits();
-      UctRoot::play_random_game(field, player, rng, possible_moves, komi)
+      UctRoot::play_random_game(field, player, rng, possible_moves, komi, self.komi_x_2)
     } else {
       if unsafe { node.get_children() }.is_none() {
         UctRoot::create_children(field, possible_moves, node, rng)
@@ -480,7 +496,7 @@ impl UctRoot {
         self.play_simulation_rec(field, player.next(), next, possible_moves, rng, -komi, depth + 1)
       } else {
         node.add_visits();
-        UctRoot::random_result(field, player, komi)
+        UctRoot::random_result(field, player, komi, self.komi_x_2)
       }
     };
     if let Some(player_random_result) = random_result {
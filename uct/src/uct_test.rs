@@ -29,7 +29,7 @@ macro_rules! uct_test {
       env_logger::try_init().ok();
       let mut rng = Xoshiro256PlusPlus::seed_from_u64($seed);
       let mut field = construct_field(&mut rng, $image.image);
-      let mut uct = UctRoot::new(UCT_CONFIG, field.length());
+      let mut uct = UctRoot::new(UCT_CONFIG, field.length(), 0);
       let (moves, _, _) = uct.best_moves(&mut field, Player::Red, &mut rng, &|| false, $iterations);
       let pos = moves.into_iter()
       .reduce(
@@ -61,3 +61,28 @@ uct_test!(uct_12, IMAGE_12, 100_000, 7);
 uct_test!(uct_13, IMAGE_13, 100_000, 7);
 // too unstable
 // uct_test!(uct_14, IMAGE_14, 10_000_000, 7);
+
+// Red has captured two points and no move can change the score, so the komi
+// alone decides who wins.
+#[test]
+fn komi_flips_the_result() {
+  let config = UctConfig {
+    komi_type: UctKomiType::None,
+    ..UCT_CONFIG
+  };
+  let winrate = |komi_x_2| {
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+    let mut field = construct_field(
+      &mut rng,
+      "
+      .a.a.
+      aAaAa
+      .a.a.
+      ",
+    );
+    let mut uct = UctRoot::new(config.clone(), field.length(), komi_x_2);
+    uct.best_moves(&mut field, Player::Red, &mut rng, &|| false, 10_000).2
+  };
+  assert!(winrate(0) > 0.9);
+  assert!(winrate(-5) < 0.1);
+}
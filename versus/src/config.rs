@@ -1,10 +1,13 @@
 #[cfg(feature = "term-render")]
 use clap::ArgAction;
 use clap::{Arg, Command};
+use oppai_initial::opening::{Opening, Placement};
 
 pub struct Config {
   pub width: u32,
   pub height: u32,
+  pub opening: Opening,
+  pub opening_placement: Placement,
   pub ai1: String,
   pub ai2: String,
   pub ai1_args: Vec<String>,
@@ -34,6 +37,22 @@ pub fn cli_parse() -> Config {
         .value_parser(clap::value_parser!(u32))
         .default_value("10"),
     )
+    .arg(
+      Arg::new("opening")
+        .long("opening")
+        .help("Opening to start the games with: a standard opening or an opening file")
+        .num_args(1)
+        .value_parser(clap::value_parser!(Opening))
+        .default_value("Cross"),
+    )
+    .arg(
+      Arg::new("opening-placement")
+        .long("opening-placement")
+        .help("Where to place the opening on the field")
+        .num_args(1)
+        .value_parser(clap::value_parser!(Placement))
+        .default_value("Centred"),
+    )
     .arg(
       Arg::new("ai1")
         .long("ai1")
@@ -74,6 +93,13 @@ pub fn cli_parse() -> Config {
   Config {
     width: *matches.get_one::<u32>("width").expect("`width` has a default"),
     height: *matches.get_one::<u32>("height").expect("`height` has a default"),
+    opening: matches
+      .get_one::<Opening>("opening")
+      .expect("`opening` has a default")
+      .clone(),
+    opening_placement: *matches
+      .get_one::<Placement>("opening-placement")
+      .expect("`opening-placement` has a default"),
     ai1: matches.get_one::<String>("ai1").expect("`ai1` is required").to_owned(),
     ai2: matches.get_one::<String>("ai2").expect("`ai2` is required").to_owned(),
     ai1_args: matches
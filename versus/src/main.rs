@@ -19,13 +19,11 @@ use oppai_client::{Client, Constraint};
 use oppai_field::extended_field::ExtendedField;
 use oppai_field::field::{NonZeroPos, Pos};
 use oppai_field::player::Player;
-use oppai_initial::initial::InitialPosition;
 #[cfg(feature = "term-render")]
 use oppai_term_render::render;
 use rand::make_rng;
 use rand::rngs::SmallRng;
 
-const INITIAL_POSITION: InitialPosition = InitialPosition::Cross;
 const TIME: Duration = Duration::from_secs(1);
 
 #[derive(Clone, Copy, PartialEq, Debug, Default)]
@@ -106,8 +104,8 @@ impl Game {
     }
   }
 
-  async fn place_initial_position(&mut self, player: Player, initial_position: InitialPosition) -> Result<()> {
-    for (pos, player) in initial_position.points(self.field.field.width(), self.field.field.height(), player) {
+  async fn place_opening(&mut self, opening: &[(Pos, Player)]) -> Result<()> {
+    for &(pos, player) in opening {
       self.put_point(pos, player).await?;
     }
     Ok(())
@@ -192,11 +190,11 @@ impl Game {
     self.field.clear();
     self
       .client1
-      .init(self.field.field.width(), self.field.field.height())
+      .init(self.field.field.width(), self.field.field.height(), 0)
       .await?;
     self
       .client2
-      .init(self.field.field.width(), self.field.field.height())
+      .init(self.field.field.width(), self.field.field.height(), 0)
       .await?;
     Ok(())
   }
@@ -218,6 +216,7 @@ fn main() -> Result<()> {
   let player = Player::default();
   let mut stats = Stats::default();
   let mut swap = false;
+  let mut opening = Vec::new();
 
   #[cfg(feature = "term-render")]
   let ascii = config.ascii;
@@ -226,7 +225,13 @@ fn main() -> Result<()> {
   let future = async {
     loop {
       game.init().await?;
-      game.place_initial_position(player, INITIAL_POSITION).await?;
+      // Both AIs play either side of every opening.
+      if !swap {
+        opening = config
+          .opening
+          .points(config.width, config.height, player, config.opening_placement, &mut rng);
+      }
+      game.place_opening(&opening).await?;
       game.play(player, swap, &mut stats, ascii).await?;
       swap = !swap;
     }
@@ -20,6 +20,7 @@ use oppai_ais::{
 use oppai_field::field::Field;
 use oppai_patterns::patterns::Patterns;
 use oppai_protocol::{Constraint, Coords, Move, Request, Response};
+use oppai_zero::zero::ZeroConfig;
 use oppai_zero_burn::model::{Model as BurnModel, ModelConfig, Predictor};
 use rand::{make_rng, rngs::SmallRng};
 use std::sync::Arc;
@@ -56,7 +57,11 @@ async fn handle(
   wgpu: bool,
 ) -> Result<Response> {
   Ok(match request {
-    Request::Init { width, height } => {
+    Request::Init {
+      width,
+      height,
+      komi_x_2,
+    } => {
       let predictor = if wgpu {
         Either::Left(load_predictor::<Wgpu>(WgpuDevice::DefaultDevice, config, model_bytes)?)
       } else {
@@ -66,6 +71,12 @@ async fn handle(
       let config = AIConfig {
         solver: Solver::Zero,
         ladders: false,
+        komi_x_2,
+        // Weak browser GPUs leave few playouts per move.
+        zero: ZeroConfig {
+          gumbel: true,
+          ..ZeroConfig::default()
+        },
         ..AIConfig::default()
       };
       *state_option = Some(State {
@@ -109,7 +120,10 @@ async fn handle(
         })
         .collect();
       moves.sort_unstable_by(|a, b| b.weight.partial_cmp(&a.weight).unwrap_or(std::cmp::Ordering::Equal));
-      Response::Analyze { moves }
+      Response::Analyze {
+        moves,
+        winrate: analysis.estimation().to_winrate(),
+      }
     }
     Request::Analyze {
       player,
@@ -136,7 +150,10 @@ async fn handle(
         })
         .collect();
       moves.sort_unstable_by(|a, b| b.weight.partial_cmp(&a.weight).unwrap_or(std::cmp::Ordering::Equal));
-      Response::Analyze { moves }
+      Response::Analyze {
+        moves,
+        winrate: analysis.estimation().to_winrate(),
+      }
     }
   })
 }
@@ -17,11 +17,11 @@ use burn::{
 };
 use derive_more::From;
 use ndarray::{Array, Array1, Array2, Array3, Array4, Dimension, ShapeError};
-use num_traits::Float;
+use num_traits::{Float, ToPrimitive};
 use oppai_zero::{
   examples::TD_VALUES,
   field_features::{CHANNELS, GLOBAL_FEATURES, SCORE_ONE_HOT_SIZE},
-  model::{Model as OppaiModel, TrainableModel as OppaiTrainableModel},
+  model::{Losses, Model as OppaiModel, TrainStats, TrainableModel as OppaiTrainableModel},
 };
 use serde::{Deserialize, Serialize};
 use std::{fs::File, io::BufReader, path::Path};
@@ -1205,16 +1205,81 @@ fn scale_gradients<B: AutodiffBackend>(
   grads
 }
 
-impl<B, O> OppaiTrainableModel<FloatElem<B>> for Learner<B, O>
+/// The loss terms of a batch as tensors, before they are summed into the loss
+/// the gradient is taken of.
+struct LossTerms<B: Backend> {
+  value: Tensor<B, 1>,
+  td_value: Tensor<B, 1>,
+  value_error: Tensor<B, 1>,
+  td_score: Tensor<B, 1>,
+  score_error: Tensor<B, 1>,
+  policy: Tensor<B, 1>,
+  opponent_policy: Tensor<B, 1>,
+  soft_policy: Tensor<B, 1>,
+  soft_opponent_policy: Tensor<B, 1>,
+  optimistic_policy: Tensor<B, 1>,
+  long_optimistic_policy: Tensor<B, 1>,
+  score_pdf: Tensor<B, 1>,
+  score_cdf: Tensor<B, 1>,
+  captured: Tensor<B, 1>,
+  q_value: Tensor<B, 1>,
+  q_score: Tensor<B, 1>,
+}
+
+impl<B: Backend> LossTerms<B> {
+  fn total(&self) -> Tensor<B, 1> {
+    self.value.clone()
+      + self.td_value.clone()
+      + self.value_error.clone()
+      + self.policy.clone()
+      + self.opponent_policy.clone()
+      + self.soft_policy.clone()
+      + self.soft_opponent_policy.clone()
+      + self.optimistic_policy.clone()
+      + self.long_optimistic_policy.clone()
+      + self.td_score.clone()
+      + self.score_error.clone()
+      + self.score_pdf.clone()
+      + self.score_cdf.clone()
+      + self.captured.clone()
+      + self.q_value.clone()
+      + self.q_score.clone()
+  }
+
+  fn losses(&self) -> Losses
+  where
+    FloatElem<B>: Float,
+  {
+    let scalar = |loss: &Tensor<B, 1>| loss.clone().into_scalar().to_f64().unwrap();
+    Losses {
+      value: scalar(&self.value),
+      td_value: scalar(&self.td_value),
+      value_error: scalar(&self.value_error),
+      td_score: scalar(&self.td_score),
+      score_error: scalar(&self.score_error),
+      policy: scalar(&self.policy),
+      opponent_policy: scalar(&self.opponent_policy),
+      soft_policy: scalar(&self.soft_policy),
+      soft_opponent_policy: scalar(&self.soft_opponent_policy),
+      optimistic_policy: scalar(&self.optimistic_policy),
+      long_optimistic_policy: scalar(&self.long_optimistic_policy),
+      score_pdf: scalar(&self.score_pdf),
+      score_cdf: scalar(&self.score_cdf),
+      captured: scalar(&self.captured),
+      q_value: scalar(&self.q_value),
+      q_score: scalar(&self.q_score),
+    }
+  }
+}
+
+impl<B, O> Learner<B, O>
 where
   B: Backend + AutodiffBackend,
   FloatElem<B>: Float,
-  O: Optimizer<Model<B>, B>,
 {
-  type TE = ModelError;
-
-  fn train(
-    mut self,
+  #[allow(clippy::too_many_arguments)]
+  fn loss_terms(
+    &self,
     inputs: Array4<FloatElem<B>>,
     global: Array2<FloatElem<B>>,
     policies: Array3<FloatElem<B>>,
@@ -1225,8 +1290,7 @@ where
     scores: Array2<FloatElem<B>>,
     captured: Array4<FloatElem<B>>,
     q_values: Array4<FloatElem<B>>,
-    learning_rate: f64,
-  ) -> Result<Self, Self::TE> {
+  ) -> LossTerms<B> {
     let (batch, channels, height, width) = inputs.dim();
     let inputs = Tensor::from_data(
       TensorData::new(into_data_vec(inputs), [batch, channels, height, width]),
@@ -1495,47 +1559,70 @@ where
     let q_scores_loss =
       ((q_score_huber * q_sqrt_weights.clone()).sum_dim(1) / (q_sqrt_weights.sum_dim(1) + 1.0)).sum() * 0.0008 / batch;
 
+    LossTerms {
+      value: values_loss,
+      td_value: td_values_loss,
+      value_error: value_error_loss,
+      td_score: td_scores_loss,
+      score_error: score_error_loss,
+      policy: policies_loss,
+      opponent_policy: opponent_policies_loss,
+      soft_policy: soft_policies_loss,
+      soft_opponent_policy: soft_opponent_policies_loss,
+      optimistic_policy: optimistic_policies_loss,
+      long_optimistic_policy: long_optimistic_policies_loss,
+      score_pdf: pdf_loss,
+      score_cdf: cdf_loss,
+      captured: captured_loss,
+      q_value: q_values_loss,
+      q_score: q_scores_loss,
+    }
+  }
+}
+
+impl<B, O> OppaiTrainableModel<FloatElem<B>> for Learner<B, O>
+where
+  B: Backend + AutodiffBackend,
+  FloatElem<B>: Float,
+  O: Optimizer<Model<B>, B>,
+{
+  type TE = ModelError;
+
+  fn train(
+    mut self,
+    inputs: Array4<FloatElem<B>>,
+    global: Array2<FloatElem<B>>,
+    policies: Array3<FloatElem<B>>,
+    opponent_policies: Array3<FloatElem<B>>,
+    values: Array2<FloatElem<B>>,
+    td_values: Array3<FloatElem<B>>,
+    td_scores: Array2<FloatElem<B>>,
+    scores: Array2<FloatElem<B>>,
+    captured: Array4<FloatElem<B>>,
+    q_values: Array4<FloatElem<B>>,
+    learning_rate: f64,
+  ) -> Result<(Self, TrainStats), Self::TE> {
+    let terms = self.loss_terms(
+      inputs,
+      global,
+      policies,
+      opponent_policies,
+      values,
+      td_values,
+      td_scores,
+      scores,
+      captured,
+      q_values,
+    );
+    let losses = terms.losses();
+
     let mut norm_visitor = ParamNormVisitor::new(&self.predictor.device);
     self.predictor.model.visit(&mut norm_visitor);
     let param_l2_norm = norm_visitor.l2_norm();
 
-    log::info!(
-      "Loss: value {} td value {} value error {} td score {} score error {} policy {} opponent policy {} soft policy {} soft opponent policy {} optimistic policy {} long optimistic policy {} pdf {} cdf {} captured {} q {} q score {} L2 norm {}",
-      values_loss.clone().into_scalar(),
-      td_values_loss.clone().into_scalar(),
-      value_error_loss.clone().into_scalar(),
-      td_scores_loss.clone().into_scalar(),
-      score_error_loss.clone().into_scalar(),
-      policies_loss.clone().into_scalar(),
-      opponent_policies_loss.clone().into_scalar(),
-      soft_policies_loss.clone().into_scalar(),
-      soft_opponent_policies_loss.clone().into_scalar(),
-      optimistic_policies_loss.clone().into_scalar(),
-      long_optimistic_policies_loss.clone().into_scalar(),
-      pdf_loss.clone().into_scalar(),
-      cdf_loss.clone().into_scalar(),
-      captured_loss.clone().into_scalar(),
-      q_values_loss.clone().into_scalar(),
-      q_scores_loss.clone().into_scalar(),
-      param_l2_norm,
-    );
+    log::info!("Loss: {} L2 norm {}", losses, param_l2_norm);
 
-    let loss = values_loss
-      + td_values_loss
-      + value_error_loss
-      + policies_loss
-      + opponent_policies_loss
-      + soft_policies_loss
-      + soft_opponent_policies_loss
-      + optimistic_policies_loss
-      + long_optimistic_policies_loss
-      + td_scores_loss
-      + score_error_loss
-      + pdf_loss
-      + cdf_loss
-      + captured_loss
-      + q_values_loss
-      + q_scores_loss;
+    let loss = terms.total();
 
     let mut grads = GradientsParams::from_grads(loss.backward(), &self.predictor.model);
     // Logged every batch next to the losses, because whether the bound is set
@@ -1555,7 +1642,41 @@ where
     }
     self.predictor.model = self.optimizer.step(learning_rate, self.predictor.model, grads);
 
-    Ok(self)
+    let stats = TrainStats {
+      losses,
+      gradient_norm: grad_norm.to_f64().unwrap(),
+      clipped: max_norm.is_some(),
+      learning_rate,
+    };
+    Ok((self, stats))
+  }
+
+  fn validate(
+    &self,
+    inputs: Array4<FloatElem<B>>,
+    global: Array2<FloatElem<B>>,
+    policies: Array3<FloatElem<B>>,
+    opponent_policies: Array3<FloatElem<B>>,
+    values: Array2<FloatElem<B>>,
+    td_values: Array3<FloatElem<B>>,
+    td_scores: Array2<FloatElem<B>>,
+    scores: Array2<FloatElem<B>>,
+    captured: Array4<FloatElem<B>>,
+    q_values: Array4<FloatElem<B>>,
+  ) -> Result<Losses, Self::TE> {
+    let terms = self.loss_terms(
+      inputs,
+      global,
+      policies,
+      opponent_policies,
+      values,
+      td_values,
+      td_scores,
+      scores,
+      captured,
+      q_values,
+    );
+    Ok(terms.losses())
   }
 }
 
@@ -1966,7 +2087,21 @@ mod tests {
 
         let (out_policies_1, out_values_1) =
           futures::executor::block_on(learner.predict(inputs.clone(), global.clone(), array![0.0])).unwrap();
-        let learner = learner
+        let losses = learner
+          .validate(
+            inputs.clone(),
+            global.clone(),
+            policies.clone(),
+            opponent_policies.clone(),
+            values.clone(),
+            td_values.clone(),
+            td_scores.clone(),
+            scores.clone(),
+            captured.clone(),
+            q_values.clone(),
+          )
+          .unwrap();
+        let (learner, stats) = learner
           .train(
             inputs.clone(),
             global.clone(),
@@ -1986,6 +2121,15 @@ mod tests {
 
         assert!((out_policies_1 - out_policies_2).iter().all(|v| v.abs() > 0.0));
         assert!((out_values_1 - out_values_2).iter().all(|v| v.abs() > 0.0));
+        // The step reports the losses of the weights it started from, which
+        // are the ones validation sees on the same batch.
+        for (trained, validated) in stats.losses.values().into_iter().zip(losses.values()) {
+          assert!((trained - validated).abs() <= 1e-4 * validated.abs().max(1.0));
+        }
+        assert!(stats.losses.total().is_finite());
+        assert!(stats.gradient_norm > 0.0);
+        assert_eq!(stats.clipped, stats.gradient_norm > 1.0);
+        assert_eq!(stats.learning_rate, 0.01);
       }
     };
   }
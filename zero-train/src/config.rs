@@ -1,7 +1,9 @@
 use clap::{Arg, Command, crate_authors, crate_description, crate_name, crate_version, value_parser};
+use oppai_initial::opening::{Opening, ParseOpeningError, Placement};
+use oppai_zero::{replay_buffer::Window, sprt::Sprt};
 use oppai_zero_burn::model::ModelConfig;
-use std::path::PathBuf;
-use strum::{EnumString, VariantNames};
+use std::{fmt, path::PathBuf};
+use strum::{EnumString, IntoStaticStr, VariantNames};
 
 pub struct InitParams {
   pub model: PathBuf,
@@ -10,10 +12,13 @@ pub struct InitParams {
   pub weight_decay: f32,
 }
 
+#[derive(Clone)]
 pub struct PlayParams {
   pub width: Vec<u32>,
   pub height: Vec<u32>,
   pub komi_x_2: Vec<i32>,
+  pub openings: Vec<(Opening, f64)>,
+  pub opening_placement: Placement,
   pub model: Option<PathBuf>,
   pub model_config: ModelConfig,
   pub games: PathBuf,
@@ -22,8 +27,11 @@ pub struct PlayParams {
   pub threads: usize,
   pub batch_games: usize,
   pub in_flight_passes: usize,
+  pub cache_size: usize,
+  pub gumbel: bool,
 }
 
+#[derive(Clone)]
 pub struct TrainParams {
   pub width: u32,
   pub height: u32,
@@ -37,6 +45,11 @@ pub struct TrainParams {
   pub swa_scale: f64,
   pub swa_period: Option<usize>,
   pub games: Vec<PathBuf>,
+  pub games_weights: Option<Vec<f64>>,
+  pub window: Option<Window>,
+  pub generation_decay: f64,
+  pub dedup: bool,
+  pub shuffle_buffer: usize,
   pub learning_rate_start: f64,
   pub learning_rate_end: f64,
   pub weight_decay: f32,
@@ -44,18 +57,71 @@ pub struct TrainParams {
   pub batch_size: usize,
   pub skip: usize,
   pub ignore_surprise: bool,
+  pub metrics: Option<PathBuf>,
+  pub validation_games: Vec<PathBuf>,
+  pub validation_period: usize,
+  pub validation_batches: usize,
 }
 
+/// A classical solver to pit a model against.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, EnumString, IntoStaticStr, VariantNames)]
+pub enum Classical {
+  Heuristic,
+  Minimax,
+  Uct,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Opponent {
+  pub solver: Classical,
+  /// Iterations of UCT per move.
+  pub uct_iterations: u32,
+  /// Depth of Minimax per move.
+  pub minimax_depth: u32,
+}
+
+impl fmt::Display for Opponent {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.solver {
+      Classical::Heuristic => write!(f, "Heuristic"),
+      Classical::Minimax => write!(f, "Minimax({})", self.minimax_depth),
+      Classical::Uct => write!(f, "Uct({})", self.uct_iterations),
+    }
+  }
+}
+
+#[derive(Clone)]
 pub struct PitParams {
   pub width: Vec<u32>,
   pub height: Vec<u32>,
+  pub openings: Vec<(Opening, f64)>,
+  pub opening_placement: Placement,
   pub model: PathBuf,
   pub model_config: ModelConfig,
-  pub model_new: PathBuf,
+  pub model_new: Option<PathBuf>,
   pub model_config_new: ModelConfig,
   pub games: Option<PathBuf>,
   pub count: u64,
   pub win_rate_threshold: f64,
+  pub sprt: Option<Sprt>,
+  pub log: Option<PathBuf>,
+  pub gumbel: bool,
+  /// Pit `model` against a classical solver instead of `model_new`.
+  pub opponent: Option<Opponent>,
+  pub elo_table: Option<PathBuf>,
+}
+
+pub struct LoopParams {
+  pub dir: PathBuf,
+  pub model_config: ModelConfig,
+  pub generations: Option<usize>,
+  pub window: usize,
+  pub weight_decay: f32,
+  /// Classical solver every generation is benchmarked against.
+  pub benchmark: Option<Opponent>,
+  pub play: PlayParams,
+  pub train: TrainParams,
+  pub pit: PitParams,
 }
 
 pub struct CountParams {
@@ -76,6 +142,7 @@ pub enum Action {
   Play(PlayParams),
   Train(TrainParams),
   Pit(PitParams),
+  Loop(Box<LoopParams>),
   Count(CountParams),
   Recalc(RecalcParams),
 }
@@ -240,6 +307,354 @@ fn weight_decay_arg() -> Arg {
     .default_value("0.000000004")
 }
 
+/// Self-play options shared by `play` and `loop`.
+fn play_option_args() -> [Arg; 7] {
+  [
+    Arg::new("komi-x2")
+      .long("komi-x2")
+      .help("Komi multiplied by 2 (to allow half-integer komi values)")
+      .num_args(1..)
+      .value_parser(value_parser!(i32))
+      .default_value("0")
+      .allow_hyphen_values(true),
+    parallel_games_arg("How many games to play concurrently, merging their positions into shared forward passes"),
+    threads_arg(),
+    batch_games_arg(),
+    in_flight_passes_arg(),
+    Arg::new("cache-size")
+      .long("cache-size")
+      .help(
+        "How many evaluations to cache across all the games, so that positions reached again - by transposition, \
+         by a later turn reusing the search, or by another game with the same opening - skip the forward pass. \
+         0 disables the cache",
+      )
+      .num_args(1)
+      .value_parser(value_parser!(usize))
+      .default_value("0"),
+    Arg::new("gumbel")
+      .long("gumbel")
+      .help(
+        "Select root moves by Gumbel sequential halving instead of PUCT and train the policy on the improved \
+         policy instead of the visits",
+      )
+      .num_args(0)
+      .action(clap::ArgAction::SetTrue),
+  ]
+}
+
+/// Training options shared by `train` and `loop`.
+fn train_option_args() -> [Arg; 17] {
+  [
+    Arg::new("validation-games")
+      .long("validation-games")
+      .help("Paths to held-out games the losses are measured on, never trained on")
+      .num_args(1..)
+      .value_parser(value_parser!(PathBuf)),
+    Arg::new("validation-period")
+      .long("validation-period")
+      .help("How many batches between measurements of the validation losses")
+      .num_args(1)
+      .value_parser(value_parser!(usize))
+      .default_value("256"),
+    Arg::new("validation-batches")
+      .long("validation-batches")
+      .help("How many batches of the validation games to measure the losses on")
+      .num_args(1)
+      .value_parser(value_parser!(usize))
+      .default_value("8"),
+    Arg::new("window-min-games")
+      .long("window-min-games")
+      .help(
+        "Train only on a window of the most recent games once there are more than this many, the window growing \
+         sublinearly with the total number of games (default: train on every game)",
+      )
+      .num_args(1)
+      .value_parser(value_parser!(usize)),
+    Arg::new("window-exponent")
+      .long("window-exponent")
+      .help("Power of the total number of games the window grows with")
+      .num_args(1)
+      .value_parser(value_parser!(f64))
+      .default_value("0.75"),
+    Arg::new("window-growth")
+      .long("window-growth")
+      .help("How fast the window grows beyond its minimum; 1 with an exponent of 1 keeps every game")
+      .num_args(1)
+      .value_parser(value_parser!(f64))
+      .default_value("0.4"),
+    Arg::new("generation-decay")
+      .long("generation-decay")
+      .help("Sampling weight multiplier for every games file after the one a game comes from, down-weighting older generations")
+      .num_args(1)
+      .value_parser(value_parser!(f64))
+      .default_value("1"),
+    Arg::new("dedup")
+      .long("dedup")
+      .help("Train on every position only once, no matter how many games reached it")
+      .num_args(0)
+      .action(clap::ArgAction::SetTrue),
+    Arg::new("shuffle-buffer")
+      .long("shuffle-buffer")
+      .help("Number of training rows the games are mixed in before batches are drawn from them, bounding the memory training takes")
+      .num_args(1)
+      .value_parser(value_parser!(usize))
+      .default_value("250000"),
+    Arg::new("swa-scale")
+      .long("swa-scale")
+      .help("Number of snapshots averaged in expectation by the SWA exponential moving average")
+      .num_args(1)
+      .value_parser(value_parser!(f64))
+      .default_value("8"),
+    Arg::new("swa-period")
+      .long("swa-period")
+      .help("How many batches between SWA snapshots (default: half of the total batch count)")
+      .num_args(1)
+      .value_parser(value_parser!(usize)),
+    Arg::new("learning-rate-start")
+      .long("learning-rate-start")
+      .short('l')
+      .help("Learning rate at the first batch (kept low to warm up momentum)")
+      .num_args(1)
+      .value_parser(value_parser!(f64))
+      .default_value("0.00001"),
+    Arg::new("learning-rate-end")
+      .long("learning-rate-end")
+      .short('e')
+      .help("Learning rate at the last batch")
+      .num_args(1)
+      .value_parser(value_parser!(f64))
+      .default_value("0.0001"),
+    weight_decay_arg(),
+    Arg::new("gradient-clipping")
+      .long("gradient-clipping")
+      .short('c')
+      .help(
+        "Clip the L2 norm of the whole gradient - every parameter's gradient taken as one vector - to this value. \
+         The loss is a mean over the batch, so a bound stated for a loss summed over the batch has to be divided \
+         by the batch size",
+      )
+      .num_args(1)
+      .value_parser(value_parser!(f32)),
+    Arg::new("batch-size")
+      .long("batch-size")
+      .short('b')
+      .help("Batch size")
+      .num_args(1)
+      .value_parser(value_parser!(usize))
+      .default_value("512"),
+    Arg::new("ignore-surprise")
+      .long("ignore-surprise")
+      .help("Ignore policy surprise values when weighting training samples")
+      .num_args(0)
+      .action(clap::ArgAction::SetTrue),
+  ]
+}
+
+/// Gating options shared by `pit` and `loop`.
+fn pit_option_args() -> [Arg; 6] {
+  [
+    Arg::new("win-rate-threshold")
+      .long("win-rate-threshold")
+      .short('t')
+      .help("Win rate threshold to accept the new model, or to decide an SPRT that ran out of games")
+      .num_args(1)
+      .value_parser(value_parser!(f64))
+      .default_value("0.55"),
+    Arg::new("sprt")
+      .long("sprt")
+      .help("Stop as soon as a sequential probability ratio test accepts either Elo bound")
+      .num_args(0)
+      .action(clap::ArgAction::SetTrue),
+    Arg::new("elo0")
+      .long("elo0")
+      .help("Elo difference of the SPRT null hypothesis, rejecting the new model")
+      .num_args(1)
+      .value_parser(value_parser!(f64))
+      .allow_negative_numbers(true)
+      .default_value("0"),
+    Arg::new("elo1")
+      .long("elo1")
+      .help("Elo difference of the SPRT alternative hypothesis, accepting the new model")
+      .num_args(1)
+      .value_parser(value_parser!(f64))
+      .allow_negative_numbers(true)
+      .default_value("20"),
+    Arg::new("alpha")
+      .long("alpha")
+      .help("SPRT probability of accepting the new model when it is no stronger than elo0")
+      .num_args(1)
+      .value_parser(value_parser!(f64))
+      .default_value("0.05"),
+    Arg::new("beta")
+      .long("beta")
+      .help("SPRT probability of rejecting the new model when it is at least elo1 stronger")
+      .num_args(1)
+      .value_parser(value_parser!(f64))
+      .default_value("0.05"),
+  ]
+}
+
+/// An opening with the weight it's drawn with, given as `NAME[:WEIGHT]`, where
+/// the name is a standard opening or the path of an opening file.
+fn parse_weighted_opening(s: &str) -> Result<(Opening, f64), String> {
+  let (opening, weight) = match s.rsplit_once(':') {
+    Some((opening, weight)) if let Ok(weight) = weight.parse::<f64>() => (opening, weight),
+    _ => (s, 1.0),
+  };
+  if !weight.is_finite() || weight <= 0.0 {
+    return Err(format!("the weight {weight} isn't positive"));
+  }
+  let opening = opening.parse().map_err(|e: ParseOpeningError| e.to_string())?;
+  Ok((opening, weight))
+}
+
+/// Opening options shared by `play`, `pit` and `loop`.
+fn opening_args() -> [Arg; 2] {
+  [
+    Arg::new("opening")
+      .long("opening")
+      .help("Openings to start the games with, as NAME[:WEIGHT]; a name is a standard opening or an opening file")
+      .num_args(1..)
+      .value_parser(parse_weighted_opening)
+      .default_values(["Cross:8", "TripleCross:1"]),
+    Arg::new("opening-placement")
+      .long("opening-placement")
+      .help("Where to place the openings on the field")
+      .num_args(1)
+      .value_parser(value_parser!(Placement))
+      .default_value("Random"),
+  ]
+}
+
+fn opening_options(matches: &clap::ArgMatches) -> (Vec<(Opening, f64)>, Placement) {
+  (
+    matches.get_many("opening").unwrap().cloned().collect(),
+    matches.get_one("opening-placement").copied().unwrap(),
+  )
+}
+
+/// Classical opponent options shared by `pit` and `loop`.
+fn opponent_option_args() -> [Arg; 2] {
+  [
+    Arg::new("opponent-iterations")
+      .long("opponent-iterations")
+      .help("UCT iterations per move of the classical opponent")
+      .num_args(1)
+      .value_parser(value_parser!(u32))
+      .default_value("10000"),
+    Arg::new("opponent-depth")
+      .long("opponent-depth")
+      .help("Minimax depth per move of the classical opponent")
+      .num_args(1)
+      .value_parser(value_parser!(u32))
+      .default_value("4"),
+  ]
+}
+
+fn opponent_option(matches: &clap::ArgMatches, id: &str) -> Option<Opponent> {
+  matches.get_one(id).map(|&solver| Opponent {
+    solver,
+    uct_iterations: matches.get_one("opponent-iterations").copied().unwrap(),
+    minimax_depth: matches.get_one("opponent-depth").copied().unwrap(),
+  })
+}
+
+/// The self-play options of `matches`, with the model, the games path and their
+/// count left for the caller to fill in.
+fn play_options(matches: &clap::ArgMatches) -> PlayParams {
+  let (openings, opening_placement) = opening_options(matches);
+  PlayParams {
+    width: matches.get_many("width").unwrap().copied().collect(),
+    height: matches.get_many("height").unwrap().copied().collect(),
+    komi_x_2: matches.get_many("komi-x2").unwrap().copied().collect(),
+    openings,
+    opening_placement,
+    model: None,
+    model_config: ModelConfig::default(),
+    games: PathBuf::new(),
+    count: 0,
+    parallel_games: matches.get_one("parallel-games").copied().unwrap(),
+    threads: matches
+      .get_one("threads")
+      .copied()
+      .unwrap_or_else(num_cpus::get_physical),
+    batch_games: matches.get_one("batch-games").copied().unwrap(),
+    in_flight_passes: matches.get_one("in-flight-passes").copied().unwrap(),
+    cache_size: matches.get_one("cache-size").copied().unwrap(),
+    gumbel: matches.get_flag("gumbel"),
+  }
+}
+
+/// The training options of `matches`, with the field size, the paths and the
+/// skipped batches left for the caller to fill in.
+fn train_options(matches: &clap::ArgMatches) -> TrainParams {
+  TrainParams {
+    width: 0,
+    height: 0,
+    model: PathBuf::new(),
+    model_config: ModelConfig::default(),
+    optimizer: PathBuf::new(),
+    model_new: PathBuf::new(),
+    optimizer_new: PathBuf::new(),
+    model_swa: None,
+    model_swa_new: None,
+    swa_scale: matches.get_one("swa-scale").copied().unwrap(),
+    swa_period: matches.get_one("swa-period").copied(),
+    games: Vec::new(),
+    games_weights: None,
+    window: matches.get_one("window-min-games").map(|&min_games| Window {
+      min_games,
+      exponent: matches.get_one("window-exponent").copied().unwrap(),
+      growth: matches.get_one("window-growth").copied().unwrap(),
+    }),
+    generation_decay: matches.get_one("generation-decay").copied().unwrap(),
+    dedup: matches.get_flag("dedup"),
+    shuffle_buffer: matches.get_one("shuffle-buffer").copied().unwrap(),
+    learning_rate_start: matches.get_one("learning-rate-start").copied().unwrap(),
+    learning_rate_end: matches.get_one("learning-rate-end").copied().unwrap(),
+    weight_decay: matches.get_one("weight-decay").copied().unwrap(),
+    gradient_clipping: matches.get_one::<f32>("gradient-clipping").copied(),
+    batch_size: matches.get_one("batch-size").copied().unwrap(),
+    skip: 0,
+    ignore_surprise: matches.get_flag("ignore-surprise"),
+    metrics: None,
+    validation_games: matches
+      .get_many("validation-games")
+      .map_or_else(Vec::new, |games| games.cloned().collect()),
+    validation_period: matches.get_one("validation-period").copied().unwrap(),
+    validation_batches: matches.get_one("validation-batches").copied().unwrap(),
+  }
+}
+
+/// The gating options of `matches`, with the models, the games and their count
+/// left for the caller to fill in.
+fn pit_options(matches: &clap::ArgMatches) -> PitParams {
+  let (openings, opening_placement) = opening_options(matches);
+  PitParams {
+    width: matches.get_many("width").unwrap().copied().collect(),
+    height: matches.get_many("height").unwrap().copied().collect(),
+    openings,
+    opening_placement,
+    model: PathBuf::new(),
+    model_config: ModelConfig::default(),
+    model_new: None,
+    model_config_new: ModelConfig::default(),
+    games: None,
+    count: 0,
+    win_rate_threshold: matches.get_one("win-rate-threshold").copied().unwrap(),
+    sprt: matches.get_flag("sprt").then(|| Sprt {
+      elo0: matches.get_one("elo0").copied().unwrap(),
+      elo1: matches.get_one("elo1").copied().unwrap(),
+      alpha: matches.get_one("alpha").copied().unwrap(),
+      beta: matches.get_one("beta").copied().unwrap(),
+    }),
+    log: None,
+    gumbel: false,
+    opponent: None,
+    elo_table: None,
+  }
+}
+
 pub fn cli_parse() -> (Config, Action) {
   let init = Command::new("init")
     .about("Initialize the neural network")
@@ -251,15 +666,6 @@ pub fn cli_parse() -> (Config, Action) {
     .about("Self-play a single game")
     .arg(width_arg().num_args(1..))
     .arg(height_arg().num_args(1..))
-    .arg(
-      Arg::new("komi-x2")
-        .long("komi-x2")
-        .help("Komi multiplied by 2 (to allow half-integer komi values)")
-        .num_args(1..)
-        .value_parser(value_parser!(i32))
-        .default_value("0")
-        .allow_hyphen_values(true),
-    )
     .arg(model_arg().required(false))
     .arg(model_config_arg())
     .arg(
@@ -280,12 +686,8 @@ pub fn cli_parse() -> (Config, Action) {
         .value_parser(value_parser!(usize))
         .default_value("1"),
     )
-    .arg(parallel_games_arg(
-      "How many games to play concurrently, merging their positions into shared forward passes",
-    ))
-    .arg(threads_arg())
-    .arg(batch_games_arg())
-    .arg(in_flight_passes_arg());
+    .args(play_option_args())
+    .args(opening_args());
   let train = Command::new("train")
     .about("Train the neural network")
     .arg(width_arg())
@@ -309,21 +711,6 @@ pub fn cli_parse() -> (Config, Action) {
         .num_args(1)
         .value_parser(value_parser!(PathBuf)),
     )
-    .arg(
-      Arg::new("swa-scale")
-        .long("swa-scale")
-        .help("Number of snapshots averaged in expectation by the SWA exponential moving average")
-        .num_args(1)
-        .value_parser(value_parser!(f64))
-        .default_value("8"),
-    )
-    .arg(
-      Arg::new("swa-period")
-        .long("swa-period")
-        .help("How many batches between SWA snapshots (default: half of the total batch count)")
-        .num_args(1)
-        .value_parser(value_parser!(usize)),
-    )
     .arg(
       Arg::new("games")
         .long("games")
@@ -334,44 +721,18 @@ pub fn cli_parse() -> (Config, Action) {
         .required(true),
     )
     .arg(
-      Arg::new("learning-rate-start")
-        .long("learning-rate-start")
-        .short('l')
-        .help("Learning rate at the first batch (kept low to warm up momentum)")
-        .num_args(1)
-        .value_parser(value_parser!(f64))
-        .default_value("0.00001"),
-    )
-    .arg(
-      Arg::new("learning-rate-end")
-        .long("learning-rate-end")
-        .short('e')
-        .help("Learning rate at the last batch")
-        .num_args(1)
-        .value_parser(value_parser!(f64))
-        .default_value("0.0001"),
-    )
-    .arg(weight_decay_arg())
-    .arg(
-      Arg::new("gradient-clipping")
-        .long("gradient-clipping")
-        .short('c')
-        .help(
-          "Clip the L2 norm of the whole gradient - every parameter's gradient taken as one vector - to this value. \
-           The loss is a mean over the batch, so a bound stated for a loss summed over the batch has to be divided \
-           by the batch size",
-        )
-        .num_args(1)
-        .value_parser(value_parser!(f32)),
+      Arg::new("games-weights")
+        .long("games-weights")
+        .help("Sampling weights of the games files, one per file (default: 1 each)")
+        .num_args(1..)
+        .value_parser(value_parser!(f64)),
     )
     .arg(
-      Arg::new("batch-size")
-        .long("batch-size")
-        .short('b')
-        .help("Batch size")
+      Arg::new("metrics")
+        .long("metrics")
+        .help("Path of the log where to append the losses of every batch and validation, as CSV if it ends in .csv and as JSON lines otherwise")
         .num_args(1)
-        .value_parser(value_parser!(usize))
-        .default_value("512"),
+        .value_parser(value_parser!(PathBuf)),
     )
     .arg(
       Arg::new("skip")
@@ -382,21 +743,37 @@ pub fn cli_parse() -> (Config, Action) {
         .value_parser(value_parser!(usize))
         .default_value("0"),
     )
-    .arg(
-      Arg::new("ignore-surprise")
-        .long("ignore-surprise")
-        .help("Ignore policy surprise values when weighting training samples")
-        .num_args(0)
-        .action(clap::ArgAction::SetTrue),
-    );
+    .args(train_option_args());
   let pit = Command::new("pit")
-    .about("Pit one neural network against another")
+    .about("Pit one neural network against another, or against a classical solver")
     .arg(width_arg().num_args(1..))
     .arg(height_arg().num_args(1..))
     .arg(model_arg())
     .arg(model_config_arg())
-    .arg(model_new_arg())
+    .arg(
+      model_new_arg()
+        .required(false)
+        .required_unless_present("opponent")
+        .conflicts_with("opponent"),
+    )
     .arg(model_config_new_arg())
+    .arg(
+      Arg::new("opponent")
+        .long("opponent")
+        .help("Classical solver to pit the model against, measuring its Elo rather than gating a new model")
+        .num_args(1)
+        .value_parser(value_parser!(Classical))
+        .conflicts_with("sprt"),
+    )
+    .args(opponent_option_args())
+    .arg(
+      Arg::new("elo-table")
+        .long("elo-table")
+        .help("Path of the CSV table where to append the Elo of the model against the classical solver")
+        .num_args(1)
+        .value_parser(value_parser!(PathBuf))
+        .requires("opponent"),
+    )
     .arg(
       Arg::new("games")
         .long("games")
@@ -409,20 +786,85 @@ pub fn cli_parse() -> (Config, Action) {
       Arg::new("count")
         .long("count")
         .short('c')
-        .help("Number of games to play per side (total games will be twice this)")
+        .help("Number of games to play per side (total games will be twice this), the most SPRT may play")
         .num_args(1)
         .value_parser(value_parser!(u64))
         .default_value("50"),
     )
+    .args(pit_option_args())
+    .args(opening_args())
     .arg(
-      Arg::new("win-rate-threshold")
-        .long("win-rate-threshold")
-        .short('t')
-        .help("Win rate threshold to accept the new model")
+      Arg::new("log")
+        .long("log")
+        .short('l')
+        .help("Path of the JSON lines log where to append the pit result")
         .num_args(1)
-        .value_parser(value_parser!(f64))
-        .default_value("0.55"),
+        .value_parser(value_parser!(PathBuf)),
+    )
+    .arg(
+      Arg::new("gumbel")
+        .long("gumbel")
+        .help("Select root moves by Gumbel sequential halving instead of PUCT")
+        .num_args(0)
+        .action(clap::ArgAction::SetTrue),
     );
+  let r#loop = Command::new("loop")
+    .about("Repeat self-play, training and pitting in a generation directory, resuming where it stopped")
+    .arg(width_arg().num_args(1..))
+    .arg(height_arg().num_args(1..))
+    .arg(
+      Arg::new("dir")
+        .long("dir")
+        .short('d')
+        .help("Generation directory holding the models, the games and the loop state")
+        .num_args(1)
+        .value_parser(value_parser!(PathBuf))
+        .required(true),
+    )
+    .arg(model_config_arg())
+    .arg(
+      Arg::new("generations")
+        .long("generations")
+        .help("Stop once this many generations are trained (default: run until interrupted)")
+        .num_args(1)
+        .value_parser(value_parser!(usize)),
+    )
+    .arg(
+      Arg::new("games-per-generation")
+        .long("games-per-generation")
+        .help("Number of self-play games to play with the best model every generation")
+        .num_args(1)
+        .value_parser(value_parser!(usize))
+        .default_value("1000"),
+    )
+    .arg(
+      Arg::new("window")
+        .long("window")
+        .help("How many of the most recent generations' games to train on")
+        .num_args(1)
+        .value_parser(value_parser!(usize))
+        .default_value("10"),
+    )
+    .arg(
+      Arg::new("pit-count")
+        .long("pit-count")
+        .help("Number of pit games to play per side (total games will be twice this), the most SPRT may play")
+        .num_args(1)
+        .value_parser(value_parser!(u64))
+        .default_value("50"),
+    )
+    .arg(
+      Arg::new("benchmark")
+        .long("benchmark")
+        .help("Classical solver to play every generation's model against, recording its Elo in elo.csv")
+        .num_args(1)
+        .value_parser(value_parser!(Classical)),
+    )
+    .args(opponent_option_args())
+    .args(play_option_args())
+    .args(train_option_args())
+    .args(pit_option_args())
+    .args(opening_args());
   let count = Command::new("count").about("Count games and trainable examples").arg(
     Arg::new("games")
       .long("games")
@@ -467,6 +909,7 @@ pub fn cli_parse() -> (Config, Action) {
     .subcommand(play)
     .subcommand(train)
     .subcommand(pit)
+    .subcommand(r#loop)
     .subcommand(count)
     .subcommand(recalc)
     .subcommand_required(true)
@@ -529,32 +972,16 @@ pub fn cli_parse() -> (Config, Action) {
       })
     }
     Some(("play", matches)) => {
-      let width = matches.get_many("width").unwrap().copied().collect();
-      let height = matches.get_many("height").unwrap().copied().collect();
-      let komi_x_2 = matches.get_many("komi-x2").unwrap().copied().collect();
       let model = matches.get_one("model").cloned();
       let model_config = parse_model_config(matches, "model-config");
       let games = matches.get_one("games").cloned().unwrap();
       let count = matches.get_one("count").copied().unwrap();
-      let parallel_games = matches.get_one("parallel-games").copied().unwrap();
-      let threads = matches
-        .get_one("threads")
-        .copied()
-        .unwrap_or_else(num_cpus::get_physical);
-      let batch_games = matches.get_one("batch-games").copied().unwrap();
-      let in_flight_passes = matches.get_one("in-flight-passes").copied().unwrap();
       Action::Play(PlayParams {
-        width,
-        height,
-        komi_x_2,
         model,
         model_config,
         games,
         count,
-        parallel_games,
-        threads,
-        batch_games,
-        in_flight_passes,
+        ..play_options(matches)
       })
     }
     Some(("train", matches)) => {
@@ -567,16 +994,12 @@ pub fn cli_parse() -> (Config, Action) {
       let optimizer_new = matches.get_one("optimizer-new").cloned().unwrap();
       let model_swa = matches.get_one("model-swa").cloned();
       let model_swa_new = matches.get_one("model-swa-new").cloned();
-      let swa_scale = matches.get_one("swa-scale").copied().unwrap();
-      let swa_period = matches.get_one("swa-period").copied();
       let games = matches.get_many("games").unwrap().cloned().collect();
-      let learning_rate_start = matches.get_one("learning-rate-start").cloned().unwrap();
-      let learning_rate_end = matches.get_one("learning-rate-end").cloned().unwrap();
-      let weight_decay = matches.get_one("weight-decay").copied().unwrap();
-      let gradient_clipping = matches.get_one::<f32>("gradient-clipping").copied();
-      let batch_size = matches.get_one("batch-size").cloned().unwrap();
+      let games_weights = matches
+        .get_many("games-weights")
+        .map(|weights| weights.copied().collect());
       let skip = matches.get_one("skip").copied().unwrap();
-      let ignore_surprise = matches.get_flag("ignore-surprise");
+      let metrics = matches.get_one("metrics").cloned();
       Action::Train(TrainParams {
         width,
         height,
@@ -587,40 +1010,72 @@ pub fn cli_parse() -> (Config, Action) {
         optimizer_new,
         model_swa,
         model_swa_new,
-        swa_scale,
-        swa_period,
         games,
-        learning_rate_start,
-        learning_rate_end,
-        weight_decay,
-        gradient_clipping,
-        batch_size,
+        games_weights,
         skip,
-        ignore_surprise,
+        metrics,
+        ..train_options(matches)
       })
     }
     Some(("pit", matches)) => {
-      let width = matches.get_many("width").unwrap().copied().collect();
-      let height = matches.get_many("height").unwrap().copied().collect();
       let model = matches.get_one("model").cloned().unwrap();
       let model_config = parse_model_config(matches, "model-config");
-      let model_new = matches.get_one("model-new").cloned().unwrap();
+      let model_new = matches.get_one("model-new").cloned();
       let model_config_new = parse_model_config(matches, "model-config-new");
       let games = matches.get_one("games").cloned();
       let count = matches.get_one("count").copied().unwrap();
-      let win_rate_threshold = matches.get_one("win-rate-threshold").copied().unwrap();
+      let log = matches.get_one("log").cloned();
+      let gumbel = matches.get_flag("gumbel");
+      let opponent = opponent_option(matches, "opponent");
+      let elo_table = matches.get_one("elo-table").cloned();
       Action::Pit(PitParams {
-        width,
-        height,
         model,
         model_config,
         model_new,
         model_config_new,
         games,
         count,
-        win_rate_threshold,
+        log,
+        gumbel,
+        opponent,
+        elo_table,
+        ..pit_options(matches)
       })
     }
+    Some(("loop", matches)) => {
+      let dir = matches.get_one("dir").cloned().unwrap();
+      let model_config = parse_model_config(matches, "model-config");
+      let generations = matches.get_one("generations").copied();
+      let window = matches.get_one("window").copied().unwrap();
+      let weight_decay = matches.get_one("weight-decay").copied().unwrap();
+      let benchmark = opponent_option(matches, "benchmark");
+      let play = PlayParams {
+        count: matches.get_one("games-per-generation").copied().unwrap(),
+        ..play_options(matches)
+      };
+      // Training takes a single size, big enough for every game played.
+      let train = TrainParams {
+        width: *Iterator::max(play.width.iter()).unwrap(),
+        height: *Iterator::max(play.height.iter()).unwrap(),
+        ..train_options(matches)
+      };
+      let pit = PitParams {
+        count: matches.get_one("pit-count").copied().unwrap(),
+        gumbel: play.gumbel,
+        ..pit_options(matches)
+      };
+      Action::Loop(Box::new(LoopParams {
+        dir,
+        model_config,
+        generations,
+        window,
+        weight_decay,
+        benchmark,
+        play,
+        train,
+        pit,
+      }))
+    }
     Some(("count", matches)) => {
       let games = matches.get_many("games").unwrap().cloned().collect();
       Action::Count(CountParams { games })
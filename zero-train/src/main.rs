@@ -1,4 +1,5 @@
 mod config;
+mod metrics;
 
 use anyhow::{Error, Result};
 #[cfg(feature = "cuda")]
@@ -22,34 +23,42 @@ use burn::{
   },
 };
 use config::{
-  Action, Backend as ConfigBackend, Config, CountParams, InitParams, PitParams, PlayParams, RecalcParams, TrainParams,
-  cli_parse,
+  Action, Backend as ConfigBackend, Classical, Config, CountParams, InitParams, LoopParams, Opponent, PitParams,
+  PlayParams, RecalcParams, TrainParams, cli_parse,
 };
 use flate2::{Compression, read::MultiGzDecoder, write::GzEncoder};
 use futures::StreamExt;
+use metrics::Metrics;
 use num_traits::Float;
+use oppai_ai::{ai::AI, analysis::Analysis};
+use oppai_ais::oppai::{Config as AIConfig, InConfidence, Oppai, Solver};
 use oppai_field::{
   any_field::AnyField,
   extended_field::ExtendedField,
-  field::{Field, length},
+  field::{Field, Hash, Pos, length},
   player::Player,
   zobrist::Zobrist,
 };
+use oppai_initial::opening::{Opening, Placement};
+use oppai_patterns::patterns::Patterns;
 use oppai_sgf::{from_sgf, to_sgf};
 use oppai_zero::{
   batch_model::{batch_model, run_evaluator},
   episode::{Visits, episode},
+  eval_cache::{CachedModel, EvalCache},
   examples::Examples,
-  mcgs::{Params, Search},
-  model::{Model, TrainableModel},
-  opening::opening,
+  mcgs::{GumbelParams, Params, Search},
+  model::{Losses, Model, TrainableModel},
   pit,
   random_model::RandomModel,
+  replay_buffer::{self, FilePlan, Interleave, ReplayFile, SeenPositions, ShuffleBuffer},
+  sprt::{Decision, EloEstimate, Results as PitResults},
 };
-use oppai_zero_burn::model::{Learner, Model as BurnModel, Predictor, ema_update};
+use oppai_zero_burn::model::{Learner, Model as BurnModel, ModelConfig, Predictor, ema_update};
 use oppai_zero_sgf::{sgf_to_visits, visits_to_sgf};
 use rand::{Rng, RngExt, SeedableRng, distr::uniform::SampleUniform, make_rng, rngs::SmallRng};
-use rand_distr::{Distribution, Exp1, Open01, StandardNormal};
+use rand_distr::{Distribution, Exp1, Open01, StandardNormal, weighted::WeightedIndex};
+use serde_json::json;
 use sgf_parse::{GameTree, SgfNode, SimpleText, serialize, unknown_game::Prop};
 use std::{
   cmp::Ordering,
@@ -57,13 +66,14 @@ use std::{
   fs::File,
   io::{BufRead, BufReader, Write},
   iter::{self, Sum},
-  path::Path,
+  path::{Path, PathBuf},
   process::ExitCode,
   sync::{
     Arc, Mutex,
     atomic::{AtomicBool, AtomicUsize},
   },
 };
+use strum::{EnumString, IntoStaticStr};
 
 fn init<B>(params: InitParams, device: B::Device) -> Result<ExitCode>
 where
@@ -112,6 +122,40 @@ fn read_games<P: AsRef<Path>>(path: P) -> Result<impl Iterator<Item = Result<Sgf
   }))
 }
 
+/// Counts the games of a file without parsing them, one game being one line.
+fn count_games<P: AsRef<Path>>(path: P) -> Result<usize> {
+  let file = File::open(path)?;
+  BufReader::new(MultiGzDecoder::new(BufReader::new(file)))
+    .lines()
+    .try_fold(0, |count, line| Ok(count + usize::from(!line?.is_empty())))
+}
+
+/// Draws one of the weighted `openings` and places it on a field of the given
+/// size, Red starting it.
+fn draw_opening<R: Rng>(
+  openings: &[(Opening, f64)],
+  placement: Placement,
+  width: u32,
+  height: u32,
+  rng: &mut R,
+) -> Vec<(Pos, Player)> {
+  let index = WeightedIndex::new(openings.iter().map(|&(_, weight)| weight))
+    .expect("opening weights are positive")
+    .sample(rng);
+  openings[index].0.points(width, height, Player::Red, placement, rng)
+}
+
+/// Plays the opening moves, returning the player to move after them. A move a
+/// user-defined opening can't make is skipped.
+fn put_opening(field: &mut Field, opening: &[(Pos, Player)]) -> Player {
+  for &(pos, player) in opening {
+    if field.put_point(pos, player) {
+      field.update_grounded();
+    }
+  }
+  opening.last().map_or(Player::Red, |&(_, player)| player.next())
+}
+
 /// Appends a game as a separate gzip member, so an interrupted process never
 /// corrupts previously written games and appending remains valid gzip.
 fn write_sgf(file: &mut File, sgf: &str) -> Result<()> {
@@ -161,6 +205,7 @@ async fn play_games<N, M, MF, R>(
   next_game: &AtomicUsize,
   parallel: usize,
   mut new_model: MF,
+  zobrist: &Arc<Zobrist<Hash>>,
   rng: &mut R,
   should_stop: &AtomicBool,
   file: &Mutex<File>,
@@ -197,31 +242,31 @@ where
     let model = new_model();
     let width = params.width[rng.random_range(0..params.width.len())];
     let height = params.height[rng.random_range(0..params.height.len())];
-    let op = opening(width, height, &mut rng);
+    let op = draw_opening(&params.openings, params.opening_placement, width, height, &mut rng);
     let komi_x_2_count = params
       .komi_x_2
       .iter()
       .copied()
       .filter(|&komi_x_2| (komi_x_2.unsigned_abs() as usize) < op.len())
       .count();
+    // An empty opening leaves no komi to choose from, so it's played without.
     let komi_x_2 = params
       .komi_x_2
       .iter()
       .copied()
       .filter(|&komi_x_2| (komi_x_2.unsigned_abs() as usize) < op.len())
-      .nth(rng.random_range(0..komi_x_2_count))
-      .unwrap();
+      .nth(rng.random_range(0..komi_x_2_count.max(1)))
+      .unwrap_or(0);
+    let zobrist = zobrist.clone();
     async move {
-      let mut player = Player::Red;
-      let mut field = Field::new_from_rng(width, height, &mut rng);
-      for (x, y) in op {
-        let pos = field.to_pos(x, y);
-        assert!(field.put_point(pos, player));
-        field.update_grounded();
-        player = player.next();
-      }
-
-      let visits = episode(&mut field, player, &model, komi_x_2, &mut rng)
+      let mut field = Field::new(width, height, zobrist);
+      let player = put_opening(&mut field, &op);
+
+      let search_params = Params {
+        gumbel: params.gumbel.then_some(GumbelParams::SELF_PLAY),
+        ..Params::SELF_PLAY
+      };
+      let visits = episode(&mut field, player, &model, komi_x_2, search_params, &mut rng)
         .await
         .map_err(|e| anyhow::anyhow!("model failure: {:?}", e))?;
 
@@ -271,6 +316,15 @@ where
   let threads = params.threads.clamp(1, params.count.min(params.parallel_games).max(1));
   let next_game = AtomicUsize::new(0);
   let file = Mutex::new(File::options().append(true).create(true).open(&params.games)?);
+  // Every field hashes with the same Zobrist table, so that equal positions of
+  // different games hash the same and the evaluation cache can answer them.
+  let zobrist = Arc::new(Zobrist::new(
+    length(
+      *Iterator::max(params.width.iter()).unwrap(),
+      *Iterator::max(params.height.iter()).unwrap(),
+    ) * 3,
+    rng,
+  ));
   log::info!(
     "Playing {} games on {} threads, {} concurrent",
     params.count,
@@ -293,6 +347,9 @@ where
       // this thread ever touches the model, so the backend needs nothing of its
       // own to be safe under threads.
       let (handle, requests) = batch_model::<FloatElem<B>>(predictor.predicts_uncertainty());
+      // One cache for all the games, in front of their handles, so that a
+      // position any game has evaluated is answered without a forward pass.
+      let cache = (params.cache_size > 0).then(|| Arc::new(EvalCache::new(params.cache_size)));
 
       std::thread::scope(|scope| -> Result<()> {
         let workers = (0..threads)
@@ -302,16 +359,19 @@ where
             let mut shard_rng = SmallRng::from_seed(rng.random());
             let parallel = split(params.parallel_games, threads, i);
             let source = handle.source();
+            let cache = cache.clone();
             let params = &params;
             let should_stop = should_stop.as_ref();
             let file = &file;
             let next_game = &next_game;
+            let zobrist = &zobrist;
             scope.spawn(move || {
               futures::executor::block_on(play_games(
                 params,
                 next_game,
                 parallel,
-                || source.clone(),
+                || CachedModel::new(source.clone(), cache.clone()),
+                zobrist,
                 &mut shard_rng,
                 should_stop,
                 file,
@@ -345,6 +405,10 @@ where
         }
         Ok(())
       })?;
+
+      if let Some(cache) = cache {
+        log::info!("Evaluation cache: {}", cache.stats());
+      }
     }
     None => {
       std::thread::scope(|scope| -> Result<()> {
@@ -357,12 +421,14 @@ where
             let should_stop = should_stop.as_ref();
             let file = &file;
             let next_game = &next_game;
+            let zobrist = &zobrist;
             scope.spawn(move || {
               futures::executor::block_on(play_games(
                 params,
                 next_game,
                 parallel,
                 || RandomModel::new(SmallRng::from_seed(seeder.random())),
+                zobrist,
                 &mut shard_rng,
                 should_stop,
                 file,
@@ -383,6 +449,125 @@ where
   Ok(ExitCode::SUCCESS)
 }
 
+/// Adds the searched positions of a game to `examples`, building its field from
+/// the Zobrist table `zobrist_seed` gives.
+#[allow(clippy::too_many_arguments)]
+fn add_game<R: Rng>(
+  examples: &mut Examples,
+  node: &SgfNode<Prop>,
+  params: &TrainParams,
+  zobrist_seed: u64,
+  surprise_weighting: bool,
+  weight: f64,
+  seen: Option<&mut SeenPositions>,
+  rng: &mut R,
+) -> Result<()> {
+  let field =
+    from_sgf::<Field, _>(node, &mut SmallRng::seed_from_u64(zobrist_seed)).ok_or(anyhow::anyhow!("invalid sgf"))?;
+  let visits = sgf_to_visits(node, field.stride);
+  let komi_x_2 = node
+    .properties
+    .iter()
+    .find_map(|prop| match prop {
+      Prop::Unknown(name, values) if name == "KM" => values.first().map(|value| {
+        let value = value.parse::<f32>().unwrap();
+        (value * 2.0).round() as i32
+      }),
+      _ => None,
+    })
+    .unwrap_or(0);
+
+  if field.width() > params.width || field.height() > params.height {
+    return Err(anyhow::anyhow!(
+      "Game is bigger than config: {}:{}",
+      field.width(),
+      field.height()
+    ));
+  }
+
+  examples.add(
+    komi_x_2,
+    visits,
+    &field,
+    field.width() <= params.height && field.height() <= params.width,
+    surprise_weighting,
+    weight,
+    seen,
+    rng,
+  );
+  Ok(())
+}
+
+/// Games left to read from a file.
+type Games<'a> = Box<dyn Iterator<Item = Result<SgfNode<Prop>>> + 'a>;
+
+/// Reads the games of the training window, every game from a file drawn in
+/// proportion to the games it has left, so that each generation is spread over
+/// the whole training run. Of the positions reached by several games, it is the
+/// game read first that keeps its row.
+struct WindowReader<'a> {
+  params: &'a TrainParams,
+  files: Vec<(Games<'a>, f64)>,
+  interleave: Interleave,
+  zobrist_seed: u64,
+  order_rng: SmallRng,
+  rows_rng: SmallRng,
+  seen: Option<SeenPositions>,
+}
+
+impl<'a> WindowReader<'a> {
+  fn new(
+    params: &'a TrainParams,
+    files: &[ReplayFile],
+    plans: &[FilePlan],
+    zobrist_seed: u64,
+    order_seed: u64,
+    rows_seed: u64,
+  ) -> Result<Self> {
+    let mut readers = Vec::new();
+    let mut games = Vec::new();
+    for ((path, file), plan) in params.games.iter().zip(files).zip(plans) {
+      if plan.taken(file.games) == 0 {
+        continue;
+      }
+      readers.push((Box::new(read_games(path)?.skip(plan.skip)) as Games, plan.weight));
+      games.push(plan.taken(file.games));
+    }
+    Ok(WindowReader {
+      params,
+      files: readers,
+      interleave: Interleave::new(games),
+      zobrist_seed,
+      order_rng: SmallRng::seed_from_u64(order_seed),
+      rows_rng: SmallRng::seed_from_u64(rows_seed),
+      seen: params.dedup.then(SeenPositions::default),
+    })
+  }
+
+  /// Adds the rows of the next game to `examples`, telling whether there was a
+  /// game left.
+  fn add_next(&mut self, examples: &mut Examples) -> Result<bool> {
+    let Some(file) = self.interleave.next(&mut self.order_rng) else {
+      return Ok(false);
+    };
+    let (games, weight) = &mut self.files[file];
+    let node = games
+      .next()
+      .ok_or(anyhow::anyhow!("games file changed while training"))??;
+    add_game(
+      examples,
+      &node,
+      self.params,
+      self.zobrist_seed,
+      !self.params.ignore_surprise,
+      *weight,
+      self.seen.as_mut(),
+      &mut self.rows_rng,
+    )?;
+    Ok(true)
+  }
+}
+
 fn train<B, R: Rng>(
   params: TrainParams,
   device: B::Device,
@@ -395,7 +580,7 @@ where
 {
   let model = BurnModel::<B>::new(&device, &params.model_config);
   let model = model.load_file(
-    params.model,
+    &params.model,
     &DefaultFileRecorder::<FullPrecisionSettings>::new(),
     &device,
   )?;
@@ -440,58 +625,127 @@ where
     gradient_clipping: params.gradient_clipping,
   };
 
-  let mut examples = Examples::default();
-  for path in params.games {
-    for node in read_games(path)? {
-      let node = node?;
-      let field = from_sgf::<Field, _>(&node, rng).ok_or(anyhow::anyhow!("invalid sgf"))?;
-      let visits = sgf_to_visits(&node, field.stride);
-      let komi_x_2 = node
-        .properties
-        .iter()
-        .find_map(|prop| match prop {
-          Prop::Unknown(name, values) if name == "KM" => values.first().map(|value| {
-            let value = value.parse::<f32>().unwrap();
-            (value * 2.0).round() as i32
-          }),
-          _ => None,
-        })
-        .unwrap_or(0);
-
-      if field.width() > params.width || field.height() > params.height {
-        return Err(anyhow::anyhow!(
-          "Game is bigger than config: {}:{}",
-          field.width(),
-          field.height()
-        ));
-      }
+  let games_weights = params
+    .games_weights
+    .clone()
+    .unwrap_or_else(|| vec![1.0; params.games.len()]);
+  anyhow::ensure!(
+    games_weights.len() == params.games.len(),
+    "there must be one weight per games file"
+  );
+  // The files are counted before any game is kept, so that the window can be
+  // placed without holding the games that fall out of it.
+  let files = params
+    .games
+    .iter()
+    .zip(&games_weights)
+    .map(|(path, &weight)| {
+      Ok(ReplayFile {
+        games: count_games(path)?,
+        weight,
+      })
+    })
+    .collect::<Result<Vec<_>>>()?;
+  let plans = replay_buffer::plan(&files, params.window, params.generation_decay);
+  log::info!(
+    "Training on {} of {} games",
+    plans
+      .iter()
+      .zip(&files)
+      .map(|(plan, file)| plan.taken(file.games))
+      .sum::<usize>(),
+    files.iter().map(|file| file.games).sum::<usize>()
+  );
 
-      examples.add(
-        komi_x_2,
-        visits,
-        &field,
-        field.width() <= params.height && field.height() <= params.width,
-        !params.ignore_surprise,
-        rng,
-      );
+  // Every field draws its Zobrist table from the same seed, so that equal
+  // positions of different games hash the same.
+  let zobrist_seed = rng.random::<u64>();
+  // The window is read twice: once to count the rows, which the learning rate
+  // schedule needs up front, and once to train on them. Both passes read the
+  // games in the same order and write the same rows.
+  let order_seed = rng.random::<u64>();
+  let rows_seed = rng.random::<u64>();
+  let mut window = WindowReader::new(&params, &files, &plans, zobrist_seed, order_seed, rows_seed)?;
+  let mut rows = 0;
+  let mut counted = Examples::default();
+  while window.add_next(&mut counted)? {
+    rows += counted.len();
+    counted = Examples::default();
+  }
+  if let Some(seen) = &window.seen {
+    log::info!("Distinct positions: {}", seen.len());
+  }
+  log::info!("Training rows: {}", rows);
+
+  // The validation positions are weighted flatly, so that the losses measure
+  // how the model does on the positions as they come rather than on the ones
+  // the training data dwells on.
+  let mut validation = Examples::default();
+  for path in &params.validation_games {
+    for node in read_games(path)? {
+      add_game(&mut validation, &node?, &params, zobrist_seed, false, 1.0, None, rng)?;
     }
   }
+  validation.shuffle(rng);
+  let mut metrics = params
+    .metrics
+    .as_ref()
+    .map(|path| Metrics::open(path, &params.model_new))
+    .transpose()?;
 
-  examples.shuffle(rng);
-  let batches_count = examples.batches_count(params.batch_size);
+  let batches_count = (rows / params.batch_size).max(1);
   // By default average a snapshot every half-epoch, so a single training run
   // contributes two snapshots to the moving average.
   let swa_period = params.swa_period.unwrap_or((batches_count / 2).max(1));
   let zobrist = Arc::new(Zobrist::new(length(params.width, params.height) * 3, rng));
-  for (i, batch) in examples
-    .batches(params.width, params.height, zobrist, params.batch_size)
-    .enumerate()
-    .skip(params.skip)
-  {
-    if should_stop.load(std::sync::atomic::Ordering::Relaxed) {
+  let validate = |learner: &Learner<B, _>, metrics: &mut Option<Metrics>, i: usize| -> Result<()> {
+    if validation.is_empty() {
+      return Ok(());
+    }
+    let losses = validation
+      .batches(params.width, params.height, zobrist.clone(), params.batch_size)
+      .take(params.validation_batches)
+      .map(|batch| {
+        learner.validate(
+          batch.inputs,
+          batch.global,
+          batch.policies,
+          batch.opponent_policies,
+          batch.values,
+          batch.td_values,
+          batch.td_scores,
+          batch.scores,
+          batch.captured,
+          batch.q_values,
+        )
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    let losses = Losses::mean(&losses);
+    log::info!("Validation after {} batches: total {} {}", i, losses.total(), losses);
+    if let Some(metrics) = metrics {
+      metrics.validation(i, &losses)?;
+    }
+    Ok(())
+  };
+  window = WindowReader::new(&params, &files, &plans, zobrist_seed, order_seed, rows_seed)?;
+  let mut buffer = ShuffleBuffer::new(params.shuffle_buffer.max(params.batch_size));
+  let mut read = false;
+  let mut trained = params.skip;
+  for i in 0..batches_count {
+    if i >= params.skip && should_stop.load(std::sync::atomic::Ordering::Relaxed) {
       log::info!("Stopping training after {} batches", i);
       break;
     }
+    while !read && !buffer.is_full() {
+      read = !window.add_next(buffer.examples_mut())?;
+    }
+    // Skipped batches still take their rows out of the buffer, so that a
+    // resumed run goes on with as much of the window left as it had.
+    let examples = buffer.draw(params.batch_size, rng);
+    if i < params.skip {
+      continue;
+    }
+    let batch = examples.batch(0..examples.len(), params.width, params.height, zobrist.clone());
     if i.is_multiple_of(64) {
       log::info!("Batch {} out of {}", i, batches_count);
     }
@@ -501,7 +755,8 @@ where
       0.0
     };
     let learning_rate = params.learning_rate_start + (params.learning_rate_end - params.learning_rate_start) * progress;
-    learner = learner.train(
+    let stats;
+    (learner, stats) = learner.train(
       batch.inputs,
       batch.global,
       batch.policies,
@@ -514,6 +769,13 @@ where
       batch.q_values,
       learning_rate,
     )?;
+    if let Some(metrics) = metrics.as_mut() {
+      metrics.train(i, &stats)?;
+    }
+    trained = i + 1;
+    if trained.is_multiple_of(params.validation_period.max(1)) {
+      validate(&learner, &mut metrics, trained)?;
+    }
 
     if let Some(swa) = swa_model.take() {
       swa_model = Some(if (i + 1).is_multiple_of(swa_period) {
@@ -523,6 +785,11 @@ where
       });
     }
   }
+  drop(window);
+
+  if !trained.is_multiple_of(params.validation_period.max(1)) {
+    validate(&learner, &mut metrics, trained)?;
+  }
 
   learner
     .predictor
@@ -544,51 +811,38 @@ where
   Ok(ExitCode::SUCCESS)
 }
 
-async fn pit<B, R: Rng>(
-  params: PitParams,
-  device: B::Device,
+/// How a series of pit games ended.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PitEnd {
+  /// Interrupted before the outcome was known.
+  Stopped,
+  /// The outcome was known before all the games were played.
+  Decided(bool),
+  /// All the games were played.
+  Finished,
+}
+
+/// Plays up to `params.count` pairs of pit games, both games of a pair sharing
+/// a field size and an opening, with the colours swapped.
+///
+/// `play` plays a game on a field after its opening, given the player to move
+/// and whether the evaluated side moves first, and returns the evaluated side's
+/// score. `decide` is called after every game and ends the series once it
+/// returns the outcome.
+async fn pit_games<R, P, D>(
+  params: &PitParams,
   rng: &mut R,
-  should_stop: Arc<AtomicBool>,
-) -> Result<ExitCode>
+  should_stop: &AtomicBool,
+  mut play: P,
+  mut decide: D,
+) -> Result<(PitResults, PitEnd)>
 where
-  B: Backend,
-  FloatElem<B>: Float + Sum + SampleUniform + Display + Debug,
+  R: Rng,
+  P: AsyncFnMut(&mut Field, Player, bool, &mut R) -> Result<i32>,
+  D: FnMut(&PitResults) -> Option<bool>,
 {
-  let model_old = BurnModel::<B>::new(&device, &params.model_config);
-  let model_old = model_old.load_file(
-    params.model,
-    &DefaultFileRecorder::<FullPrecisionSettings>::new(),
-    &device,
-  )?;
-  let model_old = Predictor {
-    model: model_old,
-    device: device.clone(),
-  };
-
-  let model_new = BurnModel::<B>::new(&device, &params.model_config_new);
-  let model_new = model_new.load_file(
-    params.model_new,
-    &DefaultFileRecorder::<FullPrecisionSettings>::new(),
-    &device,
-  )?;
-  let model_new = Predictor {
-    model: model_new,
-    device,
-  };
-
-  let mut player = Player::Red;
   let total_games = params.count * 2;
 
-  // Returns the win rate assuming all remaining games go best/worst case.
-  // best=true: remaining games are all wins; best=false: remaining games are all losses.
-  #[inline]
-  fn win_rate_bound(wins: u64, losses: u64, played: u64, total: u64, best: bool) -> f64 {
-    let draws = played - wins - losses;
-    let remaining = total - played;
-    let best_wins = if best { wins + remaining } else { wins };
-    (best_wins as f64 + draws as f64 / 2.0) / total as f64
-  }
-
   let zobrist = Arc::new(Zobrist::new(
     length(
       *Iterator::max(params.width.iter()).unwrap(),
@@ -601,34 +855,24 @@ where
   let mut height = params.height[rng.random_range(0..params.height.len())];
   let mut field = Field::new(width, height, zobrist.clone());
 
-  let mut op = opening(width, height, rng);
-  for &(x, y) in op.iter() {
-    let pos = field.to_pos(x, y);
-    assert!(field.put_point(pos, player));
-    field.update_grounded();
-    player = player.next();
-  }
+  let mut op = draw_opening(&params.openings, params.opening_placement, width, height, rng);
+  let mut player = put_opening(&mut field, &op);
 
-  let mut wins = 0u64;
-  let mut losses = 0u64;
+  let mut results = PitResults::default();
 
   let mut i = 0u64;
-  let outcome = loop {
+  let end = loop {
     if should_stop.load(std::sync::atomic::Ordering::Relaxed) {
       log::info!("Stopping after {} games", i);
-      break false;
+      break PitEnd::Stopped;
     }
 
-    let result = if i.is_multiple_of(2) {
-      pit::play(&mut field, player, &model_new, &model_old, 0, rng).await?
-    } else {
-      -pit::play(&mut field, player, &model_old, &model_new, 0, rng).await?
-    };
+    let result = play(&mut field, player, i.is_multiple_of(2), rng).await?;
 
     match result.cmp(&0) {
-      Ordering::Less => losses += 1,
-      Ordering::Greater => wins += 1,
-      Ordering::Equal => {}
+      Ordering::Less => results.losses += 1,
+      Ordering::Greater => results.wins += 1,
+      Ordering::Equal => results.draws += 1,
     };
 
     if let Some(ref games) = params.games
@@ -641,42 +885,566 @@ where
 
     i += 1;
 
-    log::info!("Game {}, result {}/{}/{}", i, wins, i - wins - losses, losses);
-
-    // Check early exit: outcome is already determined regardless of remaining games.
-    if win_rate_bound(wins, losses, i, total_games, true) <= params.win_rate_threshold {
-      break false;
-    }
-    if win_rate_bound(wins, losses, i, total_games, false) > params.win_rate_threshold {
-      break true;
+    let elo = EloEstimate::new(&results);
+    log::info!(
+      "Game {}, result {}/{}/{}, Elo {:.1} [{:.1}, {:.1}]",
+      i,
+      results.wins,
+      results.draws,
+      results.losses,
+      elo.elo,
+      elo.lower,
+      elo.upper
+    );
+
+    if let Some(outcome) = decide(&results) {
+      break PitEnd::Decided(outcome);
     }
 
     if i == total_games {
-      // All games played, no early exit triggered; do final evaluation.
-      let draws = i - wins - losses;
-      let win_rate = (wins as f64 + draws as f64 / 2.0) / total_games as f64;
-      break win_rate > params.win_rate_threshold;
+      break PitEnd::Finished;
     }
 
     if i.is_multiple_of(2) {
       width = params.width[rng.random_range(0..params.width.len())];
       height = params.height[rng.random_range(0..params.height.len())];
-      op = opening(width, height, rng);
+      op = draw_opening(&params.openings, params.opening_placement, width, height, rng);
     }
 
-    player = Player::Red;
     field = Field::new(width, height, zobrist.clone());
-    for &(x, y) in op.iter() {
-      let pos = field.to_pos(x, y);
-      assert!(field.put_point(pos, player));
-      field.update_grounded();
-      player = player.next();
+    player = put_opening(&mut field, &op);
+  };
+
+  Ok((results, end))
+}
+
+fn load_predictor<B: Backend>(path: &Path, config: &ModelConfig, device: B::Device) -> Result<Predictor<B>> {
+  let model = BurnModel::<B>::new(&device, config);
+  let model = model.load_file(path, &DefaultFileRecorder::<FullPrecisionSettings>::new(), &device)?;
+  Ok(Predictor { model, device })
+}
+
+/// Pits the new model against the old one and tells whether the new one is
+/// accepted. An interrupted pit rejects it.
+async fn pit_models<B, R: Rng>(
+  params: &PitParams,
+  device: B::Device,
+  rng: &mut R,
+  should_stop: &AtomicBool,
+) -> Result<bool>
+where
+  B: Backend,
+  FloatElem<B>: Float + Sum + SampleUniform + Display + Debug,
+{
+  if let Some(sprt) = params.sprt {
+    anyhow::ensure!(sprt.elo0 < sprt.elo1, "elo0 must be below elo1");
+    anyhow::ensure!(
+      sprt.alpha > 0.0 && sprt.alpha < 1.0 && sprt.beta > 0.0 && sprt.beta < 1.0,
+      "alpha and beta must be between 0 and 1"
+    );
+  }
+
+  let model_new_path = params
+    .model_new
+    .as_ref()
+    .ok_or_else(|| anyhow::anyhow!("no new model to pit"))?;
+  let model_old = load_predictor::<B>(&params.model, &params.model_config, device.clone())?;
+  let model_new = load_predictor::<B>(model_new_path, &params.model_config_new, device)?;
+
+  let total_games = params.count * 2;
+
+  // Returns the win rate assuming all remaining games go best/worst case.
+  // best=true: remaining games are all wins; best=false: remaining games are all losses.
+  #[inline]
+  fn win_rate_bound(results: &PitResults, total: u64, best: bool) -> f64 {
+    let remaining = total - results.games();
+    let best_wins = if best { results.wins + remaining } else { results.wins };
+    (best_wins as f64 + results.draws as f64 / 2.0) / total as f64
+  }
+
+  let search_params = Params {
+    gumbel: params.gumbel.then_some(GumbelParams::PLAY),
+    ..Params::PLAY
+  };
+
+  let (results, end) = pit_games(
+    params,
+    rng,
+    should_stop,
+    async |field: &mut Field, player, new_first, rng: &mut R| {
+      Ok(if new_first {
+        pit::play(field, player, &model_new, &model_old, 0, search_params, rng).await?
+      } else {
+        -pit::play(field, player, &model_old, &model_new, 0, search_params, rng).await?
+      })
+    },
+    |results| {
+      if let Some(sprt) = params.sprt {
+        let (lower, upper) = sprt.bounds();
+        log::info!("LLR {:.2} [{:.2}, {:.2}]", sprt.llr(results), lower, upper);
+        match sprt.decide(results) {
+          Some(Decision::H1) => Some(true),
+          Some(Decision::H0) => Some(false),
+          None => None,
+        }
+      } else if win_rate_bound(results, total_games, true) <= params.win_rate_threshold {
+        // Check early exit: outcome is already determined regardless of remaining games.
+        Some(false)
+      } else if win_rate_bound(results, total_games, false) > params.win_rate_threshold {
+        Some(true)
+      } else {
+        None
+      }
+    },
+  )
+  .await?;
+
+  let outcome = match end {
+    PitEnd::Stopped => false,
+    PitEnd::Decided(outcome) => outcome,
+    // All games played, no early exit triggered; do final evaluation.
+    PitEnd::Finished => {
+      let win_rate = (results.wins as f64 + results.draws as f64 / 2.0) / total_games as f64;
+      win_rate > params.win_rate_threshold
     }
   };
 
+  let elo = EloEstimate::new(&results);
+  log::info!(
+    "The new model is {}: {:.1} Elo [{:.1}, {:.1}] after {} games",
+    if outcome { "accepted" } else { "rejected" },
+    elo.elo,
+    elo.lower,
+    elo.upper,
+    results.games()
+  );
+
+  if let Some(ref log) = params.log {
+    let entry = json!({
+      "model": params.model,
+      "model_new": model_new_path,
+      "wins": results.wins,
+      "draws": results.draws,
+      "losses": results.losses,
+      "elo": elo.elo,
+      "elo_lower": elo.lower,
+      "elo_upper": elo.upper,
+      "sprt": params.sprt.map(|sprt| json!({
+        "elo0": sprt.elo0,
+        "elo1": sprt.elo1,
+        "alpha": sprt.alpha,
+        "beta": sprt.beta,
+        "llr": sprt.llr(&results),
+      })),
+      "stopped": end == PitEnd::Stopped,
+      "accepted": outcome,
+    });
+    let mut file = File::options().append(true).create(true).open(log)?;
+    writeln!(file, "{entry}")?;
+  }
+
+  Ok(outcome)
+}
+
+/// Pits `params.model` against a classical solver, playing every game since
+/// there is nothing to gate, and records the model's Elo against it.
+///
+/// The solver runs without the time-limited ladder search, so that its
+/// strength depends on its iterations or depth alone and not on the machine.
+async fn pit_classical<B, R: Rng>(
+  params: &PitParams,
+  opponent: Opponent,
+  device: B::Device,
+  rng: &mut R,
+  should_stop: &AtomicBool,
+) -> Result<EloEstimate>
+where
+  B: Backend,
+  FloatElem<B>: Float + Sum + SampleUniform + Display + Debug + Send,
+{
+  let model = load_predictor::<B>(&params.model, &params.model_config, device)?;
+
+  let search_params = Params {
+    gumbel: params.gumbel.then_some(GumbelParams::PLAY),
+    ..Params::PLAY
+  };
+  let config = AIConfig {
+    solver: match opponent.solver {
+      Classical::Heuristic => Solver::Heuristic,
+      Classical::Minimax => Solver::Minimax,
+      Classical::Uct => Solver::Uct,
+    },
+    ladders: false,
+    ..AIConfig::default()
+  };
+  let confidence = InConfidence {
+    minimax_depth: opponent.minimax_depth,
+    uct_iterations: opponent.uct_iterations,
+    zero_iterations: 0,
+  };
+  let patterns = Arc::new(Patterns::default());
+
+  let (results, end) = pit_games(
+    params,
+    rng,
+    should_stop,
+    async |field: &mut Field, player, model_first, rng: &mut R| {
+      let mut oppai =
+        Oppai::<FloatElem<B>, ()>::new(field.width(), field.height(), config.clone(), patterns.clone(), ());
+      let mut oppai_rng = SmallRng::from_seed(rng.random());
+      let score = pit::play_opponent(
+        field,
+        player,
+        &model,
+        model_first,
+        0,
+        search_params,
+        rng,
+        async |field: &mut Field, player| {
+          oppai
+            .analyze(&mut oppai_rng, field, player, Some(confidence.clone()), &|| false)
+            .await
+            .best_move(&mut oppai_rng)
+        },
+      )
+      .await?;
+      Ok(score)
+    },
+    |_| None,
+  )
+  .await?;
+
+  let elo = EloEstimate::new(&results);
+  log::info!(
+    "{} against {}: {:.1} Elo [{:.1}, {:.1}] after {} games",
+    params.model.display(),
+    opponent,
+    elo.elo,
+    elo.lower,
+    elo.upper,
+    results.games()
+  );
+
+  if let Some(ref log) = params.log {
+    let entry = json!({
+      "model": params.model,
+      "opponent": opponent.to_string(),
+      "wins": results.wins,
+      "draws": results.draws,
+      "losses": results.losses,
+      "elo": elo.elo,
+      "elo_lower": elo.lower,
+      "elo_upper": elo.upper,
+      "stopped": end == PitEnd::Stopped,
+    });
+    let mut file = File::options().append(true).create(true).open(log)?;
+    writeln!(file, "{entry}")?;
+  }
+
+  // A partial series would bias the table towards whichever games came first.
+  if let Some(ref table) = params.elo_table
+    && end != PitEnd::Stopped
+  {
+    let mut file = File::options().append(true).create(true).open(table)?;
+    if file.metadata()?.len() == 0 {
+      writeln!(file, "model,opponent,wins,draws,losses,elo,elo_lower,elo_upper")?;
+    }
+    writeln!(
+      file,
+      "{},{},{},{},{},{:.1},{:.1},{:.1}",
+      params.model.display(),
+      opponent,
+      results.wins,
+      results.draws,
+      results.losses,
+      elo.elo,
+      elo.lower,
+      elo.upper
+    )?;
+  }
+
+  Ok(elo)
+}
+
+async fn pit<B, R: Rng>(
+  params: PitParams,
+  device: B::Device,
+  rng: &mut R,
+  should_stop: Arc<AtomicBool>,
+) -> Result<ExitCode>
+where
+  B: Backend,
+  FloatElem<B>: Float + Sum + SampleUniform + Display + Debug + Send,
+{
+  if let Some(opponent) = params.opponent {
+    pit_classical::<B, _>(&params, opponent, device, rng, &should_stop).await?;
+    return Ok(ExitCode::SUCCESS);
+  }
+  let outcome = pit_models::<B, _>(&params, device, rng, &should_stop).await?;
   Ok(if outcome { ExitCode::SUCCESS } else { 2.into() })
 }
 
+/// The step of a generation that a `loop` run has yet to finish.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "lowercase")]
+enum Stage {
+  Play,
+  Train,
+  Pit,
+  Benchmark,
+}
+
+/// Progress of a `loop` run, saved after every finished stage so that an
+/// interrupted run resumes from the stage it was in.
+#[derive(Clone, Copy, Debug)]
+struct LoopState {
+  /// The generation being produced; generation 0 is the initial model.
+  generation: usize,
+  stage: Stage,
+  /// The generation whose model plays the self-play games.
+  best: usize,
+}
+
+impl LoopState {
+  fn load(path: &Path) -> Result<Option<LoopState>> {
+    if !path.exists() {
+      return Ok(None);
+    }
+    let state: serde_json::Value = serde_json::from_reader(File::open(path)?)?;
+    let invalid = || anyhow::anyhow!("invalid loop state {}", path.display());
+    Ok(Some(LoopState {
+      generation: state["generation"].as_u64().ok_or_else(invalid)? as usize,
+      stage: state["stage"]
+        .as_str()
+        .and_then(|stage| stage.parse().ok())
+        .ok_or_else(invalid)?,
+      best: state["best"].as_u64().ok_or_else(invalid)? as usize,
+    }))
+  }
+
+  /// Replaces the state file through a rename, so an interruption leaves either
+  /// the old state or the new one.
+  fn save(&self, path: &Path) -> Result<()> {
+    let state = json!({
+      "generation": self.generation,
+      "stage": <&str>::from(self.stage),
+      "best": self.best,
+    });
+    let tmp = path.with_extension("json.tmp");
+    let mut file = File::create(&tmp)?;
+    writeln!(file, "{state}")?;
+    file.sync_all()?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+  }
+}
+
+/// Path of a generation's file of the given kind in the generation directory.
+/// Models and optimizer states get their extension from the recorder.
+fn generation_path(dir: &Path, kind: &str, generation: usize) -> PathBuf {
+  let path = dir.join(kind).join(format!("{generation:04}"));
+  if kind == "games" {
+    path.with_extension("sgf.gz")
+  } else {
+    path
+  }
+}
+
+/// Path of a generation's averaged model, the one that is pitted and plays. The
+/// initial model is its own average.
+fn swa_path(dir: &Path, generation: usize) -> PathBuf {
+  if generation == 0 {
+    generation_path(dir, "models", 0)
+  } else {
+    generation_path(dir, "swa", generation)
+  }
+}
+
+/// Repeats self-play with the best model, training on the recent games and
+/// pitting the trained model against the best one, then optionally playing it
+/// against a classical solver for an Elo table of the whole run.
+///
+/// Training continues from the previous generation's weights whether or not
+/// they were accepted, so a rejected generation still moves the training on;
+/// only the self-play model waits for a candidate that beats it.
+fn train_loop<B, R: Rng>(
+  params: LoopParams,
+  device: B::Device,
+  rng: &mut R,
+  should_stop: Arc<AtomicBool>,
+) -> Result<ExitCode>
+where
+  B: Backend,
+  FloatElem<B>: Float + Sum + SampleUniform + Display + Debug + Send,
+  StandardNormal: Distribution<FloatElem<B>>,
+  Exp1: Distribution<FloatElem<B>>,
+  Open01: Distribution<FloatElem<B>>,
+{
+  let dir = params.dir.as_path();
+  let state_path = dir.join("state.json");
+
+  let mut state = match LoopState::load(&state_path)? {
+    Some(state) => {
+      log::info!(
+        "Resuming generation {} at {:?}, the best model is generation {}",
+        state.generation,
+        state.stage,
+        state.best
+      );
+      state
+    }
+    None => {
+      for kind in ["models", "optimizers", "swa", "games"] {
+        std::fs::create_dir_all(dir.join(kind))?;
+      }
+      log::info!("Initializing the model in {}", dir.display());
+      init::<Autodiff<B>>(
+        InitParams {
+          model: generation_path(dir, "models", 0),
+          model_config: params.model_config.clone(),
+          optimizer: generation_path(dir, "optimizers", 0),
+          weight_decay: params.weight_decay,
+        },
+        device.clone(),
+      )?;
+      let state = LoopState {
+        generation: 1,
+        stage: Stage::Play,
+        best: 0,
+      };
+      state.save(&state_path)?;
+      state
+    }
+  };
+
+  while params
+    .generations
+    .is_none_or(|generations| state.generation <= generations)
+  {
+    let generation = state.generation;
+    let games = generation_path(dir, "games", generation);
+
+    if state.stage == Stage::Play {
+      // Games are appended one by one, so an interrupted generation only has to
+      // play the ones it's missing. The file is created before the first game
+      // ends, and an empty one isn't valid gzip.
+      let played = if games.metadata().is_ok_and(|metadata| metadata.len() > 0) {
+        count_games(&games)?
+      } else {
+        0
+      };
+      let count = params.play.count.saturating_sub(played);
+      log::info!("Generation {}: playing {} games", generation, count);
+      if count > 0 {
+        play::<B, _>(
+          PlayParams {
+            model: Some(swa_path(dir, state.best)),
+            model_config: params.model_config.clone(),
+            games: games.clone(),
+            count,
+            ..params.play.clone()
+          },
+          device.clone(),
+          rng,
+          should_stop.clone(),
+        )?;
+      }
+      if should_stop.load(std::sync::atomic::Ordering::Relaxed) {
+        break;
+      }
+      state.stage = Stage::Train;
+      state.save(&state_path)?;
+    }
+
+    if state.stage == Stage::Train {
+      let first = generation.saturating_sub(params.window.max(1) - 1).max(1);
+      let window = (first..=generation)
+        .map(|generation| generation_path(dir, "games", generation))
+        .filter(|path| path.exists())
+        .collect::<Vec<_>>();
+      log::info!("Generation {}: training on {} games files", generation, window.len());
+      train::<Autodiff<B>, _>(
+        TrainParams {
+          model: generation_path(dir, "models", generation - 1),
+          model_config: params.model_config.clone(),
+          optimizer: generation_path(dir, "optimizers", generation - 1),
+          model_new: generation_path(dir, "models", generation),
+          optimizer_new: generation_path(dir, "optimizers", generation),
+          model_swa: Some(swa_path(dir, generation - 1)),
+          model_swa_new: Some(swa_path(dir, generation)),
+          games: window,
+          metrics: Some(dir.join("metrics.jsonl")),
+          ..params.train.clone()
+        },
+        device.clone(),
+        rng,
+        should_stop.clone(),
+      )?;
+      // Interrupted training still saves what it has, which the resumed run
+      // simply overwrites.
+      if should_stop.load(std::sync::atomic::Ordering::Relaxed) {
+        break;
+      }
+      state.stage = Stage::Pit;
+      state.save(&state_path)?;
+    }
+
+    if state.stage == Stage::Pit {
+      log::info!("Generation {}: pitting against generation {}", generation, state.best);
+      let accepted = futures::executor::block_on(pit_models::<B, _>(
+        &PitParams {
+          model: swa_path(dir, state.best),
+          model_config: params.model_config.clone(),
+          model_new: Some(swa_path(dir, generation)),
+          model_config_new: params.model_config.clone(),
+          log: Some(dir.join("pit.jsonl")),
+          ..params.pit.clone()
+        },
+        device.clone(),
+        rng,
+        &should_stop,
+      ))?;
+      if should_stop.load(std::sync::atomic::Ordering::Relaxed) {
+        break;
+      }
+      if accepted {
+        log::info!("Generation {} is the new best model", generation);
+        state.best = generation;
+      }
+      state.stage = Stage::Benchmark;
+      state.save(&state_path)?;
+    }
+
+    // Pits only ever compare a generation with the best one before it, so their
+    // Elo differences don't add up; a fixed classical opponent gives a scale
+    // that stays comparable across the whole run.
+    if let Some(opponent) = params.benchmark {
+      log::info!("Generation {}: benchmarking against {}", generation, opponent);
+      futures::executor::block_on(pit_classical::<B, _>(
+        &PitParams {
+          model: swa_path(dir, generation),
+          model_config: params.model_config.clone(),
+          log: Some(dir.join("benchmark.jsonl")),
+          elo_table: Some(dir.join("elo.csv")),
+          ..params.pit.clone()
+        },
+        opponent,
+        device.clone(),
+        rng,
+        &should_stop,
+      ))?;
+      if should_stop.load(std::sync::atomic::Ordering::Relaxed) {
+        break;
+      }
+    }
+    state.generation += 1;
+    state.stage = Stage::Play;
+    state.save(&state_path)?;
+  }
+
+  Ok(ExitCode::SUCCESS)
+}
+
 fn count<R: Rng>(params: CountParams, rng: &mut R) -> Result<ExitCode> {
   let mut games = 0u32;
   let mut examples = 0u32;
@@ -885,6 +1653,7 @@ where
     Action::Play(params) => play::<B, _>(params, device, &mut rng, should_stop),
     Action::Train(params) => train::<Autodiff<B>, _>(params, device, &mut rng, should_stop),
     Action::Pit(params) => futures::executor::block_on(pit::<B, _>(params, device, &mut rng, should_stop)),
+    Action::Loop(params) => train_loop::<B, _>(*params, device, &mut rng, should_stop),
     Action::Count(params) => count(params, &mut rng),
     Action::Recalc(params) => futures::executor::block_on(recalc::<B, _>(params, device, &mut rng, should_stop)),
   }
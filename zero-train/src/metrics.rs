@@ -0,0 +1,90 @@
+use anyhow::Result;
+use oppai_zero::model::{Losses, TrainStats};
+use serde_json::{Map, json};
+use std::{
+  fs::File,
+  io::Write,
+  path::{Path, PathBuf},
+};
+
+/// A log of the losses of every training batch and of every validation, for
+/// following a run - or a series of them - after the fact.
+///
+/// A path ending in `.csv` gets comma separated values under a header row, any
+/// other path one JSON object per line. Either way the log is appended to, so
+/// the generations of a training loop end up in one file, told apart by the
+/// model they trained.
+pub struct Metrics {
+  file: File,
+  csv: bool,
+  model: PathBuf,
+}
+
+impl Metrics {
+  pub fn open(path: &Path, model: &Path) -> Result<Metrics> {
+    let csv = path.extension().is_some_and(|extension| extension == "csv");
+    let mut file = File::options().append(true).create(true).open(path)?;
+    if csv && file.metadata()?.len() == 0 {
+      let names = Losses::NAMES.join(",");
+      writeln!(
+        file,
+        "model,split,batch,learning_rate,gradient_norm,clipped,total,{names}"
+      )?;
+    }
+    Ok(Metrics {
+      file,
+      csv,
+      model: model.to_owned(),
+    })
+  }
+
+  /// Records a training step: the losses of the batch before the step.
+  pub fn train(&mut self, batch: usize, stats: &TrainStats) -> Result<()> {
+    self.write(
+      "train",
+      batch,
+      Some((stats.learning_rate, stats.gradient_norm, stats.clipped)),
+      &stats.losses,
+    )
+  }
+
+  /// Records the mean losses of the validation set after `batch` batches.
+  pub fn validation(&mut self, batch: usize, losses: &Losses) -> Result<()> {
+    self.write("validation", batch, None, losses)
+  }
+
+  fn write(&mut self, split: &str, batch: usize, step: Option<(f64, f64, bool)>, losses: &Losses) -> Result<()> {
+    if self.csv {
+      let step = step.map_or_else(
+        || ",,".to_owned(),
+        |(learning_rate, gradient_norm, clipped)| format!("{learning_rate},{gradient_norm},{clipped}"),
+      );
+      let values = losses.values().map(|value| value.to_string()).join(",");
+      writeln!(
+        self.file,
+        "{},{split},{batch},{step},{},{values}",
+        self.model.display(),
+        losses.total()
+      )?;
+    } else {
+      let mut record = json!({
+        "model": self.model,
+        "split": split,
+        "batch": batch,
+        "total": losses.total(),
+        "losses": Losses::NAMES
+          .into_iter()
+          .zip(losses.values())
+          .map(|(name, value)| (name.to_owned(), json!(value)))
+          .collect::<Map<_, _>>(),
+      });
+      if let Some((learning_rate, gradient_norm, clipped)) = step {
+        record["learning_rate"] = json!(learning_rate);
+        record["gradient_norm"] = json!(gradient_norm);
+        record["clipped"] = json!(clipped);
+      }
+      writeln!(self.file, "{record}")?;
+    }
+    Ok(())
+  }
+}
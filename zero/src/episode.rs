@@ -1,3 +1,4 @@
+use crate::eval_cache::position_key;
 use crate::field_features::{field_features, global};
 use crate::mcgs::{Params, Search};
 use crate::model::Model;
@@ -65,7 +66,8 @@ pub(crate) fn reduced_search(values: &[f64]) -> (u32, f64) {
 ///   the total weight the search accumulated behind each move rather than the
 ///   number of playouts. Data recorded before weighting was introduced stores
 ///   plain visit counts, which is the same thing with every playout weighing
-///   one and normalizes to the same target.
+///   one and normalizes to the same target. Under Gumbel root selection it is
+///   the improved policy of every child instead, already normalized.
 ///
 /// * `.1` - the training weight of the row: 1 for a full search, ramped down
 ///   towards [`REDUCED_VISITS_WEIGHT`] once the game is all but decided, and 0
@@ -231,6 +233,7 @@ pub async fn episode<N, M, R>(
   mut player: Player,
   model: &M,
   mut komi_x_2: i32,
+  params: Params,
   rng: &mut R,
 ) -> Result<Vec<Visits>, M::E>
 where
@@ -257,7 +260,8 @@ where
     // The opening is sampled from the trained policy: these moves are meant to
     // spread the training positions over the openings the net actually plays.
     let (policy, _) = model
-      .predict(
+      .predict_keyed(
+        &[position_key(field, player, komi_x_2)],
         features.insert_axis(Axis(0)),
         global.insert_axis(Axis(0)),
         Array1::zeros(1),
@@ -273,7 +277,7 @@ where
     }
   }
 
-  let mut search = Search::new(Params::SELF_PLAY);
+  let mut search = Search::new(params);
   let mut visits = Vec::new();
 
   // Raw network policy priors of the root, captured before temperature and
@@ -298,12 +302,15 @@ where
       // The root has to be expanded before the noise can be applied to its children priors.
       search.mcgs(field, player, model, komi_x_2, rng).await?;
       search.root_priors(&mut raw_priors);
-      // Total Dirichlet alpha, matching AlphaZero's 0.03 per move on an empty 19x19 board
-      // (0.03 * 361 = 10.83). Kept constant across board sizes and through the game, with
-      // the shaping in `add_dirichlet_noise` deciding how it is spread across the moves.
-      let total_concentration = N::from(0.03 * 19.0.powi(2)).unwrap();
-      let temperature = interpolate_early(field, N::from(1.25).unwrap(), N::from(1.1).unwrap());
-      search.add_dirichlet_noise(rng, N::from(0.25).unwrap(), total_concentration, temperature);
+      // Sequential halving explores through its own Gumbel noise.
+      if params.gumbel.is_none() {
+        // Total Dirichlet alpha, matching AlphaZero's 0.03 per move on an empty 19x19 board
+        // (0.03 * 361 = 10.83). Kept constant across board sizes and through the game, with
+        // the shaping in `add_dirichlet_noise` deciding how it is spread across the moves.
+        let total_concentration = N::from(0.03 * 19.0.powi(2)).unwrap();
+        let temperature = interpolate_early(field, N::from(1.25).unwrap(), N::from(1.1).unwrap());
+        search.add_dirichlet_noise(rng, N::from(0.25).unwrap(), total_concentration, temperature);
+      }
       reduced_search(&search_values)
     } else {
       (MCTS_VISITS, 0.0)
@@ -324,7 +331,11 @@ where
     let value = search.winloss().to_f64().unwrap().clamp(-1.0, 1.0);
     search_values.push(if player == Player::Red { value } else { -value });
 
-    let target: Vec<(Pos, N)> = if full_search {
+    let target: Vec<(Pos, N)> = if params.gumbel.is_some() {
+      // The visits of sequential halving follow its schedule rather than the
+      // search's preferences, so the target is the improved policy instead.
+      search.improved_policy()
+    } else if full_search {
       // Use pruned weights for full searches with Dirichlet noise.
       // This removes the extra forced playouts from the policy target,
       // producing a cleaner training signal.
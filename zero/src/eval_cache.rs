@@ -0,0 +1,322 @@
+//! Evaluation cache.
+//!
+//! The net is asked about the same position more often than one would think:
+//! transpositions reach it through different move orders, a search reused for
+//! the next move or for pondering reaches it again, and reviewing a game or
+//! recalculating its targets evaluates the very positions the previous run
+//! already did. [`EvalCache`] remembers the latest evaluations, and
+//! [`CachedModel`] puts it in front of any [`Model`], asking the model only
+//! about the positions the cache has not seen.
+//!
+//! Positions are identified by [`PositionKey`]: the Zobrist hash of the dots
+//! rather than the net inputs themselves. The history planes of two move orders
+//! transposing onto one position differ, so one of them gets the other's
+//! evaluation - the same approximation the search graph makes when it merges
+//! them into one node.
+
+use crate::model::Model;
+use ndarray::{Array1, Array2, Array3, Array4, Axis, stack};
+use num_traits::Float;
+use oppai_field::{
+  field::{Field, Hash},
+  player::Player,
+};
+use std::{
+  collections::HashMap,
+  fmt::{self, Display, Formatter},
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, Ordering},
+  },
+};
+
+/// Everything that decides what the net answers for a position, apart from the
+/// optimism it is asked with.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PositionKey {
+  pub hash: Hash,
+  /// Field size, since the hash of a position does not tell the sizes apart.
+  pub width: u32,
+  pub height: u32,
+  /// Player to move.
+  pub player: Player,
+  /// Komi of the player to move, doubled.
+  pub komi_x_2: i32,
+}
+
+/// Key of the position on `field` with `player` to move, `komi_x_2` being the
+/// komi of `player`.
+pub fn position_key(field: &Field, player: Player, komi_x_2: i32) -> PositionKey {
+  PositionKey {
+    hash: field.hash(),
+    width: field.width(),
+    height: field.height(),
+    player,
+    komi_x_2,
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct CacheKey {
+  position: PositionKey,
+  /// Bits of the optimism the policy was interpolated at.
+  optimism: u64,
+}
+
+const NIL: usize = usize::MAX;
+
+struct Slot<N> {
+  key: CacheKey,
+  policy: Array2<N>,
+  values: Array1<N>,
+  prev: usize,
+  next: usize,
+}
+
+/// Least recently used list over a slab of slots, so that neither a hit nor an
+/// eviction allocates.
+struct Lru<N> {
+  map: HashMap<CacheKey, usize>,
+  slots: Vec<Slot<N>>,
+  /// Most recently used slot.
+  head: usize,
+  /// Least recently used slot, the next one to be evicted.
+  tail: usize,
+}
+
+impl<N: Clone> Lru<N> {
+  fn unlink(&mut self, idx: usize) {
+    let (prev, next) = (self.slots[idx].prev, self.slots[idx].next);
+    if prev == NIL {
+      self.head = next;
+    } else {
+      self.slots[prev].next = next;
+    }
+    if next == NIL {
+      self.tail = prev;
+    } else {
+      self.slots[next].prev = prev;
+    }
+  }
+
+  fn push_front(&mut self, idx: usize) {
+    self.slots[idx].prev = NIL;
+    self.slots[idx].next = self.head;
+    if self.head == NIL {
+      self.tail = idx;
+    } else {
+      self.slots[self.head].prev = idx;
+    }
+    self.head = idx;
+  }
+
+  fn get(&mut self, key: &CacheKey) -> Option<(Array2<N>, Array1<N>)> {
+    let idx = *self.map.get(key)?;
+    self.unlink(idx);
+    self.push_front(idx);
+    let slot = &self.slots[idx];
+    Some((slot.policy.clone(), slot.values.clone()))
+  }
+
+  fn insert(&mut self, key: CacheKey, policy: Array2<N>, values: Array1<N>, capacity: usize) {
+    if let Some(&idx) = self.map.get(&key) {
+      let slot = &mut self.slots[idx];
+      slot.policy = policy;
+      slot.values = values;
+      self.unlink(idx);
+      self.push_front(idx);
+    } else if self.slots.len() < capacity {
+      self.slots.push(Slot {
+        key,
+        policy,
+        values,
+        prev: NIL,
+        next: NIL,
+      });
+      let idx = self.slots.len() - 1;
+      self.map.insert(key, idx);
+      self.push_front(idx);
+    } else {
+      let idx = self.tail;
+      self.unlink(idx);
+      let slot = &mut self.slots[idx];
+      self.map.remove(&slot.key);
+      slot.key = key;
+      slot.policy = policy;
+      slot.values = values;
+      self.map.insert(key, idx);
+      self.push_front(idx);
+    }
+  }
+}
+
+/// Hit-rate statistics of an [`EvalCache`].
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct CacheStats {
+  pub hits: u64,
+  pub misses: u64,
+  /// Number of evaluations held.
+  pub len: usize,
+}
+
+impl CacheStats {
+  pub fn hit_rate(&self) -> f64 {
+    let lookups = self.hits + self.misses;
+    if lookups == 0 {
+      0.0
+    } else {
+      self.hits as f64 / lookups as f64
+    }
+  }
+}
+
+impl Display for CacheStats {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} hits of {} lookups ({:.1}%), {} evaluations held",
+      self.hits,
+      self.hits + self.misses,
+      self.hit_rate() * 100.0,
+      self.len
+    )
+  }
+}
+
+/// A bounded cache of net evaluations that evicts the least recently used one.
+/// It is meant to be shared behind an `Arc` by everything evaluating with the
+/// same net - the workers of a search, or all the games of a self-play run.
+pub struct EvalCache<N> {
+  capacity: usize,
+  lru: Mutex<Lru<N>>,
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+impl<N: Float> EvalCache<N> {
+  pub fn new(capacity: usize) -> Self {
+    EvalCache {
+      capacity,
+      lru: Mutex::new(Lru {
+        map: HashMap::with_capacity(capacity),
+        slots: Vec::with_capacity(capacity),
+        head: NIL,
+        tail: NIL,
+      }),
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+    }
+  }
+
+  pub fn stats(&self) -> CacheStats {
+    CacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+      len: self.lru.lock().unwrap().slots.len(),
+    }
+  }
+
+  /// Answers [`Model::predict`] for a batch of positions named by `keys`, asking
+  /// `model` only about the ones not cached yet, in one batch.
+  pub async fn predict<M: Model<N>>(
+    &self,
+    model: &M,
+    keys: &[PositionKey],
+    inputs: Array4<N>,
+    global: Array2<N>,
+    optimism: Array1<N>,
+  ) -> Result<(Array3<N>, Array2<N>), M::E> {
+    assert_eq!(keys.len(), inputs.len_of(Axis(0)));
+    if self.capacity == 0 {
+      return model.predict(inputs, global, optimism).await;
+    }
+
+    let keys = keys
+      .iter()
+      .zip(optimism.iter())
+      .map(|(&position, optimism)| CacheKey {
+        position,
+        optimism: optimism.to_f64().unwrap().to_bits(),
+      })
+      .collect::<Vec<_>>();
+    let mut evaluations = {
+      let mut lru = self.lru.lock().unwrap();
+      keys.iter().map(|key| lru.get(key)).collect::<Vec<_>>()
+    };
+    let missing = (0..keys.len())
+      .filter(|&i| evaluations[i].is_none())
+      .collect::<Vec<_>>();
+    self
+      .hits
+      .fetch_add((keys.len() - missing.len()) as u64, Ordering::Relaxed);
+    self.misses.fetch_add(missing.len() as u64, Ordering::Relaxed);
+
+    if !missing.is_empty() {
+      let (policies, values) = model
+        .predict(
+          inputs.select(Axis(0), &missing),
+          global.select(Axis(0), &missing),
+          optimism.select(Axis(0), &missing),
+        )
+        .await?;
+      let mut lru = self.lru.lock().unwrap();
+      for (j, &i) in missing.iter().enumerate() {
+        let policy = policies.index_axis(Axis(0), j).to_owned();
+        let values = values.index_axis(Axis(0), j).to_owned();
+        lru.insert(keys[i], policy.clone(), values.clone(), self.capacity);
+        evaluations[i] = Some((policy, values));
+      }
+    }
+
+    let (policies, values): (Vec<_>, Vec<_>) = evaluations.into_iter().map(Option::unwrap).unzip();
+    let policies = policies.iter().map(|policy| policy.view()).collect::<Vec<_>>();
+    let values = values.iter().map(|values| values.view()).collect::<Vec<_>>();
+    Ok((stack(Axis(0), &policies).unwrap(), stack(Axis(0), &values).unwrap()))
+  }
+}
+
+/// A [`Model`] that looks its evaluations up in a shared [`EvalCache`] before
+/// asking the model it wraps. Only [`Model::predict_keyed`] can be cached, since
+/// plain [`Model::predict`] does not say which positions it is asked about;
+/// without a cache it is just the model itself.
+#[derive(Clone)]
+pub struct CachedModel<N, M> {
+  pub model: M,
+  pub cache: Option<Arc<EvalCache<N>>>,
+}
+
+impl<N, M> CachedModel<N, M> {
+  pub fn new(model: M, cache: Option<Arc<EvalCache<N>>>) -> Self {
+    CachedModel { model, cache }
+  }
+}
+
+impl<N: Float, M: Model<N>> Model<N> for CachedModel<N, M> {
+  type E = M::E;
+
+  fn predicts_uncertainty(&self) -> bool {
+    self.model.predicts_uncertainty()
+  }
+
+  async fn predict(
+    &self,
+    inputs: Array4<N>,
+    global: Array2<N>,
+    optimism: Array1<N>,
+  ) -> Result<(Array3<N>, Array2<N>), Self::E> {
+    self.model.predict(inputs, global, optimism).await
+  }
+
+  async fn predict_keyed(
+    &self,
+    keys: &[PositionKey],
+    inputs: Array4<N>,
+    global: Array2<N>,
+    optimism: Array1<N>,
+  ) -> Result<(Array3<N>, Array2<N>), Self::E> {
+    match &self.cache {
+      Some(cache) => cache.predict(&self.model, keys, inputs, global, optimism).await,
+      None => self.model.predict_keyed(keys, inputs, global, optimism).await,
+    }
+  }
+}
@@ -0,0 +1,95 @@
+use crate::eval_cache::{CachedModel, EvalCache, PositionKey};
+use crate::model::Model;
+use ndarray::{Array1, Array2, Array3, Array4, array, s};
+use oppai_field::player::Player;
+use std::cell::RefCell;
+use std::sync::Arc;
+
+fn key(hash: u64) -> PositionKey {
+  PositionKey {
+    hash,
+    width: 2,
+    height: 1,
+    player: Player::Red,
+    komi_x_2: 0,
+  }
+}
+
+/// Asks `model` about the positions named by `hashes`, whose single input
+/// plane is filled with the hash so that the answers can be told apart.
+fn predict<M: Model<f64>>(model: &M, hashes: &[u64], optimism: f64) -> (Array3<f64>, Array2<f64>)
+where
+  M::E: std::fmt::Debug,
+{
+  let keys = hashes.iter().map(|&hash| key(hash)).collect::<Vec<_>>();
+  let inputs = Array4::from_shape_fn((hashes.len(), 1, 1, 2), |(i, _, _, _)| hashes[i] as f64);
+  let global = Array2::zeros((hashes.len(), 1));
+  let optimism = Array1::from_elem(hashes.len(), optimism);
+  futures::executor::block_on(model.predict_keyed(&keys, inputs, global, optimism)).unwrap()
+}
+
+#[test]
+fn only_unseen_positions_reach_the_model() {
+  let batches = RefCell::new(Vec::new());
+  let model = |inputs: Array4<f64>, _: Array2<f64>, _: Array1<f64>| {
+    batches.borrow_mut().push(inputs.slice(s![.., 0, 0, 0]).to_vec());
+    let policies = inputs.slice(s![.., 0, .., ..]).to_owned();
+    let values = Array2::from_shape_fn((inputs.dim().0, 4), |(i, j)| inputs[(i, 0, 0, 0)] + j as f64);
+    Ok::<_, ()>((policies, values))
+  };
+  let cache = Arc::new(EvalCache::new(16));
+  let model = CachedModel::new(model, Some(cache.clone()));
+
+  predict(&model, &[1, 2], 0.0);
+  let (policies, values) = predict(&model, &[2, 3, 1], 0.0);
+  // A different optimism is a different policy.
+  predict(&model, &[3], 1.0);
+
+  assert_eq!(*batches.borrow(), vec![vec![1.0, 2.0], vec![3.0], vec![3.0]]);
+  assert_eq!(policies, array![[[2.0, 2.0]], [[3.0, 3.0]], [[1.0, 1.0]]]);
+  assert_eq!(
+    values,
+    array![[2.0, 3.0, 4.0, 5.0], [3.0, 4.0, 5.0, 6.0], [1.0, 2.0, 3.0, 4.0]]
+  );
+  let stats = cache.stats();
+  assert_eq!((stats.hits, stats.misses, stats.len), (2, 4, 4));
+  assert_eq!(stats.hit_rate(), 1.0 / 3.0);
+}
+
+#[test]
+fn the_least_recently_used_evaluation_is_evicted() {
+  let calls = RefCell::new(0);
+  let model = |inputs: Array4<f64>, _: Array2<f64>, _: Array1<f64>| {
+    *calls.borrow_mut() += inputs.dim().0;
+    let batch = inputs.dim().0;
+    Ok::<_, ()>((Array3::zeros((batch, 1, 2)), Array2::zeros((batch, 4))))
+  };
+  let model = CachedModel::new(model, Some(Arc::new(EvalCache::new(2))));
+
+  predict(&model, &[1], 0.0);
+  predict(&model, &[2], 0.0);
+  // Touching the first one leaves the second as the least recently used.
+  predict(&model, &[1], 0.0);
+  predict(&model, &[3], 0.0);
+  assert_eq!(*calls.borrow(), 3);
+
+  predict(&model, &[1], 0.0);
+  assert_eq!(*calls.borrow(), 3);
+  predict(&model, &[2], 0.0);
+  assert_eq!(*calls.borrow(), 4);
+}
+
+#[test]
+fn without_a_cache_every_position_is_evaluated() {
+  let calls = RefCell::new(0);
+  let model = |inputs: Array4<f64>, _: Array2<f64>, _: Array1<f64>| {
+    *calls.borrow_mut() += 1;
+    let batch = inputs.dim().0;
+    Ok::<_, ()>((Array3::zeros((batch, 1, 2)), Array2::zeros((batch, 4))))
+  };
+  let model = CachedModel::new(model, None);
+
+  predict(&model, &[1], 0.0);
+  predict(&model, &[1], 0.0);
+  assert_eq!(*calls.borrow(), 2);
+}
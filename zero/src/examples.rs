@@ -4,6 +4,7 @@ use crate::{
     CHANNELS, GLOBAL_FEATURES, HISTORY_CHANNELS, SCORE_ONE_HOT_SIZE, captured_features_to_vec, field_features_to_vec,
     global_to_vec, score_one_hot_to_vec,
   },
+  replay_buffer::SeenPositions,
 };
 use ndarray::{Array, Array2, Array3, Array4};
 use num_traits::{Float, One, Zero};
@@ -14,7 +15,7 @@ use oppai_field::{
 };
 use oppai_rotate::rotate::{MIRRORS, ROTATIONS};
 use rand::{Rng, RngExt, seq::SliceRandom};
-use std::{cmp::Ordering, iter, ops::Range, sync::Arc};
+use std::{cmp::Ordering, collections::HashMap, iter, mem, ops::Range, sync::Arc};
 
 /// Number of TD value horizons.
 pub const TD_VALUES: usize = 3;
@@ -59,7 +60,7 @@ pub struct Batch<N> {
   pub q_values: Array4<N>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct ExampleGame {
   /// Field width
   pub width: u32,
@@ -126,6 +127,10 @@ fn value_surprise(target: f64, predicted: f64) -> f64 {
 const HISTORY_KEEP_PROBABILITY: f64 = 0.98;
 
 impl Examples {
+  /// Adds the searched positions of the game on `field`, each written as many
+  /// times as its frequency weight, scaled by the game's sampling `game_weight`. With
+  /// `seen`, a position already written by another game is skipped.
+  #[allow(clippy::too_many_arguments)]
   pub fn add<R: Rng>(
     &mut self,
     komi_x_2: i32,
@@ -133,6 +138,8 @@ impl Examples {
     field: &Field,
     rotations: bool,
     surprise_weighting: bool,
+    game_weight: f64,
+    mut seen: Option<&mut SeenPositions>,
     rng: &mut R,
   ) {
     let initial_moves = field.moves_count() - visits.len();
@@ -225,6 +232,18 @@ impl Examples {
       if sum_value_surprise > 0.0 {
         weight += value_surprise_weight * full_weight * value_surprises[i] / sum_value_surprise;
       }
+      weight *= game_weight;
+      // Only a position that is written at all claims its hash, so a cheap
+      // search never keeps a full search of the same position out.
+      if weight > 0.0
+        && let Some(seen) = seen.as_deref_mut()
+      {
+        let (_, player) = self.games[game_index].moves[initial_moves + i];
+        let komi_x_2 = if player == Player::Red { komi_x_2 } else { -komi_x_2 };
+        if !seen.insert(field, initial_moves + i, player, komi_x_2) {
+          continue;
+        }
+      }
       // Write the position `floor(weight)` times, plus once more with probability
       // equal to the fractional part of the weight.
       let copies = weight.floor() as usize + usize::from(weight > 0.0 && rng.random::<f64>() < weight.fract());
@@ -265,6 +284,35 @@ impl Examples {
     (self.len() / size).max(1)
   }
 
+  /// Moves up to `count` rows drawn at random, along with the games they refer
+  /// to, into a new set of examples.
+  pub fn take_random<R: Rng>(&mut self, count: usize, rng: &mut R) -> Examples {
+    let mut taken = Examples::default();
+    let mut indices = HashMap::new();
+    for _ in 0..count.min(self.len()) {
+      let mut example = self.examples.swap_remove(rng.random_range(0..self.examples.len()));
+      example.game = *indices.entry(example.game).or_insert_with(|| {
+        taken.games.push(self.games[example.game].clone());
+        taken.games.len() - 1
+      });
+      taken.examples.push(example);
+    }
+    taken
+  }
+
+  /// Drops the games no row refers to anymore.
+  pub fn drop_unused_games(&mut self) {
+    let mut indices = vec![None; self.games.len()];
+    let mut games = Vec::new();
+    for example in &mut self.examples {
+      example.game = *indices[example.game].get_or_insert_with(|| {
+        games.push(mem::take(&mut self.games[example.game]));
+        games.len() - 1
+      });
+    }
+    self.games = games;
+  }
+
   /// TD value targets for the position at `start` (an index into `visits`),
   /// from the perspective of `player`. Each horizon is an exponentially
   /// weighted blend of the future turns' search values with the remaining
@@ -348,7 +396,8 @@ impl Examples {
     values.extend_from_slice(&scores);
   }
 
-  fn batch<N: Float + Zero + One + Copy>(
+  /// Builds the batch of the rows in `range`.
+  pub fn batch<N: Float + Zero + One + Copy>(
     &self,
     range: Range<usize>,
     width: u32,
@@ -104,7 +104,7 @@ fn batch_td_scores_follow_the_game_score() {
   let visits = (0..field.moves_count())
     .map(|_| Visits(Vec::new(), 1.0, 0.0, 0.0, 0.0, Vec::new()))
     .collect();
-  examples.add(komi_x_2, visits, &field, false, false, &mut rng);
+  examples.add(komi_x_2, visits, &field, false, false, 1.0, None, &mut rng);
 
   let zobrist = Arc::new(Zobrist::new(length(width, height) * 3, &mut rng));
   let rows = examples.len();
@@ -186,7 +186,7 @@ fn opponent_policy_of_the_last_position_is_zero() {
     .iter()
     .map(|&pos| Visits(vec![(pos, 1.0)], 1.0, 0.0, 0.0, 0.0, Vec::new()))
     .collect();
-  examples.add(0, visits, &field, false, false, &mut rng);
+  examples.add(0, visits, &field, false, false, 1.0, None, &mut rng);
 
   let zobrist = Arc::new(Zobrist::new(length(width, height) * 3, &mut rng));
   let rows = examples.len();
@@ -232,7 +232,7 @@ fn batch_q_values_follow_the_search() {
     ),
     Visits(vec![(field.moves[1], 1.0)], 1.0, 0.0, 0.0, 0.0, Vec::new()),
   ];
-  examples.add(0, visits, &field, false, false, &mut rng);
+  examples.add(0, visits, &field, false, false, 1.0, None, &mut rng);
 
   let zobrist = Arc::new(Zobrist::new(length(width, height) * 3, &mut rng));
   let rows = examples.len();
@@ -4,6 +4,9 @@ pub mod batch_model_test;
 pub mod episode;
 #[cfg(test)]
 pub mod episode_test;
+pub mod eval_cache;
+#[cfg(test)]
+pub mod eval_cache_test;
 pub mod examples;
 #[cfg(test)]
 pub mod examples_test;
@@ -14,9 +17,17 @@ pub mod mcgs;
 #[cfg(test)]
 pub mod mcgs_test;
 pub mod model;
-pub mod opening;
-#[cfg(test)]
-pub mod opening_test;
 pub mod pit;
+#[cfg(test)]
+pub mod pit_test;
 pub mod random_model;
+pub mod replay_buffer;
+#[cfg(test)]
+pub mod replay_buffer_test;
+pub mod sprt;
+#[cfg(test)]
+pub mod sprt_test;
+pub mod symmetric_model;
+#[cfg(test)]
+pub mod symmetric_model_test;
 pub mod zero;
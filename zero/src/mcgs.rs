@@ -1,10 +1,11 @@
+use crate::eval_cache::{PositionKey, position_key};
 use crate::field_features::{
   CHANNELS, GLOBAL_FEATURES, HISTORY_CHANNELS, field_features, field_features_len, field_features_to_vec,
   global as global_features, global_to_vec,
 };
 use crate::model::Model;
 use either::Either;
-use ndarray::{Array, ArrayView2, Axis, s};
+use ndarray::{Array, Array2, Array3, ArrayView2, Axis, s};
 use num_traits::Float;
 use oppai_field::field::{to_x, to_y};
 use oppai_field::{
@@ -19,6 +20,7 @@ use std::cell::LazyCell;
 use std::collections::VecDeque;
 use std::hash::{BuildHasherDefault, Hasher};
 use std::mem;
+use std::sync::Mutex;
 use std::{iter, iter::Sum};
 
 /// Pass-through hasher for the transposition table.
@@ -145,7 +147,8 @@ pub struct BiasEntry<N: Float> {
 /// How much a root child is worth playing: its LCB once it carries enough
 /// search weight to be trusted, otherwise its search weight and prior. `Either`
 /// orders `Left < Right`, so every child with an LCB outranks every child
-/// without one.
+/// without one. Under [`Params::gumbel`] the survivors of sequential halving
+/// carry their halving score in place of the LCB.
 pub type PlaySelectionWeight<N> = Either<(N, N), N>;
 
 /// A visited child as [`Search::update_node`] aggregates it: its node index, the
@@ -439,6 +442,13 @@ pub struct Params {
   /// to gain and the loser something to save - where the pure win/loss value
   /// has none. See [`Search::aux_utility`].
   pub score_utility_factor: f64,
+  /// Root selection by Gumbel sequential halving instead of PUCT, or `None` for
+  /// PUCT. The visit distribution PUCT leaves behind needs a few hundred
+  /// playouts to settle before its most visited move means much, while
+  /// sequential halving spends even a handful of them comparing the most
+  /// promising moves and plays the best of those. Below the root the search
+  /// stays PUCT. See [`Search::halve_survivors`].
+  pub gumbel: Option<GumbelParams>,
 }
 
 impl Params {
@@ -467,6 +477,7 @@ impl Params {
     noise_prune_utility_scale: 0.0,
     early_utility_factor: 0.3,
     score_utility_factor: 0.1,
+    gumbel: None,
   };
 
   /// Playing to win: no search spent on moves that lose points outright, certain
@@ -493,6 +504,7 @@ impl Params {
     noise_prune_utility_scale: 0.15,
     early_utility_factor: 0.3,
     score_utility_factor: 0.1,
+    gumbel: None,
   };
 
   /// Radius of the utility range. The win/loss value spans `[-1, 1]` and each
@@ -507,6 +519,60 @@ impl Params {
   }
 }
 
+/// The knobs of Gumbel root selection, after "Policy improvement by planning
+/// with Gumbel" (Danihelka et al., 2022). See [`Params::gumbel`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GumbelParams {
+  /// How many root moves sequential halving starts from: the top ones by their
+  /// prior logits, perturbed with Gumbel noise.
+  pub considered: usize,
+  /// Whether the logits are perturbed at all. The noise is what makes the move
+  /// played a sample from the improved policy, which self-play wants for
+  /// exploration and play does not: without it the top moves by prior are
+  /// considered and the choice is deterministic.
+  pub noise: bool,
+  /// Offset of the σ transform of a q value, `(c_visit + max W) * c_scale * q`
+  /// with q rescaled from the utility range into `[0, 1]` and `max W` the
+  /// weight of the heaviest root child. The q values start out small next to
+  /// the logits and take over as the evidence behind them grows.
+  pub c_visit: f64,
+  /// Scale of the σ transform; see [`Self::c_visit`].
+  pub c_scale: f64,
+}
+
+impl GumbelParams {
+  /// Generating training data: the considered moves are sampled, so that the
+  /// move played explores the way the improved policy target says it should.
+  pub const SELF_PLAY: Self = GumbelParams {
+    considered: 16,
+    noise: true,
+    c_visit: 50.0,
+    c_scale: 0.1,
+  };
+
+  /// Playing to win: the top moves by prior are considered and the best of
+  /// them is played.
+  pub const PLAY: Self = GumbelParams {
+    noise: false,
+    ..Self::SELF_PLAY
+  };
+}
+
+/// Sequential halving at the current root under [`Params::gumbel`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct GumbelRoot<N> {
+  /// Gumbel noise of every root child, in `children` order; zeros without
+  /// [`GumbelParams::noise`].
+  pub noise: Vec<N>,
+  /// How many children sequential halving started from.
+  pub considered: usize,
+  /// Indices of the children still in the running, best first as of the last
+  /// halving.
+  pub survivors: Vec<usize>,
+  /// Playouts every survivor takes before the next halving.
+  pub phase_visits: u64,
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct Search<N: Float> {
   /// Index of the root node in `nodes`
@@ -528,6 +594,9 @@ pub struct Search<N: Float> {
   /// [`Params::policy_optimism`]. When the root wants a different optimism, the
   /// next search re-predicts them before it descends.
   pub root_priors_stale: bool,
+  /// Sequential halving at the root, drawn once the root is expanded. Always
+  /// `None` without [`Params::gumbel`].
+  pub gumbel: Option<GumbelRoot<N>>,
   /// Knobs that differ between self-play and play.
   pub params: Params,
 }
@@ -542,6 +611,7 @@ impl<N: Float> Search<N> {
       dirichlet_noise: false,
       stats_stale: false,
       root_priors_stale: false,
+      gumbel: None,
       params,
     };
 
@@ -551,6 +621,37 @@ impl<N: Float> Search<N> {
   }
 }
 
+/// A playout selected from the root, whose virtual losses stay on its path
+/// until its evaluation is backed up.
+struct Readout {
+  path: Vec<(usize, usize)>,
+  /// Moves along `path`, so that the playout can be replayed without the graph.
+  moves: Vec<Pos>,
+  /// Hash of the leaf, or `None` when the root itself is being expanded.
+  leaf: Option<Hash>,
+  terminal: bool,
+}
+
+/// What a playout backs up once its leaf is evaluated.
+struct Evaluation<N: Float> {
+  winloss: N,
+  utility: N,
+  score: N,
+  weight: N,
+  children: Vec<Edge<N>>,
+  bias_key: Option<BiasKey>,
+}
+
+/// Leaves of a batch of readouts: the terminal ones evaluated in place, and
+/// the net inputs of the rest, indexed into the readouts by `pending`.
+struct Leaves<N: Float> {
+  terminal: Vec<(usize, Evaluation<N>)>,
+  pending: Vec<usize>,
+  keys: Vec<PositionKey>,
+  features: Vec<N>,
+  global: Vec<N>,
+}
+
 impl<N: Float + Sum + Copy> Search<N> {
   fn add_node(&mut self, hash: Hash) -> usize {
     *self.map.entry(hash).or_insert_with(|| {
@@ -1011,7 +1112,7 @@ impl<N: Float + Sum + Copy> Search<N> {
   /// whole board would want: dots games fill only ~0.32 of the field, and only
   /// a part of the filled area is captured, so realistic scores run about a
   /// quarter as large.
-  fn aux_utility(&self, field: &Field, winloss: N, score: N) -> N {
+  fn aux_utility(params: &Params, field: &Field, winloss: N, score: N) -> N {
     let two_over_pi = N::from(std::f64::consts::FRAC_2_PI).unwrap();
     let area = N::from(field.width() * field.height()).unwrap();
     let fill = N::from(field.moves_count()).unwrap() / area;
@@ -1019,8 +1120,8 @@ impl<N: Float + Sum + Copy> Search<N> {
     let length_value = (fill_left / N::from(Self::FINAL_FILL_SCALE).unwrap()).atan() * two_over_pi;
     let score_scale = area.sqrt() / N::from(2).unwrap();
     let score_value = (score / score_scale).atan() * two_over_pi;
-    N::from(self.params.early_utility_factor).unwrap() * winloss * length_value
-      + N::from(self.params.score_utility_factor).unwrap() * score_value
+    N::from(params.early_utility_factor).unwrap() * winloss * length_value
+      + N::from(params.score_utility_factor).unwrap() * score_value
   }
 
   /// Typical utility standard deviation of a node; the observed stdev is
@@ -1203,18 +1304,186 @@ impl<N: Float + Sum + Copy> Search<N> {
     best
   }
 
+  /// The root edge a playout takes: under sequential halving the survivor with
+  /// the fewest playouts so far, the ones in flight included, and the better
+  /// ranked one of a tie; PUCT otherwise.
+  fn select_root_edge(&self) -> Option<usize> {
+    match &self.gumbel {
+      Some(gumbel) => {
+        let children = &self.nodes[self.root_idx].children;
+        gumbel
+          .survivors
+          .iter()
+          .copied()
+          .min_by_key(|&idx| children[idx].visits + children[idx].virtual_losses)
+      }
+      None => self.select_edge(self.root_idx, true),
+    }
+  }
+
+  /// Log of a prior, floored so that a move the net rules out entirely still
+  /// gets a finite logit.
+  fn logit(prior: N) -> N {
+    prior.max(N::from(1e-20).unwrap()).ln()
+  }
+
+  /// Draws the Gumbel noise of a freshly expanded root and considers its top
+  /// [`GumbelParams::considered`] children by perturbed logit. Does nothing
+  /// without [`Params::gumbel`], before the root is expanded, or once the
+  /// current root has its halving.
+  fn sample_gumbel<R: Rng>(&mut self, rng: &mut R) {
+    let Some(params) = self.params.gumbel else {
+      return;
+    };
+    let children = &self.nodes[self.root_idx].children;
+    if children.is_empty() || self.gumbel.is_some() {
+      return;
+    }
+
+    let noise = children
+      .iter()
+      .map(|_| {
+        if params.noise {
+          // Inverse transform sampling, floored to keep `ln(0)` out.
+          let uniform = rng.random::<f64>().max(f64::MIN_POSITIVE);
+          N::from(-(-uniform.ln()).ln()).unwrap()
+        } else {
+          N::zero()
+        }
+      })
+      .collect::<Vec<_>>();
+    let perturbed = children
+      .iter()
+      .zip(noise.iter())
+      .map(|(edge, &noise)| noise + Self::logit(edge.prior))
+      .collect::<Vec<_>>();
+    let mut survivors = (0..children.len()).collect::<Vec<_>>();
+    survivors.sort_by(|&a, &b| {
+      perturbed[b]
+        .partial_cmp(&perturbed[a])
+        .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    survivors.truncate(params.considered.max(1));
+
+    self.gumbel = Some(GumbelRoot {
+      noise,
+      considered: survivors.len(),
+      survivors,
+      phase_visits: 1,
+    });
+  }
+
+  /// The σ transform of the completed q value of every root child, in
+  /// `children` order. A child carrying weight has its own q completed by
+  /// nothing; the rest get the mixed value: the root's raw utility averaged
+  /// with the prior-weighted q of the visited children, the latter counting as
+  /// much as the weight behind them. That keeps an unvisited move at its prior
+  /// rather than assuming it is as good or as bad as anything else.
+  fn gumbel_sigma(&self, params: &GumbelParams) -> Vec<N> {
+    let root = &self.nodes[self.root_idx];
+    let visited = root
+      .children
+      .iter()
+      .map(|edge| {
+        let weight = self.edge_child_weight(edge);
+        let q = self
+          .map
+          .get(&edge.hash)
+          .filter(|_| weight > N::zero())
+          .map(|&child_idx| -self.nodes[child_idx].value);
+        (weight, q)
+      })
+      .collect::<Vec<_>>();
+
+    let total_weight = visited.iter().map(|&(weight, _)| weight).sum::<N>();
+    let max_weight = visited.iter().map(|&(weight, _)| weight).fold(N::zero(), N::max);
+    let (visited_prior, prior_q) = root
+      .children
+      .iter()
+      .zip(visited.iter())
+      .filter_map(|(edge, &(_, q))| q.map(|q| (edge.prior, edge.prior * q)))
+      .fold((N::zero(), N::zero()), |(p, pq), (prior, prior_q)| {
+        (p + prior, pq + prior_q)
+      });
+    let mixed = if visited_prior > N::zero() {
+      (root.raw_value + total_weight * prior_q / visited_prior) / (N::one() + total_weight)
+    } else {
+      root.raw_value
+    };
+
+    let radius = N::from(self.params.utility_radius()).unwrap();
+    let scale = (N::from(params.c_visit).unwrap() + max_weight) * N::from(params.c_scale).unwrap();
+    visited
+      .into_iter()
+      .map(|(_, q)| {
+        let q = (q.unwrap_or(mixed) + radius) / (radius + radius);
+        scale * q.max(N::zero()).min(N::one())
+      })
+      .collect()
+  }
+
+  /// What sequential halving ranks the root children by, in `children` order:
+  /// the Gumbel-perturbed logit plus the σ-transformed completed q.
+  fn gumbel_scores(&self, params: &GumbelParams, gumbel: &GumbelRoot<N>) -> Vec<N> {
+    self.nodes[self.root_idx]
+      .children
+      .iter()
+      .zip(gumbel.noise.iter())
+      .zip(self.gumbel_sigma(params))
+      .map(|((edge, &noise), sigma)| noise + Self::logit(edge.prior) + sigma)
+      .collect()
+  }
+
+  /// Sequential halving: once every survivor took the playouts of the current
+  /// phase, the better half of them by [`Self::gumbel_scores`] goes on to the
+  /// next one, and the rest take no more playouts at the root.
+  ///
+  /// A search does not know its playout budget - it is a time limit, or counts
+  /// the playouts of a reused subtree - so the schedule does not depend on one.
+  /// Every phase costs about as many playouts as there were moves considered,
+  /// each survivor taking twice as many as in the phase before, and the last
+  /// two survivors share whatever is left evenly. A reused subtree that already
+  /// meets a phase's playouts moves on at once.
+  fn halve_survivors(&mut self) {
+    let Some(params) = self.params.gumbel else {
+      return;
+    };
+    while let Some(gumbel) = &self.gumbel {
+      let children = &self.nodes[self.root_idx].children;
+      if gumbel.survivors.len() <= 2
+        || gumbel
+          .survivors
+          .iter()
+          .any(|&idx| children[idx].visits < gumbel.phase_visits)
+      {
+        return;
+      }
+
+      let scores = self.gumbel_scores(&params, gumbel);
+      let mut survivors = gumbel.survivors.clone();
+      survivors.sort_by(|&a, &b| scores[b].partial_cmp(&scores[a]).unwrap_or(std::cmp::Ordering::Equal));
+      survivors.truncate(survivors.len().div_ceil(2));
+      let gumbel = self.gumbel.as_mut().unwrap();
+      gumbel.phase_visits += (gumbel.considered / survivors.len()).max(1) as u64;
+      gumbel.survivors = survivors;
+    }
+  }
+
   /// Selects a path from the root to a leaf, returning the traversed edges as
   /// `(node_idx, edge_idx)` pairs and whether the leaf is terminal. The edge
   /// references let later steps update the tree (and replay the moves) by direct
   /// indexing instead of re-scanning each node's children by position.
   fn select_path(&mut self) -> (Vec<(usize, usize)>, bool) {
+    self.halve_survivors();
     let mut idx = self.root_idx;
-    let mut is_root = true;
     let mut path = Vec::new();
     let mut terminal = self.nodes[idx].visits > 0;
 
-    while let Some(edge_idx) = self.select_edge(idx, is_root) {
-      is_root = false;
+    while let Some(edge_idx) = if path.is_empty() {
+      self.select_root_edge()
+    } else {
+      self.select_edge(idx, false)
+    } {
       let edge = &mut self.nodes[idx].children[edge_idx];
       edge.virtual_losses += 1;
       path.push((idx, edge_idx));
@@ -1356,9 +1625,8 @@ impl<N: Float + Sum + Copy> Search<N> {
 
   const PARALLEL_READOUTS: usize = 8;
 
-  fn make_moves(nodes: &[Node<N>], field: &mut Field, path: &[(usize, usize)], mut player: Player, ground: bool) {
-    for &(node_idx, edge_idx) in path {
-      let pos = nodes[node_idx].children[edge_idx].pos;
+  fn play_moves(field: &mut Field, moves: &[Pos], mut player: Player, ground: bool) {
+    for &pos in moves {
       assert!(field.put_point(pos, player), "can't put point, likely a collision");
       if ground {
         field.update_grounded();
@@ -1368,7 +1636,7 @@ impl<N: Float + Sum + Copy> Search<N> {
   }
 
   fn create_children<R: Rng>(
-    &mut self,
+    params: &Params,
     field: &mut Field,
     player: Player,
     policy: &ArrayView2<N>,
@@ -1387,7 +1655,7 @@ impl<N: Float + Sum + Copy> Search<N> {
       assert!(field.put_point(pos, player));
 
       if field.get_delta_score(player) < 0 {
-        if self.params.forbid_bad {
+        if params.forbid_bad {
           field.undo();
           continue;
         }
@@ -1398,7 +1666,7 @@ impl<N: Float + Sum + Copy> Search<N> {
       let hash = field.colored_hash(player);
       field.undo();
 
-      if self.params.forbid_bad && field.is_corner(pos) {
+      if params.forbid_bad && field.is_corner(pos) {
         continue;
       }
 
@@ -1466,7 +1734,9 @@ impl<N: Float + Sum + Copy> Search<N> {
     let features = field_features::<N>(field, player, field.width(), field.height(), 0).insert_axis(Axis(0));
     let global = global_features(field, player, komi_x_2).insert_axis(Axis(0));
     let optimism = Array::from_elem(1, N::from(self.params.root_policy_optimism).unwrap());
-    let (policies, _) = model.predict(features, global, optimism).await?;
+    let (policies, _) = model
+      .predict_keyed(&[position_key(field, player, komi_x_2)], features, global, optimism)
+      .await?;
     let policy = policies.slice(s![0, .., ..]);
 
     let stride = field.stride;
@@ -1492,13 +1762,17 @@ impl<N: Float + Sum + Copy> Search<N> {
     Ok(())
   }
 
-  pub async fn mcgs<M: Model<N>, R: Rng>(
+  /// Brings a tree reused from a previous search up to date: values computed
+  /// against bias buckets that have moved since are recomputed, and a root
+  /// inherited as an ordinary leaf gets its priors re-predicted. Every search
+  /// does it before it descends; it is separate so that a [`SharedSearch`] can
+  /// have it done once before its workers start.
+  pub async fn prepare<M: Model<N>>(
     &mut self,
-    field: &mut Field,
+    field: &Field,
     player: Player,
     model: &M,
     komi_x_2: i32,
-    rng: &mut R,
   ) -> Result<(), M::E> {
     if self.stats_stale {
       self.recompute_stats();
@@ -1506,70 +1780,106 @@ impl<N: Float + Sum + Copy> Search<N> {
     if mem::take(&mut self.root_priors_stale) {
       self.refresh_root_priors(field, player, model, komi_x_2).await?;
     }
-    let weigh = self.weigh_by_uncertainty(model);
-    let mut leafs = iter::repeat_with(|| self.select_path())
+    Ok(())
+  }
+
+  /// Selects a batch of playouts, keeping the virtual losses of the ones that
+  /// will be evaluated.
+  ///
+  /// Only one readout per position the batch lands on is kept, and the rest are
+  /// abandoned. Two readouts reach the same position either by taking the same
+  /// path or by transposing onto it, and transpositions are the rule rather than
+  /// the exception here. Evaluating a position twice would count its own
+  /// evaluation twice against its subtree for the rest of the search and inflate
+  /// its squared weight, which deflates the effective sample size behind every
+  /// edge into it. Abandoning the extra playouts rather than merging them is what
+  /// keeps a node's visits equal to the playouts that reached it, and that
+  /// equality is what lets an edge take its share of the node's weight by its
+  /// share of those visits. The leaves in `in_flight` - the ones other workers
+  /// are evaluating - are abandoned the same way.
+  fn select_readouts(&mut self, in_flight: &[Option<Hash>]) -> Vec<Readout> {
+    let mut paths = iter::repeat_with(|| self.select_path())
       .take(Self::PARALLEL_READOUTS)
       .collect::<Vec<_>>();
-    for (path, _) in &leafs {
-      self.revert_virtual_loss(path);
-    }
-
-    // Keep one readout per position the batch landed on, and abandon the rest.
-    // Two readouts reach the same position either by taking the same path or by
-    // transposing onto it, and transpositions are the rule rather than the
-    // exception here. Evaluating a position twice would count its own evaluation
-    // twice against its subtree for the rest of the search and inflate its
-    // squared weight, which deflates the effective sample size behind every edge
-    // into it. Abandoning the extra playouts rather than merging them is what
-    // keeps a node's visits equal to the playouts that reached it, and that
-    // equality is what lets an edge take its share of the node's weight by its
-    // share of those visits.
-    //
+
     // Sorting first only fixes which readout of a group survives, so that the
     // batch does not depend on the order the paths were selected in.
-    leafs.sort_unstable();
-    let mut seen: Vec<Option<Hash>> = Vec::with_capacity(leafs.len());
-    leafs.retain(|(path, _)| {
+    paths.sort_unstable();
+    let mut readouts: Vec<Readout> = Vec::with_capacity(paths.len());
+    for (path, terminal) in paths {
       let leaf = path
         .last()
         .map(|&(node_idx, edge_idx)| self.nodes[node_idx].children[edge_idx].hash);
-      if seen.contains(&leaf) {
-        false
-      } else {
-        seen.push(leaf);
-        true
+      if in_flight.contains(&leaf) || readouts.iter().any(|readout| readout.leaf == leaf) {
+        self.revert_virtual_loss(&path);
+        continue;
       }
-    });
+      let moves = path
+        .iter()
+        .map(|&(node_idx, edge_idx)| self.nodes[node_idx].children[edge_idx].pos)
+        .collect();
+      readouts.push(Readout {
+        path,
+        moves,
+        leaf,
+        terminal,
+      });
+    }
+    readouts
+  }
 
+  /// Plays every readout out to its leaf: the terminal leaves are evaluated
+  /// exactly, and the others get their net inputs collected. Reads nothing of
+  /// the graph, so that a [`SharedSearch`] can do it outside of its lock.
+  fn evaluate_leaves(
+    params: &Params,
+    readouts: &[Readout],
+    field: &mut Field,
+    player: Player,
+    komi_x_2: i32,
+    weigh: bool,
+  ) -> Leaves<N> {
     let features_len = field_features_len(field.width(), field.height());
-    let mut features = Vec::with_capacity(features_len * leafs.len());
-    let mut global = Vec::with_capacity(GLOBAL_FEATURES * leafs.len());
+    let mut leaves = Leaves {
+      terminal: Vec::new(),
+      pending: Vec::new(),
+      keys: Vec::new(),
+      features: Vec::with_capacity(features_len * readouts.len()),
+      global: Vec::with_capacity(GLOBAL_FEATURES * readouts.len()),
+    };
     let red_komi_x_2 = if player == Player::Red { komi_x_2 } else { -komi_x_2 };
 
-    leafs.retain(|(path, terminal)| {
-      Self::make_moves(&self.nodes, field, path, player, true);
+    for (i, readout) in readouts.iter().enumerate() {
+      Self::play_moves(field, &readout.moves, player, true);
 
-      let player = if path.len().is_multiple_of(2) {
+      let player = if readout.moves.len().is_multiple_of(2) {
         player
       } else {
         player.next()
       };
 
-      let leaf_komi_x_2 = if path.len().is_multiple_of(2) {
+      let leaf_komi_x_2 = if readout.moves.len().is_multiple_of(2) {
         komi_x_2
       } else {
         -komi_x_2
       };
 
-      let result = if *terminal || field.is_game_over(red_komi_x_2) {
+      if readout.terminal || field.is_game_over(red_komi_x_2) {
         // Terminal nodes get no bias correction. Their value is exact, so they
         // get the maximum weight. The score is the exact final score too.
-        let weight = Self::terminal_weight(weigh);
         let winloss = game_result(field, player, leaf_komi_x_2);
         let score = game_score(field, player, leaf_komi_x_2);
-        let utility = winloss + self.aux_utility(field, winloss, score);
-        self.add_result(path, winloss, utility, score, weight, Vec::new(), None);
-        false
+        leaves.terminal.push((
+          i,
+          Evaluation {
+            winloss,
+            utility: winloss + Self::aux_utility(params, field, winloss, score),
+            score,
+            weight: Self::terminal_weight(weigh),
+            children: Vec::new(),
+            bias_key: None,
+          },
+        ));
       } else {
         field_features_to_vec::<N>(
           field,
@@ -1578,77 +1888,172 @@ impl<N: Float + Sum + Copy> Search<N> {
           field.height(),
           0,
           HISTORY_CHANNELS,
-          &mut features,
+          &mut leaves.features,
         );
-        global_to_vec(field, player, leaf_komi_x_2, &mut global);
-        true
-      };
+        global_to_vec(field, player, leaf_komi_x_2, &mut leaves.global);
+        leaves.keys.push(position_key(field, player, leaf_komi_x_2));
+        leaves.pending.push(i);
+      }
 
-      for _ in 0..path.len() {
+      for _ in 0..readout.moves.len() {
         field.undo();
       }
-
-      result
-    });
-
-    if features.is_empty() {
-      return Ok(());
     }
 
+    leaves
+  }
+
+  /// Asks the net about the leaves still pending after [`Self::evaluate_leaves`].
+  async fn predict_leaves<M: Model<N>>(
+    params: &Params,
+    model: &M,
+    readouts: &[Readout],
+    leaves: Leaves<N>,
+    field: &Field,
+  ) -> Result<(Array3<N>, Array2<N>), M::E> {
+    let features_len = field_features_len(field.width(), field.height());
     let features = Array::from_shape_vec(
       (
-        features.len() / features_len,
+        leaves.features.len() / features_len,
         CHANNELS,
         field.height() as usize,
         field.width() as usize,
       ),
-      features,
+      leaves.features,
     )
     .unwrap();
-    let global = Array::from_shape_vec((global.len() / GLOBAL_FEATURES, GLOBAL_FEATURES), global).unwrap();
+    let global =
+      Array::from_shape_vec((leaves.global.len() / GLOBAL_FEATURES, GLOBAL_FEATURES), leaves.global).unwrap();
     // An empty path is the root itself being expanded, and it asks for the
     // root's own optimism; everything below the root asks for the search-wide
     // one.
-    let optimism = Array::from_iter(leafs.iter().map(|(path, _)| {
-      N::from(if path.is_empty() {
-        self.params.root_policy_optimism
+    let optimism = Array::from_iter(leaves.pending.iter().map(|&i| {
+      N::from(if readouts[i].path.is_empty() {
+        params.root_policy_optimism
       } else {
-        self.params.policy_optimism
+        params.policy_optimism
       })
       .unwrap()
     }));
 
-    let (policies, values) = model.predict(features, global, optimism).await?;
+    model.predict_keyed(&leaves.keys, features, global, optimism).await
+  }
 
-    for (i, (path, _)) in leafs.iter().enumerate() {
-      Self::make_moves(&self.nodes, field, path, player, false);
+  /// Turns the net's predictions for the pending leaves into their evaluations,
+  /// expanding every leaf with its children. Like [`Self::evaluate_leaves`] it
+  /// reads nothing of the graph.
+  #[allow(clippy::too_many_arguments)]
+  fn expand_leaves<R: Rng>(
+    params: &Params,
+    readouts: &[Readout],
+    pending: &[usize],
+    policies: &Array3<N>,
+    values: &Array2<N>,
+    field: &mut Field,
+    player: Player,
+    weigh: bool,
+    rng: &mut R,
+  ) -> Vec<(usize, Evaluation<N>)> {
+    let mut evaluations = Vec::with_capacity(pending.len());
 
-      let player = if path.len().is_multiple_of(2) {
+    for (j, &i) in pending.iter().enumerate() {
+      let moves = &readouts[i].moves;
+      Self::play_moves(field, moves, player, false);
+
+      let player = if moves.len().is_multiple_of(2) {
         player
       } else {
         player.next()
       };
 
-      let policy = policies.slice(s![i, .., ..]);
-      let winloss = values[(i, 0)] - values[(i, 1)];
-      let weight = Self::eval_weight(weigh, values[(i, 2)]);
+      let policy = policies.slice(s![j, .., ..]);
+      let winloss = values[(j, 0)] - values[(j, 1)];
       // A model without a score estimate leaves the column - and so the whole
       // tree's scores - at 0: its utilities carry no score term and the
       // per-move q score targets stay zero, which only the trainable model,
       // one that does estimate scores, ever reads back.
-      let score = values[(i, 3)];
-      let utility = winloss + self.aux_utility(field, winloss, score);
+      let score = values[(j, 3)];
+      evaluations.push((
+        i,
+        Evaluation {
+          winloss,
+          utility: winloss + Self::aux_utility(params, field, winloss, score),
+          score,
+          weight: Self::eval_weight(weigh, values[(j, 2)]),
+          children: Self::create_children(params, field, player, &policy, rng),
+          // Bucket the leaf by the local context of the move that created it.
+          // The field currently has all of the path's moves played, so
+          // `field.moves` ends with this node's move and the move before it.
+          bias_key: Self::bias_key(field),
+        },
+      ));
+
+      for _ in 0..moves.len() {
+        field.undo();
+      }
+    }
 
-      let children = self.create_children(field, player, &policy, rng);
-      // Bucket the leaf by the local context of the move that created it. The
-      // field currently has all of the path's moves played, so `field.moves`
-      // ends with this node's move and the move before it.
-      let bias_key = Self::bias_key(field);
-      self.add_result(path, winloss, utility, score, weight, children, bias_key);
+    evaluations
+  }
 
-      for _ in 0..path.len() {
-        field.undo();
+  /// Backs a playout's evaluation up the graph and lifts its virtual losses.
+  fn backup(&mut self, readout: &Readout, evaluation: Evaluation<N>) {
+    self.revert_virtual_loss(&readout.path);
+    self.add_result(
+      &readout.path,
+      evaluation.winloss,
+      evaluation.utility,
+      evaluation.score,
+      evaluation.weight,
+      evaluation.children,
+      evaluation.bias_key,
+    );
+  }
+
+  pub async fn mcgs<M: Model<N>, R: Rng>(
+    &mut self,
+    field: &mut Field,
+    player: Player,
+    model: &M,
+    komi_x_2: i32,
+    rng: &mut R,
+  ) -> Result<(), M::E> {
+    self.prepare(field, player, model, komi_x_2).await?;
+    self.sample_gumbel(rng);
+    let weigh = self.weigh_by_uncertainty(model);
+    let readouts = self.select_readouts(&[]);
+    let mut leaves = Self::evaluate_leaves(&self.params, &readouts, field, player, komi_x_2, weigh);
+    for (i, evaluation) in mem::take(&mut leaves.terminal) {
+      self.backup(&readouts[i], evaluation);
+    }
+
+    if leaves.pending.is_empty() {
+      return Ok(());
+    }
+
+    let pending = leaves.pending.clone();
+    let (policies, values) = match Self::predict_leaves(&self.params, model, &readouts, leaves, field).await {
+      Ok(predictions) => predictions,
+      Err(error) => {
+        for &i in &pending {
+          self.revert_virtual_loss(&readouts[i].path);
+        }
+        return Err(error);
       }
+    };
+    let evaluations = Self::expand_leaves(
+      &self.params,
+      &readouts,
+      &pending,
+      &policies,
+      &values,
+      field,
+      player,
+      weigh,
+      rng,
+    );
+    for (i, evaluation) in evaluations {
+      self.backup(&readouts[i], evaluation);
     }
 
     Ok(())
@@ -1767,8 +2172,27 @@ impl<N: Float + Sum + Copy> Search<N> {
   /// the raw weight instead would let a child clear the bar for the move to play
   /// while failing it for the target, so that the move played and the move the
   /// target points at could differ.
+  ///
+  /// Under sequential halving the surviving children that carry weight have
+  /// their [`Self::gumbel_scores`] instead of an LCB, so that the winner of the
+  /// halving is the move played.
   fn play_selection_weights(&self) -> Vec<PlaySelectionWeight<N>> {
     let children = &self.nodes[self.root_idx].children;
+    if let (Some(params), Some(gumbel)) = (&self.params.gumbel, &self.gumbel) {
+      let scores = self.gumbel_scores(params, gumbel);
+      return children
+        .iter()
+        .enumerate()
+        .map(|(idx, edge)| {
+          let weight = self.edge_child_weight(edge);
+          if weight > N::zero() && gumbel.survivors.contains(&idx) {
+            Either::Right(scores[idx])
+          } else {
+            Either::Left((weight, edge.prior))
+          }
+        })
+        .collect();
+    }
     let (reduced, reference_weight) = self
       .reduced_weights()
       .unwrap_or_else(|| (vec![N::zero(); children.len()], N::zero()));
@@ -1852,6 +2276,7 @@ impl<N: Float + Sum + Copy> Search<N> {
       self.detach_root_bias();
       self.stats_stale = true;
       self.root_priors_stale = true;
+      self.gumbel = None;
       NonZeroPos::new(edge_pos)
     } else {
       *self = Self::new(self.params);
@@ -1876,6 +2301,7 @@ impl<N: Float + Sum + Copy> Search<N> {
       self.detach_root_bias();
       self.stats_stale = true;
       self.root_priors_stale = true;
+      self.gumbel = None;
       true
     } else {
       *self = Self::new(self.params);
@@ -1902,6 +2328,8 @@ impl<N: Float + Sum + Copy> Search<N> {
       dirichlet_noise: self.dirichlet_noise,
       stats_stale: self.stats_stale,
       root_priors_stale: self.root_priors_stale,
+      // The root keeps its children, and their order, so its halving goes on.
+      gumbel: self.gumbel.take(),
       params: self.params,
     };
 
@@ -2015,6 +2443,33 @@ impl<N: Float + Sum + Copy> Search<N> {
       .filter(|(_, weight)| *weight > N::zero())
   }
 
+  /// The policy improvement target of Gumbel root selection: the softmax of
+  /// the prior logits plus the σ-transformed completed q values, over every
+  /// root child. Unlike a visit distribution it improves on the prior at any
+  /// number of playouts, and has nothing forced to prune. The σ transform is
+  /// the one of [`Params::gumbel`], or [`GumbelParams::SELF_PLAY`]'s when the
+  /// root runs PUCT.
+  pub fn improved_policy(&self) -> Vec<(Pos, N)> {
+    let children = &self.nodes[self.root_idx].children;
+    let params = self.params.gumbel.unwrap_or(GumbelParams::SELF_PLAY);
+    let logits = children
+      .iter()
+      .zip(self.gumbel_sigma(&params))
+      .map(|(edge, sigma)| Self::logit(edge.prior) + sigma)
+      .collect::<Vec<_>>();
+    let max_logit = logits.iter().copied().fold(-N::infinity(), N::max);
+    let exps = logits
+      .into_iter()
+      .map(|logit| (logit - max_logit).exp())
+      .collect::<Vec<_>>();
+    let sum = exps.iter().copied().sum::<N>();
+    children
+      .iter()
+      .zip(exps)
+      .map(|(edge, exp)| (edge.pos, exp / sum))
+      .collect()
+  }
+
   /// Reduced weight of every root child, in `children` order and zero for the
   /// children no playout reached, together with the weight of the reference
   /// child that the reduction is measured against.
@@ -2264,8 +2719,15 @@ impl<N: Float + Sum + SampleUniform> Search<N> {
   /// Move the root to a random child, sampled from the play selection values -
   /// the same quantity that becomes the policy target, so the forced playouts that
   /// widen the search are taken back out and the LCB bonus is applied before the
-  /// move is drawn.
+  /// move is drawn. Under sequential halving the temperature is not applied and
+  /// the root moves to the winner.
   pub fn next_root_with_temperature<R: Rng>(&mut self, temperature: N, rng: &mut R) -> Option<NonZeroPos> {
+    // Under sequential halving the Gumbel noise has already made the winner a
+    // sample from the improved policy.
+    if self.gumbel.is_some() {
+      return self.next_best_root();
+    }
+
     // Pruning leaves only children with a positive value, so every entry here can
     // be sampled and the heaviest defines the logit offset.
     let values = self.play_selection_values();
@@ -2298,6 +2760,7 @@ impl<N: Float + Sum + SampleUniform> Search<N> {
       self.detach_root_bias();
       self.stats_stale = true;
       self.root_priors_stale = true;
+      self.gumbel = None;
       NonZeroPos::new(pos)
     } else {
       *self = Self::new(self.params);
@@ -2319,3 +2782,104 @@ where
     self.dirichlet_noise = true;
   }
 }
+
+struct SharedState<'a, N: Float> {
+  search: &'a mut Search<N>,
+  /// Leaves selected by some worker and not backed up yet.
+  in_flight: Vec<Option<Hash>>,
+}
+
+/// A [`Search`] several workers run playouts on at once.
+///
+/// The workers share the graph behind a lock that is only taken to select a
+/// batch and to back it up, never while the net is asked, so that the batches
+/// of different workers can be evaluated together, e.g. by a
+/// [`crate::batch_model::BatchModel`]. Virtual losses stay on the paths of a
+/// batch until it is backed up, which steers the other workers elsewhere, and a
+/// leaf some worker is already evaluating is never selected again.
+///
+/// The search should be [prepared](Search::prepare) before it is shared.
+pub struct SharedSearch<'a, N: Float> {
+  state: Mutex<SharedState<'a, N>>,
+  params: Params,
+}
+
+impl<'a, N: Float + Sum + Copy> SharedSearch<'a, N> {
+  pub fn new(search: &'a mut Search<N>) -> Self {
+    SharedSearch {
+      params: search.params,
+      state: Mutex::new(SharedState {
+        search,
+        in_flight: Vec::new(),
+      }),
+    }
+  }
+
+  /// Runs a batch of playouts like [`Search::mcgs`], returning how many of them
+  /// were backed up. That is 0 when everything this worker selected was already
+  /// being evaluated by others.
+  pub async fn mcgs<M: Model<N>, R: Rng>(
+    &self,
+    field: &mut Field,
+    player: Player,
+    model: &M,
+    komi_x_2: i32,
+    rng: &mut R,
+  ) -> Result<usize, M::E> {
+    let (readouts, weigh) = {
+      let mut state = self.state.lock().unwrap();
+      let SharedState { search, in_flight } = &mut *state;
+      search.sample_gumbel(rng);
+      let readouts = search.select_readouts(in_flight);
+      in_flight.extend(readouts.iter().map(|readout| readout.leaf));
+      (readouts, search.weigh_by_uncertainty(model))
+    };
+
+    let mut leaves = Search::evaluate_leaves(&self.params, &readouts, field, player, komi_x_2, weigh);
+    let terminal = mem::take(&mut leaves.terminal);
+    let pending = leaves.pending.clone();
+    let evaluations = if pending.is_empty() {
+      Ok(Vec::new())
+    } else {
+      Search::predict_leaves(&self.params, model, &readouts, leaves, field)
+        .await
+        .map(|(policies, values)| {
+          Search::expand_leaves(
+            &self.params,
+            &readouts,
+            &pending,
+            &policies,
+            &values,
+            field,
+            player,
+            weigh,
+            rng,
+          )
+        })
+    };
+
+    let mut state = self.state.lock().unwrap();
+    let SharedState { search, in_flight } = &mut *state;
+    for readout in &readouts {
+      let idx = in_flight.iter().position(|&leaf| leaf == readout.leaf).unwrap();
+      in_flight.swap_remove(idx);
+    }
+    for (i, evaluation) in terminal {
+      search.backup(&readouts[i], evaluation);
+    }
+    match evaluations {
+      Ok(evaluations) => {
+        for (i, evaluation) in evaluations {
+          search.backup(&readouts[i], evaluation);
+        }
+        Ok(readouts.len())
+      }
+      Err(error) => {
+        for &i in &pending {
+          search.revert_virtual_loss(&readouts[i].path);
+        }
+        Err(error)
+      }
+    }
+  }
+}
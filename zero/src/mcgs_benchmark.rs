@@ -1,22 +1,53 @@
 #[macro_use]
 extern crate criterion;
 
-use criterion::{Bencher, Criterion};
+use criterion::{Bencher, BenchmarkId, Criterion, Throughput};
+use ndarray::{Array1, Array2, Array3, Array4};
 use oppai_field::construct_field::construct_field;
+use oppai_field::field::Field;
 use oppai_field::player::Player;
 use oppai_zero::mcgs::{Params, Search};
+use oppai_zero::model::Model;
 use oppai_zero::random_model::RandomModel;
+use oppai_zero::symmetric_model::Symmetries;
+use oppai_zero::zero::{Zero, ZeroConfig};
 use rand::SeedableRng;
 use rand_xoshiro::Xoshiro256PlusPlus;
+use std::{thread, time::Duration};
 
 const SEED: u64 = 7;
 
 const SIMS: u32 = 1_000;
 
-fn search(bencher: &mut Bencher) {
-  let mut rng = Xoshiro256PlusPlus::seed_from_u64(SEED);
-  let mut field = construct_field(
-    &mut rng,
+/// Time a forward pass takes regardless of its batch size.
+const PASS_LATENCY: Duration = Duration::from_micros(200);
+
+/// A model whose every forward pass costs [`PASS_LATENCY`], which is what a
+/// device charges for a pass however small its batch is, and what a shared
+/// evaluator amortizes over the batches of the workers.
+struct Device<M>(M);
+
+impl<M: Model<f64>> Model<f64> for Device<M> {
+  type E = M::E;
+
+  fn predicts_uncertainty(&self) -> bool {
+    self.0.predicts_uncertainty()
+  }
+
+  async fn predict(
+    &self,
+    inputs: Array4<f64>,
+    global: Array2<f64>,
+    optimism: Array1<f64>,
+  ) -> Result<(Array3<f64>, Array2<f64>), Self::E> {
+    thread::sleep(PASS_LATENCY);
+    self.0.predict(inputs, global, optimism).await
+  }
+}
+
+fn field(rng: &mut Xoshiro256PlusPlus) -> Field {
+  construct_field(
+    rng,
     "
     ........
     ........
@@ -27,7 +58,12 @@ fn search(bencher: &mut Bencher) {
     ........
     ........
     ",
-  );
+  )
+}
+
+fn search(bencher: &mut Bencher) {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(SEED);
+  let mut field = field(&mut rng);
   bencher.iter(|| {
     let mut search = Search::<f64>::new(Params {
       forbid_bad: false,
@@ -41,9 +77,39 @@ fn search(bencher: &mut Bencher) {
   });
 }
 
+/// The whole search as the engine runs it, with the playouts shared between
+/// `threads_count` workers. Every element is one batch of readouts.
+fn threaded_search(bencher: &mut Bencher, &threads_count: &usize) {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(SEED);
+  let field = field(&mut rng);
+  bencher.iter(|| {
+    let config = ZeroConfig {
+      threads_count,
+      cache_size: 0,
+      symmetries: Symmetries::Identity,
+      gumbel: false,
+    };
+    let mut zero = Zero::<f64, _>::new(config, Device(RandomModel::new(rng.clone())), 0);
+    zero
+      .best_moves(&field, Player::Red, &mut rng.clone(), &|| false, SIMS)
+      .unwrap()
+  });
+}
+
 fn mcgs() {
   let mut c = Criterion::default().sample_size(10).configure_from_args();
   c.bench_function("mcgs", search);
+
+  let mut group = c.benchmark_group("mcgs_threads");
+  group.throughput(Throughput::Elements(SIMS as u64));
+  for threads_count in [1, 2, 4, 8] {
+    group.bench_with_input(
+      BenchmarkId::from_parameter(threads_count),
+      &threads_count,
+      threaded_search,
+    );
+  }
+  group.finish();
 }
 
 criterion_main!(mcgs);
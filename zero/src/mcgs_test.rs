@@ -6,7 +6,7 @@ use oppai_field::player::Player;
 use rand::SeedableRng;
 use rand_xoshiro::Xoshiro256PlusPlus;
 
-use crate::mcgs::{BiasEntry, Edge, Node, Params, Search, t_cdf, value_weight_cdf};
+use crate::mcgs::{BiasEntry, Edge, GumbelParams, Node, Params, Search, SharedSearch, t_cdf, value_weight_cdf};
 use crate::model::Model;
 
 /// Play's parameters, but without forbidding apriori bad moves: the tests use
@@ -1159,6 +1159,62 @@ fn self_play_counts_every_playout_once() {
   );
 }
 
+#[test]
+fn shared_search_counts_every_playout_once() {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(SEED);
+  let field = construct_field(
+    &mut rng,
+    "
+    .....
+    ..aA.
+    .Aa..
+    .....
+    ",
+  );
+  let model = |inputs: Array4<f64>, _, _| {
+    let result: Result<_, ()> = Ok((uniform_policies(&inputs), depth_value(&inputs)));
+    result
+  };
+  let mut search = Search::<f64>::new(Params::SELF_PLAY);
+  futures::executor::block_on(search.mcgs(&mut field.clone(), Player::Red, &model, 0, &mut rng)).unwrap();
+
+  let shared = SharedSearch::new(&mut search);
+  let playouts = std::thread::scope(|scope| {
+    let workers = (0..4u64)
+      .map(|i| {
+        let mut field = field.clone();
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(SEED + i);
+        let shared = &shared;
+        let model = &model;
+        scope.spawn(move || {
+          (0..20)
+            .map(|_| futures::executor::block_on(shared.mcgs(&mut field, Player::Red, model, 0, &mut rng)).unwrap())
+            .sum::<usize>()
+        })
+      })
+      .collect::<Vec<_>>();
+    workers.into_iter().map(|worker| worker.join().unwrap()).sum::<usize>()
+  });
+  drop(shared);
+
+  assert!(playouts > 0);
+  assert_eq!(search.root_visits(), playouts as u64 + 1);
+  for node in &search.nodes {
+    for edge in &node.children {
+      assert_eq!(
+        edge.virtual_losses, 0,
+        "every virtual loss should be lifted after the backup"
+      );
+    }
+    assert!(
+      (node.weight_sum - node.visits as f64).abs() < 1e-9,
+      "total weight should equal the visit count, got {} vs {}",
+      node.weight_sum,
+      node.visits
+    );
+  }
+}
+
 /// Adds a root child with an arbitrary weight, decoupled from its visit count so
 /// that the heaviest child and the most stably explored one can differ.
 fn add_weighted_root_child(search: &mut Search<f64>, pos: Pos, visits: u64, weight: f64, prior: f64) {
@@ -2411,3 +2467,192 @@ fn utility_carries_the_auxiliary_terms_the_value_does_not() {
   assert_eq!(search.winloss(), winloss);
   assert_eq!(search.raw_winloss(), winloss);
 }
+
+/// Play's Gumbel root selection considering only `considered` moves.
+fn gumbel_params(considered: usize) -> Params {
+  Params {
+    gumbel: Some(GumbelParams {
+      considered,
+      ..GumbelParams::PLAY
+    }),
+    ..PARAMS
+  }
+}
+
+#[test]
+fn sequential_halving_spends_the_root_playouts_on_the_considered_moves() {
+  let (search, _) = run_search_with(gumbel_params(4), depth_value, 20);
+
+  let root = &search.nodes[search.root_idx];
+  let gumbel = search.gumbel.as_ref().unwrap();
+  // Without noise the priors alone pick the moves, and uniform ones tie, so the
+  // first four are considered.
+  assert_eq!(gumbel.considered, 4);
+  assert_eq!(gumbel.survivors.len(), 2);
+  assert!(gumbel.survivors.iter().all(|&idx| idx < 4));
+  for (idx, edge) in root.children.iter().enumerate() {
+    if idx >= 4 {
+      assert_eq!(
+        edge.visits, 0,
+        "a move that was not considered should never be played out"
+      );
+    } else if gumbel.survivors.contains(&idx) {
+      assert!(edge.visits > 3, "a survivor should take the playouts of every phase");
+    } else {
+      assert!(
+        edge.visits >= 1,
+        "every considered move should take the first phase's playout"
+      );
+    }
+  }
+  let (first, second) = (
+    root.children[gumbel.survivors[0]].visits,
+    root.children[gumbel.survivors[1]].visits,
+  );
+  assert!(
+    first.abs_diff(second) <= 1,
+    "the last two survivors share the playouts evenly"
+  );
+
+  // The move played is a survivor, and the analysis agrees with it.
+  let best = search.best_move().unwrap().get();
+  assert!(gumbel.survivors.iter().any(|&idx| root.children[idx].pos == best));
+  let selection = search.play_selection();
+  assert_eq!(
+    selection
+      .iter()
+      .filter(|(_, weight)| matches!(weight, Either::Right(_)))
+      .count(),
+    2
+  );
+}
+
+#[test]
+fn the_improved_policy_follows_the_q_values() {
+  let mut search = Search::<f64>::new(Params {
+    gumbel: Some(GumbelParams::PLAY),
+    ..PARAMS
+  });
+  // Children values are from their own perspective: the first is good for the
+  // root, the second bad, and the third was never visited.
+  add_root_child(&mut search, 10, 4, 4, -0.5, 0.25);
+  add_root_child(&mut search, 11, 4, 4, 0.5, 0.25);
+  search.nodes[0].children.push(Edge {
+    pos: 12,
+    hash: 12,
+    visits: 0,
+    prior: 0.1,
+    virtual_losses: 0,
+  });
+  search.nodes[0].visits = 9;
+
+  let policy = search.improved_policy();
+  assert_eq!(policy.iter().map(|&(pos, _)| pos).collect::<Vec<_>>(), vec![10, 11, 12]);
+  assert!((policy.iter().map(|&(_, p)| p).sum::<f64>() - 1.0).abs() < 1e-12);
+  // Equal priors, so the q values alone order the moves, and the unvisited one
+  // gets the mixed value in between.
+  assert!(policy[0].1 > policy[2].1);
+  assert!(policy[2].1 > policy[1].1);
+  // The σ transform grows with the weight behind the root, so the same q gap
+  // is a bigger gap in the policy once there is more evidence for it.
+  let mut stronger = search.clone();
+  for node in &mut stronger.nodes[1..] {
+    node.weight_sum *= 10.0;
+  }
+  let stronger = stronger.improved_policy();
+  assert!(stronger[0].1 / stronger[1].1 > policy[0].1 / policy[1].1);
+}
+
+#[test]
+fn a_new_root_draws_its_own_halving() {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(SEED);
+  let mut field = construct_field(
+    &mut rng,
+    "
+    .....
+    ..aA.
+    .Aa..
+    .....
+    ",
+  );
+  let model = |inputs: Array4<f64>, _, _| {
+    let result: Result<_, ()> = Ok((uniform_policies(&inputs), depth_value(&inputs)));
+    result
+  };
+  let mut search = Search::<f64>::new(gumbel_params(4));
+  for _ in 0..6 {
+    futures::executor::block_on(search.mcgs(&mut field, Player::Red, &model, 0, &mut rng)).unwrap();
+  }
+  assert!(search.gumbel.is_some());
+
+  let pos = search.next_best_root().unwrap().get();
+  assert!(search.gumbel.is_none());
+  assert!(field.put_point(pos, Player::Red));
+  field.update_grounded();
+  for _ in 0..2 {
+    futures::executor::block_on(search.mcgs(&mut field, Player::Black, &model, 0, &mut rng)).unwrap();
+  }
+  let root = &search.nodes[search.root_idx];
+  let gumbel = search.gumbel.as_ref().unwrap();
+  assert_eq!(gumbel.noise.len(), root.children.len());
+  // The new root considers its own top moves.
+  assert!(gumbel.survivors.iter().all(|&idx| idx < 4));
+}
+
+#[test]
+fn gumbel_noise_samples_the_considered_moves() {
+  let params = Params {
+    gumbel: Some(GumbelParams {
+      considered: 4,
+      ..GumbelParams::SELF_PLAY
+    }),
+    ..PARAMS
+  };
+  // The second batch draws the halving of the root the first one expanded.
+  let (search, _) = run_search_with(params, depth_value, 2);
+  let gumbel = search.gumbel.as_ref().unwrap();
+  assert!(gumbel.noise.iter().any(|&noise| noise != 0.0));
+
+  // Uniform priors, so the noise alone picks the moves considered, and the
+  // survivors of any halving so far are among them.
+  let mut by_noise = (0..gumbel.noise.len()).collect::<Vec<_>>();
+  by_noise.sort_by(|&a, &b| gumbel.noise[b].partial_cmp(&gumbel.noise[a]).unwrap());
+  assert!(gumbel.survivors.iter().all(|idx| by_noise[..4].contains(idx)));
+  assert_ne!(by_noise[..4], [0, 1, 2, 3]);
+}
+
+// Red has captured two points and the game is over, so the komi alone decides
+// who wins. The search takes the komi of the player to move.
+#[test]
+fn komi_flips_the_result() {
+  let winloss = |player, komi_x_2| {
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(SEED);
+    let mut field = construct_field(
+      &mut rng,
+      "
+      .a.a.
+      aAaAa
+      .a.a.
+      ",
+    );
+    let mut search = Search::<f64>::new(PARAMS);
+    for _ in 0..10 {
+      futures::executor::block_on(search.mcgs(
+        &mut field,
+        player,
+        &|inputs: Array4<f64>, _, _| {
+          let result: Result<_, ()> = Ok((uniform_policies(&inputs), const_value(&inputs, array![0.0])));
+          result
+        },
+        komi_x_2,
+        &mut rng,
+      ))
+      .unwrap();
+    }
+    search.winloss()
+  };
+  assert_eq!(winloss(Player::Red, 0), 1.0);
+  assert_eq!(winloss(Player::Red, -5), -1.0);
+  assert_eq!(winloss(Player::Black, 0), -1.0);
+  assert_eq!(winloss(Player::Black, 5), 1.0);
+}
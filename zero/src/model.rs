@@ -1,6 +1,8 @@
+use crate::eval_cache::PositionKey;
 use either::Either;
 use ndarray::{Array, Array1, Array2, Array3, Array4, Axis};
 use num_traits::Float;
+use std::fmt::{self, Display, Formatter};
 
 #[allow(async_fn_in_trait)]
 pub trait Model<N: Float> {
@@ -46,6 +48,164 @@ pub trait Model<N: Float> {
     global: Array2<N>,
     optimism: Array1<N>,
   ) -> Result<(Array3<N>, Array2<N>), Self::E>;
+
+  /// [`Model::predict`] for positions the caller can name: `keys` holds the key
+  /// of every position in the batch. Only a model that caches its evaluations,
+  /// like [`crate::eval_cache::CachedModel`], looks at them.
+  async fn predict_keyed(
+    &self,
+    keys: &[PositionKey],
+    inputs: Array4<N>,
+    global: Array2<N>,
+    optimism: Array1<N>,
+  ) -> Result<(Array3<N>, Array2<N>), Self::E> {
+    let _ = keys;
+    self.predict(inputs, global, optimism).await
+  }
+}
+
+/// The loss terms of a batch, each one weighted as it enters the total loss.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Losses {
+  pub value: f64,
+  pub td_value: f64,
+  pub value_error: f64,
+  pub td_score: f64,
+  pub score_error: f64,
+  pub policy: f64,
+  pub opponent_policy: f64,
+  pub soft_policy: f64,
+  pub soft_opponent_policy: f64,
+  pub optimistic_policy: f64,
+  pub long_optimistic_policy: f64,
+  pub score_pdf: f64,
+  pub score_cdf: f64,
+  pub captured: f64,
+  pub q_value: f64,
+  pub q_score: f64,
+}
+
+impl Losses {
+  /// Names of the terms, in the order of [`Losses::values`].
+  pub const NAMES: [&'static str; 16] = [
+    "value",
+    "td_value",
+    "value_error",
+    "td_score",
+    "score_error",
+    "policy",
+    "opponent_policy",
+    "soft_policy",
+    "soft_opponent_policy",
+    "optimistic_policy",
+    "long_optimistic_policy",
+    "score_pdf",
+    "score_cdf",
+    "captured",
+    "q_value",
+    "q_score",
+  ];
+
+  pub fn values(&self) -> [f64; 16] {
+    [
+      self.value,
+      self.td_value,
+      self.value_error,
+      self.td_score,
+      self.score_error,
+      self.policy,
+      self.opponent_policy,
+      self.soft_policy,
+      self.soft_opponent_policy,
+      self.optimistic_policy,
+      self.long_optimistic_policy,
+      self.score_pdf,
+      self.score_cdf,
+      self.captured,
+      self.q_value,
+      self.q_score,
+    ]
+  }
+
+  pub fn from_values(values: [f64; 16]) -> Self {
+    let [
+      value,
+      td_value,
+      value_error,
+      td_score,
+      score_error,
+      policy,
+      opponent_policy,
+      soft_policy,
+      soft_opponent_policy,
+      optimistic_policy,
+      long_optimistic_policy,
+      score_pdf,
+      score_cdf,
+      captured,
+      q_value,
+      q_score,
+    ] = values;
+    Losses {
+      value,
+      td_value,
+      value_error,
+      td_score,
+      score_error,
+      policy,
+      opponent_policy,
+      soft_policy,
+      soft_opponent_policy,
+      optimistic_policy,
+      long_optimistic_policy,
+      score_pdf,
+      score_cdf,
+      captured,
+      q_value,
+      q_score,
+    }
+  }
+
+  /// The loss the gradient is taken of.
+  pub fn total(&self) -> f64 {
+    self.values().into_iter().sum()
+  }
+
+  /// The average of the losses of several batches, or all zeros for none.
+  pub fn mean<'a>(losses: impl IntoIterator<Item = &'a Losses>) -> Losses {
+    let mut count = 0;
+    let mut sums = [0.0; 16];
+    for losses in losses {
+      count += 1;
+      for (sum, value) in sums.iter_mut().zip(losses.values()) {
+        *sum += value;
+      }
+    }
+    Losses::from_values(sums.map(|sum| if count > 0 { sum / count as f64 } else { 0.0 }))
+  }
+}
+
+impl Display for Losses {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    for (i, (name, value)) in Losses::NAMES.into_iter().zip(self.values()).enumerate() {
+      if i > 0 {
+        write!(f, " ")?;
+      }
+      write!(f, "{} {}", name.replace('_', " "), value)?;
+    }
+    Ok(())
+  }
+}
+
+/// What a training step reports about itself.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TrainStats {
+  pub losses: Losses,
+  /// Norm of the whole gradient before any clipping.
+  pub gradient_norm: f64,
+  /// Whether the gradient was scaled down to the clipping bound.
+  pub clipped: bool,
+  pub learning_rate: f64,
 }
 
 pub trait TrainableModel<N: Float>: Model<N> + Sized {
@@ -65,7 +225,24 @@ pub trait TrainableModel<N: Float>: Model<N> + Sized {
     captured: Array4<N>,
     q_values: Array4<N>,
     learning_rate: f64,
-  ) -> Result<Self, Self::TE>;
+  ) -> Result<(Self, TrainStats), Self::TE>;
+
+  /// The losses of a batch the model is not trained on, for telling how well
+  /// it does on positions it has never seen.
+  #[allow(clippy::too_many_arguments)]
+  fn validate(
+    &self,
+    inputs: Array4<N>,
+    global: Array2<N>,
+    policies: Array3<N>,
+    opponent_policies: Array3<N>,
+    values: Array2<N>,
+    td_values: Array3<N>,
+    td_scores: Array2<N>,
+    scores: Array2<N>,
+    captured: Array4<N>,
+    q_values: Array4<N>,
+  ) -> Result<Losses, Self::TE>;
 }
 
 impl<T, E, N: Float> Model<N> for T
@@ -126,4 +303,23 @@ impl<N: Float, A: Model<N>, B: Model<N>> Model<N> for Either<A, B> {
       Either::Right(b) => b.predict(inputs, global, optimism).await.map_err(Either::Right),
     }
   }
+
+  async fn predict_keyed(
+    &self,
+    keys: &[PositionKey],
+    inputs: Array4<N>,
+    global: Array2<N>,
+    optimism: Array1<N>,
+  ) -> Result<(Array3<N>, Array2<N>), Self::E> {
+    match self {
+      Either::Left(a) => a
+        .predict_keyed(keys, inputs, global, optimism)
+        .await
+        .map_err(Either::Left),
+      Either::Right(b) => b
+        .predict_keyed(keys, inputs, global, optimism)
+        .await
+        .map_err(Either::Right),
+    }
+  }
 }
@@ -1,7 +1,7 @@
 use crate::mcgs::{Params, Search};
 use crate::model::Model;
 use num_traits::Float;
-use oppai_field::field::Field;
+use oppai_field::field::{Field, NonZeroPos};
 use oppai_field::player::Player;
 use rand::Rng;
 use std::fmt::{Debug, Display};
@@ -16,6 +16,7 @@ pub async fn play<'a, N, M, R>(
   mut model1: &'a M,
   mut model2: &'a M,
   mut komi_x_2: i32,
+  params: Params,
   rng: &mut R,
 ) -> Result<i32, M::E>
 where
@@ -24,8 +25,8 @@ where
   R: Rng,
 {
   let mut moves_count = 0;
-  let mut search1 = Search::new(Params::PLAY);
-  let mut search2 = Search::new(Params::PLAY);
+  let mut search1 = Search::new(params);
+  let mut search2 = Search::new(params);
 
   while !field.is_game_over(if player == Player::Red { komi_x_2 } else { -komi_x_2 }) {
     for _ in 0..MCTS_SIMS {
@@ -54,3 +55,62 @@ where
   let score = field.score(player) * 2 + komi_x_2;
   Ok(if moves_count % 2 == 0 { score } else { -score })
 }
+
+/// Plays a game between `model` and an opponent choosing its moves with
+/// `opponent`, `model` moving first when `model_first` is set. The model
+/// searches as it does in [`play`]. Returns the score of `model`.
+#[allow(clippy::too_many_arguments)]
+pub async fn play_opponent<N, M, R, O>(
+  field: &mut Field,
+  mut player: Player,
+  model: &M,
+  model_first: bool,
+  mut komi_x_2: i32,
+  params: Params,
+  rng: &mut R,
+  mut opponent: O,
+) -> Result<i32, M::E>
+where
+  M: Model<N>,
+  N: Float + Sum + Display + Debug,
+  R: Rng,
+  O: AsyncFnMut(&mut Field, Player) -> Option<NonZeroPos>,
+{
+  let mut moves_count = 0;
+  let mut search = Search::new(params);
+  let mut model_turn = model_first;
+
+  while !field.is_game_over(if player == Player::Red { komi_x_2 } else { -komi_x_2 }) {
+    let pos = if model_turn {
+      for _ in 0..MCTS_SIMS {
+        search.mcgs(field, player, model, komi_x_2, rng).await?;
+      }
+      search.next_best_root()
+    } else {
+      let pos = opponent(field, player).await;
+      if let Some(pos) = pos {
+        search.next_root(pos.get());
+      }
+      pos
+    };
+
+    let pos = if let Some(pos) = pos {
+      pos
+    } else {
+      break;
+    };
+
+    search.compact();
+    assert!(field.put_point(pos.get(), player));
+    field.update_grounded();
+
+    model_turn = !model_turn;
+    player = player.next();
+    komi_x_2 = -komi_x_2;
+    moves_count += 1;
+  }
+
+  let score = field.score(player) * 2 + komi_x_2;
+  let score = if moves_count % 2 == 0 { score } else { -score };
+  Ok(if model_first { score } else { -score })
+}
@@ -0,0 +1,93 @@
+use crate::mcgs::Params;
+use crate::pit::play_opponent;
+use crate::random_model::RandomModel;
+use oppai_field::construct_field::construct_field;
+use oppai_field::field::{Field, NonZeroPos};
+use oppai_field::player::Player;
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+const SEED: u64 = 7;
+
+fn first_free(field: &Field) -> Option<NonZeroPos> {
+  (field.min_pos()..=field.max_pos())
+    .find(|&pos| field.is_putting_allowed(pos))
+    .and_then(NonZeroPos::new)
+}
+
+// Red has captured a point and the opponent plays Black.
+#[test]
+fn the_score_is_the_models_when_the_opponent_gives_up() {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(SEED);
+  let mut field = construct_field(
+    &mut rng,
+    "
+    ....
+    .a..
+    aAb.
+    .a..
+    ..a.
+    ....
+    ",
+  );
+  let model = RandomModel::new(Xoshiro256PlusPlus::seed_from_u64(SEED));
+
+  let mut calls = 0;
+  let score = futures::executor::block_on(play_opponent::<f64, _, _, _>(
+    &mut field,
+    Player::Black,
+    &model,
+    false,
+    0,
+    Params::PLAY,
+    &mut rng,
+    async |_: &mut Field, _| {
+      calls += 1;
+      None
+    },
+  ))
+  .unwrap();
+
+  assert_eq!(calls, 1);
+  assert_eq!(score, 2);
+}
+
+#[test]
+fn the_opponent_moves_only_on_its_turns() {
+  for model_first in [false, true] {
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(SEED);
+    let mut field = construct_field(
+      &mut rng,
+      "
+      .....
+      .....
+      ..aA.
+      .....
+      .....
+      ",
+    );
+    let model = RandomModel::new(Xoshiro256PlusPlus::seed_from_u64(SEED));
+    let opponent = if model_first { Player::Black } else { Player::Red };
+
+    let mut players = Vec::new();
+    let score = futures::executor::block_on(play_opponent::<f64, _, _, _>(
+      &mut field,
+      Player::Red,
+      &model,
+      model_first,
+      0,
+      Params::PLAY,
+      &mut rng,
+      async |field: &mut Field, player| {
+        players.push(player);
+        first_free(field)
+      },
+    ))
+    .unwrap();
+
+    assert!(!players.is_empty());
+    assert!(players.iter().all(|&player| player == opponent));
+    let red = field.score(Player::Red) * 2;
+    assert_eq!(score, if model_first { red } else { -red });
+  }
+}
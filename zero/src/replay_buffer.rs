@@ -0,0 +1,236 @@
+//! Replay buffer.
+//!
+//! Training on every game ever played keeps feeding the net the targets of
+//! models long surpassed, while training only on the last generation overfits
+//! it to a handful of games. The buffer keeps a window of the most recent games
+//! instead, which grows sublinearly with the total number of games: early on,
+//! when every generation improves a lot, the window stays close to the newest
+//! games, and later it spans more of them as the games pile up.
+//!
+//! Games are grouped into files, one per generation, listed oldest first. Every
+//! file has a sampling weight, multiplied by a decay for each generation it lies
+//! behind the newest one, so that older games are still seen but less often.
+//!
+//! A position reached again - by a transposition or by the same opening played
+//! again - can be trained on only once with [`SeenPositions`], keeping common
+//! positions from crowding out the rest of the buffer.
+//!
+//! The window rarely fits in memory as training rows, so the games are
+//! streamed instead: [`Interleave`] picks the file to read the next game from,
+//! and the rows pass through a bounded [`ShuffleBuffer`] that batches are drawn
+//! from at random.
+
+use crate::{eval_cache::PositionKey, examples::Examples};
+use oppai_field::{field::Field, player::Player};
+use rand::{Rng, RngExt};
+use std::collections::HashSet;
+
+/// How the number of games in the window grows with the total number of games.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Window {
+  /// All the games are kept until there are this many of them.
+  pub min_games: usize,
+  /// Power of the total number of games the window grows with beyond
+  /// `min_games`.
+  pub exponent: f64,
+  /// How fast the window grows beyond `min_games`: 1 with an exponent of 1
+  /// keeps every game.
+  pub growth: f64,
+}
+
+impl Window {
+  /// The window KataGo uses, counted in games rather than in rows.
+  pub const DEFAULT: Window = Window {
+    min_games: 5000,
+    exponent: 0.75,
+    growth: 0.4,
+  };
+
+  /// Number of the most recent games to keep out of `total` games.
+  pub fn size(&self, total: usize) -> usize {
+    if total <= self.min_games || self.min_games == 0 {
+      return total;
+    }
+    let min = self.min_games as f64;
+    let scaled = (total as f64 / min).powf(self.exponent);
+    let size = min * (1.0 + self.growth * (scaled - 1.0) / self.exponent);
+    (size.round() as usize).clamp(self.min_games, total)
+  }
+}
+
+/// A file of games as the buffer sees it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ReplayFile {
+  /// Number of games in the file.
+  pub games: usize,
+  /// Sampling weight of the file's games.
+  pub weight: f64,
+}
+
+/// What to take from a file of games.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FilePlan {
+  /// Number of the file's first (oldest) games that fall out of the window.
+  pub skip: usize,
+  /// Sampling weight of every remaining game.
+  pub weight: f64,
+}
+
+impl FilePlan {
+  /// Number of games taken from a file of `games` games.
+  pub fn taken(&self, games: usize) -> usize {
+    games - self.skip
+  }
+}
+
+/// Plans which games of `files`, listed oldest first, to train on and how
+/// heavily: the most recent `window.size(total)` of them, every file's weight
+/// multiplied by `decay` once per file after it.
+pub fn plan(files: &[ReplayFile], window: Option<Window>, decay: f64) -> Vec<FilePlan> {
+  let total = files.iter().map(|file| file.games).sum::<usize>();
+  let mut left = window.map_or(total, |window| window.size(total));
+  let mut plans = files
+    .iter()
+    .rev()
+    .enumerate()
+    .map(|(age, file)| {
+      let taken = file.games.min(left);
+      left -= taken;
+      FilePlan {
+        skip: file.games - taken,
+        weight: file.weight * decay.powi(age as i32),
+      }
+    })
+    .collect::<Vec<_>>();
+  plans.reverse();
+  plans
+}
+
+/// Positions already written into the training data.
+#[derive(Clone, Debug, Default)]
+pub struct SeenPositions(HashSet<PositionKey>);
+
+impl SeenPositions {
+  /// Marks the position after `move_number` moves of `field`, with `player` to
+  /// move with `komi_x_2`, as seen, telling whether it was new.
+  ///
+  /// Hashes are only comparable between fields sharing their Zobrist table, so
+  /// all the fields have to be created from the same one for their size.
+  pub fn insert(&mut self, field: &Field, move_number: usize, player: Player, komi_x_2: i32) -> bool {
+    let Some(hash) = field.hash_at(move_number) else {
+      return true;
+    };
+    self.0.insert(PositionKey {
+      hash,
+      width: field.width(),
+      height: field.height(),
+      player,
+      komi_x_2,
+    })
+  }
+
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+}
+
+/// Order to read the games of several files in, so that each file is spread
+/// over the whole stream rather than read in one go.
+#[derive(Clone, Debug)]
+pub struct Interleave {
+  left: Vec<usize>,
+  total: usize,
+}
+
+impl Interleave {
+  /// Interleaves files with `games` games left to read each.
+  pub fn new(games: Vec<usize>) -> Self {
+    let total = games.iter().sum();
+    Interleave { left: games, total }
+  }
+
+  /// Picks the file to read the next game from, each with a probability
+  /// proportional to the games it has left, or `None` once all are read.
+  pub fn next<R: Rng>(&mut self, rng: &mut R) -> Option<usize> {
+    if self.total == 0 {
+      return None;
+    }
+    let mut n = rng.random_range(0..self.total);
+    let file = self
+      .left
+      .iter()
+      .position(|&left| {
+        if n < left {
+          true
+        } else {
+          n -= left;
+          false
+        }
+      })
+      .unwrap();
+    self.left[file] -= 1;
+    self.total -= 1;
+    Some(file)
+  }
+}
+
+/// Training rows waiting to be drawn into batches.
+///
+/// Rows are drawn at random, and a row drawn is gone, so the buffer mixes the
+/// games read into it while holding no more than about `capacity` rows.
+#[derive(Clone, Debug)]
+pub struct ShuffleBuffer {
+  examples: Examples,
+  capacity: usize,
+  /// Number of games after the last time the unused ones were dropped.
+  compacted: usize,
+}
+
+impl ShuffleBuffer {
+  pub fn new(capacity: usize) -> Self {
+    ShuffleBuffer {
+      examples: Examples::default(),
+      capacity,
+      compacted: 0,
+    }
+  }
+
+  /// The rows in the buffer, to add games to.
+  #[inline]
+  pub fn examples_mut(&mut self) -> &mut Examples {
+    &mut self.examples
+  }
+
+  #[inline]
+  pub fn is_full(&self) -> bool {
+    self.examples.len() >= self.capacity
+  }
+
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.examples.len()
+  }
+
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.examples.is_empty()
+  }
+
+  /// Takes up to `count` rows at random out of the buffer.
+  pub fn draw<R: Rng>(&mut self, count: usize, rng: &mut R) -> Examples {
+    let taken = self.examples.take_random(count, rng);
+    // Games stay until all their rows are drawn; dropping them only once their
+    // number has doubled keeps the cost of it constant per game.
+    if self.examples.games.len() >= 2 * self.compacted.max(1) {
+      self.examples.drop_unused_games();
+      self.compacted = self.examples.games.len();
+    }
+    taken
+  }
+}
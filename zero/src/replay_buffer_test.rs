@@ -0,0 +1,183 @@
+use crate::episode::Visits;
+use crate::examples::Examples;
+use crate::replay_buffer::{FilePlan, Interleave, ReplayFile, SeenPositions, ShuffleBuffer, Window, plan};
+use oppai_field::{construct_field::construct_field, field::Field};
+use rand::SeedableRng;
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+const WINDOW: Window = Window {
+  min_games: 100,
+  exponent: 0.75,
+  growth: 0.4,
+};
+
+fn file(games: usize) -> ReplayFile {
+  ReplayFile { games, weight: 1.0 }
+}
+
+#[test]
+fn the_window_keeps_everything_until_the_minimum() {
+  assert_eq!(WINDOW.size(0), 0);
+  assert_eq!(WINDOW.size(60), 60);
+  assert_eq!(WINDOW.size(100), 100);
+}
+
+// Past the minimum the window keeps growing, but more slowly than the games do.
+#[test]
+fn the_window_grows_sublinearly() {
+  let sizes = [200, 1000, 10000].map(|total| WINDOW.size(total));
+  assert!(sizes[0] > 100 && sizes[0] < 200);
+  assert!(sizes[1] > sizes[0] && sizes[1] < 1000);
+  assert!(sizes[2] > sizes[1] && sizes[2] < 10000);
+  assert!(sizes[2] - sizes[1] < 10 * (sizes[1] - sizes[0]));
+}
+
+#[test]
+fn a_linear_full_growth_keeps_every_game() {
+  let window = Window {
+    min_games: 10,
+    exponent: 1.0,
+    growth: 1.0,
+  };
+  assert_eq!(window.size(1234), 1234);
+}
+
+#[test]
+fn without_a_window_every_game_is_taken() {
+  let plans = plan(&[file(3), file(5)], None, 1.0);
+  assert_eq!(
+    plans,
+    [FilePlan { skip: 0, weight: 1.0 }, FilePlan { skip: 0, weight: 1.0 }]
+  );
+}
+
+// The window is filled from the newest file backwards, skipping the oldest
+// games of the file it ends in and all the games of the files before it.
+#[test]
+fn the_window_takes_the_newest_games() {
+  let window = Window {
+    min_games: 70,
+    ..WINDOW
+  };
+  let files = [file(50), file(50), file(40), file(20)];
+  let plans = plan(&files, Some(window), 1.0);
+  assert_eq!(plans.iter().map(|plan| plan.skip).collect::<Vec<_>>(), [50, 8, 0, 0]);
+  let taken = plans
+    .iter()
+    .zip(&files)
+    .map(|(plan, file)| plan.taken(file.games))
+    .sum::<usize>();
+  assert_eq!(taken, window.size(160));
+}
+
+#[test]
+fn older_files_are_down_weighted() {
+  let files = [file(10), ReplayFile { games: 10, weight: 2.0 }, file(10)];
+  let plans = plan(&files, None, 0.5);
+  assert_eq!(
+    plans.iter().map(|plan| plan.weight).collect::<Vec<_>>(),
+    [0.25, 1.0, 1.0]
+  );
+}
+
+fn game(seed: u64) -> Field {
+  construct_field(
+    &mut Xoshiro256PlusPlus::seed_from_u64(seed),
+    "
+    .a..
+    bAcB
+    .d.C
+    ",
+  )
+}
+
+fn visits(field: &Field) -> Vec<Visits> {
+  (0..field.moves_count())
+    .map(|_| Visits(Vec::new(), 1.0, 0.0, 0.0, 0.0, Vec::new()))
+    .collect()
+}
+
+// Fields built from one Zobrist table hash the same positions the same, so a
+// game played again adds nothing once its positions are seen.
+#[test]
+fn seen_positions_are_written_once() {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+  let mut seen = SeenPositions::default();
+  let mut examples = Examples::default();
+
+  let first = game(1);
+  examples.add(0, visits(&first), &first, false, false, 1.0, Some(&mut seen), &mut rng);
+  let rows = examples.len();
+  assert_eq!(seen.len(), first.moves_count());
+
+  let again = game(1);
+  examples.add(0, visits(&again), &again, false, false, 1.0, Some(&mut seen), &mut rng);
+  assert_eq!(examples.len(), rows);
+
+  // The same position with the other komi is a different training target.
+  examples.add(2, visits(&again), &again, false, false, 1.0, Some(&mut seen), &mut rng);
+  assert_eq!(examples.len(), 2 * rows);
+}
+
+#[test]
+fn the_game_weight_scales_the_copies() {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+  let field = game(1);
+
+  let mut examples = Examples::default();
+  examples.add(0, visits(&field), &field, false, false, 1.0, None, &mut rng);
+  let rows = examples.len();
+
+  let mut examples = Examples::default();
+  examples.add(0, visits(&field), &field, false, false, 3.0, None, &mut rng);
+  assert_eq!(examples.len(), 3 * rows);
+
+  let mut examples = Examples::default();
+  examples.add(0, visits(&field), &field, false, false, 0.0, None, &mut rng);
+  assert!(examples.is_empty());
+}
+
+#[test]
+fn interleaving_reads_every_game_once() {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+  let mut interleave = Interleave::new(vec![3, 0, 5]);
+  let mut read = [0; 3];
+  while let Some(file) = interleave.next(&mut rng) {
+    read[file] += 1;
+  }
+  assert_eq!(read, [3, 0, 5]);
+}
+
+// Every row added comes out of the buffer exactly once, and the games of the
+// rows drawn don't pile up in it.
+#[test]
+fn the_shuffle_buffer_draws_every_row_once() {
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+  let mut buffer = ShuffleBuffer::new(8);
+  let mut added = 0;
+  let mut drawn = 0;
+  for _ in 0..32 {
+    let field = game(1);
+    let examples = buffer.examples_mut();
+    let rows = examples.len();
+    examples.add(0, visits(&field), &field, false, false, 1.0, None, &mut rng);
+    let game_rows = examples.len() - rows;
+    added += game_rows;
+    while buffer.is_full() {
+      let examples = buffer.draw(3, &mut rng);
+      assert_eq!(examples.len(), 3);
+      assert!(
+        examples
+          .examples
+          .iter()
+          .all(|example| example.game < examples.games.len())
+      );
+      drawn += examples.len();
+    }
+    assert!(buffer.examples_mut().games.len() < 2 * (8 + game_rows));
+  }
+  while !buffer.is_empty() {
+    drawn += buffer.draw(3, &mut rng).len();
+  }
+  assert_eq!(drawn, added);
+}
@@ -0,0 +1,126 @@
+//! Sequential probability ratio test for model gating.
+//!
+//! A fixed number of pit games is either too many for models that are plainly
+//! equal or plainly apart, or too few for the close calls. [`Sprt`] instead
+//! tests after every game whether the new model is [`Sprt::elo0`] or
+//! [`Sprt::elo1`] stronger than the old one, and stops as soon as the games so
+//! far tell the two apart with the requested error rates.
+//!
+//! The log-likelihood ratio is the generalized SPRT approximation of fishtest:
+//! the score of a game is normally distributed around its mean, with the
+//! variance the games so far show. Draws are common in pit games played from the
+//! same opening, so they count as half a win rather than being thrown away.
+
+/// Results of the games played so far, from the new model's perspective.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Results {
+  pub wins: u64,
+  pub draws: u64,
+  pub losses: u64,
+}
+
+impl Results {
+  pub fn games(&self) -> u64 {
+    self.wins + self.draws + self.losses
+  }
+
+  /// The mean score and its per-game variance. Every outcome counts half a game
+  /// more than it was played, so that the variance stays away from zero until
+  /// both models have shown something - a single won game would otherwise be
+  /// proof beyond doubt.
+  fn score(&self) -> (f64, f64) {
+    let wins = self.wins as f64 + 0.5;
+    let draws = self.draws as f64 + 0.5;
+    let losses = self.losses as f64 + 0.5;
+    let games = wins + draws + losses;
+    let mean = (wins + draws / 2.0) / games;
+    let variance = (wins * (1.0 - mean).powi(2) + draws * (0.5 - mean).powi(2) + losses * mean.powi(2)) / games;
+    (mean, variance)
+  }
+}
+
+/// Which hypothesis the games accepted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Decision {
+  /// The new model is no more than [`Sprt::elo0`] stronger.
+  H0,
+  /// The new model is at least [`Sprt::elo1`] stronger.
+  H1,
+}
+
+/// The hypotheses to tell apart and the error rates to tell them apart with.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Sprt {
+  /// Elo difference of the null hypothesis.
+  pub elo0: f64,
+  /// Elo difference of the alternative hypothesis, above [`Self::elo0`].
+  pub elo1: f64,
+  /// Probability of accepting H1 when H0 holds.
+  pub alpha: f64,
+  /// Probability of accepting H0 when H1 holds.
+  pub beta: f64,
+}
+
+impl Sprt {
+  /// The log-likelihood ratio below which H0 is accepted and above which H1 is.
+  pub fn bounds(&self) -> (f64, f64) {
+    (
+      (self.beta / (1.0 - self.alpha)).ln(),
+      ((1.0 - self.beta) / self.alpha).ln(),
+    )
+  }
+
+  /// Log-likelihood ratio of H1 to H0 given `results`.
+  pub fn llr(&self, results: &Results) -> f64 {
+    let (mean, variance) = results.score();
+    let score0 = elo_to_score(self.elo0);
+    let score1 = elo_to_score(self.elo1);
+    results.games() as f64 * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+  }
+
+  /// The hypothesis `results` accept, or `None` while more games are needed.
+  pub fn decide(&self, results: &Results) -> Option<Decision> {
+    let llr = self.llr(results);
+    let (lower, upper) = self.bounds();
+    if llr >= upper {
+      Some(Decision::H1)
+    } else if llr <= lower {
+      Some(Decision::H0)
+    } else {
+      None
+    }
+  }
+}
+
+/// Elo difference with its 95% confidence interval.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EloEstimate {
+  pub elo: f64,
+  pub lower: f64,
+  pub upper: f64,
+}
+
+impl EloEstimate {
+  pub fn new(results: &Results) -> Self {
+    let (mean, variance) = results.score();
+    let games = results.games().max(1) as f64;
+    let margin = 1.96 * (variance / games).sqrt();
+    EloEstimate {
+      elo: score_to_elo(mean),
+      lower: score_to_elo(mean - margin),
+      upper: score_to_elo(mean + margin),
+    }
+  }
+}
+
+/// Expected score of a player `elo` points stronger than the opponent.
+pub fn elo_to_score(elo: f64) -> f64 {
+  1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Elo difference that makes `score` the expected score. Scores of 0 and 1 map
+/// to infinities.
+pub fn score_to_elo(score: f64) -> f64 {
+  let score = score.clamp(0.0, 1.0);
+  -400.0 * (1.0 / score - 1.0).log10()
+}
@@ -0,0 +1,64 @@
+use crate::sprt::{Decision, EloEstimate, Results, Sprt, elo_to_score, score_to_elo};
+
+const SPRT: Sprt = Sprt {
+  elo0: 0.0,
+  elo1: 20.0,
+  alpha: 0.05,
+  beta: 0.05,
+};
+
+fn results(wins: u64, draws: u64, losses: u64) -> Results {
+  Results { wins, draws, losses }
+}
+
+#[test]
+fn elo_and_score_convert_back_and_forth() {
+  assert_eq!(elo_to_score(0.0), 0.5);
+  assert!((elo_to_score(400.0) - 10.0 / 11.0).abs() < 1e-12);
+  for elo in [-300.0, -20.0, 0.0, 35.0, 250.0] {
+    assert!((score_to_elo(elo_to_score(elo)) - elo).abs() < 1e-9);
+  }
+  assert_eq!(score_to_elo(1.0), f64::INFINITY);
+  assert_eq!(score_to_elo(0.0), f64::NEG_INFINITY);
+}
+
+#[test]
+fn the_bounds_follow_the_error_rates() {
+  let (lower, upper) = SPRT.bounds();
+  assert!((lower + 19f64.ln()).abs() < 1e-12);
+  assert!((upper - 19f64.ln()).abs() < 1e-12);
+}
+
+#[test]
+fn a_few_games_decide_nothing() {
+  assert_eq!(SPRT.decide(&Results::default()), None);
+  assert_eq!(SPRT.decide(&results(1, 0, 0)), None);
+  assert_eq!(SPRT.decide(&results(3, 1, 2)), None);
+}
+
+#[test]
+fn equal_models_accept_the_null_hypothesis() {
+  let even = results(600, 300, 600);
+  assert!(SPRT.llr(&even) < 0.0);
+  assert_eq!(SPRT.decide(&even), Some(Decision::H0));
+}
+
+#[test]
+fn a_stronger_model_accepts_the_alternative() {
+  // About 100 Elo: far enough above `elo1` to be settled quickly.
+  let stronger = results(180, 60, 90);
+  assert!(SPRT.llr(&stronger) > 0.0);
+  assert_eq!(SPRT.decide(&stronger), Some(Decision::H1));
+  assert_eq!(SPRT.decide(&results(90, 60, 180)), Some(Decision::H0));
+}
+
+#[test]
+fn the_confidence_interval_narrows_with_more_games() {
+  let few = EloEstimate::new(&results(6, 2, 4));
+  let many = EloEstimate::new(&results(600, 200, 400));
+  assert!(few.lower < few.elo && few.elo < few.upper);
+  assert!(many.lower < many.elo && many.elo < many.upper);
+  assert!(many.upper - many.lower < few.upper - few.lower);
+  // The half-game regularization barely moves the estimate of many games.
+  assert!((many.elo - score_to_elo(7.0 / 12.0)).abs() < 1.0);
+}
@@ -0,0 +1,218 @@
+//! Inference under the symmetries of the board.
+//!
+//! The net is trained on randomly rotated and mirrored positions, so each of
+//! the 8 symmetries of a position is a separate estimate of the same thing,
+//! with errors of its own. [`SymmetricModel`] asks the model it wraps about
+//! several of them and averages the answers back in the original orientation,
+//! which costs a forward pass per symmetry - worth it for analysis, where
+//! latency matters less than strength. Evaluating a single random symmetry
+//! costs nothing extra, and decorrelates the errors of positions evaluated
+//! close to each other instead.
+
+use crate::model::Model;
+use ndarray::{Array1, Array2, Array3, Array4, Axis};
+use num_traits::Float;
+use oppai_rotate::rotate::{MIRRORS, ROTATIONS, rotate, rotate_back, rotate_sizes};
+use rand::{rngs::SmallRng, seq::index::sample};
+use std::{
+  fmt::{self, Display, Formatter},
+  str::FromStr,
+  sync::Mutex,
+};
+
+/// Which symmetries a position is evaluated under.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Symmetries {
+  /// The position as it is.
+  #[default]
+  Identity,
+  /// One symmetry drawn at random.
+  Random,
+  /// The average over this many distinct symmetries drawn at random, all of
+  /// them for [`ROTATIONS`].
+  Average(u8),
+}
+
+impl Symmetries {
+  fn count(self) -> usize {
+    match self {
+      Symmetries::Identity | Symmetries::Random => 1,
+      Symmetries::Average(count) => count.clamp(1, ROTATIONS) as usize,
+    }
+  }
+}
+
+impl FromStr for Symmetries {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_lowercase().as_str() {
+      "identity" => Ok(Symmetries::Identity),
+      "random" => Ok(Symmetries::Random),
+      count => match count.parse::<u8>() {
+        Ok(count) if (1..=ROTATIONS).contains(&count) => Ok(Symmetries::Average(count)),
+        _ => Err(format!(
+          "expected identity, random or a number of symmetries from 1 to {}, got {}",
+          ROTATIONS, s
+        )),
+      },
+    }
+  }
+}
+
+impl Display for Symmetries {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Symmetries::Identity => write!(f, "identity"),
+      Symmetries::Random => write!(f, "random"),
+      Symmetries::Average(count) => write!(f, "{}", count),
+    }
+  }
+}
+
+/// A [`Model`] evaluating every position under the [`Symmetries`] it is set up
+/// with.
+///
+/// The positions of a batch may be padded to a larger board, as
+/// [`crate::batch_model`] does when it merges boards of different sizes. The
+/// size of each one is read from its first plane, which marks the board, and a
+/// position is only ever rotated within it.
+pub struct SymmetricModel<M> {
+  pub model: M,
+  pub symmetries: Symmetries,
+  rng: Mutex<SmallRng>,
+}
+
+impl<M> SymmetricModel<M> {
+  pub fn new(model: M, symmetries: Symmetries, rng: SmallRng) -> Self {
+    SymmetricModel {
+      model,
+      symmetries,
+      rng: Mutex::new(rng),
+    }
+  }
+
+  /// Symmetries to evaluate every position of a batch of `batch` under.
+  fn choose(&self, batch: usize) -> Vec<Vec<u8>> {
+    let mut rng = self.rng.lock().unwrap();
+    (0..batch)
+      .map(|_| match self.symmetries {
+        Symmetries::Identity => vec![0],
+        symmetries => sample(&mut *rng, ROTATIONS as usize, symmetries.count())
+          .into_iter()
+          .map(|rotation| rotation as u8)
+          .collect(),
+      })
+      .collect()
+  }
+}
+
+impl<M: Clone> Clone for SymmetricModel<M> {
+  fn clone(&self) -> Self {
+    SymmetricModel::new(self.model.clone(), self.symmetries, self.rng.lock().unwrap().clone())
+  }
+}
+
+/// Size of the board of the `i`th position: the extent of its first plane,
+/// or the whole tensor when the plane is empty.
+fn board_size<N: Float>(inputs: &Array4<N>, i: usize) -> (u32, u32) {
+  let (_, _, height, width) = inputs.dim();
+  let width = (0..width).take_while(|&x| !inputs[(i, 0, 0, x)].is_zero()).count();
+  let height = (0..height).take_while(|&y| !inputs[(i, 0, y, 0)].is_zero()).count();
+  if width == 0 || height == 0 {
+    (inputs.dim().3 as u32, inputs.dim().2 as u32)
+  } else {
+    (width as u32, height as u32)
+  }
+}
+
+impl<N: Float, M: Model<N>> Model<N> for SymmetricModel<M> {
+  type E = M::E;
+
+  fn predicts_uncertainty(&self) -> bool {
+    self.model.predicts_uncertainty()
+  }
+
+  async fn predict(
+    &self,
+    inputs: Array4<N>,
+    global: Array2<N>,
+    optimism: Array1<N>,
+  ) -> Result<(Array3<N>, Array2<N>), Self::E> {
+    if self.symmetries == Symmetries::Identity {
+      return self.model.predict(inputs, global, optimism).await;
+    }
+
+    let (batch, channels, height, width) = inputs.dim();
+    let sizes = (0..batch).map(|i| board_size(&inputs, i)).collect::<Vec<_>>();
+    let symmetries = self.choose(batch);
+    let mut policies = Array3::zeros((batch, height, width));
+    let mut values: Option<Array2<N>> = None;
+
+    // The mirrors keep the shape of the tensor and the transpositions swap its
+    // sides, so each of the two kinds is evaluated in one batch.
+    for transposed in [false, true] {
+      let rotated = symmetries
+        .iter()
+        .enumerate()
+        .flat_map(|(i, rotations)| {
+          rotations
+            .iter()
+            .filter(|&&rotation| (rotation >= MIRRORS) == transposed)
+            .map(move |&rotation| (i, rotation))
+        })
+        .collect::<Vec<_>>();
+      if rotated.is_empty() {
+        continue;
+      }
+      let (rotated_height, rotated_width) = if transposed { (width, height) } else { (height, width) };
+
+      let mut rotated_inputs = Array4::zeros((rotated.len(), channels, rotated_height, rotated_width));
+      for (k, &(i, rotation)) in rotated.iter().enumerate() {
+        let (board_width, board_height) = sizes[i];
+        let (rotated_board_width, rotated_board_height) = rotate_sizes(board_width, board_height, rotation);
+        for y in 0..rotated_board_height {
+          for x in 0..rotated_board_width {
+            let (from_x, from_y) = rotate_back(rotated_board_width, rotated_board_height, x, y, rotation);
+            for c in 0..channels {
+              rotated_inputs[(k, c, y as usize, x as usize)] = inputs[(i, c, from_y as usize, from_x as usize)];
+            }
+          }
+        }
+      }
+      let indices = rotated.iter().map(|&(i, _)| i).collect::<Vec<_>>();
+      let (rotated_policies, rotated_values) = self
+        .model
+        .predict(
+          rotated_inputs,
+          global.select(Axis(0), &indices),
+          optimism.select(Axis(0), &indices),
+        )
+        .await?;
+
+      let values = values.get_or_insert_with(|| Array2::zeros((batch, rotated_values.dim().1)));
+      for (k, &(i, rotation)) in rotated.iter().enumerate() {
+        let (board_width, board_height) = sizes[i];
+        for y in 0..board_height {
+          for x in 0..board_width {
+            let (to_x, to_y) = rotate(board_width, board_height, x, y, rotation);
+            policies[(i, y as usize, x as usize)] =
+              policies[(i, y as usize, x as usize)] + rotated_policies[(k, to_y as usize, to_x as usize)];
+          }
+        }
+        for (value, &rotated_value) in values.row_mut(i).iter_mut().zip(rotated_values.row(k)) {
+          *value = *value + rotated_value;
+        }
+      }
+    }
+
+    let mut values = values.unwrap_or_else(|| Array2::zeros((batch, 0)));
+    for (i, rotations) in symmetries.iter().enumerate() {
+      let count = N::from(rotations.len()).unwrap();
+      policies.index_axis_mut(Axis(0), i).mapv_inplace(|p| p / count);
+      values.row_mut(i).mapv_inplace(|v| v / count);
+    }
+
+    Ok((policies, values))
+  }
+}
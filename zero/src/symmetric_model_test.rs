@@ -0,0 +1,110 @@
+use crate::model::Model;
+use crate::symmetric_model::{SymmetricModel, Symmetries};
+use ndarray::{Array1, Array2, Array3, Array4, array, s};
+use rand::{SeedableRng, rngs::SmallRng};
+use std::cell::Cell;
+
+const SEED: u64 = 7;
+
+type Prediction = Result<(Array3<f64>, Array2<f64>), ()>;
+
+/// Inputs of one position: the board mask and a plane numbering the cells of a
+/// `width`x`height` board, padded to `padded_width`x`padded_height`.
+fn inputs(width: usize, height: usize, padded_width: usize, padded_height: usize) -> Array4<f64> {
+  Array4::from_shape_fn((1, 2, padded_height, padded_width), |(_, c, y, x)| {
+    if x >= width || y >= height {
+      0.0
+    } else if c == 0 {
+      1.0
+    } else {
+      (y * width + x + 1) as f64
+    }
+  })
+}
+
+/// Answers with the numbered plane as the policy, so that a symmetry rotated
+/// back correctly gives it back unchanged, and with its top left cell as the
+/// value, which every symmetry moves to another corner.
+fn plane_model(rows: &Cell<usize>) -> impl Fn(Array4<f64>, Array2<f64>, Array1<f64>) -> Prediction {
+  move |inputs: Array4<f64>, _, _| {
+    rows.set(rows.get() + inputs.dim().0);
+    let policies = inputs.slice(s![.., 1, .., ..]).to_owned();
+    let values = Array2::from_shape_fn((inputs.dim().0, 4), |(i, _)| inputs[(i, 1, 0, 0)]);
+    Ok((policies, values))
+  }
+}
+
+fn predict<M: Model<f64, E = ()>>(model: &M, inputs: Array4<f64>) -> (Array3<f64>, Array2<f64>) {
+  let batch = inputs.dim().0;
+  futures::executor::block_on(model.predict(inputs, Array2::zeros((batch, 1)), Array1::zeros(batch))).unwrap()
+}
+
+#[test]
+fn all_symmetries_are_rotated_back_and_averaged() {
+  let rows = Cell::new(0);
+  let model = SymmetricModel::new(
+    plane_model(&rows),
+    Symmetries::Average(8),
+    SmallRng::seed_from_u64(SEED),
+  );
+
+  let (policies, values) = predict(&model, inputs(3, 2, 3, 2));
+
+  assert_eq!(rows.get(), 8);
+  assert_eq!(policies, array![[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]]);
+  // Every corner is the top left one under two of the symmetries.
+  assert_eq!(values.row(0).to_vec(), vec![(1.0 + 3.0 + 4.0 + 6.0) / 4.0; 4]);
+}
+
+#[test]
+fn padded_boards_are_rotated_within_themselves() {
+  let rows = Cell::new(0);
+  let model = SymmetricModel::new(
+    plane_model(&rows),
+    Symmetries::Average(8),
+    SmallRng::seed_from_u64(SEED),
+  );
+
+  let (policies, values) = predict(&model, inputs(2, 3, 4, 3));
+
+  assert_eq!(
+    policies,
+    array![[[1.0, 2.0, 0.0, 0.0], [3.0, 4.0, 0.0, 0.0], [5.0, 6.0, 0.0, 0.0]]]
+  );
+  assert_eq!(values[(0, 0)], (1.0 + 2.0 + 5.0 + 6.0) / 4.0);
+}
+
+#[test]
+fn a_random_symmetry_costs_one_evaluation() {
+  let rows = Cell::new(0);
+  let model = SymmetricModel::new(plane_model(&rows), Symmetries::Random, SmallRng::seed_from_u64(SEED));
+
+  let batch = ndarray::concatenate(
+    ndarray::Axis(0),
+    &[
+      inputs(3, 3, 3, 3).view(),
+      inputs(3, 3, 3, 3).view(),
+      inputs(3, 3, 3, 3).view(),
+    ],
+  )
+  .unwrap();
+  let (policies, values) = predict(&model, batch);
+
+  assert_eq!(rows.get(), 3);
+  for i in 0..3 {
+    assert_eq!(
+      policies.slice(s![i, .., ..]),
+      array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]
+    );
+    assert!([1.0, 3.0, 7.0, 9.0].contains(&values[(i, 0)]));
+  }
+}
+
+#[test]
+fn symmetries_are_parsed() {
+  assert_eq!("identity".parse(), Ok(Symmetries::Identity));
+  assert_eq!("Random".parse(), Ok(Symmetries::Random));
+  assert_eq!("8".parse(), Ok(Symmetries::Average(8)));
+  assert!("9".parse::<Symmetries>().is_err());
+  assert!("0".parse::<Symmetries>().is_err());
+}
@@ -1,7 +1,14 @@
+#[cfg(not(target_arch = "wasm32"))]
 use crate::{
+  batch_model::{batch_model, run_evaluator},
+  mcgs::SharedSearch,
+};
+use crate::{
+  eval_cache::{CacheStats, CachedModel, EvalCache, position_key},
   field_features::{field_features, global},
-  mcgs::{Params, PlaySelectionWeight, Search},
+  mcgs::{GumbelParams, Params, PlaySelectionWeight, Search},
   model::Model,
+  symmetric_model::{SymmetricModel, Symmetries},
 };
 use ndarray::{Array1, Axis};
 use num_traits::Float;
@@ -9,19 +16,66 @@ use oppai_field::{
   field::{Field, Hash, Pos, to_x, to_y},
   player::Player,
 };
-use rand::Rng;
+#[cfg(not(target_arch = "wasm32"))]
+use rand::RngExt;
+use rand::{Rng, SeedableRng, distr::StandardUniform, make_rng, prelude::Distribution, rngs::SmallRng};
 use std::{
   fmt::{Debug, Display},
   iter::Sum,
+  pin::pin,
+  sync::Arc,
+  task::{Context, Poll, Wake, Waker},
+  thread,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+  sync::{
+    Condvar, Mutex,
+    atomic::{AtomicU32, Ordering},
+  },
+  time::Duration,
 };
 
 type Analysis<N> = (Vec<(Pos, PlaySelectionWeight<N>)>, u32, N);
 
 type PolicyAnalysis<N> = (Vec<(Pos, N)>, N);
 
+/// Longest time an idle worker waits for the others to back up before it checks
+/// whether the search should stop.
+#[cfg(not(target_arch = "wasm32"))]
+const IDLE_TIMEOUT: Duration = Duration::from_millis(10);
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ZeroConfig {
+  /// Number of workers running playouts on the shared graph.
+  pub threads_count: usize,
+  /// Number of net evaluations to cache, or 0 to evaluate every position anew.
+  pub cache_size: usize,
+  /// Symmetries every position is evaluated under.
+  pub symmetries: Symmetries,
+  /// Whether root moves are selected by Gumbel sequential halving instead of
+  /// PUCT, which makes the most of small playout budgets.
+  pub gumbel: bool,
+}
+
+impl Default for ZeroConfig {
+  fn default() -> Self {
+    Self {
+      #[cfg(not(target_arch = "wasm32"))]
+      threads_count: num_cpus::get_physical(),
+      #[cfg(target_arch = "wasm32")]
+      threads_count: 1,
+      cache_size: 0,
+      symmetries: Symmetries::Identity,
+      gumbel: false,
+    }
+  }
+}
+
 #[derive(Clone)]
 pub struct Zero<N: Float, M: Model<N>> {
-  model: M,
+  config: ZeroConfig,
+  model: CachedModel<N, SymmetricModel<M>>,
   search: Search<N>,
   /// Player to move at the search root.
   player: Player,
@@ -29,6 +83,8 @@ pub struct Zero<N: Float, M: Model<N>> {
   moves_count: usize,
   /// Zobrist hash of the root position, used to detect history divergence.
   hash: Hash,
+  /// Komi for Red, doubled.
+  komi_x_2: i32,
 }
 
 impl<N, M> Zero<N, M>
@@ -36,27 +92,38 @@ where
   M: Model<N>,
   N: Float + Sum + Display + Debug,
 {
-  pub fn new(model: M) -> Self {
+  pub fn new(config: ZeroConfig, model: M, komi_x_2: i32) -> Self {
+    let cache = (config.cache_size > 0).then(|| Arc::new(EvalCache::new(config.cache_size)));
+    let model = SymmetricModel::new(model, config.symmetries, make_rng::<SmallRng>());
+    let search = Search::new(search_params(&config));
     Zero {
-      model,
-      search: Search::new(Params::PLAY),
+      config,
+      model: CachedModel::new(model, cache),
+      search,
       // A fresh search holds an empty root, which corresponds to the empty
       // board: zero moves played, zero hash, Red to move.
       player: Player::Red,
       moves_count: 0,
       hash: 0,
+      komi_x_2,
     }
   }
 
+  /// Hit-rate statistics of the evaluation cache, if there is one. The cache
+  /// outlives the searches, so they cover everything evaluated so far.
+  pub fn cache_stats(&self) -> Option<CacheStats> {
+    self.model.cache.as_ref().map(|cache| cache.stats())
+  }
+
   pub fn clear(&mut self) {
-    self.search = Search::new(Params::PLAY);
+    self.search = Search::new(search_params(&self.config));
     self.player = Player::Red;
     self.moves_count = 0;
     self.hash = 0;
   }
 
   fn init(&mut self, field: &Field, player: Player) {
-    self.search = Search::new(Params::PLAY);
+    self.search = Search::new(search_params(&self.config));
     self.player = player;
     self.moves_count = field.moves_count();
     self.hash = field.hash();
@@ -96,26 +163,177 @@ where
     self.search.compact();
   }
 
-  pub async fn best_moves<SS: Fn() -> bool, R: Rng>(
+  /// Searches the position and returns the moves to choose from.
+  ///
+  /// The search blocks the calling thread until it's over, running on
+  /// [`ZeroConfig::threads_count`] worker threads. Async callers should run it
+  /// where blocking is allowed, like a blocking task of their executor.
+  pub fn best_moves<S, R, SS>(
     &mut self,
     field: &Field,
     player: Player,
     rng: &mut R,
     should_stop: &SS,
     max_iterations_count: u32,
-  ) -> Result<Analysis<N>, <M as Model<N>>::E> {
+  ) -> Result<Analysis<N>, <M as Model<N>>::E>
+  where
+    N: Send,
+    R: Rng + SeedableRng<Seed = S> + Send,
+    StandardUniform: Distribution<S>,
+    SS: Fn() -> bool + Sync,
+  {
     self.update(field, player);
 
     // TODO: check if game is over
+    let komi_x_2 = player_komi_x_2(self.komi_x_2, player);
     let mut iterations = 0;
     let mut field = field.clone();
-    while !should_stop() && iterations < max_iterations_count {
-      self.search.mcgs(&mut field, player, &self.model, 0, rng).await?;
+    // The root is expanded before the workers start: until it is, every one of
+    // them would select the same empty path and all but one would have nothing
+    // to do.
+    while !should_stop()
+      && iterations < max_iterations_count
+      && (self.config.threads_count <= 1 || self.search.root_visits() == 0)
+    {
+      block_on_current_thread(self.search.mcgs(&mut field, player, &self.model, komi_x_2, rng))?;
       iterations += 1;
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    if self.config.threads_count > 1 && !should_stop() && iterations < max_iterations_count {
+      iterations += self.parallel_mcgs(
+        &field,
+        player,
+        komi_x_2,
+        rng,
+        should_stop,
+        max_iterations_count - iterations,
+      )?;
+    }
+
+    if let Some(stats) = self.cache_stats() {
+      log::debug!("Evaluation cache: {}", stats);
+    }
+
     Ok((self.search.play_selection(), iterations, self.search.winloss()))
   }
+
+  /// Runs the search on [`ZeroConfig::threads_count`] workers sharing the graph,
+  /// returning the number of batches they backed up.
+  ///
+  /// Every worker asks a [`crate::batch_model::BatchModel`] for its
+  /// predictions, and the evaluator behind it runs on the current thread,
+  /// merging the batches of the workers that are waiting into one forward
+  /// pass.
+  #[cfg(not(target_arch = "wasm32"))]
+  fn parallel_mcgs<S, R, SS>(
+    &mut self,
+    field: &Field,
+    player: Player,
+    komi_x_2: i32,
+    rng: &mut R,
+    should_stop: &SS,
+    max_iterations_count: u32,
+  ) -> Result<u32, <M as Model<N>>::E>
+  where
+    N: Send,
+    R: Rng + SeedableRng<Seed = S> + Send,
+    StandardUniform: Distribution<S>,
+    SS: Fn() -> bool + Sync,
+  {
+    let iterations = AtomicU32::new(0);
+    // Number of backups so far, which idle workers wait to change.
+    let backups = (Mutex::new(0u64), Condvar::new());
+    let (handle, requests) = batch_model(self.model.predicts_uncertainty());
+    // The workers look their evaluations up themselves, so that only the misses
+    // reach the evaluator.
+    let model = &self.model.model;
+    let cache = &self.model.cache;
+    let search = SharedSearch::new(&mut self.search);
+
+    let result = thread::scope(|scope| {
+      for _ in 0..self.config.threads_count {
+        let model = CachedModel::new(handle.clone(), cache.clone());
+        let mut rng = R::from_seed(rng.random());
+        let mut field = field.clone();
+        let search = &search;
+        let iterations = &iterations;
+        let (backups_count, backed_up) = &backups;
+        scope.spawn(move || {
+          block_on_current_thread(async {
+            while !should_stop() && iterations.load(Ordering::Relaxed) < max_iterations_count {
+              let seen = *backups_count.lock().unwrap();
+              match search.mcgs(&mut field, player, &model, komi_x_2, &mut rng).await {
+                // Everything this worker selected is being evaluated by the
+                // others, so there is nothing to do until they back it up.
+                Ok(0) => {
+                  let count = backups_count.lock().unwrap();
+                  let _ = backed_up
+                    .wait_timeout_while(count, IDLE_TIMEOUT, |count| *count == seen)
+                    .unwrap();
+                }
+                Ok(_) => {
+                  iterations.fetch_add(1, Ordering::Relaxed);
+                  *backups_count.lock().unwrap() += 1;
+                  backed_up.notify_all();
+                }
+                // The evaluator failed, and its error is reported below.
+                Err(_) => break,
+              }
+            }
+          })
+        });
+      }
+      drop(handle);
+
+      // A forward pass is dispatched as soon as any worker is waiting for one,
+      // taking the requests of every worker waiting by then. Waiting for more
+      // could stall: a worker whose selection is all in flight elsewhere submits
+      // nothing until those are backed up.
+      block_on_current_thread(run_evaluator(model, requests, 1, 1))
+    });
+
+    result.map(|()| iterations.into_inner())
+  }
+}
+
+/// Polls `future` to completion on the current thread, parking it between the
+/// wakeups.
+///
+/// `futures::executor::block_on` would do, but it refuses to run inside of
+/// another executor, and [`Zero::best_moves`] is usually called from one.
+fn block_on_current_thread<F: Future>(future: F) -> F::Output {
+  struct ThreadWaker(thread::Thread);
+
+  impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+      self.0.unpark();
+    }
+  }
+
+  let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+  let mut context = Context::from_waker(&waker);
+  let mut future = pin!(future);
+  loop {
+    match future.as_mut().poll(&mut context) {
+      Poll::Ready(output) => return output,
+      Poll::Pending => thread::park(),
+    }
+  }
+}
+
+/// Search knobs of play under `config`.
+fn search_params(config: &ZeroConfig) -> Params {
+  Params {
+    gumbel: config.gumbel.then_some(GumbelParams::PLAY),
+    ..Params::PLAY
+  }
+}
+
+/// Converts Red's komi to the komi of `player`, which is what the search and
+/// the global features expect.
+fn player_komi_x_2(komi_x_2: i32, player: Player) -> i32 {
+  if player == Player::Red { komi_x_2 } else { -komi_x_2 }
 }
 
 /// Returns the raw neural network policy for the current position, without
@@ -126,20 +344,26 @@ pub async fn policy_moves<N, M>(
   model: &M,
   field: &Field,
   player: Player,
+  komi_x_2: i32,
 ) -> Result<PolicyAnalysis<N>, <M as Model<N>>::E>
 where
   N: Float + Sum,
   M: Model<N>,
 {
-  // The raw policy carries no notion of komi, matching the search which is
-  // driven with a zero komi here.
-  let komi_x_2 = 0;
+  let komi_x_2 = player_komi_x_2(komi_x_2, player);
   let features = field_features::<N>(field, player, field.width(), field.height(), 0).insert_axis(Axis(0));
   let global = global::<N>(field, player, komi_x_2).insert_axis(Axis(0));
 
   // The policy as trained, without any optimism mixed in: this is the raw
   // prediction, not a search that has lines to explore.
-  let (policies, values) = model.predict(features, global, Array1::zeros(1)).await?;
+  let (policies, values) = model
+    .predict_keyed(
+      &[position_key(field, player, komi_x_2)],
+      features,
+      global,
+      Array1::zeros(1),
+    )
+    .await?;
 
   let policy = policies.index_axis(Axis(0), 0);
   let value = values[(0, 0)] - values[(0, 1)];